@@ -0,0 +1,76 @@
+//! Runs each ROM headless with a cycle budget, and reports how each run ended
+//! ROMs that stop changing the screen and registers (for example, ones waiting for input) are
+//! reported as stalled instead of using up the whole budget.
+//!
+//! Usage: `cargo run --example batch -- <ROM>...`
+
+extern crate chip8;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+use chip8::Chip8;
+use chip8::config::{Config, InputPolling};
+
+/// The number of cycles each ROM may run
+const CYCLE_BUDGET: u64 = 1_000_000;
+/// The number of frames without changes after which a ROM is stalled
+const STALL_FRAMES: u64 = 300;
+
+/// An implementation of `Chip8IO` without a display, sound, or input, that requests closing once
+/// the cycle budget is used up
+struct Headless {
+    cycles: u64,
+}
+
+impl chip8::Chip8IO for Headless {
+    fn draw(&mut self, _pixels: &[bool]) {}
+    fn get_keys(&mut self) -> chip8::Keypad {
+        // This is called once per cycle, as the keys are polled before every instruction
+        self.cycles += 1;
+
+        // Press every key once the budget is used up, so a program waiting for a key doesn't block
+        // closing
+        chip8::Keypad::from([self.should_close(); 16])
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        self.cycles >= CYCLE_BUDGET
+    }
+}
+
+/// Runs the ROM, and returns how the run ended
+fn run(path: &str) -> chip8::Result<String> {
+    let mut program = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut program))
+        .map_err(|e| format!("Failed to read ROM: {}", e))?;
+
+    let config = Config::new()
+        .watchdog(STALL_FRAMES)
+        .input_polling(InputPolling::EveryInstruction);
+    let mut chip8 = Chip8::new(&program, config)?;
+    let mut io = Headless { cycles: 0 };
+    chip8.run_until_break(&mut io)?;
+
+    let result = if chip8.program_ended() {
+        "ended"
+    } else if chip8.stalled() {
+        "stalled"
+    } else {
+        "budget exhausted"
+    };
+
+    Ok(format!("{} after {} cycles", result, chip8.cycles()))
+}
+
+fn main() {
+    for path in env::args().skip(1) {
+        match run(&path) {
+            Ok(result) => println!("{}: {}", path, result),
+            Err(e) => println!("{}: error: {}", path, e),
+        }
+    }
+}
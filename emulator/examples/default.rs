@@ -10,8 +10,8 @@ use chip8::config::Log;
 #[cfg(feature = "default_io")]
 fn main() {
     let program = &[0x61, 0xFF, 0xF1, 0x18];
-    let mut io = Io::new("beep.wav");
-    chip8::run(program, &mut io, Log::Enabled).unwrap();
+    let mut io = Io::new();
+    chip8::run(program, &mut io, Log::Instructions).unwrap();
 }
 
 #[cfg(not(feature = "default_io"))]
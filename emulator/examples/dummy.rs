@@ -7,10 +7,11 @@ struct Io;
 // First implement `Chip8IO`
 impl chip8::Chip8IO for Io {
     fn draw(&mut self, _pixels: &[bool]) {}
-    fn get_keys(&mut self) -> chip8::Keys {
-        [false; 16]
+    fn get_keys(&mut self) -> chip8::Keypad {
+        chip8::Keypad::new()
     }
-    fn play_sound(&mut self) {}
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
     fn should_close(&self) -> bool {
         false
     }
@@ -22,5 +23,5 @@ fn main() {
     // Initialize I/O state
     let mut io = Io;
     // Run the program with the emulator
-    chip8::run(program, &mut io, Log::Enabled).unwrap();
+    chip8::run(program, &mut io, Log::Instructions).unwrap();
 }
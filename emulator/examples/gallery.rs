@@ -0,0 +1,95 @@
+//! Runs each ROM in a directory headless for a few seconds, and writes a screenshot of each one
+//! along with an HTML page showing them as a gallery
+//! The screenshot of a ROM is the frame with the most pixels lit, which is usually its title
+//! screen or a busy moment of gameplay.
+//!
+//! Usage: `cargo run --example gallery -- <ROM DIRECTORY> <OUTPUT DIRECTORY>`
+
+extern crate chip8;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+use chip8::Palette;
+use chip8::config::Config;
+use chip8::screenshot;
+
+/// The number of frames each ROM is run for (5 seconds)
+const FRAMES: u64 = 300;
+/// The number of cycles run per frame
+const FRAME_CYCLES: u64 = 10;
+/// The size of each screenshot pixel
+const SCALE: usize = 4;
+
+/// Runs the ROM, and writes its screenshot to the path
+fn screenshot(rom: &Path, output: &Path) -> chip8::Result<()> {
+    let mut program = Vec::new();
+    File::open(rom)
+        .and_then(|mut f| f.read_to_end(&mut program))
+        .map_err(|e| format!("Failed to read ROM: {}", e))?;
+
+    let capture = screenshot::capture(&program, Config::new().seed(0), FRAMES, FRAME_CYCLES)?;
+    let file = File::create(output).map_err(|e| format!("Failed to create screenshot: {}", e))?;
+    screenshot::write_png(&capture.planes, &Palette::default(), SCALE, BufWriter::new(file))
+        .map_err(|e| format!("Failed to write screenshot: {}", e))?;
+
+    Ok(())
+}
+
+/// Escapes text for use in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    if args.len() != 3 {
+        panic!("Usage: gallery <ROM DIRECTORY> <OUTPUT DIRECTORY>");
+    }
+
+    let output = Path::new(&args[2]);
+    fs::create_dir_all(output).unwrap_or_else(|e| panic!("Failed to create directory: {}", e));
+
+    let mut roms = fs::read_dir(&args[1])
+        .unwrap_or_else(|e| panic!("Failed to read directory: {}", e))
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    roms.sort();
+
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                                 <title>CHIP-8 ROMs</title>\n<style>\n\
+                                 body { background: #222; color: #EEE; font-family: sans-serif; }\n\
+                                 figure { display: inline-block; margin: 8px; }\n\
+                                 img { image-rendering: pixelated; width: 256px; }\n\
+                                 </style>\n</head>\n<body>\n");
+
+    for rom in &roms {
+        let name = rom.file_name().unwrap().to_string_lossy().into_owned();
+        let image = format!("{}.png", name);
+
+        match screenshot(rom, &output.join(&image)) {
+            Ok(()) => {
+                println!("{}: done", name);
+                html.push_str(&format!("<figure><img src=\"{}\" alt=\"{}\">\
+                                        <figcaption>{}</figcaption></figure>\n",
+                                       escape(&image),
+                                       escape(&name),
+                                       escape(&name)));
+            }
+            Err(e) => println!("{}: error: {}", name, e),
+        }
+    }
+
+    html.push_str("</body>\n</html>\n");
+
+    File::create(output.join("index.html"))
+        .and_then(|mut f| f.write_all(html.as_bytes()))
+        .unwrap_or_else(|e| panic!("Failed to write gallery: {}", e));
+}
@@ -0,0 +1,35 @@
+//! Renders a sound log (see `chip8::sound_log`) into a WAV file
+//!
+//! Usage: `cargo run --example render_sound -- <LOG> <OUTPUT>`
+
+extern crate chip8;
+
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Read};
+
+use chip8::sound_log::{self, SoundLog};
+
+/// The sample rate of the rendered audio
+const SAMPLE_RATE: u32 = 44100;
+/// The frequency of the beep
+const FREQUENCY: u32 = 440;
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    if args.len() != 3 {
+        panic!("Usage: render_sound <LOG> <OUTPUT>");
+    }
+
+    let mut text = String::new();
+    File::open(&args[1])
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .unwrap_or_else(|e| panic!("Failed to read sound log: {}", e));
+
+    let log = SoundLog::parse(&text).unwrap_or_else(|e| panic!("{}", e));
+    let output = File::create(&args[2]).unwrap_or_else(|e| panic!("Failed to create file: {}", e));
+
+    sound_log::render_wav(&log, BufWriter::new(output), SAMPLE_RATE, FREQUENCY)
+        .unwrap_or_else(|e| panic!("Failed to write WAV file: {}", e));
+}
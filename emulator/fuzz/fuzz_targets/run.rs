@@ -0,0 +1,15 @@
+//! Runs arbitrary inputs as programs, checking that the emulator never panics (see `chip8::fuzz`)
+//!
+//! Run with `cargo fuzz run run` from the `emulator` directory.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate chip8;
+
+/// The number of cycles each input is run for
+const CYCLES: u64 = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = chip8::fuzz::run(data, CYCLES);
+});
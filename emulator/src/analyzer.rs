@@ -0,0 +1,168 @@
+//! Detection of the quirks a program most likely expects, by running it briefly with each
+//! combination of quirks and comparing the results
+//!
+//! Programs written for a different interpreter than the one emulated usually crash, draw outside
+//! the screen, run off the end of the program, or get stuck in a loop. Combinations of quirks are
+//! ranked by how far the program got without any of these happening.
+
+use std::fmt;
+
+use super::Chip8;
+use config::{Config, Quirks};
+use io::HeadlessIO;
+
+/// The number of cycles to run per frame
+const FRAME_CYCLES: u64 = 10;
+/// The number of frames without changes after which a program is stalled
+const STALL_FRAMES: u64 = 120;
+
+/// How a run of a program ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program returned an error, such as an invalid opcode or drawing outside the screen
+    Crashed(String),
+    /// The program ran past the end of memory
+    Ended,
+    /// Neither the screen nor the registers changed for a while
+    Stalled,
+    /// The program was still running when the cycle budget was used up
+    Running,
+}
+
+impl Outcome {
+    /// Returns how likely it is that the quirks were correct, with higher being more likely
+    fn rank(&self) -> u8 {
+        match *self {
+            Outcome::Crashed(_) => 0,
+            Outcome::Ended => 1,
+            Outcome::Stalled => 2,
+            Outcome::Running => 3,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Crashed(ref error) => write!(f, "crashed ({})", error),
+            Outcome::Ended => write!(f, "ended"),
+            Outcome::Stalled => write!(f, "stalled"),
+            Outcome::Running => write!(f, "running"),
+        }
+    }
+}
+
+/// The result of running a program with a combination of quirks
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuirkResult {
+    /// The quirks the program was run with
+    pub quirks: Quirks,
+    /// How the run ended
+    pub outcome: Outcome,
+    /// The number of cycles run
+    pub cycles: u64,
+}
+
+/// Returns every combination of quirks
+/// The key press quirk only changes the timing of input, which running the program can't detect,
+/// and few programs need the wrap and index overflow quirks, so they are left at their defaults.
+fn combinations() -> Vec<Quirks> {
+    (0..16)
+        .map(|i| {
+            Quirks {
+                shift: i & 1 != 0,
+                memory: i & 2 != 0,
+                jump: i & 4 != 0,
+                font: i & 8 != 0,
+                ..Quirks::default()
+            }
+        })
+        .collect()
+}
+
+/// Returns the number of quirks that differ between the two combinations
+fn distance(a: &Quirks, b: &Quirks) -> usize {
+    (a.shift != b.shift) as usize + (a.memory != b.memory) as usize +
+    (a.jump != b.jump) as usize + (a.font != b.font) as usize
+}
+
+/// Runs the program for at most `cycles` cycles with the quirks
+pub fn run(program: &[u8], quirks: Quirks, cycles: u64) -> QuirkResult {
+    let config = Config::new().quirks(quirks).seed(0).watchdog(STALL_FRAMES);
+    // Every key is pressed and released in turn while the program waits for one, so it continues
+    let mut io = HeadlessIO::new();
+
+    let mut chip8 = match Chip8::new(program, config) {
+        Ok(chip8) => chip8,
+        Err(e) => {
+            return QuirkResult {
+                quirks,
+                outcome: Outcome::Crashed(e.to_string()),
+                cycles: 0,
+            }
+        }
+    };
+
+    let outcome = loop {
+        if chip8.cycles() >= cycles {
+            break Outcome::Running;
+        }
+
+        io.next_cycle(chip8.is_waiting_for_key());
+        if let Err(e) = chip8.cycle(&mut io) {
+            break Outcome::Crashed(e.to_string());
+        }
+
+        if chip8.program_ended() {
+            break Outcome::Ended;
+        }
+
+        if chip8.cycles() % FRAME_CYCLES == 0 {
+            chip8.tick_60hz(&mut io);
+
+            if chip8.stalled() {
+                break Outcome::Stalled;
+            }
+        }
+    };
+
+    QuirkResult {
+        quirks,
+        outcome,
+        cycles: chip8.cycles(),
+    }
+}
+
+/// Runs the program for at most `cycles` cycles with each combination of quirks, and returns the
+/// results from most to least likely to be correct
+/// Results that are equally likely are ordered by how close their quirks are to the defaults.
+pub fn analyze(program: &[u8], cycles: u64) -> Vec<QuirkResult> {
+    let default = Quirks::default();
+    let mut results = combinations()
+        .into_iter()
+        .map(|quirks| run(program, quirks, cycles))
+        .collect::<Vec<_>>();
+
+    results.sort_by(|a, b| {
+        b.outcome
+            .rank()
+            .cmp(&a.outcome.rank())
+            .then_with(|| b.cycles.cmp(&a.cycles))
+            .then_with(|| distance(&a.quirks, &default).cmp(&distance(&b.quirks, &default)))
+    });
+
+    results
+}
+
+/// Returns the quirks the program most likely expects, or `None` if the default quirks work as
+/// well as any other combination
+pub fn suggest(program: &[u8], cycles: u64) -> Option<Quirks> {
+    let results = analyze(program, cycles);
+    let best = &results[0];
+
+    if best.quirks == Quirks::default() {
+        None
+    } else {
+        Some(best.quirks)
+    }
+}
@@ -0,0 +1,357 @@
+//! An assembler for the language written by `disasm`
+//!
+//! Each line holds an optional label, followed by an optional instruction or data directive, and
+//! an optional comment starting with a `;`:
+//!
+//! ```text
+//! ; Draws the 0 character in the top left corner forever
+//! start:  LD I, 0x050
+//!         DRW V0, V0, 5
+//! loop:   JP loop
+//! data:   DB 0xFF, 0x81
+//!         DW 0x1234
+//! ```
+//!
+//! Mnemonics and registers are case insensitive. Numbers are decimal or hexadecimal (starting
+//! with `0x`), and addresses can also be labels. `SHR` and `SHL` may leave out VY, in which case
+//! it is the same as VX.
+
+use std::collections::HashMap;
+
+use errors::*;
+use register::RegisterId;
+use symbols::SymbolTable;
+
+/// The result of assembling a program
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assembly {
+    /// The assembled program
+    pub program: Vec<u8>,
+    /// The addresses of the labels in the program
+    pub symbols: SymbolTable,
+}
+
+/// An operand of an instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand<'a> {
+    /// A general purpose register
+    Register(RegisterId),
+    /// The index register
+    I,
+    /// The memory at the index register
+    IndirectI,
+    /// The delay timer
+    DT,
+    /// The sound timer
+    ST,
+    /// A key press
+    K,
+    /// A font character
+    F,
+    /// A large font character
+    HF,
+    /// BCD digits
+    B,
+    /// A number or a label
+    Value(&'a str),
+}
+
+/// A line that produces bytes, saved by the first pass for the second
+struct Statement<'a> {
+    /// The line number
+    line: usize,
+    /// The text of the line, for errors
+    text: &'a str,
+    /// The mnemonic or directive, in uppercase
+    mnemonic: String,
+    /// The operands
+    operands: Vec<Operand<'a>>,
+}
+
+/// Assembles a program, which is loaded at `PROGRAM_START` (see the module documentation for the
+/// language)
+pub fn assemble(source: &str) -> Result<Assembly> {
+    let mut labels = HashMap::new();
+    let mut symbols = SymbolTable::new();
+    let mut statements = Vec::new();
+    let mut address = ::PROGRAM_START;
+
+    // First pass: find the addresses of labels
+    for (i, text) in source.lines().enumerate() {
+        let invalid = |message: &str| ErrorKind::InvalidAssembly(i + 1, message.to_string());
+        let mut code = text.split(';').next().unwrap_or("").trim();
+
+        if let Some(colon) = code.find(':') {
+            let label = code[..colon].trim();
+
+            if !is_label(label) {
+                bail!(invalid(&format!("invalid label `{}`", label)));
+            }
+
+            if labels.insert(label.to_string(), address as u16).is_some() {
+                bail!(invalid(&format!("duplicate label `{}`", label)));
+            }
+
+            symbols.insert(address as u16, label);
+            code = code[colon + 1..].trim();
+        }
+
+        if code.is_empty() {
+            continue;
+        }
+
+        let mut parts = code.splitn(2, char::is_whitespace);
+        let mnemonic = parts.next().unwrap_or("").to_uppercase();
+        let operands = match parts.next().map(str::trim) {
+            Some(operands) if !operands.is_empty() => {
+                operands.split(',').map(|o| parse_operand(o.trim())).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        address += match &mnemonic[..] {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            _ => 2,
+        };
+
+        statements.push(Statement {
+            line: i + 1,
+            text,
+            mnemonic,
+            operands,
+        });
+    }
+
+    if address >= ::MEMORY {
+        bail!(ErrorKind::ProgramTooLarge(address - ::PROGRAM_START, ::MEMORY - ::PROGRAM_START));
+    }
+
+    // Second pass: encode instructions
+    let mut program = Vec::with_capacity(address - ::PROGRAM_START);
+
+    for statement in &statements {
+        let text = statement.text.trim().to_string();
+        encode(statement, &labels, &mut program)
+            .chain_err(|| ErrorKind::InvalidAssembly(statement.line, text))?;
+    }
+
+    Ok(Assembly {
+        program,
+        symbols,
+    })
+}
+
+/// Returns whether the text is a valid label
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Parses an operand
+fn parse_operand<'a>(text: &'a str) -> Operand<'a> {
+    match &text.to_uppercase()[..] {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::HF,
+        "B" => Operand::B,
+        upper => {
+            if upper.len() == 2 && upper.starts_with('V') {
+                if let Ok(x) = u8::from_str_radix(&upper[1..], 16) {
+                    return Operand::Register(RegisterId::from_nibble(x));
+                }
+            }
+
+            Operand::Value(text)
+        }
+    }
+}
+
+/// Returns the value of a number or label, which must be at most `max`
+fn value(text: &str, labels: &HashMap<String, u16>, max: u16) -> Result<u16> {
+    let value = if text.starts_with("0x") || text.starts_with("0X") {
+        u32::from_str_radix(&text[2..], 16).ok()
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse().ok()
+    } else {
+        labels.get(text).map(|&a| a as u32)
+    };
+
+    match value {
+        Some(value) if value <= max as u32 => Ok(value as u16),
+        Some(_) => bail!("value `{}` is larger than 0x{:X}", text, max),
+        None => bail!("unknown label `{}`", text),
+    }
+}
+
+/// Encodes a statement, appending its bytes to the program
+fn encode(statement: &Statement,
+          labels: &HashMap<String, u16>,
+          program: &mut Vec<u8>)
+          -> Result<()> {
+    use self::Operand::*;
+    use instruction::Instruction::*;
+
+    let addr = |text| value(text, labels, 0xFFF);
+    let byte = |text| value(text, labels, 0xFF).map(|n| n as u8);
+    let nibble = |text| value(text, labels, 0xF).map(|n| n as u8);
+
+    match &statement.mnemonic[..] {
+        "DB" => {
+            for operand in &statement.operands {
+                match *operand {
+                    Value(text) => program.push(byte(text)?),
+                    _ => bail!("expected a byte"),
+                }
+            }
+
+            return Ok(());
+        }
+        "DW" => {
+            for operand in &statement.operands {
+                match *operand {
+                    Value(text) => {
+                        let word = value(text, labels, 0xFFFF)?;
+                        program.push((word >> 8) as u8);
+                        program.push(word as u8);
+                    }
+                    _ => bail!("expected a word"),
+                }
+            }
+
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let instruction = match (&statement.mnemonic[..], &statement.operands[..]) {
+        ("CLS", &[]) => Ok(ClearScreen),
+        ("RET", &[]) => Ok(Return),
+        ("JP", &[Value(a)]) => addr(a).map(Goto),
+        ("JP", &[Register(RegisterId::V0), Value(a)]) => addr(a).map(OffsetGoto),
+        ("CALL", &[Value(a)]) => addr(a).map(Call),
+        ("SE", &[Register(x), Register(y)]) => Ok(SkipEq(x, y)),
+        ("SE", &[Register(x), Value(n)]) => byte(n).map(|n| SkipEqConst(x, n)),
+        ("SNE", &[Register(x), Register(y)]) => Ok(SkipNeq(x, y)),
+        ("SNE", &[Register(x), Value(n)]) => byte(n).map(|n| SkipNeqConst(x, n)),
+        ("LD", &[Register(x), Register(y)]) => Ok(Move(x, y)),
+        ("LD", &[Register(x), Value(n)]) => byte(n).map(|n| SetConst(x, n)),
+        ("LD", &[I, Value(a)]) => addr(a).map(SetIndex),
+        ("LD", &[Register(x), DT]) => Ok(GetDelay(x)),
+        ("LD", &[Register(x), K]) => Ok(WaitKey(x)),
+        ("LD", &[DT, Register(x)]) => Ok(SetDelay(x)),
+        ("LD", &[ST, Register(x)]) => Ok(SetSound(x)),
+        ("LD", &[F, Register(x)]) => Ok(SetIndexChar(x)),
+        ("LD", &[HF, Register(x)]) => Ok(SetIndexLargeChar(x)),
+        ("LD", &[B, Register(x)]) => Ok(BCD(x)),
+        ("LD", &[IndirectI, Register(x)]) => Ok(RegDump(x)),
+        ("LD", &[Register(x), IndirectI]) => Ok(RegLoad(x)),
+        ("ADD", &[Register(x), Register(y)]) => Ok(Add(x, y)),
+        ("ADD", &[Register(x), Value(n)]) => byte(n).map(|n| AddConst(x, n)),
+        ("ADD", &[I, Register(x)]) => Ok(AddIndex(x)),
+        ("OR", &[Register(x), Register(y)]) => Ok(BitOr(x, y)),
+        ("AND", &[Register(x), Register(y)]) => Ok(BitAnd(x, y)),
+        ("XOR", &[Register(x), Register(y)]) => Ok(BitXor(x, y)),
+        ("SUB", &[Register(x), Register(y)]) => Ok(Sub(x, y)),
+        ("SHR", &[Register(x)]) => Ok(Shr(x, x)),
+        ("SHR", &[Register(x), Register(y)]) => Ok(Shr(x, y)),
+        ("SUBN", &[Register(x), Register(y)]) => Ok(InverseSub(x, y)),
+        ("SHL", &[Register(x)]) => Ok(Shl(x, x)),
+        ("SHL", &[Register(x), Register(y)]) => Ok(Shl(x, y)),
+        ("RND", &[Register(x), Value(n)]) => byte(n).map(|n| Rand(x, n)),
+        ("DRW", &[Register(x), Register(y), Value(n)]) => nibble(n).map(|n| Draw(x, y, n)),
+        ("SKP", &[Register(x)]) => Ok(SkipKey(x)),
+        ("SKNP", &[Register(x)]) => Ok(SkipNotKey(x)),
+        ("AUDIO", &[]) => Ok(LoadAudio),
+        ("PITCH", &[Register(x)]) => Ok(SetPitch(x)),
+        ("PLANE", &[Value(n)]) => nibble(n).map(SelectPlanes),
+        ("MEGAON", &[]) => Ok(MegaOn),
+        ("MEGAOFF", &[]) => Ok(MegaOff),
+        ("LDHI", &[Value(n)]) => byte(n).map(SetIndexLong),
+        ("LDPAL", &[Value(n)]) => byte(n).map(LoadPalette),
+        ("SPRW", &[Value(n)]) => byte(n).map(SpriteWidth),
+        ("SPRH", &[Value(n)]) => byte(n).map(SpriteHeight),
+        ("ALPHA", &[Value(n)]) => byte(n).map(SetAlpha),
+        ("DIGISND", &[Value(n)]) => nibble(n).map(PlaySample),
+        ("STOPSND", &[]) => Ok(StopSample),
+        ("BMODE", &[Value(n)]) => nibble(n).map(SetBlendMode),
+        ("CCOL", &[Value(n)]) => byte(n).map(SetCollisionColor),
+        ("SCRU", &[Value(n)]) => nibble(n).map(ScrollUp),
+        ("BGSTEP", &[]) => Ok(StepBackground),
+        ("ADDN", &[Register(x), Register(y)]) => Ok(AddNibbles(x, y)),
+        ("COLOR", &[Register(x), Register(y)]) => Ok(SetColorZones(x, y)),
+        ("COLOR", &[Register(x), Register(y), Value(n)]) => {
+            nibble(n).map(|n| SetColorRows(x, y, n))
+        }
+        ("SKP2", &[Register(x)]) => Ok(SkipKey2(x)),
+        ("SKNP2", &[Register(x)]) => Ok(SkipNotKey2(x)),
+        _ => bail!("unknown instruction"),
+    }?;
+    let opcode = instruction.encode();
+
+    program.push((opcode >> 8) as u8);
+    program.push(opcode as u8);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disasm;
+
+    #[test]
+    fn test_assemble() {
+        let source = "; Draws the 0 character forever\n\
+                      start:  ld i, 0x050\n\
+                      \x20       DRW V0, V0, 5\n\
+                      loop:   JP loop ; Wait\n\
+                      data:   DB 0xFF, 1\n\
+                      \x20       DW 0x1234";
+        let assembly = assemble(source).unwrap();
+
+        assert_eq!(vec![0xA0, 0x50, 0xD0, 0x05, 0x12, 0x04, 0xFF, 0x01, 0x12, 0x34],
+                   assembly.program);
+        assert_eq!(Some("loop"), assembly.symbols.get(0x204));
+        assert_eq!(Some("data"), assembly.symbols.get(0x206));
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        let program = [0x00, 0xE0, 0x00, 0xEE, 0x12, 0x34, 0x22, 0x34, 0xB2, 0x34, 0x31, 0x23,
+                       0x41, 0x23, 0x51, 0x20, 0x91, 0x20, 0x61, 0x23, 0x71, 0x23, 0x81, 0x20,
+                       0x81, 0x21, 0x81, 0x22, 0x81, 0x23, 0x81, 0x24, 0x81, 0x25, 0x81, 0x26,
+                       0x81, 0x27, 0x81, 0x2E, 0xA1, 0x23, 0xC1, 0x23, 0xD1, 0x23, 0xE1, 0x9E,
+                       0xE1, 0xA1, 0xF1, 0x07, 0xF1, 0x0A, 0xF1, 0x15, 0xF1, 0x18, 0xF1, 0x1E,
+                       0xF1, 0x29, 0xF1, 0x30, 0xF1, 0x33, 0xF1, 0x55, 0xF1, 0x65, 0xF2, 0x01,
+                       0xF0, 0x02, 0xF1, 0x3A, 0x00, 0x11, 0x00, 0x10, 0x01, 0x12, 0x02, 0x03,
+                       0x03, 0x10, 0x04, 0x10, 0x05, 0xFF, 0x06, 0x01, 0x07, 0x00, 0x08, 0x02,
+                       0x09, 0x05, 0x00, 0xB3];
+        let source = disasm::disassemble(&program)
+            .into_iter()
+            .map(|line| line.instruction.unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        assert_eq!(&program[..], &assemble(&source).unwrap().program[..]);
+    }
+
+    #[test]
+    fn test_assemble_invalid() {
+        assert!(assemble("JP nowhere").is_err());
+        assert!(assemble("LD V0, 0x100").is_err());
+        assert!(assemble("MOV V0, V1").is_err());
+        assert!(assemble("a: CLS\na: CLS").is_err());
+        assert!(assemble("1abc: CLS").is_err());
+    }
+}
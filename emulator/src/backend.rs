@@ -0,0 +1,246 @@
+//! The parts of a backend, as separate traits
+//!
+//! `Chip8IO` does all of the I/O of the emulator, which makes backends that only need some of it,
+//! such as a test that only supplies input, implement the rest as well. Instead, a backend can be
+//! built from a `Display`, an `Input`, and an `Audio`: any type that implements all three
+//! implements `Chip8IO`, and `Split` combines three separate values into one. `()` implements each
+//! trait by doing nothing, so it can stand in for the parts a backend doesn't need.
+//!
+//! ```rust
+//! use chip8::backend::{Input, Split};
+//! use chip8::config::Config;
+//! use chip8::{Chip8, Keypad};
+//!
+//! /// Holds down the 5 key
+//! struct HoldFive;
+//!
+//! impl Input for HoldFive {
+//!     fn get_keys(&mut self) -> Keypad {
+//!         let mut keys = Keypad::new();
+//!         keys.set(5, true);
+//!         keys
+//!     }
+//! }
+//!
+//! // Waits for a key, and stores it in V0
+//! let mut chip8 = Chip8::new(&[0xF0, 0x0A], Config::new()).unwrap();
+//! chip8.step(&mut Split::new((), HoldFive, ())).unwrap();
+//! ```
+
+use std::path::PathBuf;
+
+use bindings::Action;
+use io::{Keypad, Palette, Sprite, AUDIO_PATTERN_LEN};
+use megachip::scale_down;
+
+/// The display of a backend (see `Chip8IO` for the methods)
+pub trait Display {
+    /// See `Chip8IO::draw`
+    fn draw(&mut self, pixels: &[bool]);
+    /// See `Chip8IO::draw_planes`
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        let _ = palette;
+        let pixels = planes.iter().map(|&p| p != 0).collect::<Vec<_>>();
+        self.draw(&pixels);
+    }
+    /// See `Chip8IO::draw_sprites`
+    fn draw_sprites(&mut self, sprites: &[Sprite]) {
+        let _ = sprites;
+    }
+    /// See `Chip8IO::draw_mega`
+    fn draw_mega(&mut self, pixels: &[u8], palette: &Palette) {
+        self.draw_planes(&scale_down(pixels), palette);
+    }
+    /// See `Chip8IO::set_status`
+    fn set_status(&mut self, status: &str) {
+        let _ = status;
+    }
+}
+
+/// The input of a backend (see `Chip8IO` for the methods)
+pub trait Input {
+    /// See `Chip8IO::get_keys`
+    fn get_keys(&mut self) -> Keypad;
+    /// See `Chip8IO::get_second_keys`
+    fn get_second_keys(&mut self) -> Keypad {
+        Keypad::new()
+    }
+    /// See `Chip8IO::should_close`
+    /// Returns `false` by default.
+    fn should_close(&self) -> bool {
+        false
+    }
+    /// See `Chip8IO::actions`
+    fn actions(&mut self) -> Vec<Action> {
+        Vec::new()
+    }
+    /// See `Chip8IO::is_action_held`
+    fn is_action_held(&self, action: Action) -> bool {
+        let _ = action;
+        false
+    }
+    /// See `Chip8IO::dropped_file`
+    fn dropped_file(&mut self) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// The audio of a backend (see `Chip8IO` for the methods)
+pub trait Audio {
+    /// See `Chip8IO::sound_start`
+    fn sound_start(&mut self);
+    /// See `Chip8IO::sound_stop`
+    fn sound_stop(&mut self);
+    /// See `Chip8IO::set_audio_pattern`
+    fn set_audio_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_LEN], pitch: u8) {
+        let _ = (pattern, pitch);
+    }
+    /// See `Chip8IO::on_timer_tick`
+    fn on_timer_tick(&mut self, delay: u8, sound: u8) {
+        let _ = (delay, sound);
+    }
+}
+
+/// Shows nothing
+impl Display for () {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_planes(&mut self, _: &[u8], _: &Palette) {}
+    fn draw_mega(&mut self, _: &[u8], _: &Palette) {}
+}
+
+/// Never presses any keys
+impl Input for () {
+    fn get_keys(&mut self) -> Keypad {
+        Keypad::new()
+    }
+}
+
+/// Plays nothing
+impl Audio for () {
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+}
+
+impl<T: Display + Input + Audio> ::Chip8IO for T {
+    fn draw(&mut self, pixels: &[bool]) {
+        Display::draw(self, pixels)
+    }
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        Display::draw_planes(self, planes, palette)
+    }
+    fn draw_sprites(&mut self, sprites: &[Sprite]) {
+        Display::draw_sprites(self, sprites)
+    }
+    fn draw_mega(&mut self, pixels: &[u8], palette: &Palette) {
+        Display::draw_mega(self, pixels, palette)
+    }
+    fn set_status(&mut self, status: &str) {
+        Display::set_status(self, status)
+    }
+    fn get_keys(&mut self) -> Keypad {
+        Input::get_keys(self)
+    }
+    fn get_second_keys(&mut self) -> Keypad {
+        Input::get_second_keys(self)
+    }
+    fn should_close(&self) -> bool {
+        Input::should_close(self)
+    }
+    fn actions(&mut self) -> Vec<Action> {
+        Input::actions(self)
+    }
+    fn is_action_held(&self, action: Action) -> bool {
+        Input::is_action_held(self, action)
+    }
+    fn dropped_file(&mut self) -> Option<PathBuf> {
+        Input::dropped_file(self)
+    }
+    fn sound_start(&mut self) {
+        Audio::sound_start(self)
+    }
+    fn sound_stop(&mut self) {
+        Audio::sound_stop(self)
+    }
+    fn set_audio_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_LEN], pitch: u8) {
+        Audio::set_audio_pattern(self, pattern, pitch)
+    }
+    fn on_timer_tick(&mut self, delay: u8, sound: u8) {
+        Audio::on_timer_tick(self, delay, sound)
+    }
+}
+
+/// A backend made of a separate display, input, and audio, which implements `Chip8IO`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Split<D, I, A> {
+    /// The display
+    pub display: D,
+    /// The input
+    pub input: I,
+    /// The audio
+    pub audio: A,
+}
+
+impl<D: Display, I: Input, A: Audio> Split<D, I, A> {
+    /// Returns a backend made of the display, input, and audio
+    pub fn new(display: D, input: I, audio: A) -> Split<D, I, A> {
+        Split {
+            display,
+            input,
+            audio,
+        }
+    }
+}
+
+impl<D: Display, I, A> Display for Split<D, I, A> {
+    fn draw(&mut self, pixels: &[bool]) {
+        self.display.draw(pixels)
+    }
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        self.display.draw_planes(planes, palette)
+    }
+    fn draw_sprites(&mut self, sprites: &[Sprite]) {
+        self.display.draw_sprites(sprites)
+    }
+    fn draw_mega(&mut self, pixels: &[u8], palette: &Palette) {
+        self.display.draw_mega(pixels, palette)
+    }
+    fn set_status(&mut self, status: &str) {
+        self.display.set_status(status)
+    }
+}
+
+impl<D, I: Input, A> Input for Split<D, I, A> {
+    fn get_keys(&mut self) -> Keypad {
+        self.input.get_keys()
+    }
+    fn get_second_keys(&mut self) -> Keypad {
+        self.input.get_second_keys()
+    }
+    fn should_close(&self) -> bool {
+        self.input.should_close()
+    }
+    fn actions(&mut self) -> Vec<Action> {
+        self.input.actions()
+    }
+    fn is_action_held(&self, action: Action) -> bool {
+        self.input.is_action_held(action)
+    }
+    fn dropped_file(&mut self) -> Option<PathBuf> {
+        self.input.dropped_file()
+    }
+}
+
+impl<D, I, A: Audio> Audio for Split<D, I, A> {
+    fn sound_start(&mut self) {
+        self.audio.sound_start()
+    }
+    fn sound_stop(&mut self) {
+        self.audio.sound_stop()
+    }
+    fn set_audio_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_LEN], pitch: u8) {
+        self.audio.set_audio_pattern(pattern, pitch)
+    }
+    fn on_timer_tick(&mut self, delay: u8, sound: u8) {
+        self.audio.on_timer_tick(delay, sound)
+    }
+}
@@ -0,0 +1,272 @@
+//! Bindings of keys to emulator actions, such as pausing or saving the state
+//!
+//! Keys are identified by name, so the bindings are independent of the backend used for input.
+//! Bindings can be written to and read from a simple text format, where each line binds an action
+//! to a key:
+//!
+//! ```text
+//! # Comments start with a '#'
+//! pause = P
+//! save_state_1 = F1
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use errors::*;
+
+/// The number of save state slots that can be bound to keys
+pub const SAVE_SLOTS: u8 = 4;
+
+/// An action performed by the emulator rather than the Chip-8 program
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Pauses or resumes emulation
+    Pause,
+    /// Runs a single frame while paused
+    FrameAdvance,
+    /// Steps emulation backwards in time
+    Rewind,
+    /// Restarts the program
+    Reset,
+    /// Runs emulation 8 times faster while held
+    Turbo,
+    /// Doubles the speed of emulation
+    SpeedUp,
+    /// Halves the speed of emulation
+    SlowDown,
+    /// Switches the window between fullscreen and windowed mode
+    Fullscreen,
+    /// Switches CRT-style rendering on or off
+    Crt,
+    /// Saves an image of the screen
+    Screenshot,
+    /// Starts or stops recording the screen to an animated image
+    Record,
+    /// Saves the state of the emulator to the slot, which is kept until another program is loaded
+    SaveState(u8),
+    /// Loads the state of the emulator from the slot, if a state was saved to it
+    LoadState(u8),
+}
+
+impl Action {
+    /// Returns a list of every action
+    pub fn all() -> Vec<Action> {
+        let mut actions = vec![Action::Pause,
+                               Action::FrameAdvance,
+                               Action::Rewind,
+                               Action::Reset,
+                               Action::Turbo,
+                               Action::SpeedUp,
+                               Action::SlowDown,
+                               Action::Fullscreen,
+                               Action::Crt,
+                               Action::Screenshot,
+                               Action::Record];
+
+        actions.extend((1..SAVE_SLOTS + 1).map(Action::SaveState));
+        actions.extend((1..SAVE_SLOTS + 1).map(Action::LoadState));
+
+        actions
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::Pause => write!(f, "pause"),
+            Action::FrameAdvance => write!(f, "frame_advance"),
+            Action::Rewind => write!(f, "rewind"),
+            Action::Reset => write!(f, "reset"),
+            Action::Turbo => write!(f, "turbo"),
+            Action::SpeedUp => write!(f, "speed_up"),
+            Action::SlowDown => write!(f, "slow_down"),
+            Action::Fullscreen => write!(f, "fullscreen"),
+            Action::Crt => write!(f, "crt"),
+            Action::Screenshot => write!(f, "screenshot"),
+            Action::Record => write!(f, "record"),
+            Action::SaveState(slot) => write!(f, "save_state_{}", slot),
+            Action::LoadState(slot) => write!(f, "load_state_{}", slot),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Action> {
+        Action::all()
+            .into_iter()
+            .find(|a| a.to_string() == s)
+            .ok_or_else(|| ErrorKind::UnknownAction(s.to_string()).into())
+    }
+}
+
+/// A set of bindings of keys to actions
+///
+/// # Examples
+///
+/// ```rust
+/// use chip8::bindings::{Action, Bindings};
+///
+/// let mut bindings = Bindings::default();
+/// bindings.bind(Action::Pause, "Space").unwrap();
+///
+/// assert_eq!(Some(Action::Pause), bindings.action("Space"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bindings {
+    /// Pairs of actions and the names of the keys bound to them
+    bindings: Vec<(Action, String)>,
+}
+
+impl Bindings {
+    /// Returns an empty set of bindings
+    pub fn new() -> Bindings {
+        Bindings { bindings: Vec::new() }
+    }
+
+    /// Binds the action to the key, replacing the previous binding of the action
+    /// Returns an error if the key name is invalid or the key is already bound to another action
+    pub fn bind(&mut self, action: Action, key: &str) -> Result<()> {
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            bail!(ErrorKind::InvalidKeyName(key.to_string()));
+        }
+
+        if let Some(other) = self.action(key) {
+            if other != action {
+                bail!(ErrorKind::DuplicateBinding(key.to_string(), other.to_string()));
+            }
+        }
+
+        self.unbind(action);
+        self.bindings.push((action, key.to_string()));
+
+        Ok(())
+    }
+
+    /// Removes the binding of the action
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.retain(|&(a, _)| a != action);
+    }
+
+    /// Returns the name of the key bound to the action
+    pub fn key(&self, action: Action) -> Option<&str> {
+        self.bindings.iter().find(|&&(a, _)| a == action).map(|(_, k)| k.as_str())
+    }
+
+    /// Returns the action bound to the key
+    pub fn action(&self, key: &str) -> Option<Action> {
+        self.bindings.iter().find(|&(_, k)| k == key).map(|&(a, _)| a)
+    }
+
+    /// Returns an iterator over the actions and the names of the keys bound to them
+    pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (Action, &'a str)> + 'a> {
+        Box::new(self.bindings.iter().map(|&(a, ref k)| (a, k.as_str())))
+    }
+
+    /// Parses bindings from their text representation (see the module documentation for the
+    /// format)
+    /// Actions not mentioned in the text are left unbound
+    pub fn parse(text: &str) -> Result<Bindings> {
+        let mut bindings = Bindings::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let action = parts.next().unwrap_or("").trim();
+            let key = parts.next()
+                .map(str::trim)
+                .ok_or_else(|| ErrorKind::InvalidBinding(i + 1, line.to_string()))?;
+
+            let invalid = || ErrorKind::InvalidBinding(i + 1, line.to_string());
+
+            let action = action.parse().chain_err(invalid)?;
+            bindings.bind(action, key).chain_err(invalid)?;
+        }
+
+        Ok(bindings)
+    }
+}
+
+impl Default for Bindings {
+    /// Returns the default bindings
+    fn default() -> Bindings {
+        let mut bindings = Bindings::new();
+
+        bindings.bindings.push((Action::Pause, "P".to_string()));
+        bindings.bindings.push((Action::FrameAdvance, "Period".to_string()));
+        bindings.bindings.push((Action::Rewind, "Backspace".to_string()));
+        bindings.bindings.push((Action::Reset, "Delete".to_string()));
+        bindings.bindings.push((Action::Turbo, "Tab".to_string()));
+        bindings.bindings.push((Action::SpeedUp, "Equals".to_string()));
+        bindings.bindings.push((Action::SlowDown, "Minus".to_string()));
+        bindings.bindings.push((Action::Fullscreen, "F11".to_string()));
+        bindings.bindings.push((Action::Crt, "F9".to_string()));
+        bindings.bindings.push((Action::Screenshot, "F12".to_string()));
+        bindings.bindings.push((Action::Record, "F10".to_string()));
+
+        for slot in 1..SAVE_SLOTS + 1 {
+            bindings.bindings.push((Action::SaveState(slot), format!("F{}", slot)));
+            bindings.bindings.push((Action::LoadState(slot), format!("F{}", slot + SAVE_SLOTS)));
+        }
+
+        bindings
+    }
+}
+
+impl fmt::Display for Bindings {
+    /// Writes the bindings in the format read by `Bindings::parse`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(action, ref key) in &self.bindings {
+            writeln!(f, "{} = {}", action, key)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let bindings = Bindings::default();
+        let parsed = Bindings::parse(&bindings.to_string()).unwrap();
+
+        assert_eq!(bindings, parsed);
+    }
+
+    #[test]
+    fn test_parse() {
+        let bindings = Bindings::parse("# comment\n\npause = Space\nload_state_2 = F9\n").unwrap();
+
+        assert_eq!(Some("Space"), bindings.key(Action::Pause));
+        assert_eq!(Some(Action::LoadState(2)), bindings.action("F9"));
+        assert_eq!(None, bindings.key(Action::Rewind));
+    }
+
+    #[test]
+    fn test_parse_unknown_action() {
+        match Bindings::parse("jump = Space") {
+            Err(Error(ErrorKind::InvalidBinding(1, _), _)) => {}
+            r => panic!("Expected error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_bind_duplicate() {
+        let mut bindings = Bindings::default();
+
+        match bindings.bind(Action::Turbo, "P") {
+            Err(Error(ErrorKind::DuplicateBinding(..), _)) => {}
+            r => panic!("Expected error, got {:?}", r),
+        }
+    }
+}
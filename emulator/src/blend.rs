@@ -0,0 +1,111 @@
+//! Blending of frames to reduce flicker
+//!
+//! Chip-8 programs move sprites by erasing and drawing them again, so they flicker when the screen
+//! is shown between the two. Screens of the time used phosphors that glowed for a moment after
+//! being lit, which hid most of the flicker. A `FrameBlender` imitates this by fading pixels out
+//! over a few frames after they are turned off, instead of turning them off instantly.
+
+use io::{Color, Palette};
+
+/// Fades pixels out over a number of frames after they are turned off
+///
+/// # Examples
+///
+/// ```rust
+/// use chip8::blend::FrameBlender;
+/// use chip8::Palette;
+///
+/// let palette = Palette::default();
+/// let mut blender = FrameBlender::new(3);
+///
+/// blender.blend(&[1], &palette);
+///
+/// // The pixel is turned off, but still partly lit
+/// let colors = blender.blend(&[0], &palette);
+/// assert_eq!([0xBF; 3], colors[0]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameBlender {
+    /// The number of frames pixels fade out over
+    fade_frames: u32,
+    /// For each pixel, the planes it was last on in (or zero if it is not lit at all) and the
+    /// number of frames since then
+    pixels: Vec<(u8, u32)>,
+    /// The colors of the pixels in the last blended frame
+    colors: Vec<Color>,
+}
+
+impl FrameBlender {
+    /// Returns a blender that fades pixels out over `fade_frames` frames
+    /// With zero frames, pixels are turned off instantly, as without blending.
+    pub fn new(fade_frames: u32) -> FrameBlender {
+        FrameBlender {
+            fade_frames,
+            pixels: Vec::new(),
+            colors: Vec::new(),
+        }
+    }
+
+    /// Returns the number of frames pixels fade out over
+    pub fn fade_frames(&self) -> u32 {
+        self.fade_frames
+    }
+
+    /// Advances by a frame showing the planes (as in `Chip8::planes`), returning the colors to
+    /// show for each pixel
+    /// This should be called once per frame, even if the screen didn't change, so pixels keep
+    /// fading out.
+    pub fn blend(&mut self, planes: &[u8], palette: &Palette) -> &[Color] {
+        if self.pixels.len() != planes.len() {
+            self.pixels = vec![(0, 0); planes.len()];
+            self.colors = vec![palette.colors[0]; planes.len()];
+        }
+
+        for (i, &planes) in planes.iter().enumerate() {
+            let pixel = &mut self.pixels[i];
+
+            if planes != 0 {
+                *pixel = (planes, 0);
+            } else if pixel.0 != 0 {
+                pixel.1 += 1;
+                if pixel.1 > self.fade_frames {
+                    *pixel = (0, 0);
+                }
+            }
+
+            self.colors[i] = if planes == 0 && pixel.0 != 0 {
+                // The pixel fades from its color to the background
+                let faded = pixel.1 as f64 / (self.fade_frames + 1) as f64;
+                mix(palette.colors[pixel.0 as usize], palette.colors[0], faded)
+            } else {
+                palette.colors[planes as usize]
+            };
+        }
+
+        &self.colors
+    }
+
+    /// Returns whether any pixels are fading out, so the screen will change in the next frame even
+    /// if the planes don't
+    pub fn is_fading(&self) -> bool {
+        self.pixels.iter().any(|&(planes, frames)| planes != 0 && frames > 0)
+    }
+
+    /// Turns off every pixel instantly, such as when the program is restarted
+    pub fn clear(&mut self) {
+        self.pixels.clear();
+        self.colors.clear();
+    }
+}
+
+/// Returns the color a fraction of the way from one color to another
+fn mix(from: Color, to: Color, fraction: f64) -> Color {
+    let mut color = [0; 3];
+
+    for i in 0..3 {
+        let component = from[i] as f64 + (to[i] as f64 - from[i] as f64) * fraction;
+        color[i] = component.round() as u8;
+    }
+
+    color
+}
@@ -0,0 +1,101 @@
+//! The color board of CHIP-8X
+//!
+//! CHIP-8X programs color the screen with the VP-590 color board, which has 8 colors. The
+//! background is one color, stepped through blue, black, green, and red by the program. The
+//! foreground color is set for zones of 8 pixels wide, which are 4 rows tall when set with
+//! `SetColorZones`, or any number of rows when set with `SetColorRows`. Pixels that are on are
+//! shown in the color of their zone, and pixels that are off in the background color.
+
+use io::{Color, Palette};
+use super::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+/// The colors of the color board, by their number
+const COLORS: [Color; 8] = [[0x00, 0x00, 0x00],
+                            [0xFF, 0x00, 0x00],
+                            [0x00, 0x00, 0xFF],
+                            [0xFF, 0x00, 0xFF],
+                            [0x00, 0xFF, 0x00],
+                            [0xFF, 0xFF, 0x00],
+                            [0x00, 0xFF, 0xFF],
+                            [0xFF, 0xFF, 0xFF]];
+/// The background colors, in the order the program steps through them
+const BACKGROUNDS: [u8; 4] = [2, 0, 4, 1];
+/// The foreground color of every zone until the program sets them
+const DEFAULT_FOREGROUND: u8 = 1;
+/// The width of a zone, in pixels
+const ZONE_WIDTH: usize = 8;
+/// The height of the zones set by `SetColorZones`, in pixels
+const ZONE_HEIGHT: usize = 4;
+/// The number of zones in each row of pixels
+const ZONE_COLUMNS: usize = SCREEN_WIDTH / ZONE_WIDTH;
+
+/// The colors of the screen of a CHIP-8X program
+#[derive(Clone, Debug)]
+pub struct ColorBoard {
+    /// The index of the background color into `BACKGROUNDS`
+    background: usize,
+    /// The foreground color of each zone, for each row of pixels
+    foreground: Vec<u8>,
+}
+
+impl ColorBoard {
+    /// Returns a board with a blue background and a red foreground
+    pub fn new() -> ColorBoard {
+        ColorBoard {
+            background: 0,
+            foreground: vec![DEFAULT_FOREGROUND; ZONE_COLUMNS * SCREEN_HEIGHT],
+        }
+    }
+
+    /// Steps to the next background color
+    pub fn step_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    /// Sets the color of the zones from the first to the last column and row of zones, inclusive
+    /// Only the lowest 3 bits of the color are used.
+    pub fn set_zones(&mut self, columns: (usize, usize), rows: (usize, usize), color: u8) {
+        for row in rows.0 * ZONE_HEIGHT..((rows.1 + 1) * ZONE_HEIGHT).min(SCREEN_HEIGHT) {
+            self.set_row(columns, row, color);
+        }
+    }
+
+    /// Sets the color of the zone at the X coordinate, for the rows of pixels starting at `y`
+    /// Only the lowest 3 bits of the color are used.
+    pub fn set_rows(&mut self, x: usize, y: usize, rows: usize, color: u8) {
+        let column = x / ZONE_WIDTH;
+
+        for row in y..(y + rows).min(SCREEN_HEIGHT) {
+            self.set_row((column, column), row, color);
+        }
+    }
+
+    /// Sets the color of the zones from the first to the last column, for the row of pixels
+    fn set_row(&mut self, columns: (usize, usize), row: usize, color: u8) {
+        for column in columns.0..(columns.1 + 1).min(ZONE_COLUMNS) {
+            self.foreground[column + row * ZONE_COLUMNS] = color & 7;
+        }
+    }
+
+    /// Returns the color number of each pixel of the planes, which are indices into `palette()`
+    pub fn colorize(&self, planes: &[u8]) -> Vec<u8> {
+        let background = BACKGROUNDS[self.background];
+
+        planes.iter()
+            .enumerate()
+            .map(|(i, &plane)| {
+                let (x, y) = (i % SCREEN_WIDTH, i / SCREEN_WIDTH);
+                if plane != 0 {
+                    self.foreground[x / ZONE_WIDTH + y * ZONE_COLUMNS]
+                } else {
+                    background
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the palette of the color board, where each color is at the index of its number
+pub fn palette() -> Palette {
+    Palette::new(&COLORS)
+}
@@ -0,0 +1,96 @@
+//! Sources of time used for pacing the emulator
+//!
+//! `Chip8::run_until_break` paces cycles and timer updates using a clock, which is the system clock
+//! by default. Another clock can be set with `Config::clock`, for example to run deterministic
+//! tests, or on platforms where `std::time::Instant` is not available.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A source of time
+pub trait Clock {
+    /// Returns the time passed since a fixed point, such as when the clock was created
+    /// The time must never decrease.
+    fn now(&mut self) -> Duration;
+    /// Waits for about the duration, which is used while the emulator has nothing to do
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// A clock usable by the emulator
+pub type BoxedClock = Box<dyn Clock + Send>;
+
+/// The system clock, which measures real time
+#[derive(Clone, Copy, Debug)]
+pub struct SystemClock {
+    /// When the clock was created
+    start: Instant,
+}
+
+impl SystemClock {
+    /// Returns a clock starting at the current time
+    pub fn new() -> SystemClock {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&mut self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves forward when told to
+///
+/// Clones of a clock share the same time, so a clone can be kept to drive the time of an emulator
+/// (for example, from another thread). Sleeping moves the time forward by the duration slept.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use chip8::clock::{Clock, ManualClock};
+///
+/// let mut clock = ManualClock::new();
+/// let driver = clock.clone();
+///
+/// driver.advance(Duration::from_millis(16));
+/// assert_eq!(Duration::from_millis(16), clock.now());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    /// The current time
+    now: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    /// Returns a clock starting at zero
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    /// Moves the time forward by the duration
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().expect("Clock lock poisoned") += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&mut self) -> Duration {
+        *self.now.lock().expect("Clock lock poisoned")
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.advance(duration);
+    }
+}
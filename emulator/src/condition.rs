@@ -0,0 +1,231 @@
+//! Conditions on the state of the emulator, written in a small expression language
+//!
+//! A condition compares values with `==`, `!=`, `<`, `<=`, `>`, or `>=`, and comparisons can be
+//! combined with `&&` and `||` (`&&` binds tighter). The values that can be compared are:
+//!
+//! - Numbers, written in decimal or in hexadecimal with a `0x` prefix
+//! - `pc` and `i`: the program counter and index register
+//! - `v0` through `vf`: the general purpose registers
+//! - `dt` and `st`: the delay and sound timers
+//! - `mem[ADDRESS]`: the byte in memory at the address
+//! - `pixel(X, Y)`: 1 if the pixel at the coordinates is on, 0 otherwise
+//! - `lit(X, Y, WIDTH, HEIGHT)`: the number of pixels that are on in the rectangle
+//!
+//! # Examples
+//!
+//! ```rust
+//! use chip8::condition::Condition;
+//!
+//! // The program reached a subroutine while V3 is zero
+//! let condition = Condition::parse("pc == 0x2A4 && v3 == 0").unwrap();
+//! // The screen is completely dark
+//! let condition = Condition::parse("lit(0, 0, 64, 32) == 0").unwrap();
+//! # let _ = condition;
+//! ```
+
+use std::str::FromStr;
+
+use errors::*;
+use super::{Chip8, SCREEN_WIDTH, SCREEN_HEIGHT};
+use register::RegisterId;
+
+/// A value that can be compared
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(u32),
+    ProgramCounter,
+    Index,
+    Register(RegisterId),
+    DelayTimer,
+    SoundTimer,
+    Memory(u32),
+    Pixel(u32, u32),
+    Lit(u32, u32, u32, u32),
+}
+
+impl Value {
+    /// Returns the value in the current state of the emulator
+    /// Out of bounds memory and pixels are read as zero
+    fn eval(&self, chip8: &Chip8) -> u32 {
+        match *self {
+            Value::Number(n) => n,
+            Value::ProgramCounter => chip8.registers.program_counter as u32,
+            Value::Index => chip8.registers.index as u32,
+            Value::Register(x) => chip8.registers.get(x) as u32,
+            Value::DelayTimer => chip8.delay_timer as u32,
+            Value::SoundTimer => chip8.sound_timer as u32,
+            Value::Memory(addr) => chip8.memory.get(addr as usize).map_or(0, |&b| b as u32),
+            Value::Pixel(x, y) => lit(chip8, x, y, 1, 1),
+            Value::Lit(x, y, width, height) => lit(chip8, x, y, width, height),
+        }
+    }
+}
+
+/// Returns the number of pixels that are on in the rectangle
+fn lit(chip8: &Chip8, x: u32, y: u32, width: u32, height: u32) -> u32 {
+    let pixels = chip8.io.pixels();
+    let x_end = (x + width).min(SCREEN_WIDTH as u32);
+    let y_end = (y + height).min(SCREEN_HEIGHT as u32);
+    let mut count = 0;
+
+    for py in y..y_end {
+        for px in x..x_end {
+            if pixels[px as usize + py as usize * SCREEN_WIDTH] {
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
+
+/// A comparison operator
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A condition on the state of the emulator (see the module documentation)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    /// The comparisons, as a disjunction of conjunctions
+    any: Vec<Vec<(Value, Op, Value)>>,
+}
+
+impl Condition {
+    /// Parses a condition (see the module documentation for the syntax)
+    pub fn parse(text: &str) -> Result<Condition> {
+        let invalid = || ErrorKind::InvalidCondition(text.to_string());
+        let mut any = Vec::new();
+
+        for clause in text.split("||") {
+            let mut all = Vec::new();
+
+            for comparison in clause.split("&&") {
+                all.push(parse_comparison(comparison).chain_err(invalid)?);
+            }
+
+            any.push(all);
+        }
+
+        Ok(Condition { any })
+    }
+
+    /// Returns whether the condition holds in the current state of the emulator
+    pub fn eval(&self, chip8: &Chip8) -> bool {
+        self.any.iter().any(|all| {
+            all.iter().all(|&(ref a, op, ref b)| {
+                let (a, b) = (a.eval(chip8), b.eval(chip8));
+
+                match op {
+                    Op::Eq => a == b,
+                    Op::Neq => a != b,
+                    Op::Lt => a < b,
+                    Op::Le => a <= b,
+                    Op::Gt => a > b,
+                    Op::Ge => a >= b,
+                }
+            })
+        })
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Condition> {
+        Condition::parse(s)
+    }
+}
+
+/// Parses a single comparison
+fn parse_comparison(text: &str) -> Result<(Value, Op, Value)> {
+    // Two character operators are checked first so `<=` isn't read as `<`
+    let ops = [("==", Op::Eq), ("!=", Op::Neq), ("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt),
+               (">", Op::Gt)];
+
+    for &(symbol, op) in &ops {
+        if let Some(pos) = text.find(symbol) {
+            let a = parse_value(&text[..pos])?;
+            let b = parse_value(&text[pos + symbol.len()..])?;
+
+            return Ok((a, op, b));
+        }
+    }
+
+    bail!("Missing comparison operator: `{}`", text.trim())
+}
+
+/// Parses a value
+fn parse_value(text: &str) -> Result<Value> {
+    let text = text.trim().to_lowercase();
+
+    let value = match text.as_str() {
+        "pc" => Value::ProgramCounter,
+        "i" => Value::Index,
+        "dt" => Value::DelayTimer,
+        "st" => Value::SoundTimer,
+        _ => {
+            if let Some(args) = parse_call(&text, "mem[", "]")? {
+                match args.as_slice() {
+                    &[addr] => Value::Memory(addr),
+                    _ => bail!("`mem` takes 1 address"),
+                }
+            } else if let Some(args) = parse_call(&text, "pixel(", ")")? {
+                match args.as_slice() {
+                    &[x, y] => Value::Pixel(x, y),
+                    _ => bail!("`pixel` takes 2 arguments"),
+                }
+            } else if let Some(args) = parse_call(&text, "lit(", ")")? {
+                match args.as_slice() {
+                    &[x, y, w, h] => Value::Lit(x, y, w, h),
+                    _ => bail!("`lit` takes 4 arguments"),
+                }
+            } else if text.len() == 2 && text.starts_with('v') {
+                let x = u8::from_str_radix(&text[1..], 16)
+                    .ok()
+                    .and_then(RegisterId::new)
+                    .ok_or_else(|| format!("Invalid register: `{}`", text))?;
+                Value::Register(x)
+            } else {
+                Value::Number(parse_number(&text)?)
+            }
+        }
+    };
+
+    Ok(value)
+}
+
+/// Parses the comma-separated numbers between `start` and `end`, or returns `None` if the text
+/// doesn't start with `start`
+fn parse_call(text: &str, start: &str, end: &str) -> Result<Option<Vec<u32>>> {
+    if !text.starts_with(start) {
+        return Ok(None);
+    }
+
+    if !text.ends_with(end) {
+        bail!("Expected `{}`: `{}`", end, text);
+    }
+
+    text[start.len()..text.len() - end.len()]
+        .split(',')
+        .map(|n| parse_number(n.trim()))
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// Parses a decimal or hexadecimal number
+fn parse_number(text: &str) -> Result<u32> {
+    let result = if let Some(hex) = text.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else {
+        text.parse()
+    };
+
+    result.chain_err(|| format!("Invalid number: `{}`", text))
+}
@@ -1,27 +1,592 @@
 //! Configuration of the emulator
 
-/// Whether to log things such as opcodes being executed
-#[derive(Debug)]
+use std::fmt;
+use std::ops::Range;
+
+use rand::{Rng, SeedableRng, StdRng};
+
+use errors::Error;
+use io::Palette;
+use fontset::Font;
+use speedrun::SpeedrunTimer;
+use pause::PauseHandle;
+use clock::{Clock, BoxedClock};
+
+/// The target of the messages logged by the emulator, which can be used to filter them
+pub const LOG_TARGET: &str = "chip8::emulation";
+
+/// Where programs are loaded by the COSMAC VIP and most other interpreters
+pub const START_COSMAC_VIP: u16 = 0x200;
+/// Where CHIP-8X programs are loaded
+pub const START_CHIP8X: u16 = 0x300;
+/// Where programs are loaded by the ETI-660
+pub const START_ETI_660: u16 = 0x600;
+
+/// Returns the address programs are loaded at by the platform with the given name (`vip`,
+/// `chip8x`, or `eti660`)
+pub fn program_start_preset(name: &str) -> Option<u16> {
+    match name {
+        "vip" => Some(START_COSMAC_VIP),
+        "chip8x" => Some(START_CHIP8X),
+        "eti660" => Some(START_ETI_660),
+        _ => None,
+    }
+}
+
+/// What the emulator logs, from least to most verbose
+/// Each level also logs everything the levels before it log. Messages are logged with the `log`
+/// crate, to `LOG_TARGET`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Log {
-    #[allow(missing_docs)]
-    Enabled,
-    #[allow(missing_docs)]
-    Disabled,
+    /// Nothing is logged
+    Off,
+    /// Errors that stop the program, and invalid opcodes skipped in permissive mode
+    Errors,
+    /// Each instruction that is run
+    Instructions,
+    /// The changes each instruction makes to the registers and timers
+    State,
 }
 
 impl Log {
-    /// Returns whether logging is enabled
+    /// Returns whether anything is logged
     pub fn is_enabled(&self) -> bool {
-        if let &Log::Enabled = self {
-            true
-        } else {
-            false
+        *self != Log::Off
+    }
+
+    /// Returns the level with the given name (`off`, `errors`, `instructions`, or `state`)
+    pub fn from_name(name: &str) -> Option<Log> {
+        match name {
+            "off" => Some(Log::Off),
+            "errors" => Some(Log::Errors),
+            "instructions" => Some(Log::Instructions),
+            "state" => Some(Log::State),
+            _ => None,
         }
     }
 }
 
+/// `true` logs instructions, and `false` logs nothing
 impl From<bool> for Log {
     fn from(val: bool) -> Self {
-        if val { Log::Enabled } else { Log::Disabled }
+        if val { Log::Instructions } else { Log::Off }
+    }
+}
+
+/// Behaviors that differ between Chip-8 interpreters
+///
+/// Each quirk is named after the behavior that deviates from the original COSMAC VIP interpreter,
+/// so `Quirks::chip8()` disables every quirk. The default quirks match the behavior of older
+/// versions of this emulator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `Shr` and `Shl` shift VX in place instead of storing the shifted value of VY in VX
+    pub shift: bool,
+    /// `RegDump` and `RegLoad` leave I unchanged instead of incrementing it past the last register
+    pub memory: bool,
+    /// `OffsetGoto` adds VX to the address (where X is the highest nibble of the address) instead
+    /// of V0
+    pub jump: bool,
+    /// `SetIndexChar` returns an error if VX is over 15 instead of using its lowest nibble
+    pub font: bool,
+    /// `WaitKey` finishes as soon as a key is pressed, instead of waiting for it to be released
+    pub key_press: bool,
+    /// `BCD`, `RegDump`, `RegLoad`, and `Draw` wrap around to the start of memory when they go
+    /// past its end, instead of returning an error
+    pub wrap: bool,
+    /// `AddIndex` sets VF to 1 if I goes past 0xFFF, and to 0 otherwise, as in the Amiga
+    /// interpreter
+    pub index_overflow: bool,
+}
+
+impl Quirks {
+    /// Returns the quirks of the original Chip-8 interpreter (no quirks)
+    pub fn chip8() -> Quirks {
+        Quirks {
+            shift: false,
+            memory: false,
+            jump: false,
+            font: false,
+            key_press: false,
+            wrap: false,
+            index_overflow: false,
+        }
+    }
+
+    /// Returns the quirks of the SUPER-CHIP interpreter
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift: true,
+            memory: true,
+            jump: true,
+            font: false,
+            key_press: false,
+            wrap: false,
+            index_overflow: false,
+        }
+    }
+
+    /// Returns the quirks of XO-CHIP interpreters, which are the same as the original Chip-8
+    /// interpreter
+    pub fn xochip() -> Quirks {
+        Quirks::chip8()
+    }
+
+    /// Returns the quirk preset with the given name (`chip8`, `schip`, or `xochip`)
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "chip8" => Some(Quirks::chip8()),
+            "schip" => Some(Quirks::schip()),
+            "xochip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+
+    /// Enables the quirk with the given name (the name of its field), returning whether the name
+    /// was valid
+    pub fn enable(&mut self, name: &str) -> bool {
+        match name {
+            "shift" => self.shift = true,
+            "memory" => self.memory = true,
+            "jump" => self.jump = true,
+            "font" => self.font = true,
+            "key_press" => self.key_press = true,
+            "wrap" => self.wrap = true,
+            "index_overflow" => self.index_overflow = true,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+impl fmt::Display for Quirks {
+    /// Writes the names of the enabled quirks, separated by commas, or `none`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [("shift", self.shift),
+                     ("memory", self.memory),
+                     ("jump", self.jump),
+                     ("font", self.font),
+                     ("key_press", self.key_press),
+                     ("wrap", self.wrap),
+                     ("index_overflow", self.index_overflow)]
+            .iter()
+            .filter(|&&(_, enabled)| enabled)
+            .map(|&(name, _)| name)
+            .collect::<Vec<_>>();
+
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            shift: true,
+            memory: true,
+            jump: false,
+            font: true,
+            key_press: true,
+            wrap: false,
+            index_overflow: false,
+        }
+    }
+}
+
+/// When the emulator reads the keys from `Chip8IO::get_keys` (see `Config::input_polling`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputPolling {
+    /// Before every instruction
+    EveryInstruction,
+    /// Before the first instruction of each frame (each update of the timers)
+    #[default]
+    PerFrame,
+    /// Before each instruction that reads the keys, such as `SkipKey` and `WaitKey`
+    KeyInstructions,
+}
+
+impl InputPolling {
+    /// Returns the strategy with the given name (`instruction`, `frame`, or `key`)
+    pub fn from_name(name: &str) -> Option<InputPolling> {
+        match name {
+            "instruction" => Some(InputPolling::EveryInstruction),
+            "frame" => Some(InputPolling::PerFrame),
+            "key" => Some(InputPolling::KeyInstructions),
+            _ => None,
+        }
+    }
+}
+
+/// A random number generator usable by the emulator
+pub type BoxedRng = Box<dyn Rng + Send>;
+
+/// A function called with the problems that the emulator skips over instead of stopping (see
+/// `Config::permissive` and `WriteProtection::Warn`)
+pub type WarningHook = Box<dyn FnMut(&Error) + Send>;
+
+/// What happens when the program writes to the memory below the program start, which holds the
+/// interpreter and the font (see `Config::protect_interpreter`)
+pub enum WriteProtection {
+    /// The write is made as usual
+    Off,
+    /// The write is made, and passed to the function as an `ErrorKind::ProtectedWrite` error
+    Warn(WarningHook),
+    /// The program stops with an `ErrorKind::ProtectedWrite` error, without making the write
+    Error,
+}
+
+impl fmt::Debug for WriteProtection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WriteProtection::Off => write!(f, "Off"),
+            WriteProtection::Warn(_) => write!(f, "Warn"),
+            WriteProtection::Error => write!(f, "Error"),
+        }
+    }
+}
+
+/// Options used when creating an emulator
+///
+/// # Examples
+///
+/// ```rust
+/// use chip8::config::{Config, Log};
+///
+/// // Log instructions, and make the `Rand` instruction deterministic
+/// let config = Config::new().log(Log::Instructions).seed(42);
+/// # let _ = config;
+/// ```
+pub struct Config {
+    /// What to log
+    pub(crate) log: Log,
+    /// The addresses of the instructions to log
+    /// If this is empty, instructions at every address are logged
+    pub(crate) log_ranges: Vec<Range<u16>>,
+    /// The random number generator used by the `Rand` instruction
+    /// If this is `None`, a randomly seeded generator is used
+    pub(crate) rng: Option<BoxedRng>,
+    /// Behaviors that differ between interpreters
+    pub(crate) quirks: Quirks,
+    /// The number of instructions to run per second
+    /// If this is `None`, instructions are run as fast as possible
+    pub(crate) speed: Option<u32>,
+    /// The number of frames between rewind snapshots, and the maximum number of snapshots
+    /// If this is `None`, rewinding is disabled
+    pub(crate) rewind: Option<(usize, usize)>,
+    /// The colors used to display the planes of the screen
+    pub(crate) palette: Palette,
+    /// A timer for speedrunning, or `None` if it is disabled
+    pub(crate) speedrun: Option<SpeedrunTimer>,
+    /// Whether to log changes to the sound timer
+    pub(crate) sound_log: bool,
+    /// The handle used to pause the emulator
+    pub(crate) pause: PauseHandle,
+    /// The number of frames without changes after which the program is stalled
+    /// If this is `None`, the watchdog is disabled
+    pub(crate) watchdog: Option<u64>,
+    /// The number of frames to run ahead
+    /// If this is `None`, run-ahead is disabled
+    pub(crate) run_ahead: Option<usize>,
+    /// The maximum number of calls on the stack
+    pub(crate) stack_depth: usize,
+    /// Whether to count the instructions run
+    pub(crate) profile: bool,
+    /// Whether to record the addresses written by instructions
+    pub(crate) detect_self_modification: bool,
+    /// The clock used for pacing the emulator
+    /// If this is `None`, the system clock is used
+    pub(crate) clock: Option<BoxedClock>,
+    /// The function called when an invalid opcode is skipped
+    /// If this is `None`, invalid opcodes are errors
+    pub(crate) permissive: Option<WarningHook>,
+    /// Whether to run programs as CHIP-8X programs
+    pub(crate) chip8x: bool,
+    /// The address the program is loaded at
+    pub(crate) program_start: u16,
+    /// The font used by `SetIndexChar`
+    pub(crate) font: Font,
+    /// The large font used by `SetIndexLargeChar`
+    pub(crate) large_font: Font,
+    /// The size of memory, in bytes
+    pub(crate) memory_size: usize,
+    /// What happens when the program writes below the program start
+    pub(crate) protection: WriteProtection,
+    /// When the keys are read
+    pub(crate) input_polling: InputPolling,
+}
+
+impl Config {
+    /// Returns the default configuration
+    pub fn new() -> Config {
+        Config {
+            log: Log::Off,
+            log_ranges: Vec::new(),
+            rng: None,
+            quirks: Quirks::default(),
+            speed: None,
+            rewind: None,
+            palette: Palette::default(),
+            speedrun: None,
+            sound_log: false,
+            pause: PauseHandle::new(),
+            watchdog: None,
+            run_ahead: None,
+            stack_depth: 16,
+            profile: false,
+            detect_self_modification: false,
+            clock: None,
+            permissive: None,
+            chip8x: false,
+            program_start: START_COSMAC_VIP,
+            font: Font::small(),
+            large_font: Font::large(),
+            memory_size: ::MEMORY,
+            protection: WriteProtection::Off,
+            input_polling: InputPolling::default(),
+        }
+    }
+
+    /// Sets what to log, such as instructions being run (nothing is logged by default)
+    pub fn log<L: Into<Log>>(mut self, log: L) -> Config {
+        self.log = log.into();
+        self
+    }
+
+    /// Only logs the instructions with addresses in `range`, which can be called more than once to
+    /// log several ranges
+    /// By default, instructions at every address are logged. Errors are logged wherever they happen.
+    pub fn log_range(mut self, range: Range<u16>) -> Config {
+        self.log_ranges.push(range);
+        self
+    }
+
+    /// Sets the random number generator used by the `Rand` instruction
+    pub fn rng<R: Rng + Send + 'static>(mut self, rng: R) -> Config {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    /// Seeds the random number generator used by the `Rand` instruction, making runs of the same
+    /// program with the same input reproducible
+    pub fn seed(self, seed: usize) -> Config {
+        self.rng(StdRng::from_seed(&[seed][..]))
+    }
+
+    /// Sets the behaviors that differ between interpreters
+    pub fn quirks(mut self, quirks: Quirks) -> Config {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Sets the number of instructions to run per second, which must not be zero
+    /// By default, instructions are run as fast as possible
+    pub fn speed(mut self, instructions_per_second: u32) -> Config {
+        assert!(instructions_per_second > 0, "Speed must not be zero");
+        self.speed = Some(instructions_per_second);
+        self
+    }
+
+    /// Sets the clock used for pacing cycles and timer updates in `Chip8::run_until_break`
+    /// By default, the system clock is used (see `clock::SystemClock`).
+    pub fn clock<C: Clock + Send + 'static>(mut self, clock: C) -> Config {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Sets the colors passed to `Chip8IO::draw_planes`
+    pub fn palette(mut self, palette: Palette) -> Config {
+        self.palette = palette;
+        self
+    }
+
+    /// Enables the speedrun timer, which is shown with `Chip8IO::set_status`
+    pub fn speedrun(mut self, timer: SpeedrunTimer) -> Config {
+        self.speedrun = Some(timer);
+        self
+    }
+
+    /// Sets whether to log changes to the sound timer (see `Chip8::sound_log`)
+    pub fn sound_log(mut self, enabled: bool) -> Config {
+        self.sound_log = enabled;
+        self
+    }
+
+    /// Sets whether to count how many times each kind of instruction and each address is run (see
+    /// `Chip8::profile`)
+    pub fn profile(mut self, enabled: bool) -> Config {
+        self.profile = enabled;
+        self
+    }
+
+    /// Sets whether to record which instruction last wrote each address, to find instructions that
+    /// were both written and run (see `Chip8::self_modifications`)
+    pub fn detect_self_modification(mut self, enabled: bool) -> Config {
+        self.detect_self_modification = enabled;
+        self
+    }
+
+    /// Sets the handle used to pause, resume, and step the emulator, so a clone of it can be kept to
+    /// control an emulator run with `chip8::run`
+    pub fn pause(mut self, handle: PauseHandle) -> Config {
+        self.pause = handle;
+        self
+    }
+
+    /// Enables the watchdog, which stops `Chip8::run_until_break` when neither the screen nor the
+    /// registers change for `frames` frames (see `Chip8::stalled`)
+    pub fn watchdog(mut self, frames: u64) -> Config {
+        assert!(frames > 0, "Watchdog frames must not be zero");
+        self.watchdog = Some(frames);
+        self
+    }
+
+    /// Enables run-ahead, which reduces input latency by showing the screen as it will be `frames`
+    /// frames later, assuming the input doesn't change (usually 1 frame is enough)
+    /// Works best with a configured speed, as the number of cycles run ahead per frame is the
+    /// number run during the previous frame.
+    pub fn run_ahead(mut self, frames: usize) -> Config {
+        assert!(frames > 0, "Run-ahead frames must not be zero");
+        self.run_ahead = Some(frames);
+        self
+    }
+
+    /// Sets the maximum number of calls on the stack, which must not be zero
+    /// Calling a subroutine with the stack full is an error. The default depth is 16, as in the
+    /// original interpreter.
+    pub fn stack_depth(mut self, depth: usize) -> Config {
+        assert!(depth > 0, "Stack depth must not be zero");
+        self.stack_depth = depth;
+        self
+    }
+
+    /// Enables permissive mode, in which an invalid opcode is skipped as if it was an instruction
+    /// that does nothing, and passed to `on_warning` as an `ErrorKind::InvalidOpcode` error
+    /// By default, invalid opcodes stop the program with an error. Many programs store data
+    /// between their code, which some interpreters run through without problems.
+    pub fn permissive<F: FnMut(&Error) + Send + 'static>(mut self, on_warning: F) -> Config {
+        self.permissive = Some(Box::new(on_warning));
+        self
+    }
+
+    /// Sets what happens when the program writes to the memory below the program start, which
+    /// holds the interpreter and the font, with `BCD` or `RegDump`
+    /// Such writes are usually bugs that trash the font. By default, they are made as usual.
+    /// Writes made through `Chip8::write_memory`, such as by a debugger, are always allowed.
+    pub fn protect_interpreter(mut self, protection: WriteProtection) -> Config {
+        self.protection = protection;
+        self
+    }
+
+    /// Sets when the keys are read from `Chip8IO::get_keys` (once per frame by default)
+    /// Reading the keys can be slow, for example if it handles the events of a window, so reading
+    /// them before every instruction wastes time. Programs that are run with `Chip8::cycle`
+    /// without calling `Chip8::tick_60hz` never start a new frame, so they should read the keys
+    /// before every instruction or before the instructions that read them instead.
+    pub fn input_polling(mut self, polling: InputPolling) -> Config {
+        self.input_polling = polling;
+        self
+    }
+
+    /// Sets whether to run programs as CHIP-8X programs, which color the screen and can use a
+    /// second keypad (see `Chip8IO::get_second_keys`)
+    /// CHIP-8X replaces the `OffsetGoto` instruction, so it must be enabled for CHIP-8X programs
+    /// only. While it is enabled, the screen is shown with the colors of the CHIP-8X color board
+    /// instead of the palette.
+    pub fn chip8x(mut self, enabled: bool) -> Config {
+        self.chip8x = enabled;
+        self
+    }
+
+    /// Sets the address the program is loaded at and started from, which must be after the fonts
+    /// (see `program_start_preset` for the addresses used by different platforms)
+    /// By default, programs are loaded at 0x200, as on the COSMAC VIP. If the program doesn't fit
+    /// in the memory after the program start, creating the emulator returns an error.
+    pub fn program_start(mut self, address: u16) -> Config {
+        self.program_start = address;
+        self
+    }
+
+    /// Sets the size of memory in bytes, which can be at most 64 KiB (the addresses I can hold)
+    /// By default, memory is 4 KiB, as on the COSMAC VIP. XO-CHIP programs may use up to 64 KiB.
+    /// Addresses past the end of memory are errors, or wrap around with the wrap quirk.
+    pub fn memory_size(mut self, size: usize) -> Config {
+        assert!(size > 0 && size <= 0x10000, "Memory size must be between 1 byte and 64 KiB");
+        self.memory_size = size;
+        self
+    }
+
+    /// Replaces the font with `glyphs`, loaded at `start`, which holds the sprites of the
+    /// characters 0 through F, 5 bytes each
+    /// The font must end before the program start and not overlap the large font, or creating the
+    /// emulator returns an error. By default, the Chip-8 font set is loaded at 0x50.
+    pub fn font(mut self, glyphs: &[u8], start: u16) -> Config {
+        self.font = Font::new(glyphs, start);
+        self
+    }
+
+    /// Replaces the large font with `glyphs`, loaded at `start`, which holds the sprites of either
+    /// the digits 0 through 9 or the characters 0 through F, 10 bytes each
+    /// The large font must end before the program start and not overlap the font, or creating
+    /// the emulator returns an error. By default, the large digits of SUPER-CHIP are loaded at
+    /// 0x110.
+    pub fn large_font(mut self, glyphs: &[u8], start: u16) -> Config {
+        self.large_font = Font::new(glyphs, start);
+        self
+    }
+
+    /// Enables rewinding, taking a snapshot of the emulator every `interval` frames and keeping at
+    /// most `capacity` snapshots (see `Chip8::rewind`)
+    /// Neither value may be zero
+    pub fn rewind(mut self, interval: usize, capacity: usize) -> Config {
+        assert!(interval > 0 && capacity > 0, "Rewind interval and capacity must not be zero");
+        self.rewind = Some((interval, capacity));
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+impl From<Log> for Config {
+    fn from(log: Log) -> Config {
+        Config::new().log(log)
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("log", &self.log)
+            .field("log_ranges", &self.log_ranges)
+            .field("rng", &self.rng.as_ref().map(|_| "custom"))
+            .field("quirks", &self.quirks)
+            .field("speed", &self.speed)
+            .field("rewind", &self.rewind)
+            .field("palette", &self.palette)
+            .field("speedrun", &self.speedrun)
+            .field("sound_log", &self.sound_log)
+            .field("pause", &self.pause)
+            .field("watchdog", &self.watchdog)
+            .field("run_ahead", &self.run_ahead)
+            .field("stack_depth", &self.stack_depth)
+            .field("profile", &self.profile)
+            .field("detect_self_modification", &self.detect_self_modification)
+            .field("clock", &self.clock.as_ref().map(|_| "custom"))
+            .field("permissive", &self.permissive.is_some())
+            .field("chip8x", &self.chip8x)
+            .field("program_start", &self.program_start)
+            .field("font", &self.font)
+            .field("large_font", &self.large_font)
+            .field("memory_size", &self.memory_size)
+            .field("protection", &self.protection)
+            .field("input_polling", &self.input_polling)
+            .finish()
     }
 }
@@ -0,0 +1,77 @@
+//! Tracking of which addresses have been run as instructions, for finding dead code and telling
+//! code apart from data
+
+use std::fmt;
+
+use super::{Chip8, MEMORY};
+
+/// A map of the addresses of memory that instructions were fetched from
+///
+/// Only the address of the first byte of each instruction is marked.
+#[derive(Clone)]
+pub struct Coverage {
+    /// One bit per address, where bit `N % 8` of byte `N / 8` is set if address `N` was run
+    bitmap: Vec<u8>,
+}
+
+impl Coverage {
+    /// Returns a map of memory of the size, with no addresses marked
+    pub fn new(memory_size: usize) -> Coverage {
+        Coverage { bitmap: vec![0; memory_size.div_ceil(8)] }
+    }
+
+    /// Marks the address as run
+    /// Addresses outside of the map are ignored, which happens after loading a state with more
+    /// memory.
+    pub(crate) fn mark(&mut self, address: u16) {
+        let address = address as usize;
+        if let Some(byte) = self.bitmap.get_mut(address / 8) {
+            *byte |= 1 << (address % 8);
+        }
+    }
+
+    /// Returns whether an instruction at the address was run
+    /// Addresses outside of memory were never run.
+    pub fn is_covered(&self, address: u16) -> bool {
+        let address = address as usize;
+        self.bitmap.get(address / 8).is_some_and(|&byte| byte & (1 << (address % 8)) != 0)
+    }
+
+    /// Returns the addresses that were run, in ascending order
+    pub fn addresses(&self) -> Vec<u16> {
+        (0..self.bitmap.len() * 8).map(|a| a as u16).filter(|&a| self.is_covered(a)).collect()
+    }
+
+    /// Returns the map as a bitmap of every address in memory, where bit `N % 8` of byte `N / 8`
+    /// is set if address `N` was run
+    pub fn bitmap(&self) -> &[u8] {
+        &self.bitmap
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new(MEMORY)
+    }
+}
+
+impl fmt::Debug for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Coverage")
+            .field("addresses", &self.addresses())
+            .finish()
+    }
+}
+
+impl Chip8 {
+    /// Returns the map of addresses that instructions have been run from since the emulator was
+    /// created or the map was last cleared
+    pub fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+
+    /// Clears the map of addresses that instructions have been run from
+    pub fn clear_coverage(&mut self) {
+        self.coverage = Coverage::new(self.memory.len());
+    }
+}
@@ -1,64 +1,150 @@
 //! Emulation of the Chip-8 CPU
 
-use rand;
+use rand::Rng;
 
 use super::Chip8;
+use config::{Log, WriteProtection, InputPolling, LOG_TARGET};
 use errors::*;
-use interpreter::interpret_instruction;
+use interpreter::{interpret_instruction, interpret_chip8x_instruction};
 use instruction::Instruction;
-use fontset::FONTSET_START;
+use fontset::{GLYPH_SIZE, LARGE_GLYPH_SIZE};
+use io::{PLANES, AUDIO_PATTERN_LEN, DisplayMode, Sprite};
+use register::RegisterId;
+use sound_log::SoundEvent;
 use utils;
 
+/// The opcode that clears the screen in hi-res mode
+const HIRES_CLEAR_SCREEN: u16 = 0x0230;
+
 impl Chip8 {
     /// Runs a CPU cycle, calling the input function to update the internal key state
     /// Requires a type that implements `Chip8IO` to do I/O (see `Chip8IO` for more)
-    pub fn cycle<T: ::Chip8IO>(&mut self, mut io: &mut T) -> Result<()> {
-        let memory = &mut self.memory;
-        let stack = &mut self.stack;
-        // Registers
-        let registers = &mut self.registers;
-        let pc = registers.program_counter;
+    pub fn cycle<T: ::Chip8IO>(&mut self, io: &mut T) -> Result<()> {
+        let result = self.run_instruction(io);
+
+        if let Err(ref e) = result {
+            if self.log >= Log::Errors {
+                error!(target: LOG_TARGET, "{}", e);
+            }
+        }
+
+        result
+    }
+
+    /// Runs the instruction at the program counter (see `cycle`)
+    fn run_instruction<T: ::Chip8IO>(&mut self, io: &mut T) -> Result<()> {
+        let pc = self.registers.program_counter;
         // Used for indexing
         let pc_index = pc as usize;
 
         // If the program counter is out of bounds, end the program
-        if memory.get(pc_index + 1).is_none() {
+        if self.memory.get(pc_index + 1).is_none() {
             self.program_ended = true;
             return Ok(());
         }
 
         // Load the opcode from memory
-        let opcode = (memory[pc_index] as u16) << 8 | memory[pc_index + 1] as u16;
+        let bytes = [self.memory[pc_index], self.memory[pc_index + 1]];
+        let opcode = (bytes[0] as u16) << 8 | bytes[1] as u16;
+        self.coverage.mark(pc);
         // Try to convert the opcode to an instruction
-        let instruction = interpret_instruction(opcode)
-            .chain_err(|| format!("Invalid opcode at address {}", pc))?;
-
-        if self.log.is_enabled() {
-            info!("OPCODE: 0x{:04X}", opcode);
+        // Hi-res programs clear the screen with an opcode of their own
+        let decoded = if opcode == HIRES_CLEAR_SCREEN && self.io.mode() == DisplayMode::HiRes {
+            Ok(Instruction::ClearScreen)
+        } else if self.chip8x {
+            interpret_chip8x_instruction(opcode)
+        } else {
+            interpret_instruction(opcode)
+        };
+        let instruction = match decoded {
+            Ok(instruction) => instruction,
+            Err(_) => {
+                let location = Location {
+                    pc,
+                    opcode,
+                    instruction: "Invalid",
+                };
+                let error = ErrorKind::InvalidOpcode(location, self.invalid_opcode_context(pc))
+                    .into();
+
+                return match self.permissive {
+                    Some(ref mut on_warning) => {
+                        if self.log >= Log::Errors {
+                            warn!(target: LOG_TARGET, "Skipped: {}", error);
+                        }
+                        on_warning(&error);
+                        self.skip_instruction(pc, opcode);
+                        Ok(())
+                    }
+                    None => Err(error),
+                };
+            }
+        };
+        // Where errors caused by the instruction happened
+        let location = Location {
+            pc,
+            opcode,
+            instruction: instruction.name(),
+        };
+
+        if let Some(ref mut profile) = self.profile {
+            profile.record(pc, instruction.name());
         }
 
+        // The state needed to trace the step, if it is being traced
+        let trace = if self.tracing(pc) {
+            Some((instruction.to_string(), self.trace_snapshot()))
+        } else {
+            None
+        };
+
+        let memory = &mut self.memory;
+        let memory_size = memory.len();
+        let stack = &mut self.stack;
+        // Registers
+        let registers = &mut self.registers;
+
         // Not all instructions require incrementing the program counter
         // This is set to false by those instructions to prevent the increment
         let mut increment_pc = true;
 
-        self.io.set_keys(io.get_keys());
+        // The keys from before they were read, used to detect new key presses
+        let previous_keys = self.io.keys();
+        let poll = match self.input_polling {
+            InputPolling::EveryInstruction => true,
+            InputPolling::PerFrame => self.poll_keys,
+            InputPolling::KeyInstructions => instruction.reads_keys(),
+        };
+        if poll {
+            self.io.set_keys(io.get_keys());
+            if self.chip8x {
+                self.io.set_second_keys(io.get_second_keys());
+            }
+            self.poll_keys = false;
+        }
+        self.waiting_for_key = false;
 
         match instruction {
             Instruction::Return => {
-                if let Some(addr) = stack.pop() {
-                    registers.program_counter = addr;
+                match stack.pop() {
+                    Some(addr) => registers.program_counter = addr,
+                    None => bail!(ErrorKind::StackUnderflow(location)),
                 }
             }
             Instruction::Goto(addr) => {
-                if addr as usize >= ::MEMORY {
-                    bail!(ErrorKind::InvalidAddress(addr as usize, "Goto"));
+                if addr as usize >= memory.len() {
+                    bail!(ErrorKind::InvalidAddress(addr as usize, location));
                 }
                 registers.program_counter = addr;
                 increment_pc = false;
             }
             Instruction::Call(addr) => {
-                if addr as usize >= ::MEMORY {
-                    bail!(ErrorKind::InvalidAddress(addr as usize, "Call"));
+                if addr as usize >= memory.len() {
+                    bail!(ErrorKind::InvalidAddress(addr as usize, location));
+                }
+
+                if stack.len() >= self.stack_depth {
+                    bail!(ErrorKind::StackOverflow(self.stack_depth, location));
                 }
 
                 registers.program_counter = addr;
@@ -66,13 +152,18 @@ impl Chip8 {
                 increment_pc = false;
             }
             Instruction::OffsetGoto(addr) => {
-                let v0 = registers.get_u16(0);
-
-                if (v0 + addr) as usize >= ::MEMORY {
-                    bail!(ErrorKind::InvalidAddress(addr as usize, "OffsetGoto"));
+                let offset_register = if self.quirks.jump {
+                    RegisterId::from_nibble((addr >> 8) as u8)
+                } else {
+                    RegisterId::V0
+                };
+                let target = addr as usize + registers.get(offset_register) as usize;
+
+                if target >= memory.len() {
+                    bail!(ErrorKind::InvalidAddress(target, location));
                 }
 
-                registers.program_counter = addr + v0;
+                registers.program_counter = target as u16;
                 increment_pc = false;
             }
             Instruction::SetConst(x, n) => registers.set(x, n),
@@ -96,21 +187,29 @@ impl Chip8 {
                 let val = registers.get(x) ^ registers.get(y);
                 registers.set(x, val);
             }
-            Instruction::Shr(x_id) => {
-                let x = registers.get(x_id);
+            Instruction::Shr(x_id, y_id) => {
+                let x = if self.quirks.shift {
+                    registers.get(x_id)
+                } else {
+                    registers.get(y_id)
+                };
                 let val = x >> 1;
                 registers.set(x_id, val);
 
-                // Set VF to the least significant bit of VX
-                registers.set(0xF, x & 1);
+                // Set VF to the least significant bit of the shifted value
+                registers.set(RegisterId::VF, x & 1);
             }
-            Instruction::Shl(x_id) => {
-                let x = registers.get(x_id);
+            Instruction::Shl(x_id, y_id) => {
+                let x = if self.quirks.shift {
+                    registers.get(x_id)
+                } else {
+                    registers.get(y_id)
+                };
                 let val = x << 1;
                 registers.set(x_id, val);
 
-                // Set VF to the most significant bit of VX
-                registers.set(0xF, (x & 0x80) >> 7);
+                // Set VF to the most significant bit of the shifted value
+                registers.set(RegisterId::VF, (x & 0x80) >> 7);
             }
             Instruction::Add(x_id, y) => {
                 let x = registers.get(x_id);
@@ -118,7 +217,7 @@ impl Chip8 {
                 registers.set(x_id, x.wrapping_add(y));
 
                 // Set VF to 1 if a carry happened, 0 otherwise
-                registers.set(0xF, x.checked_add(y).is_none() as u8);
+                registers.set(RegisterId::VF, x.checked_add(y).is_none() as u8);
             }
             Instruction::Sub(x_id, y) => {
                 let x = registers.get(x_id);
@@ -126,7 +225,7 @@ impl Chip8 {
                 registers.set(x_id, x.wrapping_sub(y));
 
                 // Set VF to 1 if a borrow happened, 0 otherwise
-                registers.set(0xF, x.checked_sub(y).is_none() as u8);
+                registers.set(RegisterId::VF, x.checked_sub(y).is_none() as u8);
             }
             Instruction::InverseSub(x_id, y) => {
                 let x = registers.get(x_id);
@@ -134,87 +233,171 @@ impl Chip8 {
                 registers.set(x_id, y.wrapping_sub(x));
 
                 // Set VF to 1 if a borrow happened, 0 otherwise
-                registers.set(0xF, y.checked_sub(x).is_none() as u8);
+                registers.set(RegisterId::VF, y.checked_sub(x).is_none() as u8);
             }
             Instruction::Rand(x, n) => {
-                registers.set(x, rand::random::<u8>() & n);
+                registers.set(x, self.rng.gen::<u8>() & n);
             }
             Instruction::BCD(a) => {
                 let a = registers.get(a);
                 let i = registers.index as usize;
 
-                if i + 2 >= memory.len() {
-                    bail!(ErrorKind::InvalidAddress(i, "BCD"));
+                if i + 2 >= memory.len() && !self.quirks.wrap {
+                    bail!(ErrorKind::InvalidAddress(i, location));
                 }
 
-                memory[i..i + 3].copy_from_slice(&utils::bcd(a));
+                check_write(&mut self.protection,
+                            (i..i + 3).map(|a| a % memory_size),
+                            self.program_start,
+                            self.log,
+                            location)?;
+
+                for (offset, &digit) in utils::bcd(a).iter().enumerate() {
+                    let address = (i + offset) % memory_size;
+                    memory[address] = digit;
+                    if let Some(ref mut writes) = self.writes {
+                        writes.record(address as u16, pc);
+                    }
+                }
             }
             Instruction::SkipEqConst(x, n) => {
                 if registers.get(x) == n {
-                    registers.program_counter += 2;
+                    registers.advance();
                 }
             }
             Instruction::SkipNeqConst(x, n) => {
                 if registers.get(x) != n {
-                    registers.program_counter += 2;
+                    registers.advance();
                 }
             }
             Instruction::SkipEq(x, y) => {
                 if registers.get(x) == registers.get(y) {
-                    registers.program_counter += 2;
+                    registers.advance();
                 }
             }
             Instruction::SkipNeq(x, y) => {
                 if registers.get(x) != registers.get(y) {
-                    registers.program_counter += 2;
+                    registers.advance();
                 }
             }
             Instruction::RegDump(x) => {
                 let i = registers.index as usize;
-                let x = x as usize;
+                let x = x.index() as usize;
 
-                if i + x >= memory.len() {
-                    bail!(ErrorKind::InvalidAddress(i, "RegDump"));
+                if i + x >= memory.len() && !self.quirks.wrap {
+                    bail!(ErrorKind::InvalidAddress(i, location));
                 }
 
-                memory[i..i + x + 1].copy_from_slice(&registers.get_registers()[..x + 1]);
+                check_write(&mut self.protection,
+                            (i..i + x + 1).map(|a| a % memory_size),
+                            self.program_start,
+                            self.log,
+                            location)?;
+
+                for (offset, &value) in registers.get_registers()[..x + 1].iter().enumerate() {
+                    let address = (i + offset) % memory_size;
+                    memory[address] = value;
+                    if let Some(ref mut writes) = self.writes {
+                        writes.record(address as u16, pc);
+                    }
+                }
+
+                if !self.quirks.memory {
+                    registers.index = registers.index.wrapping_add(x as u16 + 1);
+                }
             }
             Instruction::RegLoad(x) => {
                 let i = registers.index as usize;
-                let x = x as usize;
+                let x = x.index() as usize;
+
+                if i + x >= memory.len() && !self.quirks.wrap {
+                    bail!(ErrorKind::InvalidAddress(i, location));
+                }
 
-                if i + x >= memory.len() {
-                    bail!(ErrorKind::InvalidAddress(i, "RegLoad"));
+                let values = &mut registers.get_mut_registers()[..x + 1];
+                for (offset, value) in values.iter_mut().enumerate() {
+                    *value = memory[(i + offset) % memory_size];
                 }
 
-                registers.get_mut_registers()[..x + 1].copy_from_slice(&memory[i..i + x + 1]);
+                if !self.quirks.memory {
+                    registers.index = registers.index.wrapping_add(x as u16 + 1);
+                }
             }
             Instruction::SetIndex(addr) => registers.index = addr,
-            Instruction::AddIndex(addr) => registers.index += registers.get_u16(addr),
+            Instruction::AddIndex(x) => {
+                let i = registers.index;
+                let vx = registers.get_u16(x);
+                // I can't go past 0xFFFF, and past 0xFFF it can't be used to access memory
+                let index = match i.checked_add(vx) {
+                    Some(index) => index,
+                    None => bail!(ErrorKind::InvalidAddress(i as usize + vx as usize, location)),
+                };
+
+                if self.quirks.index_overflow {
+                    registers.set(RegisterId::VF, (index > 0xFFF) as u8);
+                }
+                registers.index = index;
+            }
             Instruction::SetIndexChar(x) => {
-                let x = registers.get_u16(x);
-                // Only values 0 through 15 are valid
+                let mut x = registers.get_u16(x);
+                // Only values 0 through 15 are valid, so others are either an error or masked to
+                // their lowest nibble
                 if x > 15 {
-                    bail!(ErrorKind::UnknownCharacter(x as u8));
+                    if self.quirks.font {
+                        bail!(ErrorKind::UnknownCharacter(x as u8, location));
+                    }
+                    x &= 0xF;
                 }
-                registers.index = FONTSET_START as u16 + 5 * x;
+                registers.index = (self.font.start() + GLYPH_SIZE * x as usize) as u16;
+            }
+            Instruction::SetIndexLargeChar(x) => {
+                // The large font may only have the digits 0 through 9
+                let x = registers.get(x);
+                if x as usize >= self.large_font.characters(LARGE_GLYPH_SIZE) {
+                    bail!(ErrorKind::UnknownCharacter(x, location));
+                }
+                registers.index = (self.large_font.start() + LARGE_GLYPH_SIZE * x as usize) as u16;
             }
             Instruction::GetDelay(x) => registers.set(x, self.delay_timer),
             Instruction::SetDelay(x) => self.delay_timer = registers.get(x),
             Instruction::WaitKey(x) => {
-                let key = self.io.wait_key(io);
-                registers.set(x, key);
+                // The instruction is run again until the keys are read with a key pressed that
+                // wasn't pressed when they were read before (see `Config::input_polling`)
+                let keys = self.io.keys();
+                let pressed = (0..16).find(|&i| keys.get(i) && !previous_keys.get(i));
+
+                let key = if self.quirks.key_press {
+                    pressed
+                } else {
+                    // Remember the first key pressed, and finish once it is released
+                    if self.held_key.is_none() {
+                        self.held_key = pressed;
+                    }
+
+                    match self.held_key {
+                        Some(key) if !keys.get(key) => self.held_key.take(),
+                        _ => None,
+                    }
+                };
+
+                match key {
+                    Some(key) => registers.set(x, key),
+                    None => {
+                        self.waiting_for_key = true;
+                        increment_pc = false;
+                    }
+                }
             }
             Instruction::SkipKey(x) => {
                 let x = registers.get(x);
 
                 // Only values 0 to 15 are valid
                 if x > 15 {
-                    bail!(ErrorKind::UnknownKey(x, "SkipKey"));
+                    bail!(ErrorKind::UnknownKey(x, location));
                 }
 
                 if self.io.is_key_pressed(x) {
-                    registers.program_counter += 2;
+                    registers.advance();
                 }
             }
             Instruction::SkipNotKey(x) => {
@@ -222,70 +405,354 @@ impl Chip8 {
 
                 // Only values 0 to 15 are valid
                 if x > 15 {
-                    bail!(ErrorKind::UnknownKey(x, "SkipNotKey"));
+                    bail!(ErrorKind::UnknownKey(x, location));
                 }
 
                 if !self.io.is_key_pressed(x) {
-                    registers.program_counter += 2;
+                    registers.advance();
+                }
+            }
+            Instruction::LoadAudio => {
+                let i = registers.index as usize;
+
+                if i + AUDIO_PATTERN_LEN > memory.len() {
+                    bail!(ErrorKind::InvalidAddress(i, location));
+                }
+
+                self.audio_pattern.copy_from_slice(&memory[i..i + AUDIO_PATTERN_LEN]);
+                self.audio_changed = true;
+            }
+            Instruction::SetPitch(x) => {
+                self.pitch = registers.get(x);
+                self.audio_changed = true;
+            }
+            Instruction::SetSound(x) => {
+                self.sound_timer = registers.get(x);
+
+                if let Some(ref mut log) = self.sound_log {
+                    log.push(SoundEvent {
+                        cycle: self.cycles,
+                        frame: self.frames,
+                        value: self.sound_timer,
+                    });
                 }
             }
-            Instruction::SetSound(x) => self.sound_timer = registers.get(x),
+            Instruction::Draw(x, y, _) if self.io.mega().is_some() => {
+                let x = registers.get(x) as usize;
+                let y = registers.get(y) as usize;
+                let mega = self.io.mega_mut().unwrap();
+                let (width, height) = mega.sprite_size();
+                let i = registers.index as usize;
+
+                if i + width * height > memory.len() {
+                    bail!(ErrorKind::InvalidAddress(i, location));
+                }
+
+                let collision = mega.draw_sprite(x, y, &memory[i..i + width * height]);
+                registers.set(RegisterId::VF, collision as u8);
+                self.metrics.record_draw();
+            }
             Instruction::Draw(x, y, height) => {
                 let x = registers.get(x);
                 let y = registers.get(y);
 
                 let index = registers.index;
-                // Set VF to 0, will be set to 1 later if a pixel is unset (used for collision
+                let (screen_width, screen_height) = self.io.size();
+                // Set VF to 0, will be set to 1 later if a pixel is turned off (used for collision
                 // detection)
-                registers.set(0xF, 0);
+                registers.set(RegisterId::VF, 0);
 
-                for line in 0..height {
-                    let i = (index + line as u16) as usize;
+                // The sprite is drawn to each selected plane, with the data for each plane
+                // stored one after another starting at I
+                let mut offset = 0;
 
-                    if i >= memory.len() {
-                        bail!(ErrorKind::InvalidAddress(i, "Draw"));
+                for plane in (0..PLANES).map(|p| 1 << p) {
+                    if self.io.selected_planes() & plane == 0 {
+                        continue;
                     }
 
-                    // Iterator through each bit in the line
-                    for bit in 0..8 {
-                        // Each bit is a pixel
-                        let mem_pixel = memory[i] & (128 >> bit);
+                    for line in 0..height {
+                        let mut i = index as usize + offset + line as usize;
 
-                        let pixel_x = (x + bit) as usize;
-                        let pixel_y = (y + line) as usize;
+                        if i >= memory.len() {
+                            if !self.quirks.wrap {
+                                bail!(ErrorKind::InvalidAddress(i, location));
+                            }
+                            i %= memory_size;
+                        }
 
-                        let pixel_index = pixel_x + pixel_y * ::SCREEN_WIDTH;
+                        let pixel_x = x as usize;
+                        let pixel_y = y as usize + line as usize;
 
-                        if pixel_x >= ::SCREEN_WIDTH || pixel_y >= ::SCREEN_HEIGHT {
-                            bail!(ErrorKind::PixelOutOfBounds(pixel_x, pixel_y));
+                        // The whole row of the sprite must be on the screen
+                        if pixel_y >= screen_height {
+                            bail!(ErrorKind::PixelOutOfBounds(pixel_x, pixel_y, location));
+                        }
+                        if pixel_x + 8 > screen_width {
+                            bail!(ErrorKind::PixelOutOfBounds(pixel_x.max(screen_width),
+                                                              pixel_y,
+                                                              location));
                         }
 
-                        let screen_pixel = self.io.get_mut_pixel(pixel_index);
-
-                        // If the pixel is on, and the new value is off, set VF
-                        if *screen_pixel && mem_pixel == 0 {
-                            registers.set(0xF, 1);
+                        // Pixels that are on where the sprite is drawn are turned off, which is a
+                        // collision
+                        if self.io.draw_sprite_row(pixel_x, pixel_y, plane, memory[i]) {
+                            registers.set(RegisterId::VF, 1);
                         }
+                    }
+
+                    offset += height as usize;
+                }
 
-                        *screen_pixel = mem_pixel > 0;
+                self.sprites.push(Sprite {
+                    x,
+                    y,
+                    height,
+                    address: index,
+                    planes: self.io.selected_planes(),
+                    collision: registers.get(RegisterId::VF) == 1,
+                });
+                self.io.set_draw_flag();
+                self.metrics.record_draw();
+            }
+            Instruction::ClearScreen => {
+                match self.io.mega_mut() {
+                    // The frame the program drew is shown before the screen is cleared for the
+                    // next one
+                    Some(mega) => {
+                        io.draw_mega(mega.pixels(), mega.palette());
+                        mega.clear();
                     }
+                    None => self.io.clear_screen(),
+                }
+            }
+            Instruction::SelectPlanes(n) => self.io.select_planes(n),
+            Instruction::MegaOn => self.io.enable_mega(self.palette),
+            Instruction::MegaOff => self.io.disable_mega(),
+            Instruction::SetIndexLong(n) => {
+                let low = match memory.get(pc_index + 2..pc_index + 4) {
+                    Some(bytes) => (bytes[0] as usize) << 8 | bytes[1] as usize,
+                    None => bail!(ErrorKind::InvalidAddress(pc_index + 2, location)),
+                };
+                let address = (n as usize) << 16 | low;
+
+                if address >= memory.len() {
+                    bail!(ErrorKind::InvalidAddress(address, location));
                 }
 
+                registers.index = address as u16;
+                registers.advance();
+            }
+            Instruction::LoadPalette(n) => {
+                let i = registers.index as usize;
+                let end = i + n as usize * 4;
+
+                if end > memory.len() {
+                    bail!(ErrorKind::InvalidAddress(i, location));
+                }
+
+                if let Some(mega) = self.io.mega_mut() {
+                    mega.load_palette(&memory[i..end]);
+                }
+            }
+            Instruction::SpriteWidth(n) => {
+                if let Some(mega) = self.io.mega_mut() {
+                    mega.set_sprite_width(n);
+                }
+            }
+            Instruction::SpriteHeight(n) => {
+                if let Some(mega) = self.io.mega_mut() {
+                    mega.set_sprite_height(n);
+                }
+            }
+            Instruction::SetCollisionColor(n) => {
+                if let Some(mega) = self.io.mega_mut() {
+                    mega.set_collision_color(n);
+                }
+            }
+            Instruction::ScrollUp(n) => {
+                match self.io.mega_mut() {
+                    Some(mega) => mega.scroll_up(n as usize),
+                    None => self.io.scroll_up(n as usize),
+                }
+            }
+            Instruction::StepBackground => {
+                if let Some(board) = self.io.color_board_mut() {
+                    board.step_background();
+                }
+                self.io.set_draw_flag();
+            }
+            Instruction::AddNibbles(x, y) => {
+                let val = ((registers.get(x) & 0x77) + (registers.get(y) & 0x77)) & 0x77;
+                registers.set(x, val);
+            }
+            Instruction::SetColorZones(x, y) => {
+                let columns = registers.get(x);
+                let rows = registers.get(RegisterId::from_nibble(x.index() + 1));
+                let color = registers.get(y);
+
+                if let Some(board) = self.io.color_board_mut() {
+                    board.set_zones(((columns >> 4) as usize, (columns & 0xF) as usize),
+                                    ((rows >> 4) as usize, (rows & 0xF) as usize),
+                                    color);
+                }
                 self.io.set_draw_flag();
             }
-            Instruction::ClearScreen => self.io.clear_screen(),
+            Instruction::SetColorRows(x, y, n) => {
+                let column = registers.get(x) as usize;
+                let row = registers.get(RegisterId::from_nibble(x.index() + 1)) as usize;
+                let color = registers.get(y);
+
+                if let Some(board) = self.io.color_board_mut() {
+                    board.set_rows(column, row, n as usize, color);
+                }
+                self.io.set_draw_flag();
+            }
+            Instruction::SkipKey2(x) => {
+                let x = registers.get(x);
+
+                // Only values 0 to 15 are valid
+                if x > 15 {
+                    bail!(ErrorKind::UnknownKey(x, location));
+                }
+
+                if self.io.is_second_key_pressed(x) {
+                    registers.advance();
+                }
+            }
+            Instruction::SkipNotKey2(x) => {
+                let x = registers.get(x);
+
+                // Only values 0 to 15 are valid
+                if x > 15 {
+                    bail!(ErrorKind::UnknownKey(x, location));
+                }
+
+                if !self.io.is_second_key_pressed(x) {
+                    registers.advance();
+                }
+            }
+            Instruction::SetAlpha(_) |
+            Instruction::PlaySample(_) |
+            Instruction::StopSample |
+            Instruction::SetBlendMode(_) => {}
+        }
+
+        // Draw the screen (in Mega-Chip mode, it is drawn when the program clears it)
+        if self.io.draw_flag() && self.io.mega().is_none() {
+            if !self.sprites.is_empty() {
+                io.draw_sprites(&self.sprites);
+                self.sprites.clear();
+            }
+
+            let (planes, palette) = self.io.screen(&self.palette);
+            io.draw_planes(&planes, &palette);
         }
 
-        // Draw the screen
-        if self.io.draw_flag() {
-            io.draw(self.io.pixels());
+        if self.audio_changed {
+            io.set_audio_pattern(&self.audio_pattern, self.pitch);
+            self.audio_changed = false;
         }
 
         // Increment the program counter
         if increment_pc {
-            registers.program_counter += 2;
+            registers.advance();
         }
 
+        let jumped = registers.program_counter != pc.wrapping_add(2);
+        self.previous = Some((pc, jumped));
+
+        if let Some((mnemonic, before)) = trace {
+            self.push_trace(pc, opcode, mnemonic, &before);
+        }
+
+        self.cycles += 1;
+        self.metrics.record_cycle(opcode);
+        self.check_watches(pc);
+
         Ok(())
     }
+
+    /// Moves past the instruction at `pc` without running it, as if it did nothing
+    fn skip_instruction(&mut self, pc: u16, opcode: u16) {
+        self.registers.program_counter = pc.wrapping_add(2);
+        self.previous = Some((pc, false));
+        self.cycles += 1;
+        self.metrics.record_cycle(opcode);
+        self.check_watches(pc);
+    }
+
+    /// Describes the call stack, one address per line along with its nearest symbol: first the
+    /// instruction about to run, then the calls that led to it, most recent first
+    /// After `cycle` returns an error, this shows how the program got to the faulting instruction.
+    pub fn describe_call_stack(&self) -> String {
+        let describe = |address: u16| match self.symbols.describe(address) {
+            Some(symbol) => format!("0x{:03X} ({})", address, symbol),
+            None => format!("0x{:03X}", address),
+        };
+
+        let mut lines = vec![format!("at {}", describe(self.registers.program_counter))];
+        for &call in self.stack.iter().rev() {
+            lines.push(format!("called from {}", describe(call)));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Describes how the program reached an invalid opcode at the address, and the nearest symbol
+    /// to it, as text that follows the error message
+    fn invalid_opcode_context(&self, pc: u16) -> String {
+        let mut context = String::new();
+
+        if let Some(symbol) = self.symbols.describe(pc) {
+            context.push_str(&format!(" ({})", symbol));
+        }
+
+        match self.previous {
+            Some((previous, true)) => {
+                context.push_str(&format!(", reached by a jump from 0x{:03X}", previous))
+            }
+            Some((previous, false)) => {
+                context.push_str(&format!(", reached by running past the instruction at 0x{:03X} \
+                                           (the program may have run into data)",
+                                          previous))
+            }
+            None => {}
+        }
+
+        context
+    }
+}
+
+/// Checks a write to the addresses with the write protection, returning an error if one of them is
+/// below the program start and such writes are errors, or passing the error to the hook if they are
+/// warnings
+fn check_write<I>(protection: &mut WriteProtection,
+                  addresses: I,
+                  program_start: usize,
+                  log: Log,
+                  location: Location)
+                  -> Result<()>
+    where I: IntoIterator<Item = usize>
+{
+    if let WriteProtection::Off = *protection {
+        return Ok(());
+    }
+
+    let address = match addresses.into_iter().find(|&address| address < program_start) {
+        Some(address) => address,
+        None => return Ok(()),
+    };
+    let error = ErrorKind::ProtectedWrite(address, location).into();
+
+    match *protection {
+        WriteProtection::Warn(ref mut on_warning) => {
+            if log >= Log::Errors {
+                warn!(target: LOG_TARGET, "Allowed: {}", error);
+            }
+            on_warning(&error);
+            Ok(())
+        }
+        _ => Err(error),
+    }
 }
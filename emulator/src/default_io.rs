@@ -1,102 +1,707 @@
-//! A basic implementation of `Chip8IO` using `piston` for graphics and input, and `ears` for sound
+//! A basic implementation of `Chip8IO` using `piston` for graphics and input, and `rodio` for sound
 //! Press `Escape` to exit the emulator
+//!
+//! The beep is generated in memory (see `Beep`), so no sound file is needed.
 
 // FIXME: Cannot quit the emulator
 // NOTE: Maybe not, do further testing (might just be slow in debug mode)
 
 extern crate piston_window;
-extern crate ears;
+extern crate rodio;
 
-use std::path::Path;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::mem;
+use std::path::PathBuf;
+use std::time::Instant;
+use std::time::Duration;
 
 use self::piston_window::*;
-use self::ears::{Sound, AudioController};
+use self::rodio::{OutputStream, OutputStreamHandle, Sink, Source};
 use super::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use bindings::{Action, Bindings};
+use blend::FrameBlender;
+use keymap::KeyMap;
+use io::{Color, Palette};
+use pause::PauseHandle;
+use recording::Recorder;
+use screenshot;
 
-/// The size of each pixel (in pixels)
-const PIXEL_SIZE: usize = 10;
+/// The default size of each pixel (in pixels)
+const DEFAULT_SCALE: u32 = 10;
+/// The size of each pixel in screenshots (in pixels)
+const SCREENSHOT_SCALE: usize = 4;
+/// The size of each pixel in recordings (in pixels)
+const RECORDING_SCALE: usize = 2;
+/// The sample rate of the beep
+const SAMPLE_RATE: u32 = 44100;
+/// The opacity of the scanlines between rows of pixels in CRT mode
+const SCANLINE_ALPHA: f32 = 0.35;
+/// The opacity of the lines between columns of pixels in CRT mode
+const GRID_ALPHA: f32 = 0.15;
+/// The number of bands the vignette of CRT mode is made of, and the opacity of each one
+const VIGNETTE_BANDS: u32 = 8;
+const VIGNETTE_ALPHA: f32 = 0.05;
+
+/// The shape of the wave of the beep
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    /// A square wave, which sounds like the beep of old computers
+    Square,
+    /// A sine wave, which is a softer tone
+    Sine,
+}
+
+/// The tone played while the sound timer is running
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Beep {
+    /// The shape of the wave
+    pub waveform: Waveform,
+    /// The frequency of the tone, in Hz
+    pub frequency: f32,
+    /// The volume of the tone, from 0 to 1
+    pub volume: f32,
+}
+
+impl Default for Beep {
+    /// Returns a square wave at 440 Hz
+    fn default() -> Beep {
+        Beep {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+/// An endless source of the samples of a beep
+struct Tone {
+    beep: Beep,
+    /// The position in the current period of the wave, from 0 to 1
+    phase: f32,
+}
+
+impl Iterator for Tone {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let value = match self.beep.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+        };
+
+        self.phase = (self.phase + self.beep.frequency / SAMPLE_RATE as f32) % 1.0;
+        Some(value * self.beep.volume)
+    }
+}
+
+impl Source for Tone {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Plays the beep from an audio output stream
+struct Sound {
+    /// The stream must be kept alive for the sound to play
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    /// The sink playing the beep, which is paused while no sound is played
+    sink: Sink,
+}
+
+impl Sound {
+    /// Opens the default audio output device, returning `None` if it can't be opened
+    fn new(beep: Beep) -> Option<Sound> {
+        let (stream, handle) = OutputStream::try_default().ok()?;
+        let sink = beep_sink(&handle, beep)?;
+
+        Some(Sound {
+            _stream: stream,
+            handle: handle,
+            sink: sink,
+        })
+    }
+}
+
+/// Returns a paused sink that plays the beep when resumed
+fn beep_sink(handle: &OutputStreamHandle, beep: Beep) -> Option<Sink> {
+    let sink = Sink::try_new(handle).ok()?;
+    sink.pause();
+    sink.append(Tone {
+        beep: beep,
+        phase: 0.0,
+    });
+
+    Some(sink)
+}
+
+/// Options for creating the window
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use chip8::default_io::{Io, Options};
+///
+/// let io = Io::with_options(Options::new().scale(6).fullscreen(true));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Options {
+    pub(crate) scale: u32,
+    pub(crate) fullscreen: bool,
+    pub(crate) phosphor: u32,
+    pub(crate) crt: bool,
+    pub(crate) mute: bool,
+}
+
+impl Options {
+    /// Returns the default options, which create a window that is not fullscreen, where each pixel
+    /// is 10 pixels in size, pixels are turned off instantly, CRT mode is disabled, and sound is
+    /// played
+    pub fn new() -> Options {
+        Options {
+            scale: DEFAULT_SCALE,
+            fullscreen: false,
+            phosphor: 0,
+            crt: false,
+            mute: false,
+        }
+    }
+
+    /// Sets the initial size of each pixel (in pixels), which sets the size of the window
+    /// The pixels are resized along with the window. A scale of 0 is treated as 1.
+    pub fn scale(mut self, scale: u32) -> Options {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// Sets whether the window starts in fullscreen mode (it can be toggled with the `fullscreen`
+    /// action)
+    pub fn fullscreen(mut self, fullscreen: bool) -> Options {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    /// Sets the number of frames pixels fade out over after they are turned off, which reduces
+    /// flicker (see `blend::FrameBlender`)
+    /// With zero frames, pixels are turned off instantly.
+    pub fn phosphor(mut self, frames: u32) -> Options {
+        self.phosphor = frames;
+        self
+    }
+
+    /// Sets whether the screen starts in CRT mode (it can be toggled with the `crt` action)
+    /// In CRT mode, the lines between pixels are darkened like the scanlines of an old monitor,
+    /// and the edges of the screen are darkened slightly.
+    pub fn crt(mut self, crt: bool) -> Options {
+        self.crt = crt;
+        self
+    }
+
+    /// Sets whether sound is disabled, in which case no audio device is opened
+    pub fn mute(mut self, mute: bool) -> Options {
+        self.mute = mute;
+        self
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options::new()
+    }
+}
 
 /// Stores state used for doing I/O
 #[allow(missing_debug_implementations)]
 pub struct Io {
     window: PistonWindow,
-    keys: ::Keys,
+    /// Whether the window is in fullscreen mode
+    fullscreen: bool,
+    /// The size of the window when it is not in fullscreen mode, which is kept when it is resized
+    window_size: [u32; 2],
+    keys: ::Keypad,
     should_close: bool,
-    sound: Sound,
+    /// The sound output, or `None` if no audio device could be opened
+    sound: Option<Sound>,
+    bindings: Bindings,
+    keymap: KeyMap,
+    /// Actions triggered since the last call to `take_actions`
+    actions: Vec<Action>,
+    /// Actions whose keys are held down
+    held_actions: Vec<Action>,
+    /// The title of the program being run
+    title: Option<String>,
+    /// The status line shown in the title of the window
+    status: String,
+    /// The handle used to pause the emulator when the window loses focus, if that is enabled
+    focus_pause: Option<PauseHandle>,
+    /// Whether the emulator was paused because the window lost focus, so it should be resumed
+    /// when the window gains focus
+    paused_by_focus: bool,
+    /// The planes and palette of the last frame drawn, used for screenshots and recordings
+    frame: Option<(Vec<u8>, Palette)>,
+    /// The blender used to fade pixels out, if enabled
+    blender: Option<FrameBlender>,
+    /// Whether the screen is drawn in CRT mode (see `Options::crt`)
+    crt: bool,
+    /// Whether the screen must be drawn again because the way it is drawn changed, even if the
+    /// program didn't draw anything
+    redraw: bool,
+    /// The directory screenshots and recordings are saved to
+    screenshot_dir: PathBuf,
+    /// The recording being made and when it was started, if one is being made
+    recording: Option<(Recorder, Instant)>,
 }
 
 impl Io {
-    /// Initializes the state, creating the window and sound data
-    /// Requires a path to a sound file, used for playing sounds
-    /// The sound file must be in a format recognized by `ears`, for example wav or ogg
-    pub fn new<P: AsRef<Path>>(sound_path: P) -> Io {
-        let window: PistonWindow = WindowSettings::new("Chip-8 Emulator",
-                                                       [(SCREEN_WIDTH * PIXEL_SIZE) as u32,
-                                                        (SCREEN_HEIGHT * PIXEL_SIZE) as u32])
-            .build()
-            .unwrap();
-
-        let path = sound_path.as_ref().to_str().unwrap_or_else(|| {
-            panic!("Path to sound file was invalid");
-        });
+    /// Initializes the state, creating the window and opening the audio device
+    /// The emulator runs without sound if no audio device can be opened. The beep is the default
+    /// one, which can be changed with `set_beep`.
+    pub fn new() -> Io {
+        Io::with_options(Options::default())
+    }
 
-        let sound = Sound::new(path).unwrap_or_else(|| {
-            panic!("Failed to create sound from file: {}", path);
-        });
+    /// Initializes the state like `new`, creating the window with the options
+    pub fn with_options(options: Options) -> Io {
+        let window_size = [SCREEN_WIDTH as u32 * options.scale,
+                           SCREEN_HEIGHT as u32 * options.scale];
 
         Io {
-            window: window,
-            keys: [false; 16],
+            window: create_window(window_size, options.fullscreen),
+            fullscreen: options.fullscreen,
+            window_size: window_size,
+            keys: ::Keypad::new(),
             should_close: false,
-            sound: sound,
+            sound: if options.mute {
+                None
+            } else {
+                Sound::new(Beep::default())
+            },
+            bindings: Bindings::default(),
+            keymap: KeyMap::default(),
+            actions: Vec::new(),
+            held_actions: Vec::new(),
+            title: None,
+            status: String::new(),
+            focus_pause: None,
+            paused_by_focus: false,
+            frame: None,
+            blender: if options.phosphor > 0 {
+                Some(FrameBlender::new(options.phosphor))
+            } else {
+                None
+            },
+            crt: options.crt,
+            redraw: false,
+            screenshot_dir: PathBuf::from("."),
+            recording: None,
         }
     }
 
+    /// Sets the tone played while the sound timer is running
+    pub fn set_beep(&mut self, beep: Beep) {
+        if let Some(ref mut sound) = self.sound {
+            let playing = !sound.sink.is_paused();
+
+            if let Some(sink) = beep_sink(&sound.handle, beep) {
+                if playing {
+                    sink.play();
+                }
+                sound.sink = sink;
+            }
+        }
+    }
+
+    /// Sets the title of the window, which is shown along with the name of the emulator
+    pub fn set_title(&mut self, title: &str) {
+        self.title = Some(title.to_string());
+        self.update_title();
+    }
+
+    /// Updates the title of the window to show the title of the program and the status line
+    fn update_title(&mut self) {
+        let mut title = match self.title {
+            Some(ref title) => format!("{} - Chip-8 Emulator", title),
+            None => "Chip-8 Emulator".to_string(),
+        };
+
+        if !self.status.is_empty() {
+            title = format!("{} [{}]", title, self.status);
+        }
+
+        self.window.set_title(title);
+    }
+
+    /// Sets the bindings of keys to emulator actions
+    /// Keys are named after the variants of `piston_window::Key`, for example `F1` or `Backspace`
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    /// Returns the bindings of keys to emulator actions
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// Sets the mapping of keys to the keys of the keypad
+    /// Keys are named after the variants of `piston_window::Key`, for example `D1` or `Q`. Keys
+    /// bound to actions are not passed on to the program, even if they are mapped.
+    pub fn set_keymap(&mut self, keymap: KeyMap) {
+        self.keymap = keymap;
+    }
+
+    /// Returns the mapping of keys to the keys of the keypad
+    pub fn keymap(&self) -> &KeyMap {
+        &self.keymap
+    }
+
+    /// Pauses the emulator using the handle (see `Chip8::pause_handle`) and stops any sound when
+    /// the window loses focus, and resumes it when the window gains focus again
+    /// The emulator is only resumed if it was paused by losing focus, not if it was paused
+    /// manually.
+    pub fn pause_on_focus_loss(&mut self, handle: PauseHandle) {
+        self.focus_pause = Some(handle);
+    }
+
+    /// Returns the actions triggered by key presses since the last call to this function
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        mem::replace(&mut self.actions, Vec::new())
+    }
+
     /// A helper function to detect keyboard input and when to close the window
     fn handle_event(&mut self, event: &Event) {
         if let Event::Input(ref input) = *event {
             match *input {
                 Input::Press(button) => self.set_key(button, true),
                 Input::Release(button) => self.set_key(button, false),
+                Input::Focus(focused) => self.set_focus(focused),
+                // Pixels are resized when drawing, but remember the size for when the window is
+                // created again
+                Input::Resize(width, height) if !self.fullscreen => {
+                    self.window_size = [width, height];
+                }
                 _ => {}
             }
         }
     }
 
+    /// Handles the window gaining or losing focus, pausing or resuming the emulator if enabled
+    fn set_focus(&mut self, focused: bool) {
+        let handle = match self.focus_pause {
+            Some(ref handle) => handle,
+            None => return,
+        };
+
+        if !focused && !handle.is_paused() {
+            handle.pause();
+            if let Some(ref sound) = self.sound {
+                sound.sink.pause();
+            }
+            self.paused_by_focus = true;
+        } else if focused && self.paused_by_focus {
+            handle.resume();
+            self.paused_by_focus = false;
+        }
+    }
+
+    /// Sets the directory screenshots and recordings are saved to, which is the current directory
+    /// by default
+    pub fn set_screenshot_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.screenshot_dir = dir.into();
+    }
+
+    /// Saves the last frame drawn as a PNG image in the screenshot directory, and returns its path
+    /// Files are named `screenshot-<number>.png`, using the first number that isn't taken. Does
+    /// nothing and returns `None` if nothing has been drawn yet.
+    pub fn screenshot(&self) -> io::Result<Option<PathBuf>> {
+        let (planes, palette) = match self.frame {
+            Some((ref planes, ref palette)) => (planes, palette),
+            None => return Ok(None),
+        };
+
+        let path = screenshot::unused_path(&self.screenshot_dir, "screenshot", "png");
+        let file = BufWriter::new(File::create(&path)?);
+        screenshot::write_png(planes, palette, SCREENSHOT_SCALE, file)?;
+
+        Ok(Some(path))
+    }
+
+    /// Starts recording the screen, replacing the current recording if there is one
+    pub fn start_recording(&mut self) {
+        self.recording = Some((Recorder::new(), Instant::now()));
+        self.record_frame();
+    }
+
+    /// Stops recording the screen, and saves the recording as an animated GIF image in the
+    /// screenshot directory, returning its path
+    /// Files are named `recording-<number>.gif`, using the first number that isn't taken. Does
+    /// nothing and returns `None` if no recording is being made, or nothing was drawn while
+    /// recording.
+    pub fn stop_recording(&mut self) -> io::Result<Option<PathBuf>> {
+        let recorder = match self.recording.take() {
+            Some((recorder, _)) => recorder,
+            None => return Ok(None),
+        };
+
+        if recorder.is_empty() {
+            return Ok(None);
+        }
+
+        let path = screenshot::unused_path(&self.screenshot_dir, "recording", "gif");
+        let file = BufWriter::new(File::create(&path)?);
+        recorder.write_gif(RECORDING_SCALE, file)?;
+
+        Ok(Some(path))
+    }
+
+    /// Returns whether the screen is being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Records the last frame drawn if a recording is being made
+    fn record_frame(&mut self) {
+        if let (Some(&mut (ref mut recorder, start)), Some(&(ref planes, ref palette))) =
+            (self.recording.as_mut(), self.frame.as_ref()) {
+            let elapsed = start.elapsed();
+            let frame = elapsed.as_secs() * 60 + elapsed.subsec_nanos() as u64 * 60 / 1_000_000_000;
+
+            recorder.record(frame, planes, palette);
+        }
+    }
+
+    /// Draws the last frame if the event is a render event, fading out pixels that were turned off
+    /// if that is enabled
+    fn render(&mut self, e: &Event) {
+        if e.render_args().is_none() {
+            return;
+        }
+
+        let (planes, palette) = match self.frame {
+            Some((ref planes, ref palette)) => (planes, palette),
+            None => return,
+        };
+
+        // The planes the pixels are on in select their colors
+        let colors = match self.blender {
+            Some(ref mut blender) => {
+                blender.blend(planes, palette).iter().map(|&c| to_piston_color(c)).collect()
+            }
+            None => {
+                planes.iter()
+                    .map(|&p| to_piston_color(palette.colors[p as usize]))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let crt = self.crt;
+        self.redraw = false;
+
+        self.window.draw_2d(e, |c, g| {
+            let (size, [left, top]) = screen_layout(c.get_view_size());
+
+            // Clear the borders around the screen, which are shown when the aspect ratio of the
+            // window differs from the screen
+            clear([0.0, 0.0, 0.0, 1.0], g);
+
+            // Iterate through each pixel, get its coordinates and draw a square at its location
+            for x in 0..SCREEN_WIDTH {
+                for y in 0..SCREEN_HEIGHT {
+                    let color = colors[x + y * SCREEN_WIDTH];
+                    let screen_x = left + x as f64 * size;
+                    let screen_y = top + y as f64 * size;
+
+                    // Draw a square for the pixel
+                    rectangle(color, [screen_x, screen_y, size, size], c.transform, g);
+                }
+            }
+
+            if crt {
+                draw_crt_overlay(size, [left, top], c.transform, g);
+            }
+        });
+    }
+
+    /// Returns whether the screen must be drawn even if nothing changed, because pixels are fading
+    /// out or the way it is drawn changed
+    fn needs_redraw(&self) -> bool {
+        match self.blender {
+            Some(ref blender) => self.redraw || blender.is_fading(),
+            None => self.redraw,
+        }
+    }
+
+    /// Switches CRT mode on or off (see `Options::crt`)
+    pub fn toggle_crt(&mut self) {
+        self.crt = !self.crt;
+        self.redraw = true;
+    }
+
+    /// Returns whether the screen is drawn in CRT mode
+    pub fn is_crt(&self) -> bool {
+        self.crt
+    }
+
+    /// Switches the window between fullscreen and windowed mode
+    /// Piston can't change the mode of an open window, so the window is created again.
+    pub fn toggle_fullscreen(&mut self) {
+        self.fullscreen = !self.fullscreen;
+        self.window = create_window(self.window_size, self.fullscreen);
+        self.redraw = true;
+        self.update_title();
+    }
+
+    /// Handles an action triggered by a key, passing it on to the emulator if it doesn't only
+    /// affect the window
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::Fullscreen => self.toggle_fullscreen(),
+            Action::Crt => self.toggle_crt(),
+            Action::Screenshot => {
+                match self.screenshot() {
+                    Ok(Some(path)) => info!("Saved screenshot to `{}`", path.display()),
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to save screenshot: {}", e),
+                }
+            }
+            Action::Record if !self.is_recording() => {
+                info!("Started recording");
+                self.start_recording();
+            }
+            Action::Record => {
+                match self.stop_recording() {
+                    Ok(Some(path)) => info!("Saved recording to `{}`", path.display()),
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to save recording: {}", e),
+                }
+            }
+            _ => self.actions.push(action),
+        }
+    }
+
     /// Handles a key press, setting the keyboard state
     fn set_key(&mut self, button: Button, state: bool) {
         if let Button::Keyboard(key) = button {
-            let button = match key {
-                Key::D1 => 0x0,
-                Key::D2 => 0x1,
-                Key::D3 => 0x2,
-                Key::D4 => 0xC,
-                Key::Q => 0x4,
-                Key::W => 0x5,
-                Key::E => 0x6,
-                Key::R => 0xD,
-                Key::A => 0x7,
-                Key::S => 0x8,
-                Key::D => 0x9,
-                Key::F => 0xE,
-                Key::Z => 0xA,
-                Key::X => 0x0,
-                Key::C => 0xB,
-                Key::V => 0xF,
-                Key::Escape => {
-                    self.should_close = true;
-                    return;
+            let name = format!("{:?}", key);
+
+            // Keys bound to actions are not passed on to the program
+            if let Some(action) = self.bindings.action(&name) {
+                self.held_actions.retain(|&a| a != action);
+                if state {
+                    self.held_actions.push(action);
+                    self.handle_action(action);
                 }
-                _ => return,
-            };
+                return;
+            }
+
+            if key == Key::Escape {
+                self.should_close = true;
+                return;
+            }
 
-            self.keys[button] = state;
+            if let Some(button) = self.keymap.button(&name) {
+                self.keys.set(button, state);
+            }
         }
     }
 }
 
+/// Returns the names of the keys that can be used in bindings and key maps, which are the names of
+/// the variants of `piston_window::Key`
+pub fn key_names() -> Vec<String> {
+    // Keys are numbered after their ASCII characters, or after the scancodes of SDL
+    (0..0x80)
+        .chain(0x4000_0000..0x4000_0120)
+        .map(|code| format!("{:?}", Key::from(code)))
+        .filter(|name| name != "Unknown")
+        .collect()
+}
+
+/// Creates the window with the size, or in fullscreen mode if `fullscreen` is `true`
+fn create_window(size: [u32; 2], fullscreen: bool) -> PistonWindow {
+    WindowSettings::new("Chip-8 Emulator", size)
+        .fullscreen(fullscreen)
+        .build()
+        .unwrap()
+}
+
+/// Returns the size of each pixel and the position of the top left corner of the screen, so the
+/// screen is as large as possible within the window while keeping its aspect ratio
+/// Pixels are a whole number of pixels in size when possible, so they are all the same size.
+fn screen_layout(window_size: [f64; 2]) -> (f64, [f64; 2]) {
+    let size = (window_size[0] / SCREEN_WIDTH as f64).min(window_size[1] / SCREEN_HEIGHT as f64);
+    let size = if size >= 1.0 { size.floor() } else { size };
+
+    let x = (window_size[0] - size * SCREEN_WIDTH as f64) / 2.0;
+    let y = (window_size[1] - size * SCREEN_HEIGHT as f64) / 2.0;
+
+    (size, [x, y])
+}
+
+/// Darkens the lines between the pixels of the screen and its edges, so it looks like an old CRT
+/// monitor
+fn draw_crt_overlay<G: Graphics>(size: f64,
+                                 [left, top]: [f64; 2],
+                                 transform: math::Matrix2d,
+                                 g: &mut G) {
+    let width = size * SCREEN_WIDTH as f64;
+    let height = size * SCREEN_HEIGHT as f64;
+    // The lines cover a quarter of each pixel, but are always at least a pixel thick
+    let line = (size / 4.0).max(1.0);
+
+    for y in 1..SCREEN_HEIGHT + 1 {
+        let y = top + y as f64 * size - line;
+        rectangle([0.0, 0.0, 0.0, SCANLINE_ALPHA], [left, y, width, line], transform, g);
+    }
+
+    for x in 1..SCREEN_WIDTH + 1 {
+        let x = left + x as f64 * size - line;
+        rectangle([0.0, 0.0, 0.0, GRID_ALPHA], [x, top, line, height], transform, g);
+    }
+
+    // The bands overlap, so the vignette is darkest at the edges
+    let band = width.min(height) / 8.0 / VIGNETTE_BANDS as f64;
+    let color = [0.0, 0.0, 0.0, VIGNETTE_ALPHA];
+
+    for i in 1..VIGNETTE_BANDS + 1 {
+        let w = band * i as f64;
+
+        rectangle(color, [left, top, width, w], transform, g);
+        rectangle(color, [left, top + height - w, width, w], transform, g);
+        rectangle(color, [left, top, w, height], transform, g);
+        rectangle(color, [left + width - w, top, w, height], transform, g);
+    }
+}
+
+/// Converts a color to the format used by `piston`
+fn to_piston_color(color: Color) -> [f32; 4] {
+    [color[0] as f32 / 255.0, color[1] as f32 / 255.0, color[2] as f32 / 255.0, 1.0]
+}
+
 impl ::Chip8IO for Io {
     fn draw(&mut self, pixels: &[bool]) {
+        // Draw pixels that are on using the color of the first plane
+        let planes = pixels.iter().map(|&p| p as u8).collect::<Vec<_>>();
+        self.draw_planes(&planes, &Palette::default());
+    }
+
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        self.frame = Some((planes.to_vec(), *palette));
+        self.record_frame();
+
         // Handle all events
         while let Some(e) = self.window.next() {
             match e {
@@ -116,44 +721,56 @@ impl ::Chip8IO for Io {
             self.handle_event(&e);
 
             // Draw the display
-            self.window.draw_2d(&e, |c, g| {
-                // Clear the screen with black
-                clear([0.0; 4], g);
-
-                // Iterate through each pixel, get its coordinates and draw a square at its location
-                for x in 0..SCREEN_WIDTH {
-                    for y in 0..SCREEN_HEIGHT {
-                        let pixel = pixels[x + y * SCREEN_WIDTH];
-
-                        // White if the pixel is on, black otherwise
-                        let color = if pixel { [1.0; 4] } else { [0.0; 4] };
-                        let size = PIXEL_SIZE as f64;
-                        let screen_x = (x * PIXEL_SIZE) as f64;
-                        let screen_y = (y * PIXEL_SIZE) as f64;
-
-                        // Draw a square for the pixel
-                        rectangle(color, [screen_x, screen_y, size, size], c.transform, g);
-                    }
-                }
-            });
+            self.render(&e);
         }
     }
 
-    fn play_sound(&mut self) {
-        self.sound.play();
+    fn sound_start(&mut self) {
+        if let Some(ref sound) = self.sound {
+            sound.sink.play();
+        }
     }
 
-    fn get_keys(&mut self) -> ::Keys {
+    fn sound_stop(&mut self) {
+        if let Some(ref sound) = self.sound {
+            sound.sink.pause();
+        }
+    }
+
+    fn get_keys(&mut self) -> ::Keypad {
+        // Record frames while nothing is drawn too, so the recording has the right length
+        self.record_frame();
+
         while let Some(e) = self.window.next() {
             if let Event::Idle(_) = e {
                 return self.keys;
             }
             self.handle_event(&e);
+
+            // Nothing is drawn until the screen changes otherwise
+            if self.needs_redraw() {
+                self.render(&e);
+            }
         }
 
         self.keys
     }
 
+    fn actions(&mut self) -> Vec<Action> {
+        self.take_actions()
+    }
+
+    fn is_action_held(&self, action: Action) -> bool {
+        self.held_actions.contains(&action)
+    }
+
+    fn set_status(&mut self, status: &str) {
+        if self.status != status {
+            self.status = status.to_string();
+            self.update_title();
+        }
+    }
+
     fn should_close(&self) -> bool {
         // The `handle_event` function detects when the emulator should, this just returns the
         // flag
@@ -0,0 +1,256 @@
+//! Differential testing against other Chip-8 implementations
+//!
+//! A reference trace lists the state of the emulator before each step: the program counter, the
+//! opcode at it, the general purpose registers, and the index register. Traces are stored one step
+//! per line, as hexadecimal fields separated by spaces, so they are easy to write from other
+//! emulators:
+//!
+//! ```text
+//! # PC  OP   V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF I
+//! 0200 6105 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000
+//! 0202 A300 00 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000
+//! ```
+//!
+//! `record` runs a program and returns its trace, and `compare` finds the first step where two
+//! traces differ.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use super::{Chip8, Chip8IO};
+use errors::*;
+
+/// The number of fields of a line of a trace
+const FIELDS: usize = 19;
+
+/// The state of the emulator before a step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// The program counter
+    pub pc: u16,
+    /// The opcode at the program counter
+    pub opcode: u16,
+    /// The general purpose registers, V0 to VF
+    pub registers: [u8; 16],
+    /// The index register
+    pub index: u16,
+}
+
+impl Step {
+    /// Returns the state of the emulator
+    /// Bytes of the opcode that are outside of memory are read as zero.
+    pub fn new(chip8: &Chip8) -> Step {
+        let pc = chip8.registers.program_counter;
+        let byte = |address: usize| chip8.memory.get(address).cloned().unwrap_or(0) as u16;
+
+        Step {
+            pc,
+            opcode: byte(pc as usize) << 8 | byte(pc as usize + 1),
+            registers: *chip8.registers.get_registers(),
+            index: chip8.registers.index,
+        }
+    }
+
+    /// Returns the names of the fields that differ between the steps, such as `PC` or `V3`
+    pub fn differences(&self, other: &Step) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        if self.pc != other.pc {
+            differences.push("PC".to_string());
+        }
+        if self.opcode != other.opcode {
+            differences.push("opcode".to_string());
+        }
+        for (x, (a, b)) in self.registers.iter().zip(&other.registers).enumerate() {
+            if a != b {
+                differences.push(format!("V{:X}", x));
+            }
+        }
+        if self.index != other.index {
+            differences.push("I".to_string());
+        }
+
+        differences
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X} {:04X}", self.pc, self.opcode)?;
+        for value in &self.registers {
+            write!(f, " {:02X}", value)?;
+        }
+        write!(f, " {:04X}", self.index)
+    }
+}
+
+/// The first step where two traces differ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The index of the step in the traces
+    pub step: usize,
+    /// The step of the expected trace, or `None` if it ended first
+    pub expected: Option<Step>,
+    /// The step of the actual trace, or `None` if it ended first
+    pub actual: Option<Step>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "The traces diverge at step {}", self.step)?;
+
+        match (self.expected, self.actual) {
+            (Some(expected), Some(actual)) => {
+                writeln!(f, "expected: {}", expected)?;
+                writeln!(f, "  actual: {}", actual)?;
+                write!(f, "differs in: {}", expected.differences(&actual).join(", "))
+            }
+            (Some(expected), None) => {
+                write!(f, "expected: {}\nbut the actual trace ended", expected)
+            }
+            (None, Some(actual)) => write!(f, "the expected trace ended\nbut got: {}", actual),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Runs the emulator for at most `steps` cycles (see `Chip8::run_until`), and returns the state
+/// before each cycle that was run
+/// Returns an error if the program crashed.
+pub fn record<T: Chip8IO>(chip8: &mut Chip8, io: &mut T, steps: u64) -> Result<Vec<Step>> {
+    let mut trace = Vec::new();
+    let start = chip8.cycles();
+
+    chip8.run_until(io, steps, |chip8| {
+        trace.push(Step::new(chip8));
+        false
+    })?;
+
+    // The state after the last cycle is recorded too, but no step was run from it
+    trace.truncate((chip8.cycles() - start) as usize);
+    Ok(trace)
+}
+
+/// Writes the trace in its text representation (see the module documentation for the format)
+pub fn write<W: Write>(trace: &[Step], mut writer: W) -> io::Result<()> {
+    for step in trace {
+        writeln!(writer, "{}", step)?;
+    }
+
+    Ok(())
+}
+
+/// Parses a trace from its text representation (see the module documentation for the format)
+pub fn parse(text: &str) -> Result<Vec<Step>> {
+    let mut trace = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || ErrorKind::InvalidTrace(i + 1, line.to_string());
+
+        let fields = line.split_whitespace()
+            .map(|field| u16::from_str_radix(field, 16))
+            .collect::<::std::result::Result<Vec<_>, _>>()
+            .chain_err(invalid)?;
+
+        if fields.len() != FIELDS || fields[2..18].iter().any(|&value| value > 0xFF) {
+            bail!(invalid());
+        }
+
+        let mut registers = [0; 16];
+        for (register, &value) in registers.iter_mut().zip(&fields[2..18]) {
+            *register = value as u8;
+        }
+
+        trace.push(Step {
+            pc: fields[0],
+            opcode: fields[1],
+            registers,
+            index: fields[18],
+        });
+    }
+
+    Ok(trace)
+}
+
+/// Returns the first step where the traces differ, or `None` if they are the same
+/// If one trace is a prefix of the other, they diverge at the first step after the shorter one.
+pub fn compare(expected: &[Step], actual: &[Step]) -> Option<Divergence> {
+    (0..expected.len().max(actual.len()))
+        .map(|step| {
+            Divergence {
+                step,
+                expected: expected.get(step).cloned(),
+                actual: actual.get(step).cloned(),
+            }
+        })
+        .find(|divergence| divergence.expected != divergence.actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a step with the program counter and V0 set
+    fn step(pc: u16, v0: u8) -> Step {
+        let mut registers = [0; 16];
+        registers[0] = v0;
+
+        Step {
+            pc,
+            opcode: 0x1200,
+            registers,
+            index: 0x300,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let trace = vec![step(0x200, 1), step(0x202, 0xFF)];
+        let mut text = Vec::new();
+        write(&trace, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+
+        assert_eq!("0200 1200 01 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0300",
+                   text.lines().next().unwrap());
+        assert_eq!(trace, parse(&format!("# A comment\n\n{}", text)).unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let lines = ["0200 1200 00",
+                     "0200 1200 100 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0300",
+                     "0200 1200 0G 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0300"];
+
+        for line in &lines {
+            match parse(line) {
+                Err(Error(ErrorKind::InvalidTrace(1, _), _)) => {}
+                Err(e) => panic!("Wrong error: {}", e),
+                Ok(_) => panic!("Expected error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = vec![step(0x200, 0), step(0x202, 1), step(0x204, 2)];
+
+        assert_eq!(None, compare(&expected, &expected));
+
+        let mut actual = expected.clone();
+        actual[1].registers[0] = 5;
+        actual[2].pc = 0x300;
+        let divergence = compare(&expected, &actual).unwrap();
+        assert_eq!(1, divergence.step);
+        assert_eq!(vec!["V0"], expected[1].differences(&actual[1]));
+
+        let divergence = compare(&expected, &expected[..2]).unwrap();
+        assert_eq!(2, divergence.step);
+        assert_eq!(None, divergence.actual);
+    }
+}
@@ -0,0 +1,171 @@
+//! Disassembly of programs into a readable assembly language
+//!
+//! Instructions are written using the common Chip-8 mnemonics, for example `LD V1, 0x05` or
+//! `DRW V0, V1, 5`. Bytes that are not valid instructions are written as data (`DW 0x0000`, or
+//! `DB 0x00` for a trailing byte).
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use interpreter::interpret_instruction;
+use coverage::Coverage;
+
+/// A disassembled instruction, or data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// The address of the instruction
+    pub address: u16,
+    /// The bytes of the instruction
+    pub bytes: Vec<u8>,
+    /// The instruction in assembly language, or `None` if the bytes are not a valid instruction
+    pub instruction: Option<String>,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = self.bytes.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+        write!(f, "0x{:03X}: {:<4}  ", self.address, hex)?;
+
+        match self.instruction {
+            Some(ref instruction) => write!(f, "{}", instruction),
+            None if self.bytes.len() == 2 => write!(f, "DW 0x{}", hex),
+            None => write!(f, "DB 0x{}", hex),
+        }
+    }
+}
+
+/// Returns the line at the address of the program, which is loaded at `PROGRAM_START`
+/// Returns `None` if the address is outside the program.
+fn line(program: &[u8], address: u16) -> Option<Line> {
+    let start = (address as usize).checked_sub(::PROGRAM_START)?;
+    let bytes = program.get(start..(start + 2).min(program.len()))?;
+
+    if bytes.is_empty() {
+        return None;
+    }
+
+    let instruction = if bytes.len() == 2 {
+        interpret_instruction((bytes[0] as u16) << 8 | bytes[1] as u16)
+            .ok()
+            .map(|i| i.to_string())
+    } else {
+        None
+    };
+
+    Some(Line {
+        address,
+        bytes: bytes.to_vec(),
+        instruction,
+    })
+}
+
+/// Disassembles the whole program, two bytes at a time
+pub fn disassemble(program: &[u8]) -> Vec<Line> {
+    (0..)
+        .map(|i| (::PROGRAM_START + i * 2) as u16)
+        .map(|address| line(program, address))
+        .take_while(Option::is_some)
+        .map(Option::unwrap)
+        .collect()
+}
+
+/// Returns the addresses of the instructions that can be reached from the start of the program,
+/// by following jumps, calls and skips
+/// Jumps using `JP V0` can't be followed, as their targets depend on the registers.
+pub fn reachable(program: &[u8]) -> BTreeSet<u16> {
+    let mut reached = BTreeSet::new();
+
+    walk(program, |address, opcode| if interpret_instruction(opcode).is_ok() {
+        reached.insert(address);
+    });
+
+    reached
+}
+
+/// Calls `visit` once with the address and opcode of each instruction that can be reached from the
+/// start of the program (see `reachable`)
+/// Invalid opcodes are visited too, but aren't followed.
+pub(crate) fn walk<F: FnMut(u16, u16)>(program: &[u8], mut visit: F) {
+    use instruction::Instruction::*;
+
+    let mut reached = BTreeSet::new();
+    let mut pending = vec![::PROGRAM_START as u16];
+
+    while let Some(address) = pending.pop() {
+        if !reached.insert(address) {
+            continue;
+        }
+
+        // Addresses outside the program can't be followed
+        let bytes = (address as usize)
+            .checked_sub(::PROGRAM_START)
+            .and_then(|start| program.get(start..start + 2));
+        let opcode = match bytes {
+            Some(bytes) => (bytes[0] as u16) << 8 | bytes[1] as u16,
+            None => continue,
+        };
+
+        visit(address, opcode);
+
+        let instruction = match interpret_instruction(opcode) {
+            Ok(instruction) => instruction,
+            Err(_) => continue,
+        };
+
+        let next = address + 2;
+
+        match instruction {
+            Return | OffsetGoto(_) => {}
+            Goto(addr) => pending.push(addr),
+            Call(addr) => pending.extend(&[addr, next]),
+            SkipEqConst(..) | SkipNeqConst(..) | SkipEq(..) | SkipNeq(..) | SkipKey(_) |
+            SkipNotKey(_) | SkipKey2(_) | SkipNotKey2(_) => pending.extend(&[next, next + 2]),
+            // The low word of the address follows the instruction
+            SetIndexLong(_) => pending.push(next + 2),
+            _ => pending.push(next),
+        }
+    }
+}
+
+/// Disassembles the instructions that can be reached from the start of the program (see
+/// `reachable`)
+pub fn disassemble_reachable(program: &[u8]) -> Vec<Line> {
+    reachable(program).into_iter().filter_map(|address| line(program, address)).collect()
+}
+
+/// Disassembles the instructions of the program that were run, according to the coverage map of
+/// an emulator that ran it (see `Chip8::coverage`)
+/// Unlike `disassemble_reachable`, this includes the targets of `JP V0`, but not code that wasn't
+/// reached during the run.
+pub fn disassemble_covered(program: &[u8], coverage: &Coverage) -> Vec<Line> {
+    coverage.addresses().into_iter().filter_map(|address| line(program, address)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let program = [0x61, 0x05, 0xD0, 0x15, 0x00, 0x00, 0x12];
+        let lines = disassemble(&program)
+            .iter()
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec!["0x200: 6105  LD V1, 0x05",
+                        "0x202: D015  DRW V0, V1, 5",
+                        "0x204: 0000  DW 0x0000",
+                        "0x206: 12    DB 0x12"],
+                   lines);
+    }
+
+    #[test]
+    fn test_reachable() {
+        // Calls a subroutine, skips an instruction, then loops, with data in between
+        let program = [0x22, 0x0A, 0x30, 0x00, 0x00, 0xE0, 0x12, 0x04, 0xFF, 0xFF, 0x00, 0xEE];
+        let addresses = reachable(&program).into_iter().collect::<Vec<_>>();
+
+        assert_eq!(vec![0x200, 0x202, 0x204, 0x206, 0x20A], addresses);
+    }
+}
@@ -0,0 +1,122 @@
+//! A human-readable dump of the state of the emulator, used as its `Debug` output
+//!
+//! The dump shows the general purpose registers as a table, the program counter, index register,
+//! and timers, the stack, a hexdump of the memory around the program counter, and the screen drawn
+//! in ASCII:
+//!
+//! ```text
+//! V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF
+//! 00 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+//! PC: 0x202  I: 0x000  DT: 0x00  ST: 0x00  cycles: 1
+//! Stack: empty
+//! 0x1F0:  00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+//! 0x200:  61 05[00 E0]00 00 00 00 00 00 00 00 00 00 00 00
+//! ...
+//! ```
+
+use std::fmt;
+
+use super::{Chip8, SCREEN_WIDTH};
+
+/// The number of bytes in each row of the hexdump
+const ROW_SIZE: usize = 16;
+/// The number of rows of the hexdump before the one containing the program counter
+const ROWS_BEFORE: usize = 1;
+/// The number of rows of the hexdump
+const ROWS: usize = 4;
+
+/// Returns the character for two vertically adjacent pixels of the screen
+fn pixel_pair(top: bool, bottom: bool) -> char {
+    match (top, bottom) {
+        (false, false) => ' ',
+        (true, false) => '\'',
+        (false, true) => '.',
+        (true, true) => ':',
+    }
+}
+
+impl Chip8 {
+    /// Writes the general purpose registers as a table of their names and values
+    fn dump_registers(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers = self.registers.get_registers();
+        let names = (0..registers.len()).map(|x| format!("V{:X}", x)).collect::<Vec<_>>();
+        let values = registers.iter().map(|value| format!("{:02X}", value)).collect::<Vec<_>>();
+
+        writeln!(f, "{}", names.join(" "))?;
+        writeln!(f, "{}", values.join(" "))
+    }
+
+    /// Writes the rows of memory around the program counter, with brackets around the bytes of the
+    /// next instruction
+    fn dump_memory(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pc = self.registers.program_counter as usize;
+        let last_row = self.memory.len().saturating_sub(1) / ROW_SIZE;
+        let first = (pc / ROW_SIZE).saturating_sub(ROWS_BEFORE).min(last_row);
+
+        for row in first..(first + ROWS).min(last_row + 1) {
+            let start = row * ROW_SIZE;
+            write!(f, "0x{:03X}: ", start)?;
+
+            for address in start..start + ROW_SIZE {
+                let separator = if address == pc {
+                    '['
+                } else if address == pc + 2 {
+                    ']'
+                } else {
+                    ' '
+                };
+
+                match self.memory.get(address) {
+                    Some(byte) => write!(f, "{}{:02X}", separator, byte)?,
+                    None => write!(f, "{}  ", separator)?,
+                }
+            }
+
+            let end = if start + ROW_SIZE == pc + 2 { "]" } else { "" };
+            writeln!(f, "{}", end)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the screen in ASCII, with each character showing two rows of pixels
+    fn dump_screen(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pixels = self.pixels();
+        let border = format!("+{}+", "-".repeat(SCREEN_WIDTH));
+
+        writeln!(f, "{}", border)?;
+        for rows in pixels.chunks(SCREEN_WIDTH * 2) {
+            let (top, bottom) = rows.split_at(SCREEN_WIDTH);
+            let line = top.iter()
+                .zip(bottom)
+                .map(|(&top, &bottom)| pixel_pair(top, bottom))
+                .collect::<String>();
+            writeln!(f, "|{}|", line)?;
+        }
+        write!(f, "{}", border)
+    }
+}
+
+impl fmt::Debug for Chip8 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.dump_registers(f)?;
+        writeln!(f,
+                 "PC: 0x{:03X}  I: 0x{:03X}  DT: 0x{:02X}  ST: 0x{:02X}  cycles: {}{}",
+                 self.registers.program_counter,
+                 self.registers.index,
+                 self.delay_timer,
+                 self.sound_timer,
+                 self.cycles,
+                 if self.program_ended { "  (ended)" } else { "" })?;
+
+        if self.stack.is_empty() {
+            writeln!(f, "Stack: empty")?;
+        } else {
+            let stack = self.stack.iter().map(|a| format!("0x{:03X}", a)).collect::<Vec<_>>();
+            writeln!(f, "Stack: {}", stack.join(" "))?;
+        }
+
+        self.dump_memory(f)?;
+        self.dump_screen(f)
+    }
+}
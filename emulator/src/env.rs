@@ -0,0 +1,121 @@
+//! An environment for reinforcement learning, in the style of OpenAI Gym
+//!
+//! An `Env` runs a fixed number of cycles each step, with the keys chosen by the agent pressed, and
+//! returns what the agent can observe afterwards. Rewards depend on the program, so they are left
+//! to the user, who can compute them from the emulator (see `Env::chip8`), for example from the
+//! score kept in memory.
+//!
+//! ```rust
+//! use chip8::config::Config;
+//! use chip8::env::Env;
+//! use chip8::Keypad;
+//!
+//! let program = &[0x61, 0xFF, 0x12, 0x00];
+//! let mut env = Env::new(program, Config::new(), 100).unwrap();
+//!
+//! let mut observation = env.reset();
+//! for _ in 0..10 {
+//!     let keys = Keypad::from([observation.screen[0] == 0; 16]);
+//!     let (next, done) = env.step(keys).unwrap();
+//!     if done {
+//!         break;
+//!     }
+//!     observation = next;
+//! }
+//! ```
+
+use super::{Chip8, Keypad};
+use config::Config;
+use errors::*;
+use io::KeysIO;
+
+/// What the agent can observe of the emulator after a step
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Observation {
+    /// The screen, where each pixel is the bitmask of the planes it is on in (see `Chip8::planes`)
+    /// It is `SCREEN_WIDTH` by `SCREEN_HEIGHT` pixels, in row-major order.
+    pub screen: Vec<u8>,
+    /// Whether the sound is playing
+    pub sound: bool,
+}
+
+impl Observation {
+    /// Returns the observation of the emulator in its current state
+    fn new(chip8: &Chip8) -> Observation {
+        Observation {
+            screen: chip8.planes().to_vec(),
+            sound: chip8.sound_timer() > 0,
+        }
+    }
+}
+
+/// An emulator that is run a step at a time by an agent
+#[derive(Debug)]
+pub struct Env {
+    /// The emulator
+    chip8: Chip8,
+    /// The keys chosen by the agent
+    io: KeysIO,
+    /// The number of cycles run each step
+    cycles_per_step: u64,
+}
+
+impl Env {
+    /// Creates an environment running the program, which runs `cycles_per_step` cycles each step
+    /// Returns an error if the program is invalid (see `Chip8::new`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cycles_per_step` is zero.
+    pub fn new(program: &[u8], config: Config, cycles_per_step: u64) -> Result<Env> {
+        assert!(cycles_per_step > 0, "At least one cycle must be run each step");
+
+        Ok(Env {
+            chip8: Chip8::new(program, config)?,
+            io: KeysIO::new(),
+            cycles_per_step,
+        })
+    }
+
+    /// Restarts the program with no keys pressed (see `Chip8::reset`), and returns the first
+    /// observation
+    pub fn reset(&mut self) -> Observation {
+        self.chip8.reset();
+        self.io.keys = Keypad::new();
+
+        Observation::new(&self.chip8)
+    }
+
+    /// Runs the cycles of a step with the keys pressed (see `Chip8::run_for`), and returns the
+    /// observation afterwards, and whether the program ended or stalled
+    /// Once the program is done, stepping runs no cycles until the environment is reset. Returns
+    /// an error if the program crashed.
+    pub fn step(&mut self, keys: Keypad) -> Result<(Observation, bool)> {
+        self.io.keys = keys;
+        // Steps can start in the middle of a frame, so the keys are read again
+        self.chip8.poll_keys = true;
+        self.chip8.run_for(&mut self.io, self.cycles_per_step)?;
+
+        Ok((Observation::new(&self.chip8), self.is_done()))
+    }
+
+    /// Returns whether the program ended or stalled
+    pub fn is_done(&self) -> bool {
+        self.chip8.program_ended() || self.chip8.stalled()
+    }
+
+    /// Returns the number of cycles run each step
+    pub fn cycles_per_step(&self) -> u64 {
+        self.cycles_per_step
+    }
+
+    /// Returns the emulator, for computing rewards from its state
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    /// Returns the emulator mutably
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+}
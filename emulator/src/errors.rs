@@ -1,34 +1,328 @@
 //! Error handling
+//!
+//! Every error has an `ErrorKind`, which frontends can match on, and optionally the error that
+//! caused it. Errors that happen while running a program carry the `Location` of the instruction
+//! that caused them. `ResultExt::chain_err` wraps an error in a new one that describes what was
+//! being done when it happened, and `Error::iter` walks the chain of causes.
 
-#![allow(missing_docs)]
+use std::error::Error as StdError;
+use std::fmt;
+use std::result;
 
-error_chain! {
-    errors {
-        ProgramTooLarge(program_size: usize, memory_size: usize) {
-            description("Program too large")
-            display("Program too large: memory is {} bytes, but program was {} bytes",
-                    memory_size,
-                    program_size)
-        }
-        InvalidOpcode(opcode: String) {
-            description("Invalid opcode")
-            display("Invalid opcode: {}", opcode)
-        }
-        InvalidAddress(address: usize, instruction: &'static str) {
-            description("Invalid address")
-            display("Invalid address: {} ({})", address, instruction)
-        }
-        UnknownCharacter(character: u8) {
-            description("Unknown character")
-            display("No sprite for character: {}", character)
+/// Returns early with an error, which is either an `ErrorKind` or a message formatted like with
+/// `format!`
+macro_rules! bail {
+    ($e:expr) => {
+        return Err($e.into())
+    };
+    ($fmt:expr, $($arg:tt)+) => {
+        return Err(format!($fmt, $($arg)+).into())
+    };
+}
+
+/// The result type used by the emulator
+pub type Result<T> = result::Result<T, Error>;
+
+/// An error, along with the error that caused it
+#[derive(Debug)]
+pub struct Error(pub ErrorKind, pub Option<Box<dyn StdError + Send + Sync>>);
+
+/// The instruction that caused an error while running a program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// The address of the instruction
+    pub pc: u16,
+    /// The opcode of the instruction
+    pub opcode: u16,
+    /// The name of the instruction, such as `Draw`, or `Invalid` if the opcode isn't an instruction
+    pub instruction: &'static str,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} (0x{:04X}) at address 0x{:03X}",
+               self.instruction,
+               self.opcode,
+               self.pc)
+    }
+}
+
+/// The kinds of errors
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An error described by a message
+    Msg(String),
+    /// The program is too large to fit in memory (the size of the program, and of the memory
+    /// available to it)
+    ProgramTooLarge(usize, usize),
+    /// The opcode isn't an instruction (see `UnknownOpcode`), with a description of how the
+    /// program reached it
+    InvalidOpcode(Location, String),
+    /// The opcode isn't an instruction, found when decoding it outside of a running program
+    UnknownOpcode(u16),
+    /// An instruction used an address outside of memory
+    InvalidAddress(usize, Location),
+    /// An instruction asked for the sprite of a character that isn't in the font
+    UnknownCharacter(u8, Location),
+    /// An instruction wrote to the memory below the program start, which holds the interpreter and
+    /// the font (the address, see `Config::protect_interpreter`)
+    ProtectedWrite(usize, Location),
+    /// An instruction used a key that isn't on the keypad
+    UnknownKey(u8, Location),
+    /// An instruction drew a pixel outside of the screen
+    PixelOutOfBounds(usize, usize, Location),
+    /// A call was made with the stack full (the number of addresses the stack can hold)
+    StackOverflow(usize, Location),
+    /// A return was made with nothing on the stack
+    StackUnderflow(Location),
+    /// Memory was read or written outside of memory, through the methods of `Chip8` (the first
+    /// address that is outside)
+    OutOfMemory(usize),
+    /// The stack was replaced with more addresses than it can hold (the number it can hold)
+    StackTooLarge(usize),
+    /// A key that isn't on the keypad was given to a method
+    InvalidKey(u8),
+    /// An emulator action that doesn't exist
+    UnknownAction(String),
+    /// A key name that isn't valid in the format it was written in
+    InvalidKeyName(String),
+    /// A key name that isn't known, and a similar name that is, if there is one
+    UnknownKeyName(String, Option<String>),
+    /// A key bound to more than one action (the key, and the action it was bound to first)
+    DuplicateBinding(String, String),
+    /// Invalid metadata (the line number, and the text of the line)
+    InvalidMetadata(usize, String),
+    /// A condition that can't be parsed
+    InvalidCondition(String),
+    /// An invalid sound log (the line number, and the text of the line)
+    InvalidSoundLog(usize, String),
+    /// An invalid binding (the line number, and the text of the line)
+    InvalidBinding(usize, String),
+    /// An invalid key mapping (the line number, and the text of the line)
+    InvalidKeyMapping(usize, String),
+    /// An invalid symbol (the line number, and the text of the line)
+    InvalidSymbol(usize, String),
+    /// Invalid assembly (the line number, and the text of the line)
+    InvalidAssembly(usize, String),
+    /// An invalid reference trace (the line number, and the text of the line)
+    InvalidTrace(usize, String),
+    /// A saved state that can't be loaded, and why
+    InvalidState(String),
+    /// A font that can't be loaded, and why
+    InvalidFont(String),
+}
+
+impl ErrorKind {
+    /// Returns the instruction that caused the error, if it happened while running a program
+    pub fn location(&self) -> Option<&Location> {
+        use self::ErrorKind::*;
+
+        match *self {
+            InvalidOpcode(ref location, _) |
+            InvalidAddress(_, ref location) |
+            UnknownCharacter(_, ref location) |
+            ProtectedWrite(_, ref location) |
+            UnknownKey(_, ref location) |
+            PixelOutOfBounds(_, _, ref location) |
+            StackOverflow(_, ref location) |
+            StackUnderflow(ref location) => Some(location),
+            _ => None,
         }
-        UnknownKey(key: u8, instruction: &'static str) {
-            description("Unknown key")
-            display("Unknown key: {} ({})", key, instruction)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ErrorKind::*;
+
+        match *self {
+            Msg(ref message) => write!(f, "{}", message),
+            ProgramTooLarge(program_size, memory_size) => {
+                write!(f,
+                       "Program too large: memory is {} bytes, but program was {} bytes",
+                       memory_size,
+                       program_size)
+            }
+            InvalidOpcode(ref location, ref details) => {
+                write!(f,
+                       "Invalid opcode 0x{:02X} 0x{:02X} at address 0x{:03X}{}",
+                       location.opcode >> 8,
+                       location.opcode & 0xFF,
+                       location.pc,
+                       details)
+            }
+            UnknownOpcode(opcode) => write!(f, "Invalid opcode: 0x{:04X}", opcode),
+            InvalidAddress(address, ref location) => {
+                write!(f, "Invalid address: {} ({})", address, location)
+            }
+            UnknownCharacter(character, ref location) => {
+                write!(f, "No sprite for character: {} ({})", character, location)
+            }
+            ProtectedWrite(address, ref location) => {
+                write!(f,
+                       "Write to address 0x{:03X}, which is below the program and holds the \
+                        interpreter and the font ({})",
+                       address,
+                       location)
+            }
+            UnknownKey(key, ref location) => write!(f, "Unknown key: {} ({})", key, location),
+            PixelOutOfBounds(x, y, ref location) => {
+                write!(f, "Invalid pixel coordinates: ({}, {}) ({})", x, y, location)
+            }
+            StackOverflow(depth, ref location) => {
+                write!(f,
+                       "Stack overflow: the stack can only hold {} addresses ({})",
+                       depth,
+                       location)
+            }
+            StackUnderflow(ref location) => {
+                write!(f,
+                       "Stack underflow: returned from a subroutine with nothing on the stack ({})",
+                       location)
+            }
+            OutOfMemory(address) => write!(f, "Address outside of memory: 0x{:X}", address),
+            StackTooLarge(depth) => {
+                write!(f, "Too many addresses for the stack, which can only hold {}", depth)
+            }
+            InvalidKey(key) => write!(f, "Not a key of the keypad: {}", key),
+            UnknownAction(ref action) => write!(f, "Unknown action: `{}`", action),
+            InvalidKeyName(ref key) => write!(f, "Invalid key name: `{}`", key),
+            UnknownKeyName(ref key, ref suggestion) => {
+                write!(f,
+                       "Unknown key name: `{}`{}",
+                       key,
+                       suggestion.as_ref()
+                           .map_or(String::new(), |s| format!(" (did you mean `{}`?)", s)))
+            }
+            DuplicateBinding(ref key, ref action) => {
+                write!(f, "Key `{}` is already bound to action `{}`", key, action)
+            }
+            InvalidMetadata(line, ref text) => {
+                write!(f, "Invalid metadata on line {}: `{}`", line, text)
+            }
+            InvalidCondition(ref condition) => write!(f, "Invalid condition: `{}`", condition),
+            InvalidSoundLog(line, ref text) => {
+                write!(f, "Invalid sound log on line {}: `{}`", line, text)
+            }
+            InvalidBinding(line, ref text) => {
+                write!(f, "Invalid binding on line {}: `{}`", line, text)
+            }
+            InvalidKeyMapping(line, ref text) => {
+                write!(f, "Invalid key mapping on line {}: `{}`", line, text)
+            }
+            InvalidSymbol(line, ref text) => write!(f, "Invalid symbol on line {}: `{}`", line, text),
+            InvalidAssembly(line, ref text) => {
+                write!(f, "Invalid assembly on line {}: `{}`", line, text)
+            }
+            InvalidTrace(line, ref text) => {
+                write!(f, "Invalid reference trace on line {}: `{}`", line, text)
+            }
+            InvalidState(ref reason) => write!(f, "Invalid saved state: {}", reason),
+            InvalidFont(ref reason) => write!(f, "Invalid font: {}", reason),
         }
-        PixelOutOfBounds(x: usize, y: usize) {
-            description("Attemped to draw a pixel at invalid coordinates")
-            display("Invalid pixel coordinates: ({}, {})", x, y)
+    }
+}
+
+impl Error {
+    /// Returns the kind of the error
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0
+    }
+
+    /// Returns the instruction that caused the error, if it or any of its causes happened while
+    /// running a program
+    pub fn location(&self) -> Option<&Location> {
+        let mut error = Some(self);
+
+        while let Some(e) = error {
+            if let Some(location) = e.0.location() {
+                return Some(location);
+            }
+            error = e.1.as_ref().and_then(|cause| cause.downcast_ref::<Error>());
         }
+
+        None
+    }
+
+    /// Returns an iterator over the error and the chain of errors that caused it
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter(Some(self))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.1.as_ref().map(|cause| &**cause as &(dyn StdError + 'static))
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error(kind, None)
+    }
+}
+
+impl<'a> From<&'a str> for Error {
+    fn from(message: &'a str) -> Error {
+        ErrorKind::Msg(message.to_string()).into()
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        ErrorKind::Msg(message).into()
+    }
+}
+
+impl<'a> From<&'a str> for ErrorKind {
+    fn from(message: &'a str) -> ErrorKind {
+        ErrorKind::Msg(message.to_string())
+    }
+}
+
+impl From<String> for ErrorKind {
+    fn from(message: String) -> ErrorKind {
+        ErrorKind::Msg(message)
+    }
+}
+
+/// An iterator over an error and the chain of errors that caused it (see `Error::iter`)
+#[derive(Debug)]
+pub struct Iter<'a>(Option<&'a (dyn StdError + 'static)>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a (dyn StdError + 'static);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let error = self.0?;
+        self.0 = error.source();
+
+        Some(error)
+    }
+}
+
+/// Adds context to errors
+pub trait ResultExt<T> {
+    /// Wraps the error in a new one, of the kind returned by the callback, that is caused by it
+    fn chain_err<F, K>(self, callback: F) -> Result<T>
+        where F: FnOnce() -> K,
+              K: Into<ErrorKind>;
+}
+
+impl<T, E> ResultExt<T> for result::Result<T, E>
+    where E: StdError + Send + Sync + 'static
+{
+    fn chain_err<F, K>(self, callback: F) -> Result<T>
+        where F: FnOnce() -> K,
+              K: Into<ErrorKind>
+    {
+        self.map_err(|e| Error(callback().into(), Some(Box::new(e))))
     }
 }
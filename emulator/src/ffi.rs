@@ -0,0 +1,437 @@
+//! A C API, so the emulator can be embedded in programs written in other languages
+//!
+//! Build the library as a dynamic or static library with the `ffi` feature enabled:
+//!
+//! ```text
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! The functions are declared in C as follows:
+//!
+//! ```c
+//! typedef struct Chip8 Chip8;
+//!
+//! #define CHIP8_RUNNING 0
+//! #define CHIP8_ENDED 1
+//! #define CHIP8_ERROR -1
+//!
+//! Chip8 *chip8_new(const uint8_t *program, size_t len);
+//! int chip8_step(Chip8 *chip8, uint32_t cycles);
+//! void chip8_tick(Chip8 *chip8);
+//! void chip8_set_keys(Chip8 *chip8, uint16_t keys);
+//! const uint8_t *chip8_framebuffer(const Chip8 *chip8, size_t *width, size_t *height);
+//! const uint8_t *chip8_palette(const Chip8 *chip8, size_t *colors);
+//! bool chip8_sound_active(const Chip8 *chip8);
+//! const char *chip8_error(const Chip8 *chip8);
+//! void chip8_free(Chip8 *chip8);
+//! ```
+//!
+//! A frontend calls `chip8_step` and `chip8_tick` 60 times per second, running as many cycles per
+//! step as the program needs (usually about 10). Keys are passed as a bitmask, where bit N is set
+//! if key N is pressed.
+//!
+//! The framebuffer has one byte per pixel, which is an index into the palette, and the palette has
+//! three bytes per color (red, green, and blue). For most programs, the index is the bitmask of the
+//! planes the pixel is on in (see `Chip8::planes`), but CHIP-8X programs are colored by their color
+//! board, and Mega-Chip programs have a larger screen with a palette of their own. The framebuffer
+//! and palette are updated by `chip8_step`, and stay valid until the next step or until the
+//! emulator is freed.
+
+#![allow(unsafe_code)]
+
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use super::{Chip8, Chip8IO, Keypad, SCREEN_WIDTH, SCREEN_HEIGHT, MEGA_WIDTH, MEGA_HEIGHT};
+use config::Config;
+
+/// Returned by `chip8_step` if the program is still running
+pub const CHIP8_RUNNING: c_int = 0;
+/// Returned by `chip8_step` if the program ended
+pub const CHIP8_ENDED: c_int = 1;
+/// Returned by `chip8_step` if the program crashed (see `chip8_error`)
+pub const CHIP8_ERROR: c_int = -1;
+
+/// An emulator created by `chip8_new`
+#[allow(missing_debug_implementations)]
+pub struct Emulator {
+    chip8: Chip8,
+    io: FfiIO,
+    /// The message of the error that crashed the program, if it crashed
+    error: Option<CString>,
+    /// The screen as it is shown, as indices into `palette`
+    framebuffer: Vec<u8>,
+    /// The width and height of the screen
+    size: (usize, usize),
+    /// The colors of the screen, as three bytes per color
+    palette: Vec<u8>,
+}
+
+impl Emulator {
+    /// Copies the screen as it is shown, and its palette, from the emulator
+    fn update_screen(&mut self) {
+        let chip8 = &self.chip8;
+        let (pixels, palette, size) = match chip8.io.mega() {
+            Some(mega) => {
+                (Cow::Borrowed(mega.pixels()),
+                 Cow::Borrowed(mega.palette()),
+                 (MEGA_WIDTH, MEGA_HEIGHT))
+            }
+            None => {
+                let (planes, palette) = chip8.io.screen(&chip8.palette);
+                (planes, palette, (SCREEN_WIDTH, SCREEN_HEIGHT))
+            }
+        };
+
+        self.framebuffer.clear();
+        self.framebuffer.extend_from_slice(&pixels);
+        self.size = size;
+        self.palette.clear();
+        for color in palette.colors.iter() {
+            self.palette.extend_from_slice(color);
+        }
+    }
+}
+
+/// An implementation of `Chip8IO` that stores the keys set by the frontend and whether the sound
+/// is playing, as the screen is read with `chip8_framebuffer`
+struct FfiIO {
+    keys: Keypad,
+    sound: bool,
+}
+
+impl Chip8IO for FfiIO {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_planes(&mut self, _: &[u8], _: &::Palette) {}
+    fn get_keys(&mut self) -> Keypad {
+        self.keys
+    }
+    fn sound_start(&mut self) {
+        self.sound = true;
+    }
+    fn sound_stop(&mut self) {
+        self.sound = false;
+    }
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// Creates an emulator running the program, which is `len` bytes long
+/// Returns null if the program is too large. The emulator must be freed with `chip8_free`.
+///
+/// # Safety
+///
+/// `program` must be null, or point to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_new(program: *const u8, len: usize) -> *mut Emulator {
+    if program.is_null() {
+        return ptr::null_mut();
+    }
+
+    let program = slice::from_raw_parts(program, len);
+
+    match Chip8::new(program, Config::new()) {
+        Ok(chip8) => {
+            let mut emulator = Emulator {
+                chip8,
+                io: FfiIO {
+                    keys: Keypad::new(),
+                    sound: false,
+                },
+                error: None,
+                framebuffer: Vec::new(),
+                size: (0, 0),
+                palette: Vec::new(),
+            };
+            emulator.update_screen();
+
+            Box::into_raw(Box::new(emulator))
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Runs at most `cycles` cycles, stopping early if the program ends or crashes
+/// Returns `CHIP8_RUNNING`, `CHIP8_ENDED`, or `CHIP8_ERROR`. Once the program has crashed, this
+/// always returns `CHIP8_ERROR`.
+///
+/// # Safety
+///
+/// `emulator` must be null, or returned by `chip8_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(emulator: *mut Emulator, cycles: u32) -> c_int {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) => emulator,
+        None => return CHIP8_ERROR,
+    };
+
+    if emulator.error.is_some() {
+        return CHIP8_ERROR;
+    }
+
+    for _ in 0..cycles {
+        if emulator.chip8.program_ended() {
+            break;
+        }
+
+        if let Err(e) = emulator.chip8.cycle(&mut emulator.io) {
+            // Error messages don't contain null bytes, but replace them just in case
+            let message = e.to_string().replace('\0', " ");
+            emulator.error = CString::new(message).ok();
+            emulator.update_screen();
+            return CHIP8_ERROR;
+        }
+    }
+
+    emulator.update_screen();
+
+    if emulator.chip8.program_ended() {
+        CHIP8_ENDED
+    } else {
+        CHIP8_RUNNING
+    }
+}
+
+/// Counts down the timers, which must be done 60 times per second (see `Chip8::tick_60hz`)
+///
+/// # Safety
+///
+/// `emulator` must be null, or returned by `chip8_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick(emulator: *mut Emulator) {
+    if let Some(emulator) = emulator.as_mut() {
+        emulator.chip8.tick_60hz(&mut emulator.io);
+    }
+}
+
+/// Sets the keys that are pressed, where bit N is set if key N is pressed
+/// The keys are read before the next cycle.
+///
+/// # Safety
+///
+/// `emulator` must be null, or returned by `chip8_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_keys(emulator: *mut Emulator, keys: u16) {
+    if let Some(emulator) = emulator.as_mut() {
+        emulator.io.keys = Keypad::from_bits(keys);
+        emulator.chip8.poll_keys = true;
+    }
+}
+
+/// Returns the pixels of the screen as indices into the palette, and writes its width and height to
+/// the pointers if they are not null
+/// Returns null, and writes a size of zero, if `emulator` is null.
+///
+/// # Safety
+///
+/// `emulator` must be null, or returned by `chip8_new` and not freed yet. `width` and `height`
+/// must each be null or valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(emulator: *const Emulator,
+                                           width: *mut usize,
+                                           height: *mut usize)
+                                           -> *const u8 {
+    let emulator = emulator.as_ref();
+    let size = emulator.map_or((0, 0), |e| e.size);
+
+    if let Some(width) = width.as_mut() {
+        *width = size.0;
+    }
+    if let Some(height) = height.as_mut() {
+        *height = size.1;
+    }
+
+    emulator.map_or(ptr::null(), |e| e.framebuffer.as_ptr())
+}
+
+/// Returns the colors of the screen, as three bytes per color (red, green, and blue), and writes
+/// the number of colors to the pointer if it is not null
+/// Returns null, and writes zero colors, if `emulator` is null.
+///
+/// # Safety
+///
+/// `emulator` must be null, or returned by `chip8_new` and not freed yet. `colors` must be null
+/// or valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_palette(emulator: *const Emulator, colors: *mut usize) -> *const u8 {
+    let emulator = emulator.as_ref();
+
+    if let Some(colors) = colors.as_mut() {
+        *colors = emulator.map_or(0, |e| e.palette.len() / 3);
+    }
+
+    emulator.map_or(ptr::null(), |e| e.palette.as_ptr())
+}
+
+/// Returns whether the sound is playing
+///
+/// # Safety
+///
+/// `emulator` must be null, or returned by `chip8_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(emulator: *const Emulator) -> bool {
+    emulator.as_ref().is_some_and(|e| e.io.sound)
+}
+
+/// Returns the message of the error that crashed the program, or null if it didn't crash
+/// The message stays valid until the emulator is freed.
+///
+/// # Safety
+///
+/// `emulator` must be null, or returned by `chip8_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_error(emulator: *const Emulator) -> *const c_char {
+    emulator.as_ref()
+        .and_then(|e| e.error.as_ref())
+        .map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// Frees an emulator created by `chip8_new`
+///
+/// # Safety
+///
+/// `emulator` must be null, or returned by `chip8_new` and not freed yet. It must not be used
+/// after this.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(emulator: *mut Emulator) {
+    if !emulator.is_null() {
+        drop(Box::from_raw(emulator));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    /// Creates an emulator running the program, which must be freed with `chip8_free`
+    fn new(program: &[u8]) -> *mut Emulator {
+        let emulator = unsafe { chip8_new(program.as_ptr(), program.len()) };
+        assert!(!emulator.is_null());
+        emulator
+    }
+
+    #[test]
+    fn test_step_framebuffer() {
+        // Draws the 0 character, then loops forever
+        let emulator = new(&[0xA0, 0x50, 0xD0, 0x05, 0x12, 0x04]);
+        let (mut width, mut height, mut colors) = (0, 0, 0);
+
+        unsafe {
+            assert_eq!(CHIP8_RUNNING, chip8_step(emulator, 10));
+            chip8_tick(emulator);
+
+            let framebuffer = chip8_framebuffer(emulator, &mut width, &mut height);
+            let pixels = slice::from_raw_parts(framebuffer, width * height);
+            let palette = chip8_palette(emulator, &mut colors);
+            let palette = slice::from_raw_parts(palette, colors * 3);
+
+            assert_eq!((SCREEN_WIDTH, SCREEN_HEIGHT), (width, height));
+            assert_eq!(&[1, 1, 1, 1, 0], &pixels[..5]);
+            assert_eq!(::PALETTE_SIZE, colors);
+            assert_eq!(&::Palette::default().colors[1], &palette[3..6]);
+            assert!(!chip8_sound_active(emulator));
+            assert!(chip8_error(emulator).is_null());
+
+            chip8_free(emulator);
+        }
+    }
+
+    #[test]
+    fn test_mega_framebuffer() {
+        // Enters Mega-Chip mode, then loops forever
+        let emulator = new(&[0x00, 0x11, 0x12, 0x02]);
+        let (mut width, mut height) = (0, 0);
+
+        unsafe {
+            assert_eq!(CHIP8_RUNNING, chip8_step(emulator, 2));
+            assert!(!chip8_framebuffer(emulator, &mut width, &mut height).is_null());
+            assert_eq!((MEGA_WIDTH, MEGA_HEIGHT), (width, height));
+
+            chip8_free(emulator);
+        }
+    }
+
+    #[test]
+    fn test_null() {
+        let (mut width, mut height, mut colors) = (1, 1, 1);
+
+        unsafe {
+            assert!(chip8_new(ptr::null(), 0).is_null());
+            assert_eq!(CHIP8_ERROR, chip8_step(ptr::null_mut(), 10));
+            chip8_tick(ptr::null_mut());
+            chip8_set_keys(ptr::null_mut(), 0xFFFF);
+            assert!(chip8_framebuffer(ptr::null(), &mut width, &mut height).is_null());
+            assert_eq!((0, 0), (width, height));
+            assert!(chip8_palette(ptr::null(), &mut colors).is_null());
+            assert_eq!(0, colors);
+            assert!(!chip8_sound_active(ptr::null()));
+            assert!(chip8_error(ptr::null()).is_null());
+            chip8_free(ptr::null_mut());
+
+            // The size pointers can be null too
+            let emulator = new(&[0x12, 0x00]);
+            assert!(!chip8_framebuffer(emulator, ptr::null_mut(), ptr::null_mut()).is_null());
+            assert!(!chip8_palette(emulator, ptr::null_mut()).is_null());
+            chip8_free(emulator);
+        }
+    }
+
+    #[test]
+    fn test_error() {
+        // Returns with nothing on the stack
+        let emulator = new(&[0x00, 0xEE]);
+
+        unsafe {
+            assert_eq!(CHIP8_ERROR, chip8_step(emulator, 10));
+
+            let error = chip8_error(emulator);
+            assert!(!error.is_null());
+            assert!(CStr::from_ptr(error).to_str().unwrap().contains("stack"));
+
+            // The emulator stays crashed
+            assert_eq!(CHIP8_ERROR, chip8_step(emulator, 10));
+
+            chip8_free(emulator);
+        }
+    }
+
+    #[test]
+    fn test_set_keys() {
+        // Loops until key 5 is pressed, then ends
+        let emulator = new(&[0x60, 0x05, 0xE0, 0x9E, 0x12, 0x02, 0x1F, 0xFF]);
+
+        unsafe {
+            // Bit 10 is bit 5 counted from the other end
+            chip8_set_keys(emulator, 1 << 10);
+            assert_eq!(CHIP8_RUNNING, chip8_step(emulator, 10));
+
+            chip8_set_keys(emulator, 1 << 5);
+            chip8_tick(emulator);
+            assert_eq!(CHIP8_ENDED, chip8_step(emulator, 10));
+
+            chip8_free(emulator);
+        }
+    }
+
+    #[test]
+    fn test_set_keys_without_tick() {
+        // Loops until key 5 is pressed, then ends
+        let emulator = new(&[0x60, 0x05, 0xE0, 0x9E, 0x12, 0x02, 0x1F, 0xFF]);
+
+        unsafe {
+            assert_eq!(CHIP8_RUNNING, chip8_step(emulator, 10));
+
+            // The keys are read by the next step, even though no frame passed
+            chip8_set_keys(emulator, 1 << 5);
+            assert_eq!(CHIP8_ENDED, chip8_step(emulator, 10));
+
+            chip8_free(emulator);
+        }
+    }
+}
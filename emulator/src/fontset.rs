@@ -1,7 +1,18 @@
-//! The Chip-8 font set
+//! The Chip-8 font set, and the large digits of SUPER-CHIP
+//!
+//! Both fonts are loaded into the memory below the program, and can be replaced with custom ones
+//! (see `Config::font` and `Config::large_font`).
+
+use errors::*;
 
 /// Where to put the font set in memory
 pub const FONTSET_START: usize = 0x50;
+/// Where to put the large font set in memory
+pub const LARGE_FONTSET_START: usize = 0x110;
+/// The number of bytes of each character of the font set
+pub const GLYPH_SIZE: usize = 5;
+/// The number of bytes of each character of the large font set
+pub const LARGE_GLYPH_SIZE: usize = 10;
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
 /// The Chip-8 font set
@@ -23,3 +34,104 @@ pub const FONTSET: &'static [u8] = &[
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
+
+#[rustfmt::skip]
+/// The large digits of SUPER-CHIP, 8 pixels wide and 10 pixels tall
+pub const LARGE_FONTSET: &[u8] = &[
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C  // 9
+];
+
+/// A font loaded into memory: the sprites of its characters, stored one after another, and the
+/// address they are loaded at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Font {
+    /// The sprites of the characters
+    glyphs: Vec<u8>,
+    /// The address of the first character
+    start: usize,
+}
+
+impl Font {
+    /// Returns a font with the glyphs loaded at the address
+    pub fn new(glyphs: &[u8], start: u16) -> Font {
+        Font {
+            glyphs: glyphs.to_vec(),
+            start: start as usize,
+        }
+    }
+
+    /// Returns the Chip-8 font set
+    pub fn small() -> Font {
+        Font::new(FONTSET, FONTSET_START as u16)
+    }
+
+    /// Returns the large digits of SUPER-CHIP
+    pub fn large() -> Font {
+        Font::new(LARGE_FONTSET, LARGE_FONTSET_START as u16)
+    }
+
+    /// Returns the address of the first character
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the address after the last character
+    pub fn end(&self) -> usize {
+        self.start + self.glyphs.len()
+    }
+
+    /// Returns the number of characters, if each one is `glyph_size` bytes
+    pub fn characters(&self, glyph_size: usize) -> usize {
+        self.glyphs.len() / glyph_size
+    }
+
+    /// Copies the glyphs into memory at the start of the font
+    pub fn load(&self, memory: &mut [u8]) {
+        memory[self.start..self.end()].copy_from_slice(&self.glyphs);
+    }
+}
+
+/// Returns an error if the fonts don't hold the characters they need, or if they overlap each
+/// other or the program
+/// The font must have all 16 characters, and the large font either the 10 digits of SUPER-CHIP or
+/// all 16 characters.
+pub fn check_fonts(font: &Font, large_font: &Font, program_start: usize) -> Result<()> {
+    if font.glyphs.len() != 16 * GLYPH_SIZE {
+        bail!(ErrorKind::InvalidFont(format!("the font must be {} bytes long, not {}",
+                                             16 * GLYPH_SIZE,
+                                             font.glyphs.len())));
+    }
+
+    let large_len = large_font.glyphs.len();
+    if large_len != 10 * LARGE_GLYPH_SIZE && large_len != 16 * LARGE_GLYPH_SIZE {
+        bail!(ErrorKind::InvalidFont(format!("the large font must be {} or {} bytes long, not {}",
+                                             10 * LARGE_GLYPH_SIZE,
+                                             16 * LARGE_GLYPH_SIZE,
+                                             large_len)));
+    }
+
+    for &(name, f) in &[("font", font), ("large font", large_font)] {
+        if f.end() > program_start {
+            bail!(ErrorKind::InvalidFont(format!("the {} ends at 0x{:03X}, after the program \
+                                                  start at 0x{:03X}",
+                                                 name,
+                                                 f.end(),
+                                                 program_start)));
+        }
+    }
+
+    if font.start < large_font.end() && large_font.start < font.end() {
+        bail!(ErrorKind::InvalidFont("the font and the large font overlap".to_string()));
+    }
+
+    Ok(())
+}
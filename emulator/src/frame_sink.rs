@@ -0,0 +1,28 @@
+//! Receivers of the frames displayed by the emulator, independent of the `Chip8IO` used to run it
+//!
+//! Frame sinks are useful for recording, streaming, or analyzing the display without wrapping the
+//! interactive `Chip8IO` implementation.
+
+use io::Palette;
+
+/// A type that receives every frame displayed by the emulator
+/// Frames are sent at 60 hz, when the timers are updated.
+///
+/// Closures taking the same arguments as `frame` implement this trait.
+pub trait FrameSink {
+    /// Receives a frame
+    ///
+    /// - `frame` is the number of the frame (see `Chip8::frames`)
+    ///
+    /// - `planes` and `palette` describe the screen, as in `Chip8IO::draw_planes`
+    fn frame(&mut self, frame: u64, planes: &[u8], palette: &Palette);
+}
+
+impl<F: FnMut(u64, &[u8], &Palette)> FrameSink for F {
+    fn frame(&mut self, frame: u64, planes: &[u8], palette: &Palette) {
+        self(frame, planes, palette)
+    }
+}
+
+/// A boxed frame sink, as stored by the emulator
+pub type BoxedFrameSink = Box<dyn FrameSink + Send>;
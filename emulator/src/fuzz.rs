@@ -0,0 +1,84 @@
+//! Running arbitrary bytes as a program, for fuzzing the emulator
+//!
+//! The emulator never panics while running a program, whatever its bytes are: invalid opcodes,
+//! addresses, and keys are all returned as errors. `run` checks this for an input from a fuzzer,
+//! which also chooses the configuration and the keys that are pressed, so that quirks and the
+//! larger memory sizes are covered too. The `fuzz` directory contains a `cargo fuzz` target that
+//! calls it.
+
+use super::{Chip8, Chip8IO, Keypad};
+use config::{Config, Log, Quirks};
+use errors::*;
+
+/// The number of bytes at the start of an input that choose the configuration and keys
+pub const HEADER_SIZE: usize = 4;
+
+/// An implementation of `Chip8IO` that presses the keys chosen by the fuzzer
+#[derive(Debug)]
+struct FuzzIO {
+    /// The keys that are pressed, as a bitmask
+    keys: u16,
+}
+
+impl Chip8IO for FuzzIO {
+    fn draw(&mut self, _: &[bool]) {}
+    fn get_keys(&mut self) -> Keypad {
+        Keypad::from_bits(self.keys)
+    }
+    fn get_second_keys(&mut self) -> Keypad {
+        self.get_keys()
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// Returns the configuration chosen by the first two bytes of a header
+///
+/// Each bit of the first byte enables a quirk, except the highest, which makes memory 64 KiB
+/// instead of 4 KiB. The lowest bit of the second byte enables CHIP-8X mode, and the next enables
+/// permissive mode.
+fn config(flags: [u8; 2]) -> Config {
+    let bit = |byte: u8, n: u8| byte & 1 << n != 0;
+    let quirks = Quirks {
+        shift: bit(flags[0], 0),
+        memory: bit(flags[0], 1),
+        jump: bit(flags[0], 2),
+        font: bit(flags[0], 3),
+        key_press: bit(flags[0], 4),
+        wrap: bit(flags[0], 5),
+        index_overflow: bit(flags[0], 6),
+    };
+    let memory_size = if bit(flags[0], 7) { 0x10000 } else { ::MEMORY };
+
+    let config = Config::new()
+        .log(Log::Off)
+        .seed(0)
+        .quirks(quirks)
+        .memory_size(memory_size)
+        .chip8x(bit(flags[1], 0));
+
+    if bit(flags[1], 1) {
+        config.permissive(|_| {})
+    } else {
+        config
+    }
+}
+
+/// Runs an input from a fuzzer for at most `cycles` cycles, and returns the number of cycles run
+///
+/// The first `HEADER_SIZE` bytes choose the configuration and the keys that are pressed, and the
+/// rest is the program. Inputs that are too short run an empty program. Returns an error if the
+/// program is too large or crashes, but never panics.
+pub fn run(data: &[u8], cycles: u64) -> Result<u64> {
+    let mut header = [0; HEADER_SIZE];
+    let split = data.len().min(HEADER_SIZE);
+    header[..split].copy_from_slice(&data[..split]);
+
+    let mut chip8 = Chip8::new(&data[split..], config([header[0], header[1]]))?;
+    let mut io = FuzzIO { keys: (header[2] as u16) << 8 | header[3] as u16 };
+
+    chip8.run_for(&mut io, cycles)
+}
@@ -0,0 +1,172 @@
+//! A database of known programs, with the metadata needed to run them correctly
+//!
+//! Most programs don't say which quirks or speed they need, so they often run incorrectly with the
+//! defaults. The database maps programs, identified by the SHA-1 hash of the program file, to
+//! metadata (see `rom::Metadata`) that fills in what is missing. Its text format is a list of
+//! tables named by the hash, each holding metadata in the same format as the metadata of a ROM:
+//!
+//! ```text
+//! # Comments start with a '#'
+//! [da39a3ee5e6b4b0d3255bfef95601890afd80709]
+//! title = "Pong"
+//! speed = 500
+//! quirks = "chip8"
+//! keys = "1 and 4 move the left paddle, C and D move the right paddle"
+//! ```
+//!
+//! A database of programs is built in with the `game_db` feature (see `builtin`). It has no
+//! programs yet, as only programs whose hashes were checked against the program files are added.
+//! Until then, known programs must be added to a database of their own.
+
+use std::collections::BTreeMap;
+
+use errors::*;
+use rom::Metadata;
+use sha1::{self, Digest};
+
+/// The text of the built-in database
+#[cfg(feature = "game_db")]
+const BUILTIN: &str = include_str!("games.toml");
+
+/// A database of known programs
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GameDb {
+    /// The metadata of each program, by the hash of the program
+    games: BTreeMap<Digest, Metadata>,
+}
+
+impl GameDb {
+    /// Returns an empty database
+    pub fn new() -> GameDb {
+        GameDb::default()
+    }
+
+    /// Parses a database from its text representation (see the module documentation for the
+    /// format)
+    pub fn parse(text: &str) -> Result<GameDb> {
+        let mut db = GameDb::new();
+        // The hash of the current table, the line it starts on, and its lines
+        let mut table: Option<(Digest, usize, String)> = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                if let Some(table) = table.take() {
+                    db.add_table(table)?;
+                }
+
+                let name = trimmed[1..trimmed.len() - 1].trim();
+                let digest = Digest::parse(name)
+                    .ok_or_else(|| format!("Invalid hash on line {}: `{}`", i + 1, name))?;
+                table = Some((digest, i + 1, String::new()));
+            } else if let Some((_, _, ref mut lines)) = table {
+                lines.push_str(line);
+                lines.push('\n');
+            } else if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                bail!("Metadata outside of a table on line {}: `{}`", i + 1, trimmed);
+            }
+        }
+
+        if let Some(table) = table {
+            db.add_table(table)?;
+        }
+
+        Ok(db)
+    }
+
+    /// Adds the metadata in a table of the text representation of the database
+    fn add_table(&mut self, (digest, line, text): (Digest, usize, String)) -> Result<()> {
+        let metadata = Metadata::parse(&text)
+            .chain_err(|| format!("Invalid metadata in the table starting on line {}", line))?;
+        self.add(digest, metadata);
+
+        Ok(())
+    }
+
+    /// Adds a program to the database, merging its metadata into any metadata that was already
+    /// added for it
+    pub fn add(&mut self, digest: Digest, metadata: Metadata) {
+        self.games.entry(digest).or_default().merge(metadata);
+    }
+
+    /// Adds the programs of another database to this one, with the metadata of `other` overriding
+    /// the metadata of this database where both set it
+    pub fn merge(&mut self, other: GameDb) {
+        for (digest, metadata) in other.games {
+            self.add(digest, metadata);
+        }
+    }
+
+    /// Returns the metadata of the program, or `None` if it isn't in the database
+    pub fn get(&self, program: &[u8]) -> Option<&Metadata> {
+        self.get_by_hash(&sha1::sha1(program))
+    }
+
+    /// Returns the metadata of the program with the hash, or `None` if it isn't in the database
+    pub fn get_by_hash(&self, digest: &Digest) -> Option<&Metadata> {
+        self.games.get(digest)
+    }
+
+    /// Returns the number of programs in the database
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    /// Returns whether the database has no programs
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+}
+
+/// Returns the built-in database of programs
+/// The database has no programs yet (see the module documentation).
+#[cfg(feature = "game_db")]
+pub fn builtin() -> GameDb {
+    GameDb::parse(BUILTIN).expect("Invalid built-in database")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Quirks;
+
+    #[test]
+    fn test_parse() {
+        let text = "# Known programs\n\n\
+                    [a9993e364706816aba3e25717850c26c9cd0d89d]\ntitle = \"ABC\"\nspeed = 500\n\n\
+                    [DA39A3EE5E6B4B0D3255BFEF95601890AFD80709]\nquirks = \"schip\"\n";
+        let db = GameDb::parse(text).unwrap();
+
+        assert_eq!(2, db.len());
+        assert_eq!(Some("ABC".to_string()), db.get(b"abc").unwrap().title);
+        assert_eq!(Some(500), db.get(b"abc").unwrap().speed);
+        assert_eq!(Some(Quirks::schip()), db.get(b"").unwrap().quirks);
+        assert_eq!(None, db.get(b"abcd"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(GameDb::parse("title = \"ABC\"").is_err());
+        assert!(GameDb::parse("[abc]\ntitle = \"ABC\"").is_err());
+        assert!(GameDb::parse("[a9993e364706816aba3e25717850c26c9cd0d89d]\nspeed = fast").is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut db = GameDb::parse("[a9993e364706816aba3e25717850c26c9cd0d89d]\nspeed = 500\n\
+                                    title = \"ABC\"")
+            .unwrap();
+        db.merge(GameDb::parse("[a9993e364706816aba3e25717850c26c9cd0d89d]\nspeed = 700").unwrap());
+
+        assert_eq!(1, db.len());
+        assert_eq!(Some(700), db.get(b"abc").unwrap().speed);
+        assert_eq!(Some("ABC".to_string()), db.get(b"abc").unwrap().title);
+    }
+
+    #[cfg(feature = "game_db")]
+    #[test]
+    fn test_builtin() {
+        builtin();
+    }
+}
@@ -1,16 +1,25 @@
 //! Representation of a Chip-8 CPU instruction
 
+use std::fmt;
+
+use register::RegisterId;
+
 /// An address in memory
-type Address = u16;
+pub type Address = u16;
 /// A value in memory
-type Number = u8;
+pub type Number = u8;
 /// An ID of a register
-type Register = u8;
+pub type Register = RegisterId;
 
 #[cfg_attr(feature = "clippy", allow(doc_markdown))]
 /// An instruction
 /// For information about the instruction set, see:
 /// https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
+///
+/// Instructions are decoded from opcodes by the functions of `interpreter`. More instructions may
+/// be added as other extensions of the instruction set are supported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Instruction {
     // Flow
     /// Return from subroutine
@@ -20,6 +29,7 @@ pub enum Instruction {
     /// Call the subroutine at the address
     Call(Address),
     /// Goto the address + V0
+    /// With the jump quirk, VX is used instead of V0, where X is the highest nibble of the address
     OffsetGoto(Address),
 
     // Const
@@ -39,10 +49,12 @@ pub enum Instruction {
     BitAnd(Register, Register),
     /// Sets VX to VX ^ VY
     BitXor(Register, Register),
-    /// Shifts VX to the right by one
-    Shr(Register),
-    /// Shifts VX to the left by one
-    Shl(Register),
+    /// Shifts VY to the right by one and stores the result in VX
+    /// With the shift quirk, VX is shifted in place instead
+    Shr(Register, Register),
+    /// Shifts VY to the left by one and stores the result in VX
+    /// With the shift quirk, VX is shifted in place instead
+    Shl(Register, Register),
 
     // Math
     /// Adds VY to VX
@@ -71,9 +83,12 @@ pub enum Instruction {
     SkipNeq(Register, Register),
 
     // MEM
-    /// Writes registers V0 through VX to memory starting at address I
+    /// Writes registers V0 through VX to memory starting at address I, then increments I by X + 1
+    /// With the memory quirk, I is not incremented
     RegDump(Register),
-    /// Loads bytes in memory starting at address I into registers V0 through VX
+    /// Loads bytes in memory starting at address I into registers V0 through VX, then increments I
+    /// by X + 1
+    /// With the memory quirk, I is not incremented
     RegLoad(Register),
     /// Sets I to N
     SetIndex(Address),
@@ -81,6 +96,9 @@ pub enum Instruction {
     AddIndex(Register),
     /// Sets I to the address of the sprite of the character stored in VX
     SetIndexChar(Register),
+    /// Sets I to the address of the sprite of the character stored in VX in the large font
+    /// (SUPER-CHIP)
+    SetIndexLargeChar(Register),
 
     // Timer
     /// Sets VX to the delay timer
@@ -89,7 +107,9 @@ pub enum Instruction {
     SetDelay(Register),
 
     // KeyOp
-    /// Blocks until a key is pressed, then stores it in VX
+    /// Waits until a key is pressed, then stores it in VX
+    /// The instruction is run again each cycle until a key that wasn't already pressed is pressed,
+    /// and without the key press quirk, until that key is released
     WaitKey(Register),
     /// Skips the next instruction if the key in VX is pressed
     SkipKey(Register),
@@ -99,11 +119,333 @@ pub enum Instruction {
     // Sound
     /// Sets the sound timer to VX
     SetSound(Register),
+    /// Loads the 16 byte audio pattern played while the sound timer runs from memory starting at
+    /// address I (XO-CHIP)
+    LoadAudio,
+    /// Sets the pitch the audio pattern is played at to VX (XO-CHIP)
+    SetPitch(Register),
 
     // Disp
     /// Loads a sprite that is 8 pixels wide and N pixels tall from memory starting at address I,
     /// and draws it at coordinate (VX, VY)
     Draw(Register, Register, Number),
-    /// Clears the screen
+    /// Clears the selected planes of the screen
     ClearScreen,
+    /// Selects the planes affected by `Draw` and `ClearScreen`, where N is a bitmask of the planes
+    /// (XO-CHIP)
+    SelectPlanes(Number),
+
+    // Mega-Chip
+    /// Enters Mega-Chip mode, where the 256x192 color screen is used
+    MegaOn,
+    /// Leaves Mega-Chip mode
+    MegaOff,
+    /// Sets I to the 24 bit address made of N and the word following the instruction, and skips
+    /// that word
+    SetIndexLong(Number),
+    /// Loads N colors, stored as 4 bytes of alpha, red, green, and blue, from memory starting at
+    /// address I into the palette, starting at index 1
+    LoadPalette(Number),
+    /// Sets the width of the sprites drawn in Mega-Chip mode to N, where 0 is 256
+    SpriteWidth(Number),
+    /// Sets the height of the sprites drawn in Mega-Chip mode to N, where 0 is 256
+    SpriteHeight(Number),
+    /// Sets the alpha of the screen to N (not emulated)
+    SetAlpha(Number),
+    /// Plays the digitized sound at address I, looping if N is 0 (not emulated)
+    PlaySample(Number),
+    /// Stops the digitized sound (not emulated)
+    StopSample,
+    /// Sets how sprites are blended with the screen to N (not emulated, sprites are always drawn
+    /// over the screen)
+    SetBlendMode(Number),
+    /// Sets the color index that causes a collision when drawn over to N
+    SetCollisionColor(Number),
+    /// Scrolls the screen up by N pixels
+    ScrollUp(Number),
+
+    // CHIP-8X
+    /// Steps the background color through blue, black, green, and red
+    StepBackground,
+    /// Adds each nibble of VY to the same nibble of VX, keeping the lowest 3 bits of each
+    AddNibbles(Register, Register),
+    /// Sets the foreground color of the zones of the screen to VY, where the nibbles of VX are the
+    /// first and last column of zones, and the nibbles of VX+1 are the first and last row
+    SetColorZones(Register, Register),
+    /// Sets the foreground color of the zone at the X coordinate VX to VY, for N rows of pixels
+    /// starting at VX+1
+    SetColorRows(Register, Register, Number),
+    /// Skips the next instruction if the key in VX is pressed on the second keypad
+    SkipKey2(Register),
+    /// Skips the next instruction if the key in VX is not pressed on the second keypad
+    SkipNotKey2(Register),
+}
+
+impl Instruction {
+    /// Returns the name of the instruction, without its operands
+    pub fn name(&self) -> &'static str {
+        use self::Instruction::*;
+
+        match *self {
+            Return => "Return",
+            Goto(..) => "Goto",
+            Call(..) => "Call",
+            OffsetGoto(..) => "OffsetGoto",
+            SetConst(..) => "SetConst",
+            AddConst(..) => "AddConst",
+            Move(..) => "Move",
+            BitOr(..) => "BitOr",
+            BitAnd(..) => "BitAnd",
+            BitXor(..) => "BitXor",
+            Shr(..) => "Shr",
+            Shl(..) => "Shl",
+            Add(..) => "Add",
+            Sub(..) => "Sub",
+            InverseSub(..) => "InverseSub",
+            Rand(..) => "Rand",
+            BCD(..) => "BCD",
+            SkipEqConst(..) => "SkipEqConst",
+            SkipNeqConst(..) => "SkipNeqConst",
+            SkipEq(..) => "SkipEq",
+            SkipNeq(..) => "SkipNeq",
+            RegDump(..) => "RegDump",
+            RegLoad(..) => "RegLoad",
+            SetIndex(..) => "SetIndex",
+            AddIndex(..) => "AddIndex",
+            SetIndexChar(..) => "SetIndexChar",
+            SetIndexLargeChar(..) => "SetIndexLargeChar",
+            GetDelay(..) => "GetDelay",
+            SetDelay(..) => "SetDelay",
+            WaitKey(..) => "WaitKey",
+            SkipKey(..) => "SkipKey",
+            SkipNotKey(..) => "SkipNotKey",
+            SetSound(..) => "SetSound",
+            LoadAudio => "LoadAudio",
+            SetPitch(..) => "SetPitch",
+            Draw(..) => "Draw",
+            ClearScreen => "ClearScreen",
+            SelectPlanes(..) => "SelectPlanes",
+            MegaOn => "MegaOn",
+            MegaOff => "MegaOff",
+            SetIndexLong(..) => "SetIndexLong",
+            LoadPalette(..) => "LoadPalette",
+            SpriteWidth(..) => "SpriteWidth",
+            SpriteHeight(..) => "SpriteHeight",
+            SetAlpha(..) => "SetAlpha",
+            PlaySample(..) => "PlaySample",
+            StopSample => "StopSample",
+            SetBlendMode(..) => "SetBlendMode",
+            SetCollisionColor(..) => "SetCollisionColor",
+            ScrollUp(..) => "ScrollUp",
+            StepBackground => "StepBackground",
+            AddNibbles(..) => "AddNibbles",
+            SetColorZones(..) => "SetColorZones",
+            SetColorRows(..) => "SetColorRows",
+            SkipKey2(..) => "SkipKey2",
+            SkipNotKey2(..) => "SkipNotKey2",
+        }
+    }
+
+    /// Returns whether the instruction reads the keys, on either keypad
+    pub fn reads_keys(&self) -> bool {
+        use self::Instruction::*;
+
+        matches!(*self, WaitKey(_) | SkipKey(_) | SkipNotKey(_) | SkipKey2(_) | SkipNotKey2(_))
+    }
+
+    /// Returns the opcode of the instruction, which decodes back to it
+    ///
+    /// CHIP-8X instructions decode back with `interpret_chip8x_instruction`, and the others with
+    /// `interpret_instruction`. `SetIndexLong` is followed by the low word of its address, which
+    /// isn't part of the opcode. Operands too large for their place in the opcode are truncated,
+    /// so addresses keep their lowest 12 bits and the operands of `Draw`, `SelectPlanes`,
+    /// `PlaySample`, `SetBlendMode`, `ScrollUp`, and `SetColorRows` their lowest nibble.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use chip8::RegisterId;
+    /// use chip8::instruction::Instruction;
+    ///
+    /// let (x, y) = (RegisterId::new(1).unwrap(), RegisterId::new(2).unwrap());
+    /// assert_eq!(0xD125, Instruction::Draw(x, y, 5).encode());
+    /// ```
+    pub fn encode(&self) -> u16 {
+        use self::Instruction::*;
+
+        // Builds an opcode from its nibbles, with the operands in the last three
+        let op = |a: u16, x: RegisterId, y: RegisterId, n: u8| {
+            a << 12 | (x.index() as u16) << 8 | (y.index() as u16) << 4 | (n & 0xF) as u16
+        };
+        let xnn = |a: u16, x: RegisterId, n: u8| a << 12 | (x.index() as u16) << 8 | n as u16;
+        let fx = |x: RegisterId, n: u16| 0xF000 | (x.index() as u16) << 8 | n;
+        let ex = |x: RegisterId, n: u16| 0xE000 | (x.index() as u16) << 8 | n;
+        let nnn = |a: u16, addr: Address| a << 12 | addr & 0xFFF;
+
+        match *self {
+            Return => 0x00EE,
+            Goto(addr) => nnn(0x1, addr),
+            Call(addr) => nnn(0x2, addr),
+            OffsetGoto(addr) => nnn(0xB, addr),
+            SetConst(x, n) => xnn(0x6, x, n),
+            AddConst(x, n) => xnn(0x7, x, n),
+            Move(x, y) => op(0x8, x, y, 0x0),
+            BitOr(x, y) => op(0x8, x, y, 0x1),
+            BitAnd(x, y) => op(0x8, x, y, 0x2),
+            BitXor(x, y) => op(0x8, x, y, 0x3),
+            Shr(x, y) => op(0x8, x, y, 0x6),
+            Shl(x, y) => op(0x8, x, y, 0xE),
+            Add(x, y) => op(0x8, x, y, 0x4),
+            Sub(x, y) => op(0x8, x, y, 0x5),
+            InverseSub(x, y) => op(0x8, x, y, 0x7),
+            Rand(x, n) => xnn(0xC, x, n),
+            BCD(x) => fx(x, 0x33),
+            SkipEqConst(x, n) => xnn(0x3, x, n),
+            SkipNeqConst(x, n) => xnn(0x4, x, n),
+            SkipEq(x, y) => op(0x5, x, y, 0x0),
+            SkipNeq(x, y) => op(0x9, x, y, 0x0),
+            RegDump(x) => fx(x, 0x55),
+            RegLoad(x) => fx(x, 0x65),
+            SetIndex(addr) => nnn(0xA, addr),
+            AddIndex(x) => fx(x, 0x1E),
+            SetIndexChar(x) => fx(x, 0x29),
+            SetIndexLargeChar(x) => fx(x, 0x30),
+            GetDelay(x) => fx(x, 0x07),
+            SetDelay(x) => fx(x, 0x15),
+            WaitKey(x) => fx(x, 0x0A),
+            SkipKey(x) => ex(x, 0x9E),
+            SkipNotKey(x) => ex(x, 0xA1),
+            SetSound(x) => fx(x, 0x18),
+            LoadAudio => 0xF002,
+            SetPitch(x) => fx(x, 0x3A),
+            Draw(x, y, n) => op(0xD, x, y, n),
+            ClearScreen => 0x00E0,
+            SelectPlanes(n) => 0xF001 | ((n & 0xF) as u16) << 8,
+            MegaOn => 0x0011,
+            MegaOff => 0x0010,
+            SetIndexLong(n) => 0x0100 | n as u16,
+            LoadPalette(n) => 0x0200 | n as u16,
+            SpriteWidth(n) => 0x0300 | n as u16,
+            SpriteHeight(n) => 0x0400 | n as u16,
+            SetAlpha(n) => 0x0500 | n as u16,
+            PlaySample(n) => 0x0600 | (n & 0xF) as u16,
+            StopSample => 0x0700,
+            SetBlendMode(n) => 0x0800 | (n & 0xF) as u16,
+            SetCollisionColor(n) => 0x0900 | n as u16,
+            ScrollUp(n) => 0x00B0 | (n & 0xF) as u16,
+            StepBackground => 0x02A0,
+            AddNibbles(x, y) => op(0x5, x, y, 0x1),
+            SetColorZones(x, y) => op(0xB, x, y, 0x0),
+            SetColorRows(x, y, n) => op(0xB, x, y, n),
+            SkipKey2(x) => ex(x, 0xF2),
+            SkipNotKey2(x) => ex(x, 0xF5),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Writes the instruction in assembly language, using the common Chip-8 mnemonics, for example
+    /// `LD V1, 0xFF` or `DRW V0, V1, 5`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+
+        match *self {
+            Return => write!(f, "RET"),
+            Goto(addr) => write!(f, "JP 0x{:03X}", addr),
+            Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            OffsetGoto(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            SetConst(x, n) => write!(f, "LD V{:X}, 0x{:02X}", x, n),
+            AddConst(x, n) => write!(f, "ADD V{:X}, 0x{:02X}", x, n),
+            Move(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            BitOr(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            BitAnd(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            BitXor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            InverseSub(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Rand(x, n) => write!(f, "RND V{:X}, 0x{:02X}", x, n),
+            BCD(x) => write!(f, "LD B, V{:X}", x),
+            SkipEqConst(x, n) => write!(f, "SE V{:X}, 0x{:02X}", x, n),
+            SkipNeqConst(x, n) => write!(f, "SNE V{:X}, 0x{:02X}", x, n),
+            SkipEq(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            SkipNeq(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            RegDump(x) => write!(f, "LD [I], V{:X}", x),
+            RegLoad(x) => write!(f, "LD V{:X}, [I]", x),
+            SetIndex(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            AddIndex(x) => write!(f, "ADD I, V{:X}", x),
+            SetIndexChar(x) => write!(f, "LD F, V{:X}", x),
+            SetIndexLargeChar(x) => write!(f, "LD HF, V{:X}", x),
+            GetDelay(x) => write!(f, "LD V{:X}, DT", x),
+            SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            SkipKey(x) => write!(f, "SKP V{:X}", x),
+            SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            LoadAudio => write!(f, "AUDIO"),
+            SetPitch(x) => write!(f, "PITCH V{:X}", x),
+            Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            ClearScreen => write!(f, "CLS"),
+            SelectPlanes(n) => write!(f, "PLANE {}", n),
+            MegaOn => write!(f, "MEGAON"),
+            MegaOff => write!(f, "MEGAOFF"),
+            SetIndexLong(n) => write!(f, "LDHI 0x{:02X}", n),
+            LoadPalette(n) => write!(f, "LDPAL 0x{:02X}", n),
+            SpriteWidth(n) => write!(f, "SPRW 0x{:02X}", n),
+            SpriteHeight(n) => write!(f, "SPRH 0x{:02X}", n),
+            SetAlpha(n) => write!(f, "ALPHA 0x{:02X}", n),
+            PlaySample(n) => write!(f, "DIGISND {}", n),
+            StopSample => write!(f, "STOPSND"),
+            SetBlendMode(n) => write!(f, "BMODE {}", n),
+            SetCollisionColor(n) => write!(f, "CCOL 0x{:02X}", n),
+            ScrollUp(n) => write!(f, "SCRU {}", n),
+            StepBackground => write!(f, "BGSTEP"),
+            AddNibbles(x, y) => write!(f, "ADDN V{:X}, V{:X}", x, y),
+            SetColorZones(x, y) => write!(f, "COLOR V{:X}, V{:X}", x, y),
+            SetColorRows(x, y, n) => write!(f, "COLOR V{:X}, V{:X}, {}", x, y, n),
+            SkipKey2(x) => write!(f, "SKP2 V{:X}", x),
+            SkipNotKey2(x) => write!(f, "SKNP2 V{:X}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use interpreter::{interpret_instruction, interpret_chip8x_instruction};
+
+    #[test]
+    fn test_encode_round_trip() {
+        for opcode in 0..=0xFFFF {
+            if let Ok(instruction) = interpret_instruction(opcode) {
+                assert_eq!(opcode, instruction.encode(), "{:?}", instruction);
+            }
+            if let Ok(instruction) = interpret_chip8x_instruction(opcode) {
+                assert_eq!(opcode, instruction.encode(), "{:?}", instruction);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_truncates() {
+        use super::Instruction::*;
+        use register::RegisterId;
+
+        let x = RegisterId::from_nibble(1);
+        assert_eq!(0x1234, Goto(0xF234).encode());
+        assert_eq!(0xD115, Draw(x, x, 0x25).encode());
+        assert_eq!(0xF301, SelectPlanes(0x13).encode());
+    }
+
+    #[test]
+    fn test_display() {
+        use super::Instruction::*;
+        use register::RegisterId;
+
+        let (x, y) = (RegisterId::from_nibble(0), RegisterId::from_nibble(1));
+        assert_eq!("LD V1, 0xFF", SetConst(y, 0xFF).to_string());
+        assert_eq!("DRW V0, V1, 5", Draw(x, y, 5).to_string());
+        assert_eq!("JP 0x2A0", Goto(0x2A0).to_string());
+        assert_eq!("CLS", ClearScreen.to_string());
+    }
 }
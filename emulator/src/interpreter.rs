@@ -3,6 +3,7 @@
 use errors::*;
 
 use instruction::Instruction;
+use register::RegisterId;
 
 /// A helper macro to shorten the creation of instructions
 ///
@@ -10,7 +11,7 @@ use instruction::Instruction;
 ///
 /// ```rust
 /// assert_eq!(
-///     Instruction::SetConst(nibble(0xABCD, 1), nibbles(0xABCD, 2, 3)),
+///     Instruction::SetConst(RegisterId::from_nibble(0xB), nibbles(0xABCD, 2, 3)),
 ///     instruction!(0xABCD, SetConst(1, [2, 3]))
 /// );
 /// ```
@@ -19,9 +20,10 @@ macro_rules! instruction {
     (FIELD, $opcode:expr, [$start:expr, $end:expr]) => {{
         nibbles($opcode, $start, $end)
     }};
-    // Helper; matches a nibble index
+    // Helper; matches a nibble index, which becomes a register ID or a number depending on the
+    // field
     (FIELD, $opcode:expr, $index:tt) => {{
-        nibble($opcode, $index)
+        FromNibble::from_nibble(nibble($opcode, $index))
     }};
     // Actual macro
     ($opcode:expr, $variant:ident($($field:tt),+)) => {{
@@ -29,9 +31,37 @@ macro_rules! instruction {
     }};
 }
 
+/// A field of an instruction that can be decoded from a single nibble
+trait FromNibble {
+    fn from_nibble(nibble: u8) -> Self;
+}
+
+impl FromNibble for u8 {
+    fn from_nibble(nibble: u8) -> u8 {
+        nibble
+    }
+}
+
+impl FromNibble for RegisterId {
+    fn from_nibble(nibble: u8) -> RegisterId {
+        RegisterId::from_nibble(nibble)
+    }
+}
+
 /// Returns an instruction, interpreted from an opcode
 /// Returns an error if the opcode is not a valid instruction
-
+///
+/// # Examples
+///
+/// ```rust
+/// use chip8::RegisterId;
+/// use chip8::instruction::Instruction;
+/// use chip8::interpreter::interpret_instruction;
+///
+/// let register = RegisterId::new(0xA).unwrap();
+/// assert_eq!(Instruction::SetConst(register, 0xFF), interpret_instruction(0x6AFF).unwrap());
+/// assert!(interpret_instruction(0x5121).is_err());
+/// ```
 // Prevent rustfmt from ruining the formatting of the match arms
 #[cfg_attr(rustfmt, rustfmt_skip)]
 pub fn interpret_instruction(opcode: u16) -> Result<Instruction> {
@@ -61,8 +91,8 @@ pub fn interpret_instruction(opcode: u16) -> Result<Instruction> {
         (0x8, .., 0x1)       => instruction!(opcode, BitOr(1, 2)),
         (0x8, .., 0x2)       => instruction!(opcode, BitAnd(1, 2)),
         (0x8, .., 0x3)       => instruction!(opcode, BitXor(1, 2)),
-        (0x8, .., 0x6)       => instruction!(opcode, Shr(1)),
-        (0x8, .., 0xE)       => instruction!(opcode, Shl(1)),
+        (0x8, .., 0x6)       => instruction!(opcode, Shr(1, 2)),
+        (0x8, .., 0xE)       => instruction!(opcode, Shl(1, 2)),
 
         // Math
         (0x8, .., 0x4)       => instruction!(opcode, Add(1, 2)),
@@ -87,6 +117,7 @@ pub fn interpret_instruction(opcode: u16) -> Result<Instruction> {
         (0xA, ..)            =>                      SetIndex(opcode & 0x0FFF),
         (0xF, _, 0x1, 0xE)   => instruction!(opcode, AddIndex(1)),
         (0xF, _, 0x2, 0x9)   => instruction!(opcode, SetIndexChar(1)),
+        (0xF, _, 0x3, 0x0)   => instruction!(opcode, SetIndexLargeChar(1)),
 
         // Timer
         (0xF, _, 0x0, 0x7)   => instruction!(opcode, GetDelay(1)),
@@ -99,13 +130,53 @@ pub fn interpret_instruction(opcode: u16) -> Result<Instruction> {
 
         // Sound
         (0xF, _, 0x1, 0x8)   => instruction!(opcode, SetSound(1)),
+        (0xF, 0x0, 0x0, 0x2) =>                      LoadAudio,
+        (0xF, _, 0x3, 0xA)   => instruction!(opcode, SetPitch(1)),
 
 
         (0xD, ..)            => instruction!(opcode, Draw(1, 2, 3)),
         (0x0, 0x0, 0xE, 0x0) =>                      ClearScreen,
+        (0xF, _, 0x0, 0x1)   => instruction!(opcode, SelectPlanes(1)),
+
+        // Mega-Chip
+        (0x0, 0x0, 0x1, 0x1) =>                      MegaOn,
+        (0x0, 0x0, 0x1, 0x0) =>                      MegaOff,
+        (0x0, 0x1, ..)       => instruction!(opcode, SetIndexLong([2, 3])),
+        (0x0, 0x2, ..)       => instruction!(opcode, LoadPalette([2, 3])),
+        (0x0, 0x3, ..)       => instruction!(opcode, SpriteWidth([2, 3])),
+        (0x0, 0x4, ..)       => instruction!(opcode, SpriteHeight([2, 3])),
+        (0x0, 0x5, ..)       => instruction!(opcode, SetAlpha([2, 3])),
+        (0x0, 0x6, 0x0, _)   => instruction!(opcode, PlaySample(3)),
+        (0x0, 0x7, 0x0, 0x0) =>                      StopSample,
+        (0x0, 0x8, 0x0, _)   => instruction!(opcode, SetBlendMode(3)),
+        (0x0, 0x9, ..)       => instruction!(opcode, SetCollisionColor([2, 3])),
+        (0x0, 0x0, 0xB, _)   => instruction!(opcode, ScrollUp(3)),
 
         // Invalid instruction
-        _ => bail!(ErrorKind::InvalidOpcode(format!("0x{:04X}", opcode))),
+        _ => bail!(ErrorKind::UnknownOpcode(opcode)),
+    };
+
+    Ok(instruction)
+}
+
+/// Returns an instruction, interpreted from an opcode of a CHIP-8X program
+/// CHIP-8X replaces `OffsetGoto` with instructions that color the screen, and adds instructions
+/// for the second keypad. Other opcodes are interpreted like with `interpret_instruction`.
+#[rustfmt::skip]
+pub fn interpret_chip8x_instruction(opcode: u16) -> Result<Instruction> {
+    use instruction::Instruction::*;
+
+    let instruction = match (nibble(opcode, 0),
+                             nibble(opcode, 1),
+                             nibble(opcode, 2),
+                             nibble(opcode, 3)) {
+        (0x0, 0x2, 0xA, 0x0) =>                      StepBackground,
+        (0x5, .., 0x1)       => instruction!(opcode, AddNibbles(1, 2)),
+        (0xB, .., 0x0)       => instruction!(opcode, SetColorZones(1, 2)),
+        (0xB, ..)            => instruction!(opcode, SetColorRows(1, 2, 3)),
+        (0xE, _, 0xF, 0x2)   => instruction!(opcode, SkipKey2(1)),
+        (0xE, _, 0xF, 0x5)   => instruction!(opcode, SkipNotKey2(1)),
+        _ => return interpret_instruction(opcode),
     };
 
     Ok(instruction)
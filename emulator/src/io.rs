@@ -1,54 +1,407 @@
 //! Storage of I/O state
 
+use std::borrow::Cow;
 use std::fmt;
+use std::ops::Range;
 
+use chip8x::{self, ColorBoard};
+use megachip::MegaScreen;
 use super::{SCREEN_WIDTH, SCREEN_HEIGHT};
 
 /// The amount of pixels in the display
 pub const PIXELS: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+/// The amount of display planes (XO-CHIP has two, plain Chip-8 only uses the first one)
+pub const PLANES: usize = 2;
+/// The number of colors in a palette
+pub const PALETTE_SIZE: usize = 256;
+/// The length of an XO-CHIP audio pattern, in bytes
+pub const AUDIO_PATTERN_LEN: usize = 16;
+/// The pitch audio patterns are played at until the program sets one, which is a rate of 4000 hz
+pub const DEFAULT_PITCH: u8 = 64;
+/// The width and height of the display in hi-res mode
+pub const HIRES_SIZE: usize = 64;
+
+/// A row of a plane of the display, with a bit for each pixel, where the leftmost pixel is the
+/// highest bit
+/// This relies on the display being 128 pixels wide.
+type Row = u128;
 
 /// I/O state, including graphics, sound, and keyboard input
+#[derive(Clone)]
 pub struct Io {
-    /// The pixels of the display
+    /// The index into the palette of each pixel of the display
+    /// For programs that draw to planes, bit N is set if the pixel is on in plane N.
+    planes: [u8; PIXELS],
+    /// The pixels of the display, where a pixel is on if it is on in any plane
     pixels: [bool; PIXELS],
+    /// The rows of each plane of the display, packed into bits for drawing sprites a byte at a time
+    rows: [[Row; SCREEN_HEIGHT]; PLANES],
+    /// A bitmask of the planes affected by drawing and clearing the screen
+    selected_planes: u8,
+    /// How programs address the pixels of the display
+    mode: DisplayMode,
     /// Whether the pixels should be drawn
     draw_flag: bool,
     /// Keys being pressed
-    keys: Keys,
+    keys: Keypad,
+    /// Keys being pressed on the second keypad (CHIP-8X)
+    second_keys: Keypad,
+    /// The colors of the screen of a CHIP-8X program, or `None` for other programs
+    color_board: Option<ColorBoard>,
+    /// The Mega-Chip screen, or `None` if the program isn't in Mega-Chip mode
+    mega: Option<MegaScreen>,
 }
 
 impl fmt::Debug for Io {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.draw_flag.fmt(f)?;
         self.keys.fmt(f)?;
-        self.pixels.fmt(f)?;
+        self.selected_planes.fmt(f)?;
+        self.planes.fmt(f)?;
 
         Ok(())
     }
 }
 
-/// The state of keyboard input
+/// The state of keyboard input, as it was represented before `Keypad`
+#[deprecated(note = "use `Keypad`, which converts from and to `[bool; 16]`")]
 pub type Keys = [bool; 16];
 
+/// The state of a keypad, where bit N is set while key N is pressed
+///
+/// Keypads convert from and to arrays of 16 `bool`s, which are `true` for the keys that are
+/// pressed.
+///
+/// ```rust
+/// use chip8::Keypad;
+///
+/// let mut keys = Keypad::new();
+/// keys.set(0xA, true);
+///
+/// assert!(keys.get(0xA));
+/// assert_eq!(1 << 0xA, keys.bits());
+///
+/// let mut array = [false; 16];
+/// array[0xA] = true;
+/// assert_eq!(keys, Keypad::from(array));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Keypad(u16);
+
+impl Keypad {
+    /// Returns a keypad with no keys pressed
+    pub fn new() -> Keypad {
+        Keypad(0)
+    }
+
+    /// Returns a keypad with the keys pressed whose bits are set
+    pub fn from_bits(bits: u16) -> Keypad {
+        Keypad(bits)
+    }
+
+    /// Returns the bitmask of the keys that are pressed
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Returns whether the key is pressed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is over 0xF.
+    pub fn get(self, key: u8) -> bool {
+        assert!(key < 16, "Not a key of the keypad: {}", key);
+        self.0 & 1 << key != 0
+    }
+
+    /// Sets whether the key is pressed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is over 0xF.
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        assert!(key < 16, "Not a key of the keypad: {}", key);
+
+        if pressed {
+            self.0 |= 1 << key;
+        } else {
+            self.0 &= !(1 << key);
+        }
+    }
+
+    /// Returns whether any key is pressed
+    pub fn any(self) -> bool {
+        self.0 != 0
+    }
+
+    /// Returns an iterator over whether each key is pressed, from key 0 to key F
+    pub fn iter(self) -> impl Iterator<Item = bool> {
+        (0..16).map(move |key| self.get(key))
+    }
+}
+
+impl From<[bool; 16]> for Keypad {
+    fn from(keys: [bool; 16]) -> Keypad {
+        let mut keypad = Keypad::new();
+        for (key, &pressed) in (0..16).zip(&keys) {
+            keypad.set(key, pressed);
+        }
+        keypad
+    }
+}
+
+impl From<Keypad> for [bool; 16] {
+    fn from(keypad: Keypad) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for (key, pressed) in keys.iter_mut().zip(keypad.iter()) {
+            *key = pressed;
+        }
+        keys
+    }
+}
+
+/// How programs address the pixels of the display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayMode {
+    /// Programs draw to every pixel of the display
+    Normal,
+    /// Programs draw to a 64x64 display, used by hi-res Chip-8 programs (see
+    /// `Chip8::display_mode`)
+    /// Each pixel the program draws covers two pixels of the display, side by side.
+    HiRes,
+}
+
+/// A color, stored as red, green, and blue components
+pub type Color = [u8; 3];
+
+/// The colors used to display the screen
+///
+/// Each pixel is displayed using the color at its index into the palette. For programs that draw
+/// to planes (Chip-8, SUPER-CHIP, and XO-CHIP), the index is the bitmask of the planes the pixel is
+/// on in, so `colors[0]` is the background, `colors[1]` and `colors[2]` are the colors of the first
+/// and second planes, and `colors[3]` is used where the planes overlap. The other colors are used
+/// by programs that set the index of each pixel directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    #[allow(missing_docs)]
+    pub colors: [Color; PALETTE_SIZE],
+}
+
+/// A sprite drawn by the `Draw` instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    /// The X coordinate of the top left corner of the sprite
+    pub x: u8,
+    /// The Y coordinate of the top left corner of the sprite
+    pub y: u8,
+    /// The height of the sprite, in pixels (the width is always 8)
+    pub height: u8,
+    /// The address of the sprite data in memory
+    pub address: u16,
+    /// A bitmask of the planes the sprite was drawn to
+    pub planes: u8,
+    /// Whether drawing the sprite turned off any pixels (the value VF was set to)
+    pub collision: bool,
+}
+
+/// An implementation of `Chip8IO` without a display or sound, for running programs headless
+/// No keys are pressed, except while the program is waiting for one.
+pub(crate) struct HeadlessIO {
+    /// Whether every key is pressed
+    pressed: bool,
+}
+
+impl HeadlessIO {
+    pub fn new() -> HeadlessIO {
+        HeadlessIO { pressed: false }
+    }
+
+    /// Must be called before each cycle with whether the program is waiting for a key (see
+    /// `Chip8::is_waiting_for_key`)
+    /// While it is, the keys are pressed and released in turn, so a new key press is seen.
+    pub fn next_cycle(&mut self, waiting_for_key: bool) {
+        self.pressed = waiting_for_key && !self.pressed;
+    }
+}
+
+impl ::Chip8IO for HeadlessIO {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_planes(&mut self, _: &[u8], _: &Palette) {}
+    fn get_keys(&mut self) -> Keypad {
+        Keypad::from([self.pressed; 16])
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// An implementation of `Chip8IO` without a display or sound, that only provides the keys set by
+/// its owner
+#[derive(Debug)]
+pub(crate) struct KeysIO {
+    /// The keys that are pressed
+    pub keys: Keypad,
+}
+
+impl KeysIO {
+    pub fn new() -> KeysIO {
+        KeysIO { keys: Keypad::new() }
+    }
+}
+
+impl ::Chip8IO for KeysIO {
+    fn draw(&mut self, _: &[bool]) {}
+    fn get_keys(&mut self) -> Keypad {
+        self.keys
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// Returns the rate, in samples per second, that an audio pattern is played at with the pitch
+/// The default pitch plays at 4000 hz, and every 48 steps of pitch doubles or halves the rate.
+pub fn audio_sample_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - DEFAULT_PITCH as f64) / 48.0)
+}
+
+/// Returns the byte with each bit repeated twice, for drawing sprites in hi-res mode
+fn double_bits(byte: u8) -> u16 {
+    let mut bits = byte as u16;
+    bits = (bits | bits << 4) & 0x0F0F;
+    bits = (bits | bits << 2) & 0x3333;
+    bits = (bits | bits << 1) & 0x5555;
+
+    bits | bits << 1
+}
+
+impl Palette {
+    /// Returns a palette that starts with the colors, followed by black
+    /// Panics if there are more than `PALETTE_SIZE` colors.
+    pub fn new(colors: &[Color]) -> Palette {
+        assert!(colors.len() <= PALETTE_SIZE, "Too many colors for a palette");
+
+        let mut palette = Palette { colors: [[0; 3]; PALETTE_SIZE] };
+        palette.colors[..colors.len()].copy_from_slice(colors);
+        palette
+    }
+}
+
+impl Default for Palette {
+    /// Returns a palette with a black background, white first plane, and gray second plane
+    fn default() -> Palette {
+        Palette::new(&[[0x00; 3], [0xFF; 3], [0xAA; 3], [0x55; 3]])
+    }
+}
+
 impl Io {
     /// Initializes and returns the I/O state
     pub fn new() -> Io {
         Io {
+            planes: [0; PIXELS],
             pixels: [false; PIXELS],
+            rows: [[0; SCREEN_HEIGHT]; PLANES],
+            selected_planes: 1,
+            mode: DisplayMode::Normal,
             draw_flag: true,
-            keys: [false; 16],
+            keys: Keypad::new(),
+            second_keys: Keypad::new(),
+            color_board: None,
+            mega: None,
         }
     }
 
-    /// Clears the screen
+    /// Clears the selected planes of the screen
     pub fn clear_screen(&mut self) {
-        self.pixels = [false; PIXELS];
+        let mask = !self.selected_planes;
+
+        for (plane, pixel) in self.planes.iter_mut().zip(self.pixels.iter_mut()) {
+            *plane &= mask;
+            *pixel = *plane != 0;
+        }
+
+        for (p, rows) in self.rows.iter_mut().enumerate() {
+            if self.selected_planes & 1 << p != 0 {
+                *rows = [0; SCREEN_HEIGHT];
+            }
+        }
+
+        self.set_draw_flag();
+    }
+
+    /// Scrolls the selected planes of the screen up by the number of pixels
+    pub fn scroll_up(&mut self, rows: usize) {
+        let rows = rows.min(SCREEN_HEIGHT);
+
+        for i in 0..PIXELS {
+            let below = i + rows * SCREEN_WIDTH;
+            let moved = if below < PIXELS { self.planes[below] } else { 0 };
+            let plane = (self.planes[i] & !self.selected_planes) | (moved & self.selected_planes);
+
+            self.planes[i] = plane;
+            self.pixels[i] = plane != 0;
+        }
+
+        for (p, plane_rows) in self.rows.iter_mut().enumerate() {
+            if self.selected_planes & 1 << p != 0 {
+                for y in 0..SCREEN_HEIGHT {
+                    plane_rows[y] = plane_rows.get(y + rows).cloned().unwrap_or(0);
+                }
+            }
+        }
+
         self.set_draw_flag();
     }
 
+    /// Colors the screen with the CHIP-8X color board
+    pub fn enable_color_board(&mut self) {
+        self.color_board = Some(ColorBoard::new());
+    }
+
+    /// Returns the CHIP-8X color board, or `None` if the screen isn't colored by it
+    pub fn color_board_mut(&mut self) -> Option<&mut ColorBoard> {
+        self.color_board.as_mut()
+    }
+
+    /// Returns the index into the palette of each pixel of the screen as it is shown, and the
+    /// palette, which is the one of the color board for CHIP-8X programs and `palette` otherwise
+    pub fn screen<'a>(&'a self, palette: &'a Palette) -> (Cow<'a, [u8]>, Cow<'a, Palette>) {
+        match self.color_board {
+            Some(ref board) => {
+                (Cow::Owned(board.colorize(&self.planes)), Cow::Owned(chip8x::palette()))
+            }
+            None => (Cow::Borrowed(&self.planes), Cow::Borrowed(palette)),
+        }
+    }
+
+    /// Enters Mega-Chip mode with a cleared screen that starts with the palette
+    pub fn enable_mega(&mut self, palette: Palette) {
+        self.mega = Some(MegaScreen::new(palette));
+    }
+
+    /// Leaves Mega-Chip mode, going back to the normal screen
+    pub fn disable_mega(&mut self) {
+        self.mega = None;
+        self.set_draw_flag();
+    }
+
+    /// Returns the Mega-Chip screen, or `None` if the program isn't in Mega-Chip mode
+    pub fn mega(&self) -> Option<&MegaScreen> {
+        self.mega.as_ref()
+    }
+
+    /// Returns the Mega-Chip screen mutably, or `None` if the program isn't in Mega-Chip mode
+    pub fn mega_mut(&mut self) -> Option<&mut MegaScreen> {
+        self.mega.as_mut()
+    }
+
     /// Returns whether the key is pressed
     pub fn is_key_pressed(&self, key: u8) -> bool {
-        self.keys[key as usize]
+        self.keys.get(key)
     }
 
     /// Sets the draw flag to true (causes the screen to be redrawn)
@@ -61,33 +414,118 @@ impl Io {
         self.draw_flag
     }
 
-    /// Returns a mutable reference to the pixel at the given index
-    pub fn get_mut_pixel(&mut self, index: usize) -> &mut bool {
-        &mut self.pixels[index]
+    /// Selects the planes affected by drawing and clearing the screen
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask;
+    }
+
+    /// Returns the bitmask of the selected planes
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    /// Sets how programs address the pixels of the display
+    pub fn set_mode(&mut self, mode: DisplayMode) {
+        self.mode = mode;
     }
 
-    /// Returns a slice containing the pixels of the screen
+    /// Returns how programs address the pixels of the display
+    pub fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    /// Returns the width and height of the display, as seen by programs
+    pub fn size(&self) -> (usize, usize) {
+        match self.mode {
+            DisplayMode::Normal => (SCREEN_WIDTH, SCREEN_HEIGHT),
+            DisplayMode::HiRes => (HIRES_SIZE, HIRES_SIZE),
+        }
+    }
+
+    /// Returns the indices of the pixels of the display covered by the pixel at (x, y), as seen by
+    /// programs
+    pub fn pixel_indices(&self, x: usize, y: usize) -> Range<usize> {
+        let scale = SCREEN_WIDTH / self.size().0;
+        let start = x * scale + y * SCREEN_WIDTH;
+
+        start..start + scale
+    }
+
+    /// Sets whether the pixel at the given index is on in the plane
+    /// `plane` is a bitmask with only the bit of the plane set
+    pub fn set_pixel(&mut self, index: usize, plane: u8, on: bool) {
+        let row = &mut self.rows[plane.trailing_zeros() as usize][index / SCREEN_WIDTH];
+        let bit = 1 << (SCREEN_WIDTH - 1 - index % SCREEN_WIDTH);
+
+        if on {
+            self.planes[index] |= plane;
+            *row |= bit;
+        } else {
+            self.planes[index] &= !plane;
+            *row &= !bit;
+        }
+
+        self.pixels[index] = self.planes[index] != 0;
+    }
+
+    /// XORs a row of a sprite onto the plane at (x, y), as seen by programs, and returns whether
+    /// any pixel that was on is turned off
+    /// `plane` is a bitmask with only the bit of the plane set. The row must be on the display.
+    pub fn draw_sprite_row(&mut self, x: usize, y: usize, plane: u8, sprite: u8) -> bool {
+        // In hi-res mode, each pixel covers two pixels of the display
+        let (bits, width) = match self.mode {
+            DisplayMode::Normal => (sprite as Row, 8),
+            DisplayMode::HiRes => (double_bits(sprite) as Row, 16),
+        };
+        let start = self.pixel_indices(x, 0).start;
+        let mask = bits << (SCREEN_WIDTH - width - start);
+
+        let row = &mut self.rows[plane.trailing_zeros() as usize][y];
+        let collision = *row & mask != 0;
+        *row ^= mask;
+
+        // Only the pixels the sprite flipped need to be updated
+        let mut flipped = mask;
+        while flipped != 0 {
+            let i = y * SCREEN_WIDTH + SCREEN_WIDTH - 1 - flipped.trailing_zeros() as usize;
+            flipped &= flipped - 1;
+
+            self.planes[i] ^= plane;
+            self.pixels[i] = self.planes[i] != 0;
+        }
+
+        collision
+    }
+
+    /// Returns a slice containing the pixels of the screen, where a pixel is on if it is on in any
+    /// plane
     pub fn pixels(&self) -> &[bool] {
         &self.pixels
     }
 
+    /// Returns a slice containing the index into the palette of each pixel of the screen, which is
+    /// the bitmask of the planes it is on in for programs that draw to planes
+    pub fn planes(&self) -> &[u8] {
+        &self.planes
+    }
+
+    /// Returns the keyboard input state
+    pub fn keys(&self) -> Keypad {
+        self.keys
+    }
+
     /// Sets the keyboard input state
-    pub fn set_keys(&mut self, keys: Keys) {
+    pub fn set_keys(&mut self, keys: Keypad) {
         self.keys = keys;
     }
 
-    /// Waits for a key to be pressed, and returns it
-    /// Requires a type that implements `Chip8IO` to do I/O (see `Chip8IO` for more)
-    pub fn wait_key<T: ::Chip8IO>(&self, mut io: &mut T) -> u8 {
-        loop {
-            let new_keys = io.get_keys();
+    /// Returns whether the key is pressed on the second keypad
+    pub fn is_second_key_pressed(&self, key: u8) -> bool {
+        self.second_keys.get(key)
+    }
 
-            for (i, key) in new_keys.iter().enumerate() {
-                // If the key was not pressed, but it was just pressed, return the key
-                if !self.keys[i] && *key {
-                    return i as u8;
-                }
-            }
-        }
+    /// Sets the state of the second keypad
+    pub fn set_second_keys(&mut self, keys: Keypad) {
+        self.second_keys = keys;
     }
 }
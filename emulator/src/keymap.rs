@@ -0,0 +1,251 @@
+//! Mappings of keyboard keys to the keys of the Chip-8 keypad
+//!
+//! Like bindings, keys are identified by name, using the names of the backend used for input (for
+//! `default_io`, the variants of `piston_window::Key`). The default mapping uses the left side of
+//! a QWERTY keyboard, so users of other layouts (or with other preferences) can remap the keys.
+//!
+//! Key maps can be written to and read from a simple text format, where each line maps a key to a
+//! keypad key, written as a hex digit:
+//!
+//! ```text
+//! # Comments start with a '#'
+//! 1 = D1
+//! C = D4
+//! ```
+//!
+//! A keypad key can be mapped to more than one key, but each key can only be mapped to one keypad
+//! key.
+
+use std::fmt;
+
+use errors::*;
+
+/// The default mapping, as pairs of key names and keypad keys
+/// The keys are laid out like the keypad of the COSMAC VIP:
+///
+/// ```text
+/// 1 2 3 4    1 2 3 C
+/// Q W E R    4 5 6 D
+/// A S D F    7 8 9 E
+/// Z X C V    A 0 B F
+/// ```
+const DEFAULT_KEYS: [(&str, u8); 16] = [("D1", 0x1),
+                                        ("D2", 0x2),
+                                        ("D3", 0x3),
+                                        ("D4", 0xC),
+                                        ("Q", 0x4),
+                                        ("W", 0x5),
+                                        ("E", 0x6),
+                                        ("R", 0xD),
+                                        ("A", 0x7),
+                                        ("S", 0x8),
+                                        ("D", 0x9),
+                                        ("F", 0xE),
+                                        ("Z", 0xA),
+                                        ("X", 0x0),
+                                        ("C", 0xB),
+                                        ("V", 0xF)];
+
+/// A mapping of keys to keypad keys
+///
+/// # Examples
+///
+/// ```rust
+/// use chip8::keymap::KeyMap;
+///
+/// // Use the top left keys of an AZERTY keyboard
+/// let mut keymap = KeyMap::default();
+/// keymap.map("A", 0x4).unwrap();
+/// keymap.map("Z", 0x5).unwrap();
+///
+/// assert_eq!(Some(0x4), keymap.button("A"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMap {
+    /// Pairs of the names of keys and the keypad keys they are mapped to
+    keys: Vec<(String, u8)>,
+}
+
+impl KeyMap {
+    /// Returns an empty key map
+    pub fn new() -> KeyMap {
+        KeyMap { keys: Vec::new() }
+    }
+
+    /// Maps the key to the keypad key, replacing the previous mapping of the key
+    /// Returns an error if the key name is invalid or the keypad key is not a hex digit
+    pub fn map(&mut self, key: &str, button: u8) -> Result<()> {
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            bail!(ErrorKind::InvalidKeyName(key.to_string()));
+        }
+
+        if button > 0xF {
+            bail!(ErrorKind::InvalidKey(button));
+        }
+
+        self.unmap(key);
+        self.keys.push((key.to_string(), button));
+
+        Ok(())
+    }
+
+    /// Removes the mapping of the key
+    pub fn unmap(&mut self, key: &str) {
+        self.keys.retain(|(k, _)| k != key);
+    }
+
+    /// Returns the keypad key the key is mapped to
+    pub fn button(&self, key: &str) -> Option<u8> {
+        self.keys.iter().find(|&(k, _)| k == key).map(|&(_, b)| b)
+    }
+
+    /// Returns the names of the keys mapped to the keypad key
+    pub fn keys(&self, button: u8) -> Vec<&str> {
+        self.keys.iter().filter(|&&(_, b)| b == button).map(|(k, _)| k.as_str()).collect()
+    }
+
+    /// Returns an error for the first key that is not one of `names`, which are the names of the
+    /// keys of the backend used for input
+    /// The error suggests a name that only differs in case, or the name of the digit key for
+    /// digits (for example, `D1` for `1`), if there is one.
+    pub fn check_names<S: AsRef<str>>(&self, names: &[S]) -> Result<()> {
+        for (key, _) in &self.keys {
+            if names.iter().any(|name| name.as_ref() == key) {
+                continue;
+            }
+
+            let digit_key = format!("D{}", key);
+            let suggestion = names.iter()
+                .map(AsRef::as_ref)
+                .find(|name| name.eq_ignore_ascii_case(key) || *name == digit_key)
+                .map(str::to_string);
+
+            bail!(ErrorKind::UnknownKeyName(key.clone(), suggestion));
+        }
+
+        Ok(())
+    }
+
+    /// Returns an iterator over the names of the keys and the keypad keys they are mapped to
+    pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, u8)> + 'a> {
+        Box::new(self.keys.iter().map(|&(ref k, b)| (k.as_str(), b)))
+    }
+
+    /// Parses a key map from its text representation (see the module documentation for the
+    /// format)
+    /// Keypad keys not mentioned in the text are left unmapped
+    pub fn parse(text: &str) -> Result<KeyMap> {
+        let mut keymap = KeyMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || ErrorKind::InvalidKeyMapping(i + 1, line.to_string());
+
+            let mut parts = line.splitn(2, '=');
+            let button = parts.next().unwrap_or("").trim();
+            let key = parts.next().map(str::trim).ok_or_else(&invalid)?;
+
+            if button.len() != 1 {
+                bail!(invalid());
+            }
+            let button = u8::from_str_radix(button, 16).chain_err(invalid)?;
+
+            keymap.map(key, button).chain_err(invalid)?;
+        }
+
+        Ok(keymap)
+    }
+}
+
+impl Default for KeyMap {
+    /// Returns the default key map (see `DEFAULT_KEYS`)
+    fn default() -> KeyMap {
+        KeyMap { keys: DEFAULT_KEYS.iter().map(|&(k, b)| (k.to_string(), b)).collect() }
+    }
+}
+
+impl fmt::Display for KeyMap {
+    /// Writes the key map in the format read by `KeyMap::parse`, ordered by keypad key
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for button in 0..16 {
+            for key in self.keys(button) {
+                writeln!(f, "{:X} = {}", button, key)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let keymap = KeyMap::default();
+        let parsed = KeyMap::parse(&keymap.to_string()).unwrap();
+
+        for (key, button) in keymap.iter() {
+            assert_eq!(Some(button), parsed.button(key));
+        }
+        assert_eq!(keymap.iter().count(), parsed.iter().count());
+    }
+
+    #[test]
+    fn test_parse() {
+        let keymap = KeyMap::parse("# comment\n\n5 = Up\n5 = W\nf = V\n").unwrap();
+
+        assert_eq!(Some(0x5), keymap.button("Up"));
+        assert_eq!(vec!["Up", "W"], keymap.keys(0x5));
+        assert_eq!(Some(0xF), keymap.button("V"));
+        assert_eq!(None, keymap.button("Q"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for text in &["G = Q", "10 = Q", "1 Q", "1 = "] {
+            match KeyMap::parse(text) {
+                Err(Error(ErrorKind::InvalidKeyMapping(1, _), _)) => {}
+                r => panic!("Expected error for `{}`, got {:?}", text, r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_names() {
+        let names = ["D1", "Q", "Space"];
+        let mut keymap = KeyMap::new();
+        keymap.map("D1", 0x1).unwrap();
+        keymap.map("Q", 0x4).unwrap();
+        assert!(keymap.check_names(&names).is_ok());
+
+        for &(key, suggestion) in &[("1", Some("D1")), ("space", Some("Space")), ("Foo", None)] {
+            let mut keymap = keymap.clone();
+            keymap.map(key, 0x0).unwrap();
+
+            match keymap.check_names(&names) {
+                Err(Error(ErrorKind::UnknownKeyName(ref k, ref s), _)) => {
+                    assert_eq!(key, k);
+                    assert_eq!(suggestion, s.as_ref().map(String::as_str));
+                }
+                r => panic!("Expected error for `{}`, got {:?}", key, r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_map_replaces() {
+        let mut keymap = KeyMap::default();
+        keymap.map("Q", 0x7).unwrap();
+
+        assert_eq!(Some(0x7), keymap.button("Q"));
+        assert_eq!(vec!["A", "Q"], keymap.keys(0x7));
+        assert!(keymap.keys(0x4).is_empty());
+    }
+}
@@ -18,10 +18,11 @@
 //! // First implement `Chip8IO`
 //! impl chip8::Chip8IO for Io {
 //!     fn draw(&mut self, _pixels: &[bool]) {}
-//!     fn get_keys(&mut self) -> chip8::Keys {
-//!         [false; 16]
+//!     fn get_keys(&mut self) -> chip8::Keypad {
+//!         chip8::Keypad::new()
 //!     }
-//!     fn play_sound(&mut self) {}
+//!     fn sound_start(&mut self) {}
+//!     fn sound_stop(&mut self) {}
 //!     fn should_close(&self) -> bool {
 //!         false
 //!     }
@@ -32,7 +33,7 @@
 //! // Initialize I/O state
 //! let mut io = Io;
 //! // Run the program with the emulator
-//! chip8::run(program, &mut io, Log::Disabled).unwrap();
+//! chip8::run(program, &mut io, Log::Off).unwrap();
 //!
 //! # */
 //! ```
@@ -45,15 +46,12 @@
 //! use chip8::config::Log;
 //!
 //! let program = &[0x61, 0xFF, 0xF1, 0x18];
-//! let mut io = Io::new("beep.wav");
-//! chip8::run(program, &mut io, Log::Disabled).unwrap();
+//! let mut io = Io::new();
+//! chip8::run(program, &mut io, Log::Off).unwrap();
 //! # */
 //! ```
 
-// FIXME: In every pong game, pixels are not being cleared, causing buildup of powered pixels
-//        Fix the problem and add more tests for drawing
-//        If the screen width and height are set to 64 and 32, invalid pixel errors are thrown
-//        This may be related to the problem
+// FIXME: If the screen width and height are set to 64 and 32, invalid pixel errors are thrown
 
 // TODO: Apply clippy changes
 //
@@ -69,47 +67,156 @@
 pub const SCREEN_WIDTH: usize = 128;
 /// The height of the display
 pub const SCREEN_HEIGHT: usize = 64;
+/// The width of the display in Mega-Chip mode
+pub const MEGA_WIDTH: usize = 256;
+/// The height of the display in Mega-Chip mode
+pub const MEGA_HEIGHT: usize = 192;
 
-#[macro_use]
-extern crate error_chain;
 extern crate rand;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "wasm_io")]
+extern crate wasm_bindgen;
+#[cfg(feature = "wasm_io")]
+extern crate js_sys;
+#[cfg(feature = "wasm_io")]
+extern crate web_sys;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(test)]
+extern crate proptest;
 
+#[macro_use]
+mod errors;
 #[cfg(test)]
 mod tests;
 
 mod register;
 mod io;
+mod megachip;
+mod chip8x;
 mod fontset;
-mod instruction;
-mod interpreter;
-mod errors;
+pub mod instruction;
+pub mod interpreter;
 mod cpu;
+mod dump;
 mod utils;
+mod rewind;
+mod run_ahead;
 pub mod config;
+pub mod bindings;
+pub mod keymap;
+pub mod rom;
+pub mod gamedb;
+pub mod state;
+pub mod condition;
+pub mod speedrun;
+pub mod sound_log;
+pub mod watch;
+pub mod pause;
+pub mod watchdog;
+pub mod backend;
+pub mod frame_sink;
+pub mod symbols;
+pub mod trace;
+pub mod differential;
+pub mod analyzer;
+pub mod disasm;
+pub mod lint;
+pub mod scan;
+pub mod sha1;
+pub mod asm;
+pub mod screenshot;
+pub mod recording;
+pub mod blend;
+pub mod report;
+pub mod profile;
+pub mod coverage;
+pub mod self_modification;
+pub mod metrics;
+pub mod clock;
+pub mod runner;
+pub mod pool;
+pub mod env;
+pub mod fuzz;
 #[cfg(feature = "default_io")]
 pub mod default_io;
+#[cfg(feature = "sdl2_io")]
+pub mod sdl2_io;
+#[cfg(feature = "term_io")]
+pub mod term_io;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+#[cfg(feature = "wasm_io")]
+pub mod wasm_io;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 
-use std::time::{Duration, Instant};
-use std::fmt;
+use std::time::Duration;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 use register::Registers;
-use io::Io;
-use fontset::{FONTSET, FONTSET_START};
-use config::Log;
+use io::{Io, DEFAULT_PITCH};
+use fontset::{Font, check_fonts};
+use config::{Config, Log, Quirks, BoxedRng, WarningHook, WriteProtection, InputPolling};
+use bindings::{Action, SAVE_SLOTS};
+use rewind::Rewind;
+use speedrun::SpeedrunTimer;
+use sound_log::SoundLog;
+use profile::Profile;
+use coverage::Coverage;
+use self_modification::WriteMap;
+use metrics::Metrics;
+use watch::Watches;
+use pause::PauseHandle;
+use watchdog::Watchdog;
+use frame_sink::{FrameSink, BoxedFrameSink};
+use symbols::SymbolTable;
+use trace::Tracer;
+use run_ahead::Hidden;
+use clock::{BoxedClock, SystemClock};
+use state::State;
+use sha1::Digest;
+use rom::Rom;
 
 pub use errors::*;
+pub use io::{Keypad, Color, Palette, Sprite, DisplayMode, AUDIO_PATTERN_LEN, PALETTE_SIZE,
+             audio_sample_rate};
+#[allow(deprecated)]
 pub use io::Keys;
+pub use register::RegisterId;
 
-/// The size of memory
+/// The default size of memory, and the size the static analysis tools expect
 const MEMORY: usize = 4096;
-/// Where to put the program in memory
+/// Where programs are loaded by default, and where the static analysis tools expect them
 const PROGRAM_START: usize = 0x200;
+/// The first opcode of hi-res programs, which jumps to the code that switched the original
+/// interpreter to hi-res mode
+const HIRES_HEADER: [u8; 2] = [0x12, 0x60];
+/// Where hi-res programs start, after the code that switched to hi-res mode
+const HIRES_START: u16 = 0x2C0;
 /// The number of times to count down the timers per second
 const TIMER_SPEED: u64 = 60;
+/// The number of cycles per frame when running without a clock and no speed was configured
+const HEADLESS_FRAME_CYCLES: u64 = 10;
+/// The number of frames to rewind each time the rewind action is triggered
+const REWIND_FRAMES: usize = 60;
+/// How many times faster emulation runs while the turbo action is held
+const TURBO_FACTOR: f64 = 8.0;
+/// The lowest and highest speed factors that can be set with the speed actions
+const SPEED_FACTORS: (f64, f64) = (0.125, 8.0);
+/// How long before the next cycle or timer update to stop sleeping and spin instead, as sleeping
+/// can take longer than asked
+const SPIN_MARGIN: Duration = Duration::from_micros(500);
 
 /// A trait implemented by types used for doing I/O
+///
+/// Backends that only need some of the I/O can implement the traits of `backend` instead, which
+/// split this trait into the display, input, and audio.
 pub trait Chip8IO {
     /// Draws the array to the screen based on the following:
     ///
@@ -121,49 +228,116 @@ pub trait Chip8IO {
     /// - The top left corner is pixel (0, 0), and the bottom right corner is pixel
     /// (`SCREEN_WIDTH - 1`, `SCREEN_HEIGHT - 1`)
     fn draw(&mut self, pixels: &[bool]);
+    /// Draws the screen with color, for programs that use more than one display plane (XO-CHIP)
+    /// or set the color of each pixel
+    ///
+    /// `planes` is laid out like the array passed to `draw`, but each pixel is its index into the
+    /// colors of the palette, which is the bitmask of the planes it is on in for programs that
+    /// draw to planes (see `Palette`).
+    /// The emulator calls this rather than `draw`, and by default it calls `draw` with pixels
+    /// that are on if they are on in any plane.
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        let _ = palette;
+        let pixels = planes.iter().map(|&p| p != 0).collect::<Vec<_>>();
+        self.draw(&pixels);
+    }
+    /// Receives the sprites drawn since the screen was last drawn, just before it is drawn again
+    /// This can be used for effects such as highlighting sprites or showing collisions, and does
+    /// nothing by default
+    fn draw_sprites(&mut self, sprites: &[Sprite]) {
+        let _ = sprites;
+    }
+    /// Draws the screen of a Mega-Chip program, which is `MEGA_WIDTH` by `MEGA_HEIGHT` pixels
+    ///
+    /// `pixels` is laid out like the array passed to `draw_planes`, with each pixel being its index
+    /// into the palette loaded by the program. This is called instead of `draw_planes` while the
+    /// program is in Mega-Chip mode, each time it finishes a frame. By default it scales the screen
+    /// down to `SCREEN_WIDTH` by `SCREEN_HEIGHT` and calls `draw_planes`.
+    fn draw_mega(&mut self, pixels: &[u8], palette: &Palette) {
+        self.draw_planes(&megachip::scale_down(pixels), palette);
+    }
     /// Returns the current state of of the keyboard
-    fn get_keys(&mut self) -> Keys;
-    /// Plays a sound
-    fn play_sound(&mut self);
+    fn get_keys(&mut self) -> Keypad;
+    /// Returns the current state of the second keypad, used by two-player CHIP-8X programs
+    /// This is only called for CHIP-8X programs, and by default no keys are pressed.
+    fn get_second_keys(&mut self) -> Keypad {
+        Keypad::new()
+    }
+    /// Starts playing a tone, which should continue until `sound_stop` is called
+    /// The tone plays while the sound timer is above zero.
+    fn sound_start(&mut self);
+    /// Stops playing the tone started by `sound_start`
+    fn sound_stop(&mut self);
+    /// Receives the audio pattern and pitch set by an XO-CHIP program, whenever it changes them
+    ///
+    /// The pattern is a stream of 128 one bit samples, most significant bit first, looped while
+    /// the sound plays at the rate returned by `audio_sample_rate`. Until this is called, backends
+    /// can play a fixed beep instead. This does nothing by default.
+    fn set_audio_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_LEN], pitch: u8) {
+        let _ = (pattern, pitch);
+    }
+    /// Receives the values of the delay and sound timers each frame, after they count down
+    ///
+    /// Frontends can use this to show the timers, drive indicators, or shape the sound as the
+    /// sound timer runs out, without reading them from the emulator. This does nothing by default.
+    fn on_timer_tick(&mut self, delay: u8, sound: u8) {
+        let _ = (delay, sound);
+    }
     /// Returns whether the emulator should exit
     fn should_close(&self) -> bool;
+    /// Returns the emulator actions triggered since the last call (see `bindings::Action`)
+    /// This is called once per frame, and by default no actions are triggered
+    fn actions(&mut self) -> Vec<Action> {
+        Vec::new()
+    }
+    /// Returns whether the key bound to the action is held down, for actions that last while
+    /// their key is held, such as `Action::Turbo`
+    /// This is called once per frame, and returns `false` by default.
+    fn is_action_held(&self, action: Action) -> bool {
+        let _ = action;
+        false
+    }
+    /// Returns the path of a file dropped onto the window since the last call, which is loaded as
+    /// the new program
+    /// This is called once per frame, and by default no files are dropped
+    fn dropped_file(&mut self) -> Option<PathBuf> {
+        None
+    }
+    /// Shows a short line of text about the state of the emulator, such as the speedrun timer
+    /// This is called once per frame while there is something to show, and does nothing by
+    /// default
+    fn set_status(&mut self, status: &str) {
+        let _ = status;
+    }
 }
 
 /// Creates a Chip-8 emulator and runs it. Returns an error in the case of something invalid, for
 /// example an invalid opcode. Requires a type that implements `Chip8IO` to do I/O (see `Chip8IO`
-/// for more). Logging can be enabled with the `log` argument.
-pub fn run<T: Chip8IO>(program: &[u8], io: &mut T, log: Log) -> Result<()> {
-    let mut chip8 = Chip8::new(program, log).chain_err(|| "Failed to initialize emulator")?;
-    // The time when the next timer update should happen
-    // Used for capping the timer speed
-    let mut next_tick = Instant::now();
-
-    loop {
-        // Run a CPU cycle
-        chip8.cycle(io)?;
+/// for more). The emulator is configured with the `config` argument, which can also be a `Log` if
+/// only logging needs to be configured (see `config::Config` for more).
+pub fn run<T, C>(program: &[u8], io: &mut T, config: C) -> Result<()>
+    where T: Chip8IO,
+          C: Into<Config>
+{
+    let mut chip8 = Chip8::new(program, config.into())
+        .chain_err(|| "Failed to initialize emulator")?;
 
-        // Detect end conditions
-        if chip8.program_ended() | io.should_close() {
-            break;
-        }
-
-        if Instant::now() > next_tick {
-            // Run the next cycle `1000 / HERTZ` milliseconds from now
-            next_tick += Duration::from_millis(1000 / TIMER_SPEED);
-
-            chip8.update_timers(io);
-        }
-    }
-
-    Ok(())
+    // There are no breakpoints, so this only returns once the program ends
+    chip8.run_until_break(io).map(|_| ())
 }
 
 /// A Chip-8 emulator
-struct Chip8 {
+///
+/// Most users only need `run`, which creates an emulator and runs it until the program ends. This
+/// type can be used directly to control the emulator from outside of `run`, for example by calling
+/// `cycle` at the desired speed and `tick_60hz` 60 times per second from a custom loop.
+pub struct Chip8 {
     /// RAM
-    memory: [u8; MEMORY],
+    memory: Vec<u8>,
     /// The stack; used for storing addresses to return to from subroutines
     stack: Vec<u16>,
+    /// The maximum number of addresses on the stack
+    stack_depth: usize,
     /// Register state
     registers: Registers,
     /// I/O state
@@ -171,79 +345,925 @@ struct Chip8 {
     /// A general purpose timer that counts down at 60 hz
     delay_timer: u8,
     /// A timer that counts down at 60 hz
-    /// A sound is played when this timer reaches zero
+    /// A sound is played while this timer is above zero
     sound_timer: u8,
+    /// Whether the sound is playing (`Chip8IO::sound_start` was called last)
+    sound_active: bool,
+    /// The audio pattern played while the sound timer runs (XO-CHIP)
+    audio_pattern: [u8; AUDIO_PATTERN_LEN],
+    /// The pitch the audio pattern is played at (XO-CHIP)
+    pitch: u8,
+    /// Whether the audio pattern or pitch changed since they were last sent to `Chip8IO`
+    audio_changed: bool,
     /// Whether the program has ended
     program_ended: bool,
-    /// Whether to log things
+    /// What to log
     log: Log,
+    /// The addresses of the instructions to log, or all addresses if this is empty
+    log_ranges: Vec<Range<u16>>,
+    /// The random number generator used by the `Rand` instruction
+    rng: BoxedRng,
+    /// Behaviors that differ between interpreters
+    quirks: Quirks,
+    /// The number of instructions to run per second, or `None` to run as fast as possible
+    speed: Option<u32>,
+    /// How many times faster than normal emulation runs (see `set_speed_factor`)
+    speed_factor: f64,
+    /// Whether the turbo action is held, which speeds up emulation
+    turbo: bool,
+    /// The clock used for pacing the emulator
+    clock: BoxedClock,
+    /// Snapshots used for rewinding, or `None` if rewinding is disabled
+    rewind: Option<Rewind>,
+    /// Addresses at which `run_until_break` stops
+    breakpoints: BTreeSet<u16>,
+    /// The colors used to display the planes of the screen
+    palette: Palette,
+    /// A timer for speedrunning, or `None` if it is disabled
+    speedrun: Option<SpeedrunTimer>,
+    /// A log of changes to the sound timer, or `None` if logging them is disabled
+    sound_log: Option<SoundLog>,
+    /// The number of cycles run
+    cycles: u64,
+    /// The number of frames (timer updates) that have passed
+    frames: u64,
+    /// Values watched for changes
+    watches: Watches,
+    /// Whether the emulator is paused, shared with frontends
+    pause: PauseHandle,
+    /// Detects when the program has stalled, or `None` if it is disabled
+    watchdog: Option<Watchdog>,
+    /// Receivers of every frame
+    frame_sinks: Vec<BoxedFrameSink>,
+    /// Names of addresses in the program, used in error messages
+    symbols: SymbolTable,
+    /// The address of the last instruction run, and whether it jumped instead of continuing to the
+    /// next instruction
+    previous: Option<(u16, bool)>,
+    /// The trace of instructions run
+    tracer: Tracer,
+    /// The number of frames to run ahead, or `None` if run-ahead is disabled
+    run_ahead: Option<usize>,
+    /// The sprites drawn since the screen was last drawn
+    sprites: Vec<Sprite>,
+    /// The counts of instructions run, or `None` if profiling is disabled
+    profile: Option<Profile>,
+    /// The addresses that instructions have been run from
+    coverage: Coverage,
+    /// The addresses written by instructions, or `None` if self-modification detection is
+    /// disabled
+    writes: Option<WriteMap>,
+    /// Counters of what the emulator has done
+    metrics: Metrics,
+    /// Whether the last cycle ran `WaitKey` without a key being pressed
+    waiting_for_key: bool,
+    /// The key pressed while waiting for a key to be released (see `Quirks::key_press`)
+    held_key: Option<u8>,
+    /// When the keys are read
+    input_polling: InputPolling,
+    /// Whether a frame started since the keys were last read (see `InputPolling::PerFrame`)
+    poll_keys: bool,
+    /// The function called when an invalid opcode is skipped, or `None` if invalid opcodes are
+    /// errors
+    permissive: Option<WarningHook>,
+    /// Whether the program is a CHIP-8X program
+    chip8x: bool,
+    /// The address the program is loaded at
+    program_start: usize,
+    /// The font used by `SetIndexChar`
+    font: Font,
+    /// The large font used by `SetIndexLargeChar`
+    large_font: Font,
+    /// What happens when the program writes below the program start
+    protection: WriteProtection,
+    /// The state of the emulator when it was created, used by `reset` (always `Some` after
+    /// creation)
+    initial_state: Option<State>,
+    /// The states saved by `Action::SaveState`, indexed by slot starting from slot 1
+    save_slots: Vec<Option<State>>,
 }
 
 impl Chip8 {
-    /// Initializes and returns a Chip-8 emulator
-    fn new(program: &[u8], log: Log) -> Result<Chip8> {
-        let mut memory = [0; MEMORY];
+    /// Initializes and returns a Chip-8 emulator with the program loaded into memory
+    /// Returns an error if the program does not fit in memory (see `Config::memory_size`), or if
+    /// the fonts are invalid (see `Config::font`)
+    pub fn new(program: &[u8], config: Config) -> Result<Chip8> {
+        let mut memory = vec![0; config.memory_size];
+        let program_start = config.program_start as usize;
 
-        // Make sure the fontset doesn't go into program memory
-        assert!(0x50 + FONTSET.len() < PROGRAM_START, "Fontset too large");
+        check_program_size(program, program_start, memory.len())?;
 
-        // Load fontset into memory starting at address 0x50
-        memory[FONTSET_START..FONTSET_START + FONTSET.len()].copy_from_slice(FONTSET);
+        // Make sure the fonts don't go into program memory, then load them (at 0x50 and 0x110 by
+        // default)
+        check_fonts(&config.font, &config.large_font, program_start)?;
+        config.font.load(&mut memory);
+        config.large_font.load(&mut memory);
 
-        let program_memory_size = memory.len() - PROGRAM_START;
+        // Load the program into memory starting at the program start (0x200 by default)
+        memory[program_start..program_start + program.len()].copy_from_slice(program);
 
-        if program.len() >= program_memory_size {
-            bail!(ErrorKind::ProgramTooLarge(program_memory_size, program.len()));
-        }
-
-        // Load the program into memory starting at address 0x200
-        memory[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
-
-        Ok(Chip8 {
+        let mut chip8 = Chip8 {
             memory: memory,
-            stack: Vec::new(),
-            registers: Registers::new(),
+            stack: Vec::with_capacity(config.stack_depth),
+            stack_depth: config.stack_depth,
+            registers: Registers::new(config.program_start),
             io: Io::new(),
             delay_timer: 0,
             sound_timer: 0,
+            sound_active: false,
+            audio_pattern: [0; AUDIO_PATTERN_LEN],
+            pitch: DEFAULT_PITCH,
+            audio_changed: false,
             program_ended: false,
-            log: log,
-        })
+            log: config.log,
+            log_ranges: config.log_ranges,
+            rng: config.rng.unwrap_or_else(|| Box::new(rand::weak_rng())),
+            clock: config.clock.unwrap_or_else(|| Box::new(SystemClock::new())),
+            quirks: config.quirks,
+            speed: config.speed,
+            speed_factor: 1.0,
+            turbo: false,
+            rewind: config.rewind.map(|(interval, capacity)| Rewind::new(interval, capacity)),
+            breakpoints: BTreeSet::new(),
+            palette: config.palette,
+            speedrun: config.speedrun,
+            sound_log: if config.sound_log {
+                Some(SoundLog::new())
+            } else {
+                None
+            },
+            cycles: 0,
+            frames: 0,
+            watches: Watches::new(),
+            pause: config.pause,
+            watchdog: config.watchdog.map(Watchdog::new),
+            frame_sinks: Vec::new(),
+            symbols: SymbolTable::new(),
+            previous: None,
+            tracer: Tracer::new(),
+            run_ahead: config.run_ahead,
+            sprites: Vec::new(),
+            profile: if config.profile {
+                Some(Profile::new())
+            } else {
+                None
+            },
+            coverage: Coverage::new(config.memory_size),
+            writes: if config.detect_self_modification {
+                Some(WriteMap::new())
+            } else {
+                None
+            },
+            metrics: Metrics::new(),
+            waiting_for_key: false,
+            held_key: None,
+            input_polling: config.input_polling,
+            poll_keys: true,
+            permissive: config.permissive,
+            chip8x: config.chip8x,
+            program_start,
+            font: config.font,
+            large_font: config.large_font,
+            protection: config.protection,
+            initial_state: None,
+            save_slots: vec![None; SAVE_SLOTS as usize],
+        };
+
+        if chip8.chip8x {
+            chip8.io.enable_color_board();
+        }
+
+        chip8.detect_hires(program);
+        chip8.initial_state = Some(chip8.save_state());
+        Ok(chip8)
+    }
+
+    /// Resets the emulator to its state when it was created, restarting the program
+    /// The memory, registers, screen, and timers are reset. Settings and tools such as
+    /// breakpoints, watches, and the profiler are not, and the counts of cycles and frames keep
+    /// counting up.
+    pub fn reset(&mut self) {
+        if let Some(state) = self.initial_state.take() {
+            self.load_state(&state);
+            self.initial_state = Some(state);
+        }
+
+        self.waiting_for_key = false;
+        self.held_key = None;
+        self.poll_keys = true;
+        self.previous = None;
+        self.sprites.clear();
     }
 
     /// Returns whether the program has ended
-    fn program_ended(&self) -> bool {
+    pub fn program_ended(&self) -> bool {
         self.program_ended
     }
 
-    /// Updates the timers, and plays a sound if the sound timer reaches zero
-    fn update_timers<T: Chip8IO>(&mut self, io: &mut T) {
+    /// Returns whether the program is waiting for a key to be pressed (the last cycle ran
+    /// `WaitKey`, and no new key press was found, or the key wasn't released yet without the key
+    /// press quirk)
+    /// The instruction is run again each cycle until a key is pressed, so the timers keep running
+    /// and the emulator can still be closed while waiting.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    /// Adds a receiver of every frame displayed, in addition to the `Chip8IO` used to run the
+    /// emulator (see `frame_sink`)
+    pub fn add_frame_sink<S: FrameSink + Send + 'static>(&mut self, sink: S) {
+        self.frame_sinks.push(Box::new(sink));
+    }
+
+    /// Removes all frame sinks
+    pub fn clear_frame_sinks(&mut self) {
+        self.frame_sinks.clear();
+    }
+
+    /// Sets the names of addresses in the program, which are used to describe where errors
+    /// happened
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Returns the names of addresses in the program
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Returns whether the watchdog detected that the program stalled (see `Config::watchdog`)
+    pub fn stalled(&self) -> bool {
+        self.watchdog.as_ref().is_some_and(Watchdog::stalled)
+    }
+
+    /// Runs the emulator until the program ends, or the program counter reaches a breakpoint
+    /// Returns the address of the breakpoint, or `None` if the program ended or stalled (or `io`
+    /// requested the emulator to close). Because a cycle is always run before checking for breakpoints, this
+    /// can be called again to continue from a breakpoint.
+    ///
+    /// Timers are updated at 60 hz, and if a speed was configured, cycles are run at that speed,
+    /// both measured by the configured clock (see `Config::clock`) and multiplied by the speed
+    /// factor (see `set_speed_factor`). At most one cycle is run per read of the clock, so fast
+    /// speeds may not be reached with a slow clock. Between cycles and timer updates, the clock
+    /// sleeps until shortly before the next one is due, so the emulator doesn't spin at full CPU.
+    /// While the emulator is paused (see `pause_handle`), no cycles are run and the timers are
+    /// stopped, except for requested steps and frames.
+    ///
+    /// If run-ahead is enabled (see `Config::run_ahead`), the screen is drawn once per frame
+    /// instead of after every cycle.
+    pub fn run_until_break<T: Chip8IO>(&mut self, io: &mut T) -> Result<Option<u16>> {
+        // The time read from the clock last, and the emulated time, which passes faster or slower
+        // than the time of the clock depending on the speed factor
+        let mut last_time = self.clock.now();
+        let mut emulated_time = Duration::new(0, 0);
+        // The time when the next timer update should happen
+        // Used for capping the timer speed
+        let mut next_tick = emulated_time;
+        // The time when the next cycle should run, and the time between cycles
+        // Used for capping the speed of the emulator if a speed was configured
+        let mut next_cycle = next_tick;
+        // The number of cycles run at the start of the current frame, used for running ahead
+        let mut frame_start = self.cycles;
+
+        loop {
+            let time = self.clock.now();
+            let elapsed = time - last_time;
+            last_time = time;
+
+            let factor = self.speed_factor * if self.turbo { TURBO_FACTOR } else { 1.0 };
+            emulated_time += if factor == 1.0 {
+                elapsed
+            } else {
+                elapsed.mul_f64(factor)
+            };
+            let now = emulated_time;
+            // The speed can change when a new program is loaded
+            let cycle_duration = self.speed.map(|speed| Duration::new(0, 1_000_000_000 / speed));
+
+            let cycled = if self.pause.is_paused() {
+                // Don't catch up on the cycles and timer updates missed while paused
+                next_tick = now;
+                next_cycle = now;
+
+                if self.pause.take_step() {
+                    self.run_cycle(io)?;
+                    true
+                } else if self.pause.take_frame() {
+                    self.advance_frame(io)?;
+                    true
+                } else {
+                    // Keep handling input so the emulator can be resumed or closed
+                    io.get_keys();
+                    self.handle_actions(io);
+                    self.handle_dropped_file(io);
+                    self.clock.sleep(Duration::from_millis(1));
+                    false
+                }
+            } else {
+                // Run a CPU cycle
+                match cycle_duration {
+                    Some(duration) => {
+                        if now >= next_cycle {
+                            next_cycle += duration;
+                            self.run_cycle(io)?;
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    None => {
+                        self.run_cycle(io)?;
+                        true
+                    }
+                }
+            };
+
+            // Detect end conditions
+            if self.program_ended() | self.stalled() | io.should_close() {
+                return Ok(None);
+            }
+
+            let pc = self.registers.program_counter;
+            if cycled && self.breakpoints.contains(&pc) {
+                return Ok(Some(pc));
+            }
+
+            if now > next_tick {
+                // Run the next cycle `1000 / HERTZ` milliseconds from now
+                next_tick += Duration::from_millis(1000 / TIMER_SPEED);
+
+                self.tick_60hz(io);
+                self.handle_actions(io);
+                self.handle_dropped_file(io);
+                self.turbo = io.is_action_held(Action::Turbo);
+
+                if let Some(frames) = self.run_ahead {
+                    let frame_cycles = self.cycles - frame_start;
+                    self.draw_ahead(io, frames, frame_cycles)?;
+                }
+                frame_start = self.cycles;
+            }
+
+            // Sleep until shortly before the next cycle or timer update is due, which only happens
+            // if a speed was configured, as cycles run as fast as possible otherwise
+            if !cycled {
+                let next = if next_tick > now { next_cycle.min(next_tick) } else { next_cycle };
+                if let Some(wait) = next.checked_sub(now + SPIN_MARGIN) {
+                    self.clock.sleep(wait.div_f64(factor));
+                }
+            }
+        }
+    }
+
+    /// Runs at most `cycles` cycles as fast as possible, without pacing them or the timers by the
+    /// clock, and returns the number of cycles run
+    /// Stops early if the program ends or stalls, or `io` requests closing. The timers are updated
+    /// once every `speed / 60` cycles if a speed was configured, or every 10 cycles otherwise.
+    pub fn run_for<T: Chip8IO>(&mut self, io: &mut T, cycles: u64) -> Result<u64> {
+        let start = self.cycles;
+        self.run_until(io, cycles, |_| false)?;
+
+        Ok(self.cycles - start)
+    }
+
+    /// Runs cycles as fast as possible (see `run_for`) until the predicate returns true for the
+    /// emulator, returning whether it did
+    /// The predicate is checked before each cycle. Returns `false` if it wasn't satisfied within
+    /// `max_cycles` cycles, or if the program ended or stalled or `io` requested closing first.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use chip8::{Chip8, Chip8IO};
+    /// # use chip8::config::Config;
+    /// # use chip8::condition::Condition;
+    /// # fn f<T: Chip8IO>(program: &[u8], io: &mut T) -> chip8::Result<()> {
+    /// let mut chip8 = Chip8::new(program, Config::new())?;
+    /// let title_shown = Condition::parse("lit(0, 0, 64, 32) > 100")?;
+    ///
+    /// if chip8.run_until(io, 100_000, |c| title_shown.eval(c))? {
+    ///     println!("The title screen was shown after {} cycles", chip8.cycles());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_until<T, F>(&mut self, io: &mut T, max_cycles: u64, mut predicate: F) -> Result<bool>
+        where T: Chip8IO,
+              F: FnMut(&Chip8) -> bool
+    {
+        let start = self.cycles;
+        let frame_cycles = self.frame_cycles();
+
+        loop {
+            if predicate(self) {
+                return Ok(true);
+            }
+
+            if self.cycles - start >= max_cycles || self.program_ended() || self.stalled() ||
+               io.should_close() {
+                return Ok(false);
+            }
+
+            self.cycle(io)?;
+
+            if self.cycles.is_multiple_of(frame_cycles) {
+                self.tick_60hz(io);
+            }
+        }
+    }
+
+    /// Runs a single frame, whether or not the emulator is paused
+    /// This runs the cycles of one frame at the configured speed (or 10 if no speed was
+    /// configured), then updates the timers. Stops early if the program ends or stalls.
+    pub fn advance_frame<T: Chip8IO>(&mut self, io: &mut T) -> Result<()> {
+        for _ in 0..self.frame_cycles() {
+            if self.program_ended() || self.stalled() {
+                break;
+            }
+            self.cycle(io)?;
+        }
+
+        self.tick_60hz(io);
+        Ok(())
+    }
+
+    /// Returns the number of cycles run per frame when not running in real time
+    fn frame_cycles(&self) -> u64 {
+        self.speed.map_or(HEADLESS_FRAME_CYCLES, |speed| (speed as u64 / TIMER_SPEED).max(1))
+    }
+
+    /// Runs a CPU cycle, without drawing the screen if run-ahead is enabled (the screen is drawn
+    /// once per frame by `draw_ahead` instead)
+    fn run_cycle<T: Chip8IO>(&mut self, io: &mut T) -> Result<()> {
+        if self.run_ahead.is_some() {
+            self.cycle(&mut Hidden::new(io))
+        } else {
+            self.cycle(io)
+        }
+    }
+
+    /// Handles the actions triggered by `io`
+    fn handle_actions<T: Chip8IO>(&mut self, io: &mut T) {
+        for action in io.actions() {
+            match action {
+                Action::Pause => self.pause.toggle(),
+                Action::FrameAdvance => self.pause.advance_frame(),
+                Action::Rewind => {
+                    self.rewind(REWIND_FRAMES);
+                }
+                Action::Reset => self.reset(),
+                Action::SpeedUp => {
+                    let factor = self.speed_factor * 2.0;
+                    self.set_speed_factor(factor.min(SPEED_FACTORS.1));
+                }
+                Action::SlowDown => {
+                    let factor = self.speed_factor / 2.0;
+                    self.set_speed_factor(factor.max(SPEED_FACTORS.0));
+                }
+                Action::SaveState(slot) => {
+                    let state = self.save_state();
+                    if let Some(saved) = self.save_slot(slot) {
+                        *saved = Some(state);
+                        info!("Saved the state to slot {}", slot);
+                    }
+                }
+                Action::LoadState(slot) => {
+                    match self.save_slot(slot).and_then(|saved| saved.clone()) {
+                        Some(state) => {
+                            self.load_state(&state);
+                            info!("Loaded the state from slot {}", slot);
+                        }
+                        None => warn!("No state is saved to slot {}", slot),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the save state slot, or `None` if there is no slot with the number
+    fn save_slot(&mut self, slot: u8) -> Option<&mut Option<State>> {
+        (slot as usize).checked_sub(1).and_then(move |i| self.save_slots.get_mut(i))
+    }
+
+    /// Loads the program in the file dropped onto the window by `io`, if there is one
+    /// Errors are logged instead of returned, so a bad file doesn't stop the current program.
+    fn handle_dropped_file<T: Chip8IO>(&mut self, io: &mut T) {
+        if let Some(path) = io.dropped_file() {
+            match self.load_rom_file(&path) {
+                Ok(()) => info!("Loaded `{}`", path.display()),
+                Err(e) => error!("Failed to load `{}`: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Loads the ROM in the file (see `load_rom`)
+    fn load_rom_file(&mut self, path: &Path) -> Result<()> {
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_end(&mut bytes))
+            .chain_err(|| "Failed to read the file")?;
+
+        self.load_rom(&Rom::new(bytes)?)
+    }
+
+    /// Replaces the program with a new one and restarts it, as if the emulator was created with it
+    /// The configuration, breakpoints, and watches are kept, but the rewind history, save state
+    /// slots, coverage, written addresses, and symbols of the old program are discarded. Returns an
+    /// error if the program is too large, in which case the emulator is left unchanged.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<()> {
+        let start = self.program_start;
+        check_program_size(program, start, self.memory.len())?;
+
+        self.reset();
+        for byte in &mut self.memory[start..] {
+            *byte = 0;
+        }
+        self.memory[start..start + program.len()].copy_from_slice(program);
+        self.detect_hires(program);
+        self.initial_state = Some(self.save_state());
+
+        if let Some(ref mut rewind) = self.rewind {
+            rewind.clear();
+        }
+        for saved in &mut self.save_slots {
+            *saved = None;
+        }
+        self.clear_coverage();
+        self.clear_writes();
+        self.symbols = SymbolTable::new();
+
+        Ok(())
+    }
+
+    /// Switches to hi-res mode if the program is a hi-res program, which is loaded at 0x200 and
+    /// starts by jumping to 0x260, and to the normal display mode otherwise
+    /// Hi-res programs are started after the code that switched the original interpreter to
+    /// hi-res mode, as it isn't Chip-8 code. Other programs are started at the program start.
+    fn detect_hires(&mut self, program: &[u8]) {
+        if self.program_start == PROGRAM_START && program.starts_with(&HIRES_HEADER) {
+            self.io.set_mode(DisplayMode::HiRes);
+            self.registers.program_counter = HIRES_START;
+        } else {
+            self.io.set_mode(DisplayMode::Normal);
+            self.registers.program_counter = self.program_start as u16;
+        }
+    }
+
+    /// Returns the address the program is loaded at (see `Config::program_start`)
+    pub fn program_start(&self) -> u16 {
+        self.program_start as u16
+    }
+
+    /// Returns how the program addresses the pixels of the display, which is
+    /// `DisplayMode::HiRes` for hi-res programs (64x64 programs that start with the opcode
+    /// 0x1260)
+    pub fn display_mode(&self) -> DisplayMode {
+        self.io.mode()
+    }
+
+    /// Loads the program of the ROM like `load_program`, and uses the speed and quirks its
+    /// metadata asks for
+    /// Returns an error if the program is too large or the metadata asks for a speed of zero, in
+    /// which case the emulator is left unchanged.
+    pub fn load_rom(&mut self, rom: &Rom) -> Result<()> {
+        let metadata = rom.metadata();
+        if metadata.speed == Some(0) {
+            bail!("Invalid speed: 0");
+        }
+
+        self.load_program(rom.program())?;
+
+        if let Some(speed) = metadata.speed {
+            self.speed = Some(speed);
+        }
+        if let Some(quirks) = metadata.quirks {
+            self.quirks = quirks;
+        }
+
+        Ok(())
+    }
+
+    /// Sets how many times faster than normal `run_until_break` runs, for example `2.0` to run
+    /// twice as fast or `0.5` to run at half speed
+    /// Cycles and timer updates are both sped up, so programs run as they would at normal speed.
+    /// The factor can also be changed with the speed actions (see `bindings::Action`), and is
+    /// multiplied by 8 while the turbo action is held.
+    pub fn set_speed_factor(&mut self, factor: f64) {
+        assert!(factor > 0.0, "Speed factor must be positive");
+        self.speed_factor = factor;
+    }
+
+    /// Returns how many times faster than normal `run_until_break` runs (see `set_speed_factor`)
+    pub fn speed_factor(&self) -> f64 {
+        self.speed_factor
+    }
+
+    /// Pauses the emulator, stopping `run_until_break` from running cycles
+    pub fn pause(&mut self) {
+        self.pause.pause();
+    }
+
+    /// Resumes the emulator
+    pub fn resume(&mut self) {
+        self.pause.resume();
+    }
+
+    /// Returns whether the emulator is paused
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
+    /// Runs a single cycle, whether or not the emulator is paused
+    /// The timers are not updated.
+    pub fn step<T: Chip8IO>(&mut self, io: &mut T) -> Result<()> {
+        self.cycle(io)
+    }
+
+    /// Returns a handle that shares the paused state of the emulator, so it can be paused, resumed,
+    /// and stepped while `run_until_break` is running
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// Returns the pixels of the screen, where a pixel is on if it is on in any plane (see
+    /// `Chip8IO::draw`)
+    pub fn pixels(&self) -> &[bool] {
+        self.io.pixels()
+    }
+
+    /// Returns the planes each pixel of the screen is on in, as bitmasks (see
+    /// `Chip8IO::draw_planes`)
+    pub fn planes(&self) -> &[u8] {
+        self.io.planes()
+    }
+
+    /// Returns the pixels of the Mega-Chip screen as indices into its palette (see
+    /// `Chip8IO::draw_mega`), or `None` if the program isn't in Mega-Chip mode
+    pub fn mega_pixels(&self) -> Option<&[u8]> {
+        self.io.mega().map(|mega| mega.pixels())
+    }
+
+    /// Returns the value of the general purpose register
+    pub fn register(&self, id: RegisterId) -> u8 {
+        self.registers.get(id)
+    }
+
+    /// Sets the general purpose register to the value
+    pub fn set_register(&mut self, id: RegisterId, value: u8) {
+        self.registers.set(id, value);
+    }
+
+    /// Returns the index register (I)
+    pub fn index(&self) -> u16 {
+        self.registers.index
+    }
+
+    /// Sets the index register (I)
+    pub fn set_index(&mut self, value: u16) {
+        self.registers.index = value;
+    }
+
+    /// Returns the program counter, the address of the next instruction to run
+    pub fn program_counter(&self) -> u16 {
+        self.registers.program_counter
+    }
+
+    /// Sets the program counter, so the instruction at the address is run next
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.registers.program_counter = address;
+    }
+
+    /// Returns the addresses on the stack that subroutines will return to, with the most recent
+    /// call last
+    /// The stack pointer is the length of the stack.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Replaces the addresses on the stack (see `stack`)
+    /// Returns an error if there are more addresses than fit on the stack (see
+    /// `Config::stack_depth`)
+    pub fn set_stack(&mut self, stack: &[u16]) -> Result<()> {
+        if stack.len() > self.stack_depth {
+            bail!(ErrorKind::StackTooLarge(self.stack_depth));
+        }
+
+        self.stack = stack.to_vec();
+        Ok(())
+    }
+
+    /// Returns the delay timer
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Sets the delay timer
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    /// Returns the audio pattern played while the sound timer runs (see
+    /// `Chip8IO::set_audio_pattern`)
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_LEN] {
+        &self.audio_pattern
+    }
+
+    /// Returns the pitch the audio pattern is played at
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Returns the sound timer
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Sets the sound timer, which plays a sound until it reaches zero
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// Returns the bytes of memory in the range of addresses
+    /// Returns an error if the range is not entirely in memory
+    pub fn read_memory(&self, range: Range<u16>) -> Result<&[u8]> {
+        let (start, end) = (range.start as usize, range.end as usize);
+
+        match self.memory.get(start..end) {
+            Some(bytes) => Ok(bytes),
+            None => bail!(ErrorKind::OutOfMemory(first_invalid(start, end, self.memory.len()))),
+        }
+    }
+
+    /// Writes the bytes to memory starting at the address
+    /// Returns an error without writing anything if the bytes don't fit in memory
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> Result<()> {
+        let (start, end) = (address as usize, address as usize + bytes.len());
+
+        match self.memory.get_mut(start..end) {
+            Some(memory) => memory.copy_from_slice(bytes),
+            None => bail!(ErrorKind::OutOfMemory(first_invalid(start, end, self.memory.len()))),
+        }
+
+        Ok(())
+    }
+
+    /// Returns a digest of the memory, registers, stack, timers, and screen, for cheaply checking
+    /// that two runs reached the same state
+    /// The digest doesn't depend on the configuration or on how the state was reached, so it stays
+    /// the same across versions of the emulator unless the state itself differs. The number of
+    /// cycles, the keys, and the random number generator are not included.
+    pub fn state_hash(&self) -> Digest {
+        let mut data = self.memory.clone();
+        let words = [self.registers.index, self.registers.program_counter, self.stack.len() as u16];
+
+        data.extend_from_slice(self.registers.get_registers());
+        for &word in words.iter().chain(&self.stack) {
+            data.extend_from_slice(&[(word >> 8) as u8, word as u8]);
+        }
+
+        data.extend_from_slice(&[self.delay_timer, self.sound_timer]);
+        data.extend_from_slice(self.planes());
+        if let Some(pixels) = self.mega_pixels() {
+            data.extend_from_slice(pixels);
+        }
+
+        sha1::sha1(&data)
+    }
+
+    /// Returns the speedrun timer, or `None` if it is disabled (see `Config::speedrun`)
+    pub fn speedrun_timer(&self) -> Option<&SpeedrunTimer> {
+        self.speedrun.as_ref()
+    }
+
+    /// Returns the number of cycles run
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the number of frames (updates of the 60 hz timers) that have passed
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the log of changes to the sound timer, or `None` if logging them is disabled (see
+    /// `Config::sound_log`)
+    pub fn sound_log(&self) -> Option<&SoundLog> {
+        self.sound_log.as_ref()
+    }
+
+    /// Returns the counts of instructions run, or `None` if profiling is disabled (see
+    /// `Config::profile`)
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Adds a breakpoint at the address, which makes `run_until_break` stop when the program
+    /// counter reaches it
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes the breakpoint at the address, returning whether there was one
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Returns the addresses of all breakpoints, in ascending order
+    pub fn breakpoints(&self) -> Vec<u16> {
+        self.breakpoints.iter().cloned().collect()
+    }
+
+    /// Rewinds emulation by about `frames` frames, restoring a snapshot taken at the start of the
+    /// rewound period (snapshots are only taken every few frames, see `Config::rewind`)
+    /// Returns whether the emulator was rewound, which is not the case if rewinding is disabled or
+    /// no snapshots have been taken yet
+    pub fn rewind(&mut self, frames: usize) -> bool {
+        let state = match self.rewind.as_mut().and_then(|r| r.rewind(frames)) {
+            Some(state) => state.clone(),
+            None => return false,
+        };
+
+        self.load_state(&state);
+        true
+    }
+
+    /// Ends a frame: counts down the delay and sound timers, and starts or stops the sound when the
+    /// sound timer changes between zero and above zero
+    ///
+    /// This must be called 60 times per second of emulated time, between cycles, by anything that
+    /// runs the emulator with `cycle` instead of `run_until_break` or `run_for` (which call it
+    /// themselves). Everything else that happens once per frame is also done here: frame sinks
+    /// receive the screen, rewind snapshots are taken, and the watchdog and speedrun timer are
+    /// updated.
+    pub fn tick_60hz<T: Chip8IO>(&mut self, io: &mut T) {
+        self.frames += 1;
+        self.poll_keys = true;
+        self.metrics.record_tick(self.waiting_for_key);
+
         // Update the delay timer
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
-        // Update the sound timer, and play a sound if it reaches zero
+        // The sound plays for as many frames as the sound timer was set to, so it is started or
+        // stopped before the timer counts down
+        let active = self.sound_timer > 0;
+        if active != self.sound_active {
+            self.sound_active = active;
+
+            if active {
+                io.sound_start();
+            } else {
+                io.sound_stop();
+            }
+        }
+
+        // Update the sound timer
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
+        }
 
-            if self.sound_timer == 0 {
-                io.play_sound();
-            }
+        io.on_timer_tick(self.delay_timer, self.sound_timer);
+
+        let (planes, palette) = self.io.screen(&self.palette);
+        for sink in &mut self.frame_sinks {
+            sink.frame(self.frames, &planes, &palette);
+        }
+
+        if let Some(mut rewind) = self.rewind.take() {
+            rewind.record(|| self.save_state());
+            self.rewind = Some(rewind);
+        }
+
+        let pc = self.registers.program_counter;
+        self.check_watches(pc);
+
+        if let Some(mut watchdog) = self.watchdog.take() {
+            watchdog.update(self);
+            self.watchdog = Some(watchdog);
+        }
+
+        if let Some(mut timer) = self.speedrun.take() {
+            timer.update(self);
+            io.set_status(&timer.to_string());
+            self.speedrun = Some(timer);
         }
     }
 }
 
-impl fmt::Debug for Chip8 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.memory.fmt(f)?;
-        self.stack.fmt(f)?;
-        self.registers.fmt(f)?;
-        self.io.fmt(f)?;
-        self.stack.fmt(f)?;
-        self.delay_timer.fmt(f)?;
-        self.program_ended.fmt(f)?;
+/// Returns an error if the program doesn't fit in the memory after the program start
+fn check_program_size(program: &[u8], program_start: usize, memory_size: usize) -> Result<()> {
+    let program_memory_size = memory_size.saturating_sub(program_start);
 
-        Ok(())
+    if program.len() >= program_memory_size {
+        bail!(ErrorKind::ProgramTooLarge(program.len(), program_memory_size));
     }
+
+    Ok(())
+}
+
+/// Returns the first address that makes the range of memory invalid, for reporting errors
+fn first_invalid(start: usize, end: usize, memory_size: usize) -> usize {
+    if end > memory_size { start.max(memory_size) } else { start }
 }
@@ -0,0 +1,789 @@
+//! A libretro core, so the emulator can be run in libretro frontends such as RetroArch
+//!
+//! Build the core as a dynamic library with the `libretro` feature enabled:
+//!
+//! ```text
+//! cargo rustc --release --features libretro --crate-type cdylib
+//! ```
+//!
+//! The Chip-8 keypad is mapped to the RetroPad as follows:
+//!
+//! ```text
+//! Up: 5     Down: 8    Left: 7    Right: 9
+//! A: 6      B: 4       X: 1       Y: 2
+//! L: 3      R: C       L2: D      R2: E
+//! L3: A     R3: B      Select: 0  Start: F
+//! ```
+//!
+//! The quirks and speed can be changed with the core options. If the quirks are set to `auto`, the
+//! quirks in the metadata of the program are used (see `rom`).
+//!
+//! Save states use the format of `State::to_bytes`. The sound is a square wave beep, unless the
+//! program sets an XO-CHIP audio pattern, which is played instead.
+
+#![allow(unsafe_code)]
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+use super::{Chip8, Chip8IO, Keypad, SCREEN_WIDTH, SCREEN_HEIGHT, AUDIO_PATTERN_LEN,
+            audio_sample_rate};
+use config::{Config, Quirks};
+use rom::Rom;
+use state::State;
+
+/// The version of the libretro API implemented
+const API_VERSION: c_uint = 1;
+/// The number of frames run per second
+const FPS: f64 = 60.0;
+/// The sample rate of the audio
+const SAMPLE_RATE: usize = 44100;
+/// The frequency of the beep
+const FREQUENCY: usize = 440;
+/// The volume of the beep
+const VOLUME: i16 = 0x1000;
+/// The number of instructions run per second, unless set by the core options or the program
+const DEFAULT_SPEED: u32 = 700;
+
+/// `RETRO_ENVIRONMENT_SET_PIXEL_FORMAT`
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+/// `RETRO_ENVIRONMENT_GET_VARIABLE`
+const ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+/// `RETRO_ENVIRONMENT_SET_VARIABLES`
+const ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+/// `RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE`
+const ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+/// `RETRO_PIXEL_FORMAT_XRGB8888`
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+/// `RETRO_DEVICE_JOYPAD`
+const DEVICE_JOYPAD: c_uint = 1;
+/// `RETRO_MEMORY_SYSTEM_RAM`
+const MEMORY_SYSTEM_RAM: c_uint = 2;
+/// `RETRO_REGION_NTSC`
+const REGION_NTSC: c_uint = 0;
+
+/// The Chip-8 key for each RetroPad button, indexed by the ID of the button
+const KEYMAP: [u8; 16] = [
+    0x4, // B
+    0x2, // Y
+    0x0, // Select
+    0xF, // Start
+    0x5, // Up
+    0x8, // Down
+    0x7, // Left
+    0x9, // Right
+    0x6, // A
+    0x1, // X
+    0x3, // L
+    0xC, // R
+    0xD, // L2
+    0xE, // R2
+    0xA, // L3
+    0xB, // R3
+];
+
+/// The core option for the quirks
+const QUIRKS_OPTION: &[u8] = b"chip8_quirks\0";
+/// The core option for the speed
+const SPEED_OPTION: &[u8] = b"chip8_speed\0";
+
+/// `retro_environment_t`
+pub type EnvironmentFn = extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+/// `retro_video_refresh_t`
+pub type VideoRefreshFn = extern "C" fn(data: *const c_void,
+                                        width: c_uint,
+                                        height: c_uint,
+                                        pitch: usize);
+/// `retro_audio_sample_t`
+pub type AudioSampleFn = extern "C" fn(left: i16, right: i16);
+/// `retro_audio_sample_batch_t`
+pub type AudioSampleBatchFn = extern "C" fn(data: *const i16, frames: usize) -> usize;
+/// `retro_input_poll_t`
+pub type InputPollFn = extern "C" fn();
+/// `retro_input_state_t`
+pub type InputStateFn = extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint)
+                                      -> i16;
+
+/// `struct retro_system_info`
+#[repr(C)]
+#[derive(Debug)]
+#[allow(missing_docs)]
+pub struct SystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+/// `struct retro_game_geometry`
+#[repr(C)]
+#[derive(Debug)]
+#[allow(missing_docs)]
+pub struct GameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+/// `struct retro_system_timing`
+#[repr(C)]
+#[derive(Debug)]
+#[allow(missing_docs)]
+pub struct SystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+/// `struct retro_system_av_info`
+#[repr(C)]
+#[derive(Debug)]
+#[allow(missing_docs)]
+pub struct SystemAvInfo {
+    pub geometry: GameGeometry,
+    pub timing: SystemTiming,
+}
+
+/// `struct retro_game_info`
+#[repr(C)]
+#[derive(Debug)]
+#[allow(missing_docs)]
+pub struct GameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+/// `struct retro_variable`
+#[repr(C)]
+#[derive(Debug)]
+#[allow(missing_docs)]
+pub struct Variable {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+/// The callbacks set by the frontend
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+/// The loaded program and the emulator running it
+struct Core {
+    /// The program, used for resetting
+    rom: Rom,
+    /// The emulator
+    chip8: Chip8,
+    /// The number of cycles to run per frame
+    frame_cycles: u32,
+    /// The screen, in the `XRGB8888` format
+    framebuffer: Vec<u32>,
+    /// The input and sound state
+    io: RetroIO,
+    /// The position in the period of the beep or audio pattern, in samples of the pattern
+    phase: f64,
+}
+
+/// An implementation of `Chip8IO` that only stores the input and sound state, as the screen and
+/// sound are output once per frame
+struct RetroIO {
+    keys: Keypad,
+    /// Whether the sound is playing
+    sound: bool,
+    /// The audio pattern and pitch set by the program, if any (XO-CHIP)
+    pattern: Option<([u8; AUDIO_PATTERN_LEN], u8)>,
+}
+
+impl Chip8IO for RetroIO {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_planes(&mut self, _: &[u8], _: &::Palette) {}
+    fn get_keys(&mut self) -> Keypad {
+        self.keys
+    }
+    fn sound_start(&mut self) {
+        self.sound = true;
+    }
+    fn sound_stop(&mut self) {
+        self.sound = false;
+    }
+    fn set_audio_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_LEN], pitch: u8) {
+        self.pattern = Some((*pattern, pitch));
+    }
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+/// Returns a copy of the callbacks, so they can be called without holding the lock, as they may
+/// call functions of the core that set the callbacks
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+/// Returns the value of the core option, if the frontend provides it
+fn option(environment: EnvironmentFn, key: &'static [u8]) -> Option<String> {
+    let mut variable = Variable {
+        key: key.as_ptr() as *const c_char,
+        value: ptr::null(),
+    };
+
+    if !environment(ENVIRONMENT_GET_VARIABLE, &mut variable as *mut _ as *mut c_void) ||
+       variable.value.is_null() {
+        return None;
+    }
+
+    let value = unsafe { CStr::from_ptr(variable.value) };
+    value.to_str().ok().map(str::to_string)
+}
+
+/// Creates an emulator running the program, configured by the core options and the metadata of
+/// the program
+fn create_core(rom: Rom) -> Option<Core> {
+    let environment = callbacks().environment;
+
+    let quirks = environment.and_then(|e| option(e, QUIRKS_OPTION));
+    let speed = environment.and_then(|e| option(e, SPEED_OPTION));
+
+    let mut config = rom.metadata().apply(Config::new());
+
+    if let Some(quirks) = quirks.as_ref().and_then(|q| Quirks::preset(q)) {
+        config = config.quirks(quirks);
+    }
+
+    let speed = speed.and_then(|s| s.parse().ok())
+        .or(rom.metadata().speed)
+        .unwrap_or(DEFAULT_SPEED);
+
+    let chip8 = Chip8::new(rom.program(), config).ok()?;
+
+    Some(Core {
+        rom,
+        chip8,
+        frame_cycles: (speed as f64 / FPS).ceil() as u32,
+        framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        io: RetroIO {
+            keys: Keypad::new(),
+            sound: false,
+            pattern: None,
+        },
+        phase: 0.0,
+    })
+}
+
+/// Returns the keys pressed on the RetroPad
+fn read_keys() -> Keypad {
+    let callbacks = callbacks();
+    let mut keys = Keypad::new();
+
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+
+    if let Some(input_state) = callbacks.input_state {
+        for (id, &key) in KEYMAP.iter().enumerate() {
+            keys.set(key, input_state(0, DEVICE_JOYPAD, 0, id as c_uint) != 0);
+        }
+    }
+
+    keys
+}
+
+impl Core {
+    /// Runs a frame, then outputs the screen and sound
+    fn run_frame(&mut self, keys: Keypad) {
+        self.io.keys = keys;
+
+        for _ in 0..self.frame_cycles {
+            // There is no way to report errors to the frontend, so the program stops running
+            if self.chip8.program_ended() || self.chip8.cycle(&mut self.io).is_err() {
+                self.chip8.program_ended = true;
+                break;
+            }
+        }
+
+        self.chip8.tick_60hz(&mut self.io);
+
+        let colors = self.chip8
+            .palette
+            .colors
+            .iter()
+            .map(|c| (c[0] as u32) << 16 | (c[1] as u32) << 8 | c[2] as u32)
+            .collect::<Vec<_>>();
+
+        for (pixel, &planes) in self.framebuffer.iter_mut().zip(self.chip8.planes()) {
+            *pixel = colors[planes as usize];
+        }
+
+        let callbacks = callbacks();
+
+        if let Some(video_refresh) = callbacks.video_refresh {
+            video_refresh(self.framebuffer.as_ptr() as *const c_void,
+                          SCREEN_WIDTH as c_uint,
+                          SCREEN_HEIGHT as c_uint,
+                          SCREEN_WIDTH * 4);
+        }
+
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            let frames = (SAMPLE_RATE as f64 / FPS) as usize;
+            let mut samples = Vec::with_capacity(frames * 2);
+
+            // The beep is a square wave, which is a pattern of two samples (on, then off)
+            let (length, rate) = match self.io.pattern {
+                Some((_, pitch)) => (AUDIO_PATTERN_LEN as f64 * 8.0, audio_sample_rate(pitch)),
+                None => (2.0, FREQUENCY as f64 * 2.0),
+            };
+
+            for _ in 0..frames {
+                let position = self.phase as usize;
+                let on = match self.io.pattern {
+                    Some((ref pattern, _)) => pattern[position / 8] & (0x80 >> (position % 8)) != 0,
+                    None => position == 0,
+                };
+
+                let sample = match (self.io.sound, on) {
+                    (false, _) => 0,
+                    (true, true) => VOLUME,
+                    (true, false) => -VOLUME,
+                };
+
+                // Stereo
+                samples.push(sample);
+                samples.push(sample);
+                self.phase = (self.phase + rate / SAMPLE_RATE as f64) % length;
+            }
+
+            audio_sample_batch(samples.as_ptr(), frames);
+        }
+    }
+}
+
+/// Sets the environment callback, and declares the core options
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(environment);
+
+    let variables = [Variable {
+                         key: QUIRKS_OPTION.as_ptr() as *const c_char,
+                         value: b"Quirks; auto|chip8|schip\0".as_ptr() as *const c_char,
+                     },
+                     Variable {
+                         key: SPEED_OPTION.as_ptr() as *const c_char,
+                         value: b"Instructions per second; 700|500|1000|1500|2000\0".as_ptr() as
+                                *const c_char,
+                     },
+                     Variable {
+                         key: ptr::null(),
+                         value: ptr::null(),
+                     }];
+
+    environment(ENVIRONMENT_SET_VARIABLES, variables.as_ptr() as *mut c_void);
+}
+
+/// Sets the video refresh callback
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(video_refresh);
+}
+
+/// Sets the audio sample callback (unused, as audio is output in batches)
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_: AudioSampleFn) {}
+
+/// Sets the audio sample batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(audio_sample_batch);
+}
+
+/// Sets the input poll callback
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(input_poll);
+}
+
+/// Sets the input state callback
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(input_state);
+}
+
+/// Initializes the core
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+/// Deinitializes the core, unloading any loaded program
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// Returns the version of the libretro API implemented
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+/// Returns information about the core
+///
+/// # Safety
+///
+/// `info` must be null or valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    let info = match info.as_mut() {
+        Some(info) => info,
+        None => return,
+    };
+
+    info.library_name = b"chip8\0".as_ptr() as *const c_char;
+    info.library_version = concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char;
+    info.valid_extensions = b"ch8|c8|sc8|xo8\0".as_ptr() as *const c_char;
+    info.need_fullpath = false;
+    info.block_extract = false;
+}
+
+/// Returns the size of the screen and the timing of frames and audio
+///
+/// # Safety
+///
+/// `info` must be null or valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    let info = match info.as_mut() {
+        Some(info) => info,
+        None => return,
+    };
+
+    info.geometry = GameGeometry {
+        base_width: SCREEN_WIDTH as c_uint,
+        base_height: SCREEN_HEIGHT as c_uint,
+        max_width: SCREEN_WIDTH as c_uint,
+        max_height: SCREEN_HEIGHT as c_uint,
+        aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+    };
+    info.timing = SystemTiming {
+        fps: FPS,
+        sample_rate: SAMPLE_RATE as f64,
+    };
+}
+
+/// Sets the device plugged into a port (only the RetroPad is supported)
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// Restarts the loaded program
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    let mut core = CORE.lock().unwrap();
+
+    if let Some(rom) = core.take().map(|c| c.rom) {
+        *core = create_core(rom);
+    }
+}
+
+/// Runs a frame
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let keys = read_keys();
+
+    let environment = callbacks().environment;
+    let mut updated = false;
+    if let Some(environment) = environment {
+        environment(ENVIRONMENT_GET_VARIABLE_UPDATE,
+                    &mut updated as *mut bool as *mut c_void);
+    }
+
+    let mut core = CORE.lock().unwrap();
+
+    // Changing the core options restarts the program
+    if updated {
+        if let Some(rom) = core.take().map(|c| c.rom) {
+            *core = create_core(rom);
+        }
+    }
+
+    if let Some(ref mut core) = *core {
+        core.run_frame(keys);
+    }
+}
+
+/// Returns the size of the buffer needed for a save state
+/// The size of a state depends on the number of addresses on the stack, so there is room for as
+/// many as the stack can hold.
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    match *CORE.lock().unwrap() {
+        Some(ref core) => {
+            let state = core.chip8.save_state().to_bytes();
+            state.len() - core.chip8.stack.len() * 2 + core.chip8.stack_depth * 2
+        }
+        None => 0,
+    }
+}
+
+/// Writes a save state into the buffer
+///
+/// # Safety
+///
+/// `data` must be null, or valid to write `size` bytes to.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let core = CORE.lock().unwrap();
+    let core = match *core {
+        Some(ref core) => core,
+        None => return false,
+    };
+
+    let state = core.chip8.save_state().to_bytes();
+    if state.len() > size {
+        return false;
+    }
+
+    let buffer = slice::from_raw_parts_mut(data as *mut u8, size);
+    buffer[..state.len()].copy_from_slice(&state);
+
+    for byte in &mut buffer[state.len()..] {
+        *byte = 0;
+    }
+
+    true
+}
+
+/// Loads a save state from the buffer
+///
+/// # Safety
+///
+/// `data` must be null, or point to `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let mut core = CORE.lock().unwrap();
+    let core = match *core {
+        Some(ref mut core) => core,
+        None => return false,
+    };
+
+    let buffer = slice::from_raw_parts(data as *const u8, size);
+
+    match State::from_bytes(buffer) {
+        Ok(state) => {
+            core.chip8.load_state(&state);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Removes all cheats (cheats are not supported)
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+/// Adds a cheat (cheats are not supported)
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// Loads a program, returning whether it was loaded successfully
+///
+/// # Safety
+///
+/// `game` must be null, or point to a `GameInfo` whose `data` is null or points to `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    let game = match game.as_ref() {
+        Some(game) => game,
+        None => return false,
+    };
+
+    if game.data.is_null() {
+        return false;
+    }
+
+    let environment = callbacks().environment;
+    let mut format = PIXEL_FORMAT_XRGB8888;
+    let supported = environment.is_some_and(|e| {
+        e(ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void)
+    });
+
+    if !supported {
+        return false;
+    }
+
+    let bytes = slice::from_raw_parts(game.data as *const u8, game.size);
+    let core = Rom::new(bytes.to_vec()).ok().and_then(create_core);
+    let loaded = core.is_some();
+    *CORE.lock().unwrap() = core;
+
+    loaded
+}
+
+/// Loads a special type of program (not supported)
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_type: c_uint,
+                                          _info: *const GameInfo,
+                                          _num: usize)
+                                          -> bool {
+    false
+}
+
+/// Unloads the loaded program
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// Returns the region of the loaded program
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+/// Returns a pointer to a region of memory, so the frontend can read and write it (for example,
+/// for cheats or achievements)
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match *CORE.lock().unwrap() {
+        Some(ref mut core) if id == MEMORY_SYSTEM_RAM => {
+            core.chip8.memory.as_mut_ptr() as *mut c_void
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Returns the size of a region of memory
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match *CORE.lock().unwrap() {
+        Some(ref core) if id == MEMORY_SYSTEM_RAM => core.chip8.memory.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Held by the tests, as the core is shared by all of them
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Supports the pixel format of the core, and no core options
+    extern "C" fn environment(cmd: c_uint, _: *mut c_void) -> bool {
+        cmd == ENVIRONMENT_SET_PIXEL_FORMAT
+    }
+
+    /// Loads the program, returning whether it was loaded
+    fn load(program: &[u8]) -> bool {
+        retro_set_environment(environment);
+
+        let game = GameInfo {
+            path: ptr::null(),
+            data: program.as_ptr() as *const c_void,
+            size: program.len(),
+            meta: ptr::null(),
+        };
+
+        unsafe { retro_load_game(&game) }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let _lock = TEST_LOCK.lock().unwrap();
+
+        // Calls itself forever, filling the stack
+        let program = [0x22, 0x00];
+        assert!(load(&program));
+
+        // Use a stack deeper than the state was sized for before
+        let depth = 100;
+        let mut core = CORE.lock().unwrap();
+        let hash = {
+            let core = core.as_mut().unwrap();
+            core.chip8 = Chip8::new(&program, Config::new().stack_depth(depth)).unwrap();
+            for _ in 0..depth {
+                core.chip8.cycle(&mut core.io).unwrap();
+            }
+            core.chip8.state_hash()
+        };
+        drop(core);
+
+        let mut buffer = vec![0; retro_serialize_size()];
+        unsafe {
+            assert!(retro_serialize(buffer.as_mut_ptr() as *mut c_void, buffer.len()));
+            assert!(!retro_serialize(ptr::null_mut(), buffer.len()));
+        }
+
+        retro_reset();
+        assert!(CORE.lock().unwrap().as_ref().unwrap().chip8.stack.is_empty());
+
+        unsafe {
+            assert!(retro_unserialize(buffer.as_ptr() as *const c_void, buffer.len()));
+            assert!(!retro_unserialize(ptr::null(), buffer.len()));
+            assert!(!retro_unserialize(buffer.as_ptr() as *const c_void, 4));
+        }
+
+        let core = CORE.lock().unwrap();
+        assert_eq!(depth, core.as_ref().unwrap().chip8.stack.len());
+        assert_eq!(hash, core.as_ref().unwrap().chip8.state_hash());
+        drop(core);
+
+        retro_unload_game();
+    }
+
+    /// Sets the input state callback again while the core is reading the input
+    extern "C" fn input_poll() {
+        retro_set_input_state(input_state);
+    }
+
+    /// Presses key 5 (Up)
+    extern "C" fn input_state(_: c_uint, _: c_uint, _: c_uint, id: c_uint) -> i16 {
+        (id == 4) as i16
+    }
+
+    /// Sets the video refresh callback again while the core is drawing the frame
+    extern "C" fn video_refresh(_: *const c_void, _: c_uint, _: c_uint, _: usize) {
+        retro_set_video_refresh(video_refresh);
+    }
+
+    #[test]
+    fn test_reentrant_callbacks() {
+        let _lock = TEST_LOCK.lock().unwrap();
+
+        // Loops until key 5 is pressed, then ends
+        assert!(load(&[0x60, 0x05, 0xE0, 0x9E, 0x12, 0x02, 0x1F, 0xFF]));
+        retro_set_input_poll(input_poll);
+        retro_set_input_state(input_state);
+        retro_set_video_refresh(video_refresh);
+
+        retro_run();
+        assert!(CORE.lock().unwrap().as_ref().unwrap().chip8.program_ended());
+
+        retro_unload_game();
+    }
+}
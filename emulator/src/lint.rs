@@ -0,0 +1,222 @@
+//! Static checks that find bugs in programs before they crash at runtime
+//!
+//! `lint` follows the code that can be reached from the start of the program, one subroutine at a
+//! time, and reports invalid opcodes, jumps and calls to addresses outside the program, writes to
+//! the memory holding the font, and calls that can recurse. The value of I is tracked along each
+//! path as far as it is known without running the program, so writes to the font are only found
+//! when I was set by a `LD I` or `LD F` instruction on the same path.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use instruction::Instruction;
+use interpreter::interpret_instruction;
+use super::PROGRAM_START;
+
+/// A problem found in a program
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Issue {
+    /// The opcode isn't a valid instruction
+    InvalidOpcode(u16),
+    /// A jump or call to an address outside the program
+    OutsideProgram(u16),
+    /// Registers or a BCD number are stored to the memory holding the font
+    FontWrite,
+    /// A call to a subroutine that can end up calling the subroutine making the call again, which
+    /// overflows the stack unless the recursion is bounded
+    Recursion(u16),
+}
+
+/// A problem found at an address of a program
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Warning {
+    /// The address of the instruction with the problem
+    pub address: u16,
+    /// The problem
+    pub issue: Issue,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:03X}: ", self.address)?;
+
+        match self.issue {
+            Issue::InvalidOpcode(opcode) => write!(f, "Invalid opcode 0x{:04X}", opcode),
+            Issue::OutsideProgram(target) => {
+                write!(f, "Jump to 0x{:03X}, which is outside the program", target)
+            }
+            Issue::FontWrite => write!(f, "Write to the memory holding the font"),
+            Issue::Recursion(target) => {
+                write!(f,
+                       "Recursive call to 0x{:03X}, which overflows the stack unless it is bounded",
+                       target)
+            }
+        }
+    }
+}
+
+/// What is known about the value of I at an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Index {
+    /// I holds this address
+    Known(u16),
+    /// I points to a character of the font
+    Font,
+    /// I could hold any address
+    Unknown,
+}
+
+/// Returns the problems found in the program, ordered by address
+pub fn lint(program: &[u8]) -> Vec<Warning> {
+    let mut warnings = BTreeSet::new();
+    // The calls made by each subroutine, as the addresses of the calls and their targets
+    let mut calls = BTreeMap::new();
+    let mut pending = vec![PROGRAM_START as u16];
+
+    while let Some(entry) = pending.pop() {
+        if calls.contains_key(&entry) {
+            continue;
+        }
+
+        let subroutine_calls = check_subroutine(program, entry, &mut warnings);
+        pending.extend(subroutine_calls.iter().map(|&(_, target)| target));
+        calls.insert(entry, subroutine_calls);
+    }
+
+    for (&entry, subroutine_calls) in &calls {
+        for &(address, target) in subroutine_calls {
+            if calls_reach(&calls, target, entry) {
+                warnings.insert(Warning {
+                    address,
+                    issue: Issue::Recursion(target),
+                });
+            }
+        }
+    }
+
+    warnings.into_iter().collect()
+}
+
+/// Follows the code of the subroutine starting at `entry` until it returns, without following
+/// calls, and adds the problems found to `warnings`
+/// Returns the addresses and targets of the calls the subroutine makes.
+fn check_subroutine(program: &[u8],
+                    entry: u16,
+                    warnings: &mut BTreeSet<Warning>)
+                    -> BTreeSet<(u16, u16)> {
+    use instruction::Instruction::*;
+
+    let mut calls = BTreeSet::new();
+    let mut reached = BTreeSet::new();
+    let mut pending = vec![(entry, Index::Unknown)];
+
+    while let Some((address, index)) = pending.pop() {
+        if !reached.insert((address, index)) {
+            continue;
+        }
+
+        // Jumps outside the program are reported where they are made
+        let opcode = match opcode_at(program, address) {
+            Some(opcode) => opcode,
+            None => continue,
+        };
+        let instruction = match interpret_instruction(opcode) {
+            Ok(instruction) => instruction,
+            Err(_) => {
+                warnings.insert(Warning {
+                    address,
+                    issue: Issue::InvalidOpcode(opcode),
+                });
+                continue;
+            }
+        };
+
+        let mut warn = |issue| {
+            warnings.insert(Warning {
+                address,
+                issue,
+            });
+        };
+        let next = address + 2;
+
+        if let Some(target) = jump_target(&instruction) {
+            if opcode_at(program, target).is_none() {
+                warn(Issue::OutsideProgram(target));
+            }
+        }
+
+        match instruction {
+            Return | OffsetGoto(_) => {}
+            Goto(addr) => pending.push((addr, index)),
+            Call(addr) => {
+                calls.insert((address, addr));
+                // The subroutine may change I
+                pending.push((next, Index::Unknown));
+            }
+            SkipEqConst(..) | SkipNeqConst(..) | SkipEq(..) | SkipNeq(..) | SkipKey(_) |
+            SkipNotKey(_) | SkipKey2(_) | SkipNotKey2(_) => {
+                pending.extend(&[(next, index), (next + 2, index)])
+            }
+            SetIndex(addr) => pending.push((next, Index::Known(addr))),
+            SetIndexChar(_) | SetIndexLargeChar(_) => pending.push((next, Index::Font)),
+            AddIndex(_) | RegLoad(_) => pending.push((next, Index::Unknown)),
+            SetIndexLong(_) => pending.push((next + 2, Index::Unknown)),
+            RegDump(_) | BCD(_) => {
+                let font_write = match index {
+                    Index::Known(addr) => (addr as usize) < PROGRAM_START,
+                    Index::Font => true,
+                    Index::Unknown => false,
+                };
+                if font_write {
+                    warn(Issue::FontWrite);
+                }
+
+                // Storing registers may increment I, depending on the quirks
+                let index = if let BCD(_) = instruction { index } else { Index::Unknown };
+                pending.push((next, index));
+            }
+            _ => pending.push((next, index)),
+        }
+    }
+
+    calls
+}
+
+/// Returns the address a jump or call goes to, or the base address of a `JP V0` jump
+fn jump_target(instruction: &Instruction) -> Option<u16> {
+    match *instruction {
+        Instruction::Goto(addr) |
+        Instruction::Call(addr) |
+        Instruction::OffsetGoto(addr) => Some(addr),
+        _ => None,
+    }
+}
+
+/// Returns the opcode at the address, or `None` if the address is outside the program
+fn opcode_at(program: &[u8], address: u16) -> Option<u16> {
+    (address as usize)
+        .checked_sub(PROGRAM_START)
+        .and_then(|start| program.get(start..start + 2))
+        .map(|bytes| (bytes[0] as u16) << 8 | bytes[1] as u16)
+}
+
+/// Returns whether the subroutine at `from` can call the subroutine at `to`, directly or through
+/// other subroutines (or is the same subroutine)
+fn calls_reach(calls: &BTreeMap<u16, BTreeSet<(u16, u16)>>, from: u16, to: u16) -> bool {
+    let mut reached = BTreeSet::new();
+    let mut pending = vec![from];
+
+    while let Some(entry) = pending.pop() {
+        if entry == to {
+            return true;
+        }
+        if !reached.insert(entry) {
+            continue;
+        }
+        if let Some(subroutine_calls) = calls.get(&entry) {
+            pending.extend(subroutine_calls.iter().map(|&(_, target)| target));
+        }
+    }
+
+    false
+}
@@ -0,0 +1,134 @@
+//! The screen of Mega-Chip mode
+//!
+//! In Mega-Chip mode, programs draw to a 256x192 screen where each pixel is an index into a palette
+//! that the program loads itself. Sprites are drawn with one byte per pixel, and pixels with index
+//! 0 are transparent. Like the original interpreter, the screen is only shown when the program
+//! clears it, so programs draw each frame in full before it is shown.
+
+use io::{Color, Palette};
+use super::{MEGA_WIDTH, MEGA_HEIGHT};
+
+/// The amount of pixels in the Mega-Chip screen
+pub const MEGA_PIXELS: usize = MEGA_WIDTH * MEGA_HEIGHT;
+
+/// The state of the Mega-Chip screen
+#[derive(Clone, Debug)]
+pub struct MegaScreen {
+    /// The index into the palette of each pixel
+    pixels: Vec<u8>,
+    /// The palette, which starts as the configured one and is changed by `LoadPalette`
+    palette: Palette,
+    /// The width of sprites, where 0 is 256
+    sprite_width: u8,
+    /// The height of sprites, where 0 is 256
+    sprite_height: u8,
+    /// The color index that causes a collision when drawn over
+    collision_color: u8,
+}
+
+impl MegaScreen {
+    /// Returns a cleared screen that uses the palette
+    pub fn new(palette: Palette) -> MegaScreen {
+        MegaScreen {
+            pixels: vec![0; MEGA_PIXELS],
+            palette,
+            sprite_width: 0,
+            sprite_height: 0,
+            collision_color: 0,
+        }
+    }
+
+    /// Returns the index into the palette of each pixel
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Returns the palette
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Clears the screen
+    pub fn clear(&mut self) {
+        for pixel in &mut self.pixels {
+            *pixel = 0;
+        }
+    }
+
+    /// Sets the colors of the palette starting at index 1, from colors stored as 4 bytes of
+    /// alpha, red, green, and blue
+    pub fn load_palette(&mut self, colors: &[u8]) {
+        for (i, argb) in colors.chunks(4).enumerate() {
+            let color: Color = [argb[1], argb[2], argb[3]];
+            self.palette.colors[i + 1] = color;
+        }
+    }
+
+    /// Returns the width and height of sprites, in pixels
+    pub fn sprite_size(&self) -> (usize, usize) {
+        let size = |n| if n == 0 { 256 } else { n as usize };
+        (size(self.sprite_width), size(self.sprite_height))
+    }
+
+    /// Sets the width of sprites, where 0 is 256
+    pub fn set_sprite_width(&mut self, width: u8) {
+        self.sprite_width = width;
+    }
+
+    /// Sets the height of sprites, where 0 is 256
+    pub fn set_sprite_height(&mut self, height: u8) {
+        self.sprite_height = height;
+    }
+
+    /// Sets the color index that causes a collision when drawn over
+    pub fn set_collision_color(&mut self, color: u8) {
+        self.collision_color = color;
+    }
+
+    /// Draws a sprite with its top left corner at (x, y), where `sprite` holds the color index of
+    /// each pixel in row-major order
+    /// Pixels with index 0 are not drawn, and neither are pixels outside of the screen. Returns
+    /// whether a pixel of the collision color was drawn over.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        let (width, _) = self.sprite_size();
+        let mut collision = false;
+
+        for (i, &color) in sprite.iter().enumerate() {
+            let (pixel_x, pixel_y) = (x + i % width, y + i / width);
+
+            if color == 0 || pixel_x >= MEGA_WIDTH || pixel_y >= MEGA_HEIGHT {
+                continue;
+            }
+
+            let pixel = &mut self.pixels[pixel_x + pixel_y * MEGA_WIDTH];
+            if *pixel != 0 && *pixel == self.collision_color {
+                collision = true;
+            }
+            *pixel = color;
+        }
+
+        collision
+    }
+
+    /// Scrolls the screen up by the number of pixels, clearing the rows at the bottom
+    pub fn scroll_up(&mut self, rows: usize) {
+        let rows = rows.min(MEGA_HEIGHT);
+        let start = rows * MEGA_WIDTH;
+
+        self.pixels.drain(..start);
+        self.pixels.resize(MEGA_PIXELS, 0);
+    }
+}
+
+/// Returns the pixels of the Mega-Chip screen scaled down to the size of the normal screen, for
+/// backends that can't show the Mega-Chip screen
+pub fn scale_down(pixels: &[u8]) -> Vec<u8> {
+    let (scale_x, scale_y) = (MEGA_WIDTH / ::SCREEN_WIDTH, MEGA_HEIGHT / ::SCREEN_HEIGHT);
+
+    (0..::SCREEN_WIDTH * ::SCREEN_HEIGHT)
+        .map(|i| {
+            let (x, y) = (i % ::SCREEN_WIDTH * scale_x, i / ::SCREEN_WIDTH * scale_y);
+            pixels[x + y * MEGA_WIDTH]
+        })
+        .collect()
+}
@@ -0,0 +1,126 @@
+//! Counters of what the emulator has done, for showing statistics such as the number of
+//! instructions or frames per second
+//!
+//! Unlike the profile (see `profile`), metrics are always counted, and only count what is cheap to
+//! count. A frontend can read them once per second and reset them (see `Chip8::reset_metrics`) to
+//! get the rates over the last second.
+
+use std::time::Duration;
+
+use super::Chip8;
+
+/// The number of timer ticks per second
+const TICKS_PER_SECOND: u64 = 60;
+
+/// Counters of what the emulator has done since it was created or the metrics were last reset
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// The number of cycles run
+    cycles: u64,
+    /// The number of instructions run in each class, by the highest nibble of their opcode
+    classes: [u64; 16],
+    /// The number of sprites drawn
+    draws: u64,
+    /// The number of timer ticks (frames)
+    ticks: u64,
+    /// The number of timer ticks that passed while the program was waiting for a key
+    key_wait_ticks: u64,
+}
+
+impl Metrics {
+    /// Returns metrics with every counter at zero
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Counts a cycle that ran the opcode
+    pub(crate) fn record_cycle(&mut self, opcode: u16) {
+        self.cycles += 1;
+        self.classes[(opcode >> 12) as usize] += 1;
+    }
+
+    /// Counts a sprite drawn by a `Draw` instruction
+    pub(crate) fn record_draw(&mut self) {
+        self.draws += 1;
+    }
+
+    /// Counts a timer tick, during which the program may have been waiting for a key
+    pub(crate) fn record_tick(&mut self, waiting_for_key: bool) {
+        self.ticks += 1;
+        if waiting_for_key {
+            self.key_wait_ticks += 1;
+        }
+    }
+
+    /// Returns the number of cycles run
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the number of instructions run in each class, where class `N` holds the opcodes
+    /// whose highest nibble is `N` (for example, class `0xD` holds `Draw`)
+    /// Invalid opcodes skipped in permissive mode are counted as well.
+    pub fn instruction_classes(&self) -> &[u64; 16] {
+        &self.classes
+    }
+
+    /// Returns the number of sprites drawn
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+
+    /// Returns the number of timer ticks (frames)
+    pub fn timer_ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Returns the emulated time that passed, going by the number of timer ticks
+    pub fn emulated_time(&self) -> Duration {
+        ticks_to_duration(self.ticks)
+    }
+
+    /// Returns the emulated time that passed while the program was waiting for a key, going by the
+    /// number of timer ticks
+    pub fn key_wait_time(&self) -> Duration {
+        ticks_to_duration(self.key_wait_ticks)
+    }
+
+    /// Returns the number of instructions run per second of emulated time, or `None` if no time
+    /// has passed
+    pub fn instructions_per_second(&self) -> Option<f64> {
+        self.per_second(self.cycles)
+    }
+
+    /// Returns the number of sprites drawn per second of emulated time, or `None` if no time has
+    /// passed
+    pub fn draws_per_second(&self) -> Option<f64> {
+        self.per_second(self.draws)
+    }
+
+    /// Returns the count per second of emulated time, or `None` if no time has passed
+    fn per_second(&self, count: u64) -> Option<f64> {
+        if self.ticks == 0 {
+            None
+        } else {
+            Some(count as f64 * TICKS_PER_SECOND as f64 / self.ticks as f64)
+        }
+    }
+}
+
+/// Returns the duration of the number of timer ticks
+fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_secs(ticks / TICKS_PER_SECOND) +
+    Duration::from_nanos((ticks % TICKS_PER_SECOND) * 1_000_000_000 / TICKS_PER_SECOND)
+}
+
+impl Chip8 {
+    /// Returns the metrics counted since the emulator was created or they were last reset
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Resets every counter of the metrics to zero
+    pub fn reset_metrics(&mut self) {
+        self.metrics = Metrics::new();
+    }
+}
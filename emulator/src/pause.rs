@@ -0,0 +1,106 @@
+//! Pausing and single-stepping the emulator
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// A handle used to pause, resume, and step the emulator
+///
+/// Clones of a handle share the same state, so a frontend can keep a clone to control an emulator
+/// run with `Chip8::run_until_break` (for example, from another thread)
+///
+/// # Examples
+///
+/// ```rust
+/// use chip8::pause::PauseHandle;
+///
+/// let handle = PauseHandle::new();
+/// let frontend = handle.clone();
+///
+/// frontend.pause();
+/// assert!(handle.is_paused());
+///
+/// frontend.toggle();
+/// assert!(!handle.is_paused());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PauseHandle {
+    /// Whether the emulator is paused
+    paused: Arc<AtomicBool>,
+    /// The number of cycles requested to run while paused
+    steps: Arc<AtomicUsize>,
+    /// The number of frames requested to run while paused
+    frames: Arc<AtomicUsize>,
+}
+
+impl PauseHandle {
+    /// Returns a new handle, which is not paused
+    pub fn new() -> PauseHandle {
+        PauseHandle::default()
+    }
+
+    /// Pauses the emulator
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resumes the emulator, discarding any requested steps and frames
+    pub fn resume(&self) {
+        self.steps.store(0, Ordering::SeqCst);
+        self.frames.store(0, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Pauses the emulator if it is running, or resumes it if it is paused
+    pub fn toggle(&self) {
+        if self.is_paused() {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Returns whether the emulator is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Requests a single cycle to run while the emulator is paused
+    /// Has no effect if the emulator is not paused
+    pub fn step(&self) {
+        if self.is_paused() {
+            self.steps.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Requests a single frame to run while the emulator is paused (see `Chip8::advance_frame`)
+    /// Has no effect if the emulator is not paused
+    pub fn advance_frame(&self) {
+        if self.is_paused() {
+            self.frames.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Takes a requested step, returning whether there was one
+    pub(crate) fn take_step(&self) -> bool {
+        take_request(&self.steps)
+    }
+
+    /// Takes a requested frame, returning whether there was one
+    pub(crate) fn take_frame(&self) -> bool {
+        take_request(&self.frames)
+    }
+}
+
+/// Decrements the number of requests if there are any, returning whether there were
+fn take_request(requests: &AtomicUsize) -> bool {
+    let mut count = requests.load(Ordering::SeqCst);
+
+    while count > 0 {
+        match requests.compare_exchange(count, count - 1, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return true,
+            Err(current) => count = current,
+        }
+    }
+
+    false
+}
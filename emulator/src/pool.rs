@@ -0,0 +1,215 @@
+//! Running many independent emulators at once, for training agents on Chip-8 programs
+//!
+//! A `Chip8Pool` steps all of its emulators one frame at a time, each with its own keys, and
+//! returns their screens as one flat array. With the `parallel` feature, the emulators are stepped
+//! in parallel on a thread pool.
+//!
+//! ```rust
+//! use chip8::config::Config;
+//! use chip8::pool::Chip8Pool;
+//! use chip8::Keypad;
+//!
+//! let program = &[0x61, 0xFF, 0x12, 0x00];
+//! let mut pool = Chip8Pool::new(program, 64, Config::new).unwrap();
+//!
+//! pool.set_keys(3, Keypad::from_bits(0xFFFF));
+//! for result in pool.step() {
+//!     result.unwrap();
+//! }
+//!
+//! let screens = pool.framebuffers();
+//! assert_eq!(screens.len(), pool.framebuffer_shape().iter().product::<usize>());
+//! ```
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::{Chip8, Keypad, SCREEN_WIDTH, SCREEN_HEIGHT};
+use config::Config;
+use errors::*;
+use io::KeysIO;
+
+/// An instance of the emulator in a pool
+#[derive(Debug)]
+struct Instance {
+    /// The emulator
+    chip8: Chip8,
+    /// The keys set by `Chip8Pool::set_keys`
+    io: KeysIO,
+}
+
+impl Instance {
+    /// Sets the keys pressed in the emulator, which are read before its next cycle
+    fn set_keys(&mut self, keys: Keypad) {
+        self.io.keys = keys;
+        self.chip8.poll_keys = true;
+    }
+}
+
+/// Many independent emulators, stepped together
+#[derive(Debug)]
+pub struct Chip8Pool {
+    /// The emulators, in the order they were added
+    instances: Vec<Instance>,
+}
+
+impl Chip8Pool {
+    /// Creates `count` emulators running the program, each configured with the config returned by
+    /// `config`
+    /// Returns an error if the program is invalid (see `Chip8::new`).
+    pub fn new<F>(program: &[u8], count: usize, mut config: F) -> Result<Chip8Pool>
+        where F: FnMut() -> Config
+    {
+        let chip8s = (0..count)
+            .map(|_| Chip8::new(program, config()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Chip8Pool::from_instances(chip8s))
+    }
+
+    /// Creates a pool from existing emulators, which can run different programs
+    pub fn from_instances(chip8s: Vec<Chip8>) -> Chip8Pool {
+        let instances = chip8s.into_iter()
+            .map(|chip8| {
+                Instance {
+                    chip8,
+                    io: KeysIO::new(),
+                }
+            })
+            .collect();
+
+        Chip8Pool { instances }
+    }
+
+    /// Returns the number of emulators in the pool
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Returns whether the pool has no emulators
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Returns the emulator at the index
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn instance(&self, index: usize) -> &Chip8 {
+        &self.instances[index].chip8
+    }
+
+    /// Returns the emulator at the index mutably
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn instance_mut(&mut self, index: usize) -> &mut Chip8 {
+        &mut self.instances[index].chip8
+    }
+
+    /// Returns the emulators, removing them from the pool
+    pub fn into_instances(self) -> Vec<Chip8> {
+        self.instances.into_iter().map(|instance| instance.chip8).collect()
+    }
+
+    /// Sets the keys pressed in the emulator at the index, which stay pressed until they are set
+    /// again
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set_keys(&mut self, index: usize, keys: Keypad) {
+        self.instances[index].set_keys(keys);
+    }
+
+    /// Sets the keys pressed in every emulator, in the order of the emulators
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of keys is not the number of emulators.
+    pub fn set_all_keys(&mut self, keys: &[Keypad]) {
+        assert_eq!(keys.len(), self.instances.len(), "Wrong number of keys");
+
+        for (instance, &keys) in self.instances.iter_mut().zip(keys) {
+            instance.set_keys(keys);
+        }
+    }
+
+    /// Runs a frame of every emulator (see `Chip8::advance_frame`), and returns the result of
+    /// each, in the order of the emulators
+    /// Emulators that ended or crashed keep their screen until they are reset.
+    pub fn step(&mut self) -> Vec<Result<()>> {
+        map_instances(&mut self.instances,
+                      |instance| instance.chip8.advance_frame(&mut instance.io))
+    }
+
+    /// Restarts the program of every emulator (see `Chip8::reset`), and releases their keys
+    pub fn reset(&mut self) {
+        map_instances(&mut self.instances, |instance| {
+            instance.chip8.reset();
+            instance.io.keys = Keypad::new();
+        });
+    }
+
+    /// Returns whether each emulator ended or stalled, in the order of the emulators
+    pub fn done(&self) -> Vec<bool> {
+        self.instances
+            .iter()
+            .map(|instance| instance.chip8.program_ended() || instance.chip8.stalled())
+            .collect()
+    }
+
+    /// Returns the shape of the array returned by `framebuffers`, which is the number of
+    /// emulators, then `SCREEN_HEIGHT`, then `SCREEN_WIDTH`
+    pub fn framebuffer_shape(&self) -> [usize; 3] {
+        [self.instances.len(), SCREEN_HEIGHT, SCREEN_WIDTH]
+    }
+
+    /// Returns the screens of every emulator as one flat array, in row-major order with the shape
+    /// returned by `framebuffer_shape`
+    /// Each pixel is the bitmask of the planes it is on in (see `Chip8::planes`), so it is 1 for
+    /// pixels that are on in programs that only use one plane.
+    pub fn framebuffers(&self) -> Vec<u8> {
+        let mut framebuffers = vec![0; self.framebuffer_shape().iter().product()];
+        self.write_framebuffers(&mut framebuffers);
+        framebuffers
+    }
+
+    /// Writes the screens of every emulator to the array, like `framebuffers` but without
+    /// allocating
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the array is not the number of pixels of all screens.
+    pub fn write_framebuffers(&self, framebuffers: &mut [u8]) {
+        let screen_size = SCREEN_WIDTH * SCREEN_HEIGHT;
+        assert_eq!(framebuffers.len(),
+                   self.instances.len() * screen_size,
+                   "Wrong framebuffer size");
+
+        for (instance, screen) in self.instances.iter().zip(framebuffers.chunks_mut(screen_size)) {
+            screen.copy_from_slice(instance.chip8.planes());
+        }
+    }
+}
+
+/// Calls the function on every instance, in parallel if the `parallel` feature is enabled, and
+/// returns the results in the order of the instances
+#[cfg(feature = "parallel")]
+fn map_instances<F, R>(instances: &mut [Instance], f: F) -> Vec<R>
+    where F: Fn(&mut Instance) -> R + Sync + Send,
+          R: Send
+{
+    instances.par_iter_mut().map(f).collect()
+}
+
+/// Calls the function on every instance, in parallel if the `parallel` feature is enabled, and
+/// returns the results in the order of the instances
+#[cfg(not(feature = "parallel"))]
+fn map_instances<F, R>(instances: &mut [Instance], f: F) -> Vec<R>
+    where F: Fn(&mut Instance) -> R
+{
+    instances.iter_mut().map(f).collect()
+}
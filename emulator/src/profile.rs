@@ -0,0 +1,86 @@
+//! Profiling of the instructions run by a program, for finding hot loops
+//!
+//! When enabled (see `Config::profile`), the emulator counts how many times each kind of
+//! instruction and each address was run. The profile is displayed as a report of the most run
+//! instructions and addresses.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The number of addresses shown in the report
+const REPORT_ADDRESSES: usize = 20;
+
+/// The number of times each kind of instruction and each address was run
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The counts of each kind of instruction, by name (such as `Draw`)
+    instructions: BTreeMap<&'static str, u64>,
+    /// The counts of each address
+    addresses: BTreeMap<u16, u64>,
+    /// The total number of instructions run
+    total: u64,
+}
+
+impl Profile {
+    /// Returns an empty profile
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    /// Counts a run of the instruction with the given name at the address
+    pub(crate) fn record(&mut self, address: u16, name: &'static str) {
+        *self.instructions.entry(name).or_insert(0) += 1;
+        *self.addresses.entry(address).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    /// Returns the total number of instructions run
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the number of runs of each kind of instruction, most run first
+    /// Instructions are named after the variants of `Instruction`, for example `Draw`.
+    pub fn instructions(&self) -> Vec<(&'static str, u64)> {
+        sorted(self.instructions.iter().map(|(&name, &count)| (name, count)))
+    }
+
+    /// Returns the number of runs of each address, most run first
+    pub fn addresses(&self) -> Vec<(u16, u64)> {
+        sorted(self.addresses.iter().map(|(&address, &count)| (address, count)))
+    }
+
+    /// Returns the percentage of all instructions run that the count makes up
+    fn percentage(&self, count: u64) -> f64 {
+        count as f64 * 100.0 / self.total.max(1) as f64
+    }
+}
+
+/// Sorts the counts from the most to least runs, keeping ties in their original order
+fn sorted<T, I: Iterator<Item = (T, u64)>>(counts: I) -> Vec<(T, u64)> {
+    let mut counts = counts.collect::<Vec<_>>();
+    counts.sort_by_key(|&(_, count)| Reverse(count));
+    counts
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Instructions run: {}", self.total)?;
+        writeln!(f)?;
+        writeln!(f, "By instruction:")?;
+
+        for (name, count) in self.instructions() {
+            writeln!(f, "  {:<14}{:>12} ({:.1}%)", name, count, self.percentage(count))?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Most run addresses:")?;
+
+        for (address, count) in self.addresses().into_iter().take(REPORT_ADDRESSES) {
+            writeln!(f, "  0x{:03X}{:>12} ({:.1}%)", address, count, self.percentage(count))?;
+        }
+
+        Ok(())
+    }
+}
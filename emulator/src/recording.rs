@@ -0,0 +1,237 @@
+//! Recordings of the display, encoded as animated GIF images
+//!
+//! A `Recorder` collects frames as a program runs, numbered by when they were shown at 60 frames
+//! per second, and `write_gif` encodes them. Frames that are the same as the previous one are only
+//! stored once, so recordings of programs that draw rarely stay small.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use io::Palette;
+use super::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+/// The number of bits used for pixel values, which index a color table of every color of the
+/// palette
+const COLOR_BITS: u8 = 8;
+/// The largest number of bits an LZW code can have
+const MAX_CODE_BITS: u8 = 12;
+/// The largest amount of data a sub-block can hold
+const MAX_SUB_BLOCK: usize = 0xFF;
+
+/// A frame of a recording
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Frame {
+    /// The number of the frame the image was first shown on
+    start: u64,
+    /// The planes of the screen, as in `Chip8::planes`
+    planes: Vec<u8>,
+    palette: Palette,
+}
+
+/// Collects the frames of a recording
+///
+/// # Examples
+///
+/// ```rust
+/// use chip8::recording::Recorder;
+/// use chip8::{Palette, SCREEN_WIDTH, SCREEN_HEIGHT};
+///
+/// let mut recorder = Recorder::new();
+/// let mut planes = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+///
+/// recorder.record(0, &planes, &Palette::default());
+/// planes[0] = 1;
+/// recorder.record(30, &planes, &Palette::default());
+///
+/// let mut gif = Vec::new();
+/// recorder.write_gif(1, &mut gif).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recorder {
+    frames: Vec<Frame>,
+    /// The number of the last frame recorded, even if it was the same as the previous one
+    end: u64,
+}
+
+impl Recorder {
+    /// Returns an empty recorder
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    /// Records the screen shown on the frame, where frames are numbered at 60 frames per second
+    /// Frames must be recorded in order, and frames between recorded ones show the previous
+    /// recorded frame.
+    pub fn record(&mut self, frame: u64, planes: &[u8], palette: &Palette) {
+        self.end = frame;
+
+        if let Some(last) = self.frames.last() {
+            if last.planes == planes && last.palette == *palette {
+                return;
+            }
+        }
+
+        self.frames.push(Frame {
+            start: frame,
+            planes: planes.to_vec(),
+            palette: *palette,
+        });
+    }
+
+    /// Returns whether no frames have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns the number of distinct images recorded
+    pub fn images(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Writes the recording as an animated GIF image that loops forever, with each pixel drawn as
+    /// a `scale` by `scale` square in its color in the palette
+    pub fn write_gif<W: Write>(&self, scale: usize, mut writer: W) -> io::Result<()> {
+        let width = (SCREEN_WIDTH * scale) as u16;
+        let height = (SCREEN_HEIGHT * scale) as u16;
+
+        writer.write_all(b"GIF89a")?;
+        // The logical screen, with no global color table
+        writer.write_all(&[width as u8, (width >> 8) as u8, height as u8, (height >> 8) as u8])?;
+        writer.write_all(&[0, 0, 0])?;
+        // The application extension that makes the animation loop forever
+        writer.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+        let first = self.frames.first().map_or(0, |f| f.start);
+        let end = self.end + 1;
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let next = self.frames.get(i + 1).map_or(end, |f| f.start);
+            // GIF delays are in hundredths of a second, so round the times of the frames to keep
+            // the total length correct
+            let delay = to_centiseconds(next - first) - to_centiseconds(frame.start - first);
+            let delay = delay.min(0xFFFF) as u16;
+
+            // The graphic control extension, which sets the delay
+            writer.write_all(&[0x21, 0xF9, 0x04, 0x00, delay as u8, (delay >> 8) as u8, 0, 0])?;
+            // The image descriptor, with a local color table of the palette
+            writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+            writer.write_all(&[width as u8,
+                               (width >> 8) as u8,
+                               height as u8,
+                               (height >> 8) as u8,
+                               0x80 | (COLOR_BITS - 1)])?;
+            for color in &frame.palette.colors {
+                writer.write_all(color)?;
+            }
+
+            let mut pixels = Vec::with_capacity(width as usize * height as usize);
+            for y in 0..height as usize {
+                for x in 0..width as usize {
+                    pixels.push(frame.planes[x / scale + y / scale * SCREEN_WIDTH]);
+                }
+            }
+
+            writer.write_all(&[COLOR_BITS])?;
+            for block in lzw_encode(&pixels).chunks(MAX_SUB_BLOCK) {
+                writer.write_all(&[block.len() as u8])?;
+                writer.write_all(block)?;
+            }
+            writer.write_all(&[0])?;
+        }
+
+        // The trailer
+        writer.write_all(&[0x3B])
+    }
+}
+
+/// Converts a number of frames at 60 frames per second to hundredths of a second, rounding to
+/// the nearest one
+fn to_centiseconds(frames: u64) -> u64 {
+    (frames * 100 + 30) / 60
+}
+
+/// Writes codes as a stream of bits, least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits that don't fill a byte yet
+    buffer: u32,
+    /// The number of bits in the buffer
+    bits: u8,
+}
+
+impl BitWriter {
+    /// Writes a code that is `size` bits long
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    /// Returns the bytes written, padding the last byte with zeros
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+
+        self.bytes
+    }
+}
+
+/// Compresses pixel values with the variant of LZW used by GIF
+fn lzw_encode(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << COLOR_BITS;
+    let end = clear + 1;
+    // Each code after the end code is a string of pixels, which is found by the code of its prefix
+    // followed by its last pixel
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = COLOR_BITS + 1;
+    let mut writer = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+
+    writer.write(clear, size);
+
+    let mut pixels = pixels.iter();
+    let mut current = match pixels.next() {
+        Some(&pixel) => pixel as u16,
+        None => {
+            writer.write(end, size);
+            return writer.finish();
+        }
+    };
+
+    for &pixel in pixels {
+        match table.get(&(current, pixel)) {
+            Some(&code) => current = code,
+            None => {
+                writer.write(current, size);
+
+                let next = end + 1 + table.len() as u16;
+                if next == 1 << MAX_CODE_BITS {
+                    // The table is full, so start over
+                    writer.write(clear, size);
+                    table.clear();
+                    size = COLOR_BITS + 1;
+                } else {
+                    table.insert((current, pixel), next);
+                    if next == 1 << size {
+                        size += 1;
+                    }
+                }
+
+                current = pixel as u16;
+            }
+        }
+    }
+
+    writer.write(current, size);
+    writer.write(end, size);
+    writer.finish()
+}
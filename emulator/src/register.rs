@@ -1,9 +1,62 @@
 //! Storage of registers
 
+use std::fmt;
+
 type GeneralRegisters = [u8; 16];
 
+/// The ID of a general purpose register, V0 through VF
+/// Can only hold valid IDs, so indexing the registers with it can never go out of bounds
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegisterId(u8);
+
+impl RegisterId {
+    /// The register V0, used as the offset of `OffsetGoto`
+    pub const V0: RegisterId = RegisterId(0);
+    /// The register VF, used as the flag register by many instructions
+    pub const VF: RegisterId = RegisterId(0xF);
+
+    /// Returns the ID of register VX, or `None` if `x` is not in the range 0 to 15
+    pub fn new(x: u8) -> Option<RegisterId> {
+        if x < 16 { Some(RegisterId(x)) } else { None }
+    }
+
+    /// Returns the ID of the register named by the lowest nibble of `nibble`
+    pub fn from_nibble(nibble: u8) -> RegisterId {
+        RegisterId(nibble & 0xF)
+    }
+
+    /// Returns an iterator over the IDs of all registers, from V0 to VF
+    pub fn all() -> impl Iterator<Item = RegisterId> {
+        (0..16).map(RegisterId)
+    }
+
+    /// Returns X, the number of the register VX
+    pub fn index(self) -> u8 {
+        self.0
+    }
+}
+
+// Formatted as the bare number, so decoded instructions read the same as the opcode table
+impl fmt::Debug for RegisterId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::UpperHex for RegisterId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for RegisterId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "V{:X}", self.0)
+    }
+}
+
 /// The registers of the CHIP-8
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Registers {
     /// General purpose registers
     general: GeneralRegisters,
@@ -14,22 +67,23 @@ pub struct Registers {
 }
 
 impl Registers {
-    pub fn new() -> Registers {
+    /// Returns cleared registers, with the program counter at the start of the program
+    pub fn new(program_start: u16) -> Registers {
         Registers {
             general: [0; 16],
             index: 0,
-            program_counter: ::PROGRAM_START as u16,
+            program_counter: program_start,
         }
     }
 
     /// Sets the register to the given value
-    pub fn set(&mut self, id: u8, value: u8) {
-        self.general[id as usize] = value;
+    pub fn set(&mut self, id: RegisterId, value: u8) {
+        self.general[id.0 as usize] = value;
     }
 
     /// Returns the value of the register
-    pub fn get(&self, id: u8) -> u8 {
-        self.general[id as usize]
+    pub fn get(&self, id: RegisterId) -> u8 {
+        self.general[id.0 as usize]
     }
 
     /// Returns a reference to the general purpose registers
@@ -41,7 +95,14 @@ impl Registers {
         &mut self.general
     }
 
-    pub fn get_u16(&self, id: u8) -> u16 {
+    pub fn get_u16(&self, id: RegisterId) -> u16 {
         self.get(id) as u16
     }
+
+    /// Moves the program counter to the next instruction
+    /// The program counter wraps around at the end of the 64 KiB address space, which memory can
+    /// fill (see `Config::memory_size`).
+    pub fn advance(&mut self) {
+        self.program_counter = self.program_counter.wrapping_add(2);
+    }
 }
@@ -0,0 +1,195 @@
+//! Step-by-step reports of how a program runs, for teaching how Chip-8 works
+//!
+//! `record` runs a short program headless, recording every instruction along with the registers
+//! after it and the screen after each instruction that changes it. A `Report` is displayed as a
+//! Markdown document, with the registers changed by each instruction in bold.
+
+use std::fmt;
+
+use errors::*;
+use config::Config;
+use interpreter::interpret_instruction;
+use io::HeadlessIO;
+use register::RegisterId;
+use trace::TraceEntry;
+use watch::Watch;
+use super::{Chip8, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+/// The number of cycles run per frame (between updates of the timers)
+const FRAME_CYCLES: u64 = 10;
+/// The characters used to show pixels, indexed by the bitmask of the planes they are on in
+const PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+/// A single instruction run by the program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// The trace of the instruction
+    pub trace: TraceEntry,
+    /// The instruction in assembly syntax (see `Instruction`'s `Display` impl)
+    pub instruction: String,
+    /// The general purpose registers after the instruction
+    pub registers: [u8; 16],
+    /// The index register after the instruction
+    pub index: u16,
+    /// The planes of the screen after the instruction, if it drew to or cleared the screen
+    pub screen: Option<Vec<u8>>,
+}
+
+/// A record of the steps of a program (see `record`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// The steps, in the order they were run
+    pub steps: Vec<Step>,
+    /// How the program stopped, if it did so before running all steps
+    pub stopped: Option<String>,
+}
+
+/// Runs the program for at most `steps` instructions, and records each one
+/// No keys are pressed, except when the program waits for one. Errors from running the program
+/// are recorded in the report; an error is only returned if the emulator can't be created.
+pub fn record(program: &[u8], config: Config, steps: usize) -> Result<Report> {
+    let mut chip8 = Chip8::new(program, config)?;
+    let mut io = HeadlessIO::new();
+    let mut report = Report {
+        steps: Vec::new(),
+        stopped: None,
+    };
+
+    chip8.enable_trace(1);
+
+    while report.steps.len() < steps {
+        io.next_cycle(chip8.is_waiting_for_key());
+
+        if let Err(e) = chip8.cycle(&mut io) {
+            report.stopped = Some(format!("Error: {}", e));
+            break;
+        }
+
+        if chip8.cycles() % FRAME_CYCLES == 0 {
+            chip8.tick_60hz(&mut io);
+        }
+
+        let trace = chip8.trace()[0].clone();
+        // The instruction was just run, so it must be valid
+        let instruction = interpret_instruction(trace.opcode)
+            .expect("Invalid opcode in trace");
+        let screen = if trace.opcode & 0xF000 == 0xD000 || trace.opcode == 0x00E0 {
+            Some(chip8.planes().to_vec())
+        } else {
+            None
+        };
+
+        report.steps.push(Step {
+            trace,
+            instruction: instruction.to_string(),
+            registers: *chip8.registers.get_registers(),
+            index: chip8.registers.index,
+            screen,
+        });
+
+        if chip8.program_ended() {
+            report.stopped = Some("The program ended".to_string());
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# Execution report")?;
+        writeln!(f)?;
+        writeln!(f,
+                 "Each row is one instruction, followed by the registers after it runs. \
+                  Registers it changed are in bold.")?;
+
+        let mut table_open = false;
+
+        for step in &self.steps {
+            if !table_open {
+                write_header(f)?;
+                table_open = true;
+            }
+
+            write_step(f, step)?;
+
+            if let Some(ref screen) = step.screen {
+                writeln!(f)?;
+                writeln!(f, "Screen after step {}:", step.trace.cycle)?;
+                writeln!(f)?;
+                write_screen(f, screen)?;
+                table_open = false;
+            }
+        }
+
+        if let Some(ref stopped) = self.stopped {
+            writeln!(f)?;
+            writeln!(f, "{}.", stopped)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the header of a table of steps
+fn write_header(f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f)?;
+    write!(f, "| Step | Address | Opcode | Instruction |")?;
+    for x in RegisterId::all() {
+        write!(f, " {} |", x)?;
+    }
+    writeln!(f, " I |")?;
+
+    write!(f, "|---|---|---|---|")?;
+    for _ in RegisterId::all() {
+        write!(f, "---|")?;
+    }
+    writeln!(f, "---|")
+}
+
+/// Writes a step as a row of a table
+fn write_step(f: &mut fmt::Formatter, step: &Step) -> fmt::Result {
+    let changed = |watch| step.trace.changes.iter().any(|c| c.watch == watch);
+    let bold = |changed| if changed { "**" } else { "" };
+
+    write!(f,
+           "| {} | 0x{:03X} | {:04X} | `{}` |",
+           step.trace.cycle,
+           step.trace.pc,
+           step.trace.opcode,
+           step.instruction)?;
+
+    for x in RegisterId::all() {
+        let b = bold(changed(Watch::Register(x)));
+        write!(f, " {}{:02X}{} |", b, step.registers[x.index() as usize], b)?;
+    }
+
+    let b = bold(changed(Watch::Index));
+    writeln!(f, " {}{:03X}{} |", b, step.index, b)
+}
+
+/// Writes the screen as a block of text
+/// Programs that only use the top left quarter of the screen (like most Chip-8 programs, which
+/// run in low resolution) are shown at that size.
+fn write_screen(f: &mut fmt::Formatter, planes: &[u8]) -> fmt::Result {
+    let (width, height) = (SCREEN_WIDTH / 2, SCREEN_HEIGHT / 2);
+    let low_res = planes.iter()
+        .enumerate()
+        .all(|(i, &p)| p == 0 || (i % SCREEN_WIDTH < width && i / SCREEN_WIDTH < height));
+    let (width, height) = if low_res {
+        (width, height)
+    } else {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    };
+
+    writeln!(f, "```text")?;
+    for y in 0..height {
+        let line = planes[y * SCREEN_WIDTH..y * SCREEN_WIDTH + width]
+            .iter()
+            .map(|&p| PIXELS[p as usize & 3])
+            .collect::<String>();
+        writeln!(f, "{}", line)?;
+    }
+    writeln!(f, "```")
+}
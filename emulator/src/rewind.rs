@@ -0,0 +1,75 @@
+//! Rewinding of emulation using a bounded buffer of snapshots
+
+use std::collections::VecDeque;
+
+use state::State;
+
+/// Records snapshots of the emulator every few frames so emulation can be rewound
+#[derive(Debug)]
+pub struct Rewind {
+    /// The number of frames between snapshots
+    interval: usize,
+    /// The maximum number of snapshots to keep
+    capacity: usize,
+    /// The number of frames since the last snapshot
+    frames: usize,
+    /// The snapshots, oldest first
+    snapshots: VecDeque<State>,
+}
+
+impl Rewind {
+    /// Returns a `Rewind` that takes a snapshot every `interval` frames, keeping at most `capacity`
+    /// snapshots
+    pub fn new(interval: usize, capacity: usize) -> Rewind {
+        assert!(interval > 0, "Rewind interval must not be zero");
+        assert!(capacity > 0, "Rewind capacity must not be zero");
+
+        Rewind {
+            interval,
+            capacity,
+            frames: 0,
+            snapshots: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Counts a frame, taking a snapshot with `snapshot` if enough frames have passed since the
+    /// last one
+    /// The oldest snapshot is dropped if the buffer is full
+    pub fn record<F: FnOnce() -> State>(&mut self, snapshot: F) {
+        self.frames += 1;
+
+        if self.frames < self.interval {
+            return;
+        }
+
+        self.frames = 0;
+
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+
+        self.snapshots.push_back(snapshot());
+    }
+
+    /// Discards every snapshot
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.snapshots.clear();
+    }
+
+    /// Discards the snapshots taken in the last `frames` frames (rounded up to the interval), and
+    /// returns the newest remaining one
+    /// The oldest snapshot is returned if there are not enough snapshots, and `None` is returned
+    /// if there are none
+    pub fn rewind(&mut self, frames: usize) -> Option<&State> {
+        let discard = frames.div_ceil(self.interval);
+        let discard = discard.min(self.snapshots.len().saturating_sub(1));
+
+        for _ in 0..discard {
+            self.snapshots.pop_back();
+        }
+
+        self.frames = 0;
+        self.snapshots.back()
+    }
+}
@@ -0,0 +1,243 @@
+//! Programs along with metadata describing how to run them
+//!
+//! Metadata can be appended to a program as a block of text, starting with `METADATA_MARKER` and
+//! followed by `key = value` lines (a subset of TOML, so the same text can also be stored in a
+//! separate file):
+//!
+//! ```text
+//! CH8META
+//! # Comments start with a '#'
+//! title = "Pong"
+//! speed = 700
+//! quirks = ["shift", "memory"]
+//! keys = "Q and A move the paddle"
+//! ```
+//!
+//! The supported keys are:
+//!
+//! - `title`: the name of the program
+//! - `speed`: the number of instructions to run per second
+//! - `quirks`: either the name of a preset (`"chip8"`, `"schip"`, or `"xochip"`), or a list of
+//!   quirks to enable
+//!   (see `config::Quirks`)
+//! - `keys`: a description of the controls of the program, shown to users
+
+use std::str;
+
+use errors::*;
+use config::{Config, Quirks};
+
+/// The line that starts a block of metadata appended to a program
+pub const METADATA_MARKER: &[u8] = b"CH8META\n";
+
+/// Information about how to run a program
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    /// The name of the program
+    pub title: Option<String>,
+    /// The number of instructions to run per second
+    pub speed: Option<u32>,
+    /// The quirks the program depends on
+    pub quirks: Option<Quirks>,
+    /// A description of the controls of the program
+    pub keys: Option<String>,
+}
+
+impl Metadata {
+    /// Parses metadata from its text representation (see the module documentation for the format)
+    pub fn parse(text: &str) -> Result<Metadata> {
+        let mut metadata = Metadata::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || ErrorKind::InvalidMetadata(i + 1, line.to_string());
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts.next().map(str::trim).ok_or_else(&invalid)?;
+
+            match key {
+                "title" => metadata.title = Some(parse_string(value).ok_or_else(&invalid)?),
+                "speed" => {
+                    let speed = value.parse().ok().and_then(|s| if s > 0 { Some(s) } else { None });
+                    metadata.speed = Some(speed.ok_or_else(&invalid)?);
+                }
+                "quirks" => metadata.quirks = Some(parse_quirks(value).ok_or_else(&invalid)?),
+                "keys" => metadata.keys = Some(parse_string(value).ok_or_else(&invalid)?),
+                _ => bail!(invalid()),
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    /// Replaces the values of this metadata with the values that are set in `other`
+    pub fn merge(&mut self, other: Metadata) {
+        if other.title.is_some() {
+            self.title = other.title;
+        }
+        if other.speed.is_some() {
+            self.speed = other.speed;
+        }
+        if other.quirks.is_some() {
+            self.quirks = other.quirks;
+        }
+        if other.keys.is_some() {
+            self.keys = other.keys;
+        }
+    }
+
+    /// Applies the speed and quirks specified by this metadata to the configuration
+    pub fn apply(&self, mut config: Config) -> Config {
+        if let Some(speed) = self.speed {
+            config = config.speed(speed);
+        }
+        if let Some(quirks) = self.quirks {
+            config = config.quirks(quirks);
+        }
+
+        config
+    }
+}
+
+/// A program, and the metadata appended to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rom {
+    /// The program, without the metadata
+    program: Vec<u8>,
+    /// The metadata, which is empty if none was appended to the program
+    metadata: Metadata,
+}
+
+impl Rom {
+    /// Splits the contents of a ROM file into the program and its metadata
+    /// Returns an error if the metadata is invalid
+    pub fn new(mut bytes: Vec<u8>) -> Result<Rom> {
+        let marker = bytes.windows(METADATA_MARKER.len())
+            .rposition(|w| w == METADATA_MARKER);
+
+        let metadata = match marker {
+            Some(start) => {
+                let metadata = {
+                    let text = str::from_utf8(&bytes[start + METADATA_MARKER.len()..])
+                        .chain_err(|| "Metadata is not valid UTF-8")?;
+                    Metadata::parse(text)?
+                };
+                bytes.truncate(start);
+                metadata
+            }
+            None => Metadata::default(),
+        };
+
+        Ok(Rom {
+            program: bytes,
+            metadata,
+        })
+    }
+
+    /// Returns the program
+    pub fn program(&self) -> &[u8] {
+        &self.program
+    }
+
+    /// Returns the metadata of the program
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns a mutable reference to the metadata of the program, for example to merge metadata
+    /// loaded from another file into it
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+}
+
+/// Parses a double-quoted string
+fn parse_string(value: &str) -> Option<String> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Some(value[1..value.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+/// Parses either a preset name or a list of quirk names
+fn parse_quirks(value: &str) -> Option<Quirks> {
+    if let Some(name) = parse_string(value) {
+        return Quirks::preset(&name);
+    }
+
+    if !value.starts_with('[') || !value.ends_with(']') {
+        return None;
+    }
+
+    let mut quirks = Quirks::chip8();
+    let list = value[1..value.len() - 1].trim();
+
+    if !list.is_empty() {
+        for name in list.split(',') {
+            let name = parse_string(name.trim())?;
+
+            if !quirks.enable(&name) {
+                return None;
+            }
+        }
+    }
+
+    Some(quirks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_without_metadata() {
+        let rom = Rom::new(vec![0x60, 0x01]).unwrap();
+
+        assert_eq!(&[0x60, 0x01], rom.program());
+        assert_eq!(&Metadata::default(), rom.metadata());
+    }
+
+    #[test]
+    fn test_rom_with_metadata() {
+        let mut bytes = vec![0x60, 0x01];
+        bytes.extend_from_slice(METADATA_MARKER);
+        bytes.extend_from_slice(b"title = \"Test\"\nspeed = 500\nquirks = [\"jump\"]\n");
+        bytes.extend_from_slice(b"keys = \"1 to start\"\n");
+
+        let rom = Rom::new(bytes).unwrap();
+        let mut quirks = Quirks::chip8();
+        quirks.jump = true;
+
+        assert_eq!(&[0x60, 0x01], rom.program());
+        assert_eq!(Some("Test".to_string()), rom.metadata().title);
+        assert_eq!(Some(500), rom.metadata().speed);
+        assert_eq!(Some(quirks), rom.metadata().quirks);
+        assert_eq!(Some("1 to start".to_string()), rom.metadata().keys);
+    }
+
+    #[test]
+    fn test_parse_preset() {
+        let metadata = Metadata::parse("quirks = \"schip\"").unwrap();
+
+        assert_eq!(Some(Quirks::schip()), metadata.quirks);
+
+        let metadata = Metadata::parse("quirks = \"xochip\"").unwrap();
+
+        assert_eq!(Some(Quirks::xochip()), metadata.quirks);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        match Metadata::parse("title = \"Test\"\nspeed = fast") {
+            Err(Error(ErrorKind::InvalidMetadata(2, _), _)) => {}
+            r => panic!("Expected error, got {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Run-ahead, which reduces input latency by showing the screen as it will be a few frames later,
+//! assuming the input doesn't change
+//!
+//! Every frame, the state of the emulator is loaded into a separate emulator which runs ahead
+//! without any output, and its screen is drawn. The real emulator is unaffected, so the result is
+//! the same as rolling back and running again with the real input.
+
+use super::{Chip8, Chip8IO, Keypad};
+use config::Config;
+use errors::*;
+
+/// A wrapper around a `Chip8IO` that only passes on input, for running cycles whose output isn't
+/// shown
+pub struct Hidden<'a, T: 'a> {
+    /// The wrapped `Chip8IO`
+    io: &'a mut T,
+}
+
+impl<'a, T: Chip8IO> Hidden<'a, T> {
+    /// Returns a wrapper around the `Chip8IO`
+    pub fn new(io: &'a mut T) -> Hidden<'a, T> {
+        Hidden { io }
+    }
+}
+
+impl<'a, T: Chip8IO> Chip8IO for Hidden<'a, T> {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_planes(&mut self, _: &[u8], _: &::Palette) {}
+    fn get_keys(&mut self) -> Keypad {
+        self.io.get_keys()
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        self.io.should_close()
+    }
+}
+
+impl Chip8 {
+    /// Draws the screen as it will be `frames` frames from now, running `frame_cycles` cycles per
+    /// frame
+    pub(crate) fn draw_ahead<T: Chip8IO>(&self,
+                                         io: &mut T,
+                                         frames: usize,
+                                         frame_cycles: u64)
+                                         -> Result<()> {
+        let config = Config::new().quirks(self.quirks).stack_depth(self.stack_depth);
+        let mut ahead = Chip8::new(&[], config)?;
+        ahead.load_state(&self.save_state());
+
+        {
+            let mut hidden = Hidden::new(io);
+
+            'frames: for _ in 0..frames {
+                for _ in 0..frame_cycles {
+                    // Errors are left for the real emulator to report when it reaches them
+                    if ahead.program_ended() || ahead.cycle(&mut hidden).is_err() {
+                        break 'frames;
+                    }
+                }
+
+                ahead.tick_60hz(&mut hidden);
+            }
+        }
+
+        let (planes, palette) = ahead.io.screen(&self.palette);
+        io.draw_planes(&planes, &palette);
+
+        Ok(())
+    }
+}
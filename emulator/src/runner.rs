@@ -0,0 +1,279 @@
+//! Running an emulator on a background thread, controlled with commands
+//!
+//! GUI frontends usually can't block their own thread in `Chip8::run_until_break`. A `Runner` moves
+//! the emulator to a thread of its own, where it runs one frame every 60th of a second (multiplied
+//! by the speed factor). The frontend sends it `Command`s, and receives the frames, sounds, and
+//! other `Event`s of the emulator in return.
+//!
+//! ```rust,no_run
+//! use chip8::Chip8;
+//! use chip8::config::Config;
+//! use chip8::runner::{Command, Event, Runner};
+//!
+//! # fn f(program: &[u8]) -> chip8::Result<()> {
+//! let runner = Runner::new(Chip8::new(program, Config::new())?);
+//! runner.send(Command::SetSpeedFactor(2.0));
+//!
+//! while let Some(event) = runner.wait_event() {
+//!     match event {
+//!         Event::Frame(planes, palette) => { /* Draw the screen */ }
+//!         Event::Ended | Event::Error(_) => break,
+//!         _ => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{Chip8, Chip8IO, Keypad, Palette};
+use errors::*;
+use state::State;
+
+/// The time between frames at normal speed
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// A command sent to the emulator of a `Runner`
+#[derive(Clone, Debug)]
+pub enum Command {
+    /// Stops running frames until resumed
+    Pause,
+    /// Resumes running frames
+    Resume,
+    /// Restarts the program (see `Chip8::reset`)
+    Reset,
+    /// Replaces the program with a new one (see `Chip8::load_program`)
+    LoadProgram(Vec<u8>),
+    /// Sets how many times faster than normal the emulator runs (see `Chip8::set_speed_factor`)
+    /// An `Event::Error` is sent instead if the factor isn't positive, or is too small to wait for.
+    SetSpeedFactor(f64),
+    /// Sets the keys that are pressed
+    SetKeys(Keypad),
+    /// Requests a snapshot of the emulator, which is sent back as `Event::State`
+    SaveState,
+    /// Restores the emulator to the state in the snapshot
+    LoadState(Box<State>),
+}
+
+/// Something that happened in the emulator of a `Runner`
+#[derive(Debug)]
+pub enum Event {
+    /// The screen changed during the last frame (see `Chip8IO::draw_planes`)
+    Frame(Vec<u8>, Box<Palette>),
+    /// The sound started playing
+    SoundStarted,
+    /// The sound stopped playing
+    SoundStopped,
+    /// A snapshot of the emulator, requested with `Command::SaveState`
+    State(Box<State>),
+    /// The program ended or stalled
+    Ended,
+    /// The program crashed, or a command failed
+    Error(Error),
+}
+
+/// An emulator running on a background thread
+///
+/// The thread stops when the runner is dropped, or with `stop`, which returns the emulator.
+#[derive(Debug)]
+pub struct Runner {
+    /// Sends commands to the thread, and tells it to stop when dropped
+    commands: Option<Sender<Command>>,
+    /// Receives events from the thread
+    events: Receiver<Event>,
+    /// The thread, which returns the emulator when it stops
+    thread: Option<JoinHandle<Chip8>>,
+}
+
+impl Runner {
+    /// Moves the emulator to a new thread, and starts running it
+    pub fn new(chip8: Chip8) -> Runner {
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let thread = thread::spawn(move || run(chip8, &command_receiver, event_sender));
+
+        Runner {
+            commands: Some(commands),
+            events,
+            thread: Some(thread),
+        }
+    }
+
+    /// Sends a command to the emulator
+    /// Commands are handled in the order they are sent, between frames.
+    pub fn send(&self, command: Command) {
+        if let Some(ref commands) = self.commands {
+            // The thread only stops when the runner is stopped or dropped
+            let _ = commands.send(command);
+        }
+    }
+
+    /// Returns the next event if there is one, without waiting
+    pub fn try_event(&self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+
+    /// Waits for the next event
+    /// Returns `None` if the thread stopped, which only happens if it panicked.
+    pub fn wait_event(&self) -> Option<Event> {
+        self.events.recv().ok()
+    }
+
+    /// Stops the thread, and returns the emulator
+    pub fn stop(mut self) -> Chip8 {
+        self.join().expect("Runner thread panicked")
+    }
+
+    /// Stops the thread and waits for it, returning the emulator if the thread didn't panic
+    fn join(&mut self) -> Option<Chip8> {
+        // Disconnecting the channel tells the thread to stop
+        self.commands = None;
+        self.thread.take().and_then(|thread| thread.join().ok())
+    }
+}
+
+impl Drop for Runner {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// An implementation of `Chip8IO` that uses the keys set by commands, and keeps the last screen
+/// drawn and sends sound events for the runner
+struct RunnerIO {
+    /// The keys set by `Command::SetKeys`
+    keys: Keypad,
+    /// The screen drawn last during the current frame, if it was drawn
+    frame: Option<(Vec<u8>, Box<Palette>)>,
+    /// Sends events to the frontend
+    events: Sender<Event>,
+}
+
+impl RunnerIO {
+    /// Sends an event to the frontend, which may have stopped listening
+    fn send(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+}
+
+impl Chip8IO for RunnerIO {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        self.frame = Some((planes.to_vec(), Box::new(*palette)));
+    }
+    fn get_keys(&mut self) -> Keypad {
+        self.keys
+    }
+    fn sound_start(&mut self) {
+        self.send(Event::SoundStarted);
+    }
+    fn sound_stop(&mut self) {
+        self.send(Event::SoundStopped);
+    }
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// Runs the emulator one frame at a time and handles commands between frames, until the runner
+/// disconnects, then returns the emulator
+fn run(mut chip8: Chip8, commands: &Receiver<Command>, events: Sender<Event>) -> Chip8 {
+    let mut io = RunnerIO {
+        keys: Keypad::new(),
+        frame: None,
+        events,
+    };
+    // Whether the program can run, which it can't after it ends or crashes until it is restarted
+    let mut running = true;
+    let mut next_frame = Instant::now();
+
+    loop {
+        let now = Instant::now();
+
+        if now >= next_frame {
+            if running && !chip8.is_paused() {
+                running = run_frame(&mut chip8, &mut io);
+            }
+
+            // Speed factors sent as commands are checked, but the emulator could have been given
+            // any factor before the runner was created
+            next_frame += frame_duration(chip8.speed_factor()).unwrap_or(FRAME_DURATION);
+            // Don't try to catch up on frames that were missed
+            if next_frame < now {
+                next_frame = now;
+            }
+        }
+
+        let timeout = next_frame.saturating_duration_since(Instant::now());
+        let command = match commands.recv_timeout(timeout) {
+            Ok(command) => command,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return chip8,
+        };
+
+        match command {
+            Command::Pause => chip8.pause(),
+            Command::Resume => chip8.resume(),
+            Command::Reset => {
+                chip8.reset();
+                running = true;
+            }
+            Command::LoadProgram(program) => {
+                match chip8.load_program(&program) {
+                    Ok(()) => running = true,
+                    Err(e) => io.send(Event::Error(e)),
+                }
+            }
+            Command::SetSpeedFactor(factor) => {
+                if frame_duration(factor).is_some() {
+                    chip8.set_speed_factor(factor);
+                } else {
+                    io.send(Event::Error(format!("Invalid speed factor: {}", factor).into()));
+                }
+            }
+            Command::SetKeys(keys) => io.keys = keys,
+            Command::SaveState => io.send(Event::State(Box::new(chip8.save_state()))),
+            Command::LoadState(state) => {
+                chip8.load_state(&state);
+                running = true;
+            }
+        }
+    }
+}
+
+/// Returns the time between frames at the speed factor, or `None` if the factor isn't positive, or
+/// is so small that the time is too long to wait
+fn frame_duration(factor: f64) -> Option<Duration> {
+    if !factor.is_finite() || factor <= 0.0 {
+        return None;
+    }
+
+    Duration::try_from_secs_f64(FRAME_DURATION.as_secs_f64() / factor)
+        .ok()
+        .filter(|&duration| Instant::now().checked_add(duration).is_some())
+}
+
+/// Runs a frame, and sends the screen if it changed
+/// Returns whether the program can keep running, sending the reason if it can't.
+fn run_frame(chip8: &mut Chip8, io: &mut RunnerIO) -> bool {
+    let result = chip8.advance_frame(io);
+
+    if let Some((planes, palette)) = io.frame.take() {
+        io.send(Event::Frame(planes, palette));
+    }
+
+    match result {
+        Err(e) => {
+            io.send(Event::Error(e));
+            false
+        }
+        Ok(()) if chip8.program_ended() || chip8.stalled() => {
+            io.send(Event::Ended);
+            false
+        }
+        Ok(()) => true,
+    }
+}
@@ -0,0 +1,125 @@
+//! Static analysis of programs, without running them
+//!
+//! `scan` summarizes a program: its size and hash, the first instruction, which extensions of
+//! Chip-8 it uses, and obvious problems with the file. Only code that can be reached from the start
+//! of the program (see `disasm::reachable`) is checked for extensions, so data isn't mistaken for
+//! instructions.
+
+use std::fmt;
+
+use disasm;
+use sha1::{self, Digest};
+use super::{MEMORY, PROGRAM_START};
+
+/// An extension of the Chip-8 instruction set
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Extension {
+    /// SUPER-CHIP, which adds a high resolution mode, scrolling and large sprites
+    Schip,
+    /// XO-CHIP, which adds a second plane, audio patterns and more memory
+    XoChip,
+    /// Mega-Chip, which adds a 256x192 screen with colored sprites and palettes
+    MegaChip,
+    /// CHIP-8X, which adds colors and a second keypad
+    Chip8X,
+}
+
+impl Extension {
+    /// Returns the extension that adds the opcode, or `None` if it is a Chip-8 opcode or not a
+    /// known opcode
+    pub fn of_opcode(opcode: u16) -> Option<Extension> {
+        let nibbles = (opcode >> 12, opcode >> 8 & 0xF, opcode >> 4 & 0xF, opcode & 0xF);
+
+        match nibbles {
+            (0x0, 0x0, 0xC, _) | (0xF, _, 0x3, 0x0) | (0xF, _, 0x7, 0x5) | (0xF, _, 0x8, 0x5) => {
+                Some(Extension::Schip)
+            }
+            (0x0, 0x0, 0xF, n) if n >= 0xB => Some(Extension::Schip),
+            (0x0, 0x0, 0xD, _) | (0x5, _, _, 0x2) | (0x5, _, _, 0x3) | (0xF, 0x0, 0x0, 0x0) |
+            (0xF, _, 0x0, 0x1) | (0xF, 0x0, 0x0, 0x2) | (0xF, _, 0x3, 0xA) => {
+                Some(Extension::XoChip)
+            }
+            (0x0, 0x0, 0x1, 0x0) | (0x0, 0x0, 0x1, 0x1) | (0x0, 0x0, 0xB, _) |
+            (0x0, 0x1..=0x5, _, _) | (0x0, 0x6, 0x0, _) | (0x0, 0x7, 0x0, 0x0) |
+            (0x0, 0x8, 0x0, _) | (0x0, 0x9, _, _) => Some(Extension::MegaChip),
+            // The other CHIP-8X opcodes are also opcodes of other extensions
+            (0x5, _, _, 0x1) | (0xE, _, 0xF, 0x2) | (0xE, _, 0xF, 0x5) => Some(Extension::Chip8X),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Extension::Schip => write!(f, "SUPER-CHIP"),
+            Extension::XoChip => write!(f, "XO-CHIP"),
+            Extension::MegaChip => write!(f, "Mega-Chip"),
+            Extension::Chip8X => write!(f, "CHIP-8X"),
+        }
+    }
+}
+
+/// An obvious problem with a program file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The program has an odd number of bytes, so its last instruction is cut off
+    OddLength,
+    /// The program doesn't fit in memory, so the bytes after this many can't be loaded
+    TooLarge(usize),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::OddLength => write!(f, "The program has an odd number of bytes"),
+            Problem::TooLarge(max) => {
+                write!(f, "The program is too large to fit in memory (at most {} bytes)", max)
+            }
+        }
+    }
+}
+
+/// A summary of a program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// The size of the program, in bytes
+    pub size: usize,
+    /// The SHA-1 hash of the program
+    pub sha1: Digest,
+    /// The first opcode of the program, or `None` if it is shorter than an opcode
+    pub entry_opcode: Option<u16>,
+    /// The extensions of Chip-8 used by the program
+    pub extensions: Vec<Extension>,
+    /// Obvious problems with the program
+    pub problems: Vec<Problem>,
+}
+
+/// Summarizes the program
+pub fn scan(program: &[u8]) -> Summary {
+    let max_size = MEMORY - PROGRAM_START - 1;
+    let mut extensions = Vec::new();
+    let mut problems = Vec::new();
+
+    disasm::walk(program, |_, opcode| if let Some(extension) = Extension::of_opcode(opcode) {
+        if !extensions.contains(&extension) {
+            extensions.push(extension);
+        }
+    });
+    extensions.sort();
+
+    if program.len() % 2 == 1 {
+        problems.push(Problem::OddLength);
+    }
+    if program.len() > max_size {
+        problems.push(Problem::TooLarge(max_size));
+    }
+
+    Summary {
+        size: program.len(),
+        sha1: sha1::sha1(program),
+        entry_opcode: program.get(..2).map(|b| (b[0] as u16) << 8 | b[1] as u16),
+        extensions,
+        problems,
+    }
+}
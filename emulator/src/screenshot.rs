@@ -0,0 +1,193 @@
+//! Screenshots of the display, encoded as PNG images
+//!
+//! `write_png` encodes a frame, and `capture` runs a program headless to find a representative
+//! frame of it, for example to show in a game picker. `unused_path` picks names for screenshot
+//! files. `write_pbm` writes the pixels as a plain text bitmap, which is easy to compare in tests.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use errors::*;
+use config::Config;
+use io::{HeadlessIO, Palette};
+use super::{Chip8, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+/// The PNG file signature
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// The largest amount of data a stored deflate block can hold
+const MAX_BLOCK: usize = 0xFFFF;
+
+/// A representative frame of a program (see `capture`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capture {
+    /// The planes of the frame, as in `Chip8::planes`
+    pub planes: Vec<u8>,
+    /// The number of the frame (see `Chip8::frames`)
+    pub frame: u64,
+}
+
+/// Runs the program headless for `frames` frames of `frame_cycles` cycles each, and returns the
+/// frame with the most pixels lit (the earliest one if there is a tie)
+///
+/// No keys are pressed, except when the program waits for one. If the program crashes, the frames
+/// before the crash are used, and an error is only returned if no pixels were lit before it.
+pub fn capture(program: &[u8],
+               config: Config,
+               frames: u64,
+               frame_cycles: u64)
+               -> Result<Capture> {
+    let mut chip8 = Chip8::new(program, config)?;
+    let mut io = HeadlessIO::new();
+    let mut best = Capture {
+        planes: chip8.planes().to_vec(),
+        frame: 0,
+    };
+    let mut best_lit = 0;
+
+    for _ in 0..frames {
+        for _ in 0..frame_cycles {
+            io.next_cycle(chip8.is_waiting_for_key());
+
+            if let Err(e) = chip8.cycle(&mut io) {
+                return if best_lit > 0 { Ok(best) } else { Err(e) };
+            }
+
+            if chip8.program_ended() {
+                return Ok(best);
+            }
+        }
+
+        chip8.tick_60hz(&mut io);
+
+        let lit = chip8.planes().iter().filter(|&&p| p != 0).count();
+        if lit > best_lit {
+            best_lit = lit;
+            best = Capture {
+                planes: chip8.planes().to_vec(),
+                frame: chip8.frames(),
+            };
+        }
+    }
+
+    Ok(best)
+}
+
+/// Writes the pixels (as in `Chip8::pixels`) as a plain PBM image, where each row of the screen is
+/// a line of `1` for pixels that are on and `0` for pixels that are off
+pub fn write_pbm<W: Write>(pixels: &[bool], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "P1")?;
+    writeln!(writer, "{} {}", SCREEN_WIDTH, SCREEN_HEIGHT)?;
+
+    for row in pixels.chunks(SCREEN_WIDTH) {
+        let line = row.iter().map(|&p| if p { "1" } else { "0" }).collect::<Vec<_>>();
+        writeln!(writer, "{}", line.join(" "))?;
+    }
+
+    Ok(())
+}
+
+/// Writes the planes as a PNG image, with each pixel drawn as a `scale` by `scale` square in its
+/// color in the palette
+pub fn write_png<W: Write>(planes: &[u8],
+                           palette: &Palette,
+                           scale: usize,
+                           mut writer: W)
+                           -> io::Result<()> {
+    let width = SCREEN_WIDTH * scale;
+    let height = SCREEN_HEIGHT * scale;
+
+    // Each row starts with its filter type (0 for none), followed by the RGB pixels
+    let mut image = Vec::with_capacity((width * 3 + 1) * height);
+    for y in 0..height {
+        image.push(0);
+
+        for x in 0..width {
+            let pixel = planes[x / scale + y / scale * SCREEN_WIDTH];
+            image.extend_from_slice(&palette.colors[pixel as usize]);
+        }
+    }
+
+    let mut header = Vec::new();
+    push_u32(&mut header, width as u32);
+    push_u32(&mut header, height as u32);
+    // 8 bit depth, RGB color, deflate compression, no filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    writer.write_all(&SIGNATURE)?;
+    write_chunk(&mut writer, b"IHDR", &header)?;
+    write_chunk(&mut writer, b"IDAT", &zlib_stored(&image))?;
+    write_chunk(&mut writer, b"IEND", &[])
+}
+
+/// Returns the first path in the directory named `<prefix>-<number>.<extension>` that doesn't exist,
+/// counting up from 1, so screenshots don't overwrite each other
+pub fn unused_path<P: AsRef<Path>>(dir: P, prefix: &str, extension: &str) -> PathBuf {
+    (1..)
+        .map(|n| dir.as_ref().join(format!("{}-{}.{}", prefix, n, extension)))
+        .find(|path| !path.exists())
+        .expect("Ran out of file names")
+}
+
+/// Writes a PNG chunk
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    push_u32(&mut chunk, data.len() as u32);
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    push_u32(&mut chunk, crc);
+
+    writer.write_all(&chunk)
+}
+
+/// Wraps the data in a zlib stream of uncompressed deflate blocks
+/// Screenshots are small, so compressing them isn't worth the code
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks = data.chunks(MAX_BLOCK).count();
+
+    for (i, block) in data.chunks(MAX_BLOCK).enumerate() {
+        let len = block.len() as u16;
+        // The first bit marks the final block, and the next two bits being 0 mark it as stored
+        stream.push((i + 1 == blocks) as u8);
+        stream.extend_from_slice(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        stream.extend_from_slice(block);
+    }
+
+    push_u32(&mut stream, adler32(data));
+    stream
+}
+
+/// Returns the CRC-32 checksum of the data, as used by PNG chunks
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// Returns the Adler-32 checksum of the data, as used by zlib streams
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    b << 16 | a
+}
+
+/// Pushes a big endian `u32`
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&[(value >> 24) as u8,
+                              (value >> 16) as u8,
+                              (value >> 8) as u8,
+                              value as u8]);
+}
@@ -0,0 +1,358 @@
+//! An implementation of `Chip8IO` using `sdl2` for graphics, sound, and input
+//! Press `Escape` to exit the emulator
+//!
+//! This is an alternative to `default_io` with the same interface, except that the beep is always
+//! a square wave. XO-CHIP audio patterns are also played.
+//!
+//! Dropping a file onto the window loads it as the new program.
+
+extern crate sdl2;
+
+use std::mem;
+use std::path::PathBuf;
+
+use self::sdl2::EventPump;
+use self::sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use self::sdl2::event::{Event, WindowEvent};
+use self::sdl2::keyboard::Keycode;
+use self::sdl2::pixels::Color as SdlColor;
+use self::sdl2::rect::Rect;
+use self::sdl2::render::Canvas;
+use self::sdl2::video::Window;
+use super::{SCREEN_WIDTH, SCREEN_HEIGHT, AUDIO_PATTERN_LEN, audio_sample_rate};
+use bindings::{Action, Bindings};
+use io::{Color, Palette};
+use pause::PauseHandle;
+
+/// The size of each pixel (in pixels)
+const PIXEL_SIZE: usize = 10;
+/// The sample rate of the audio
+const SAMPLE_RATE: i32 = 44100;
+/// The frequency of the beep
+const FREQUENCY: f32 = 440.0;
+/// The volume of the beep, from 0 to 1
+const VOLUME: f32 = 0.1;
+
+/// Generates the sound, which is a square wave beep unless an audio pattern was set
+struct Beep {
+    /// The position in the period of the beep or audio pattern, in samples of the pattern
+    phase: f32,
+    /// The audio pattern and the rate it is played at, if the program set one (XO-CHIP)
+    pattern: Option<([u8; AUDIO_PATTERN_LEN], f32)>,
+}
+
+impl AudioCallback for Beep {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        // The beep is a square wave, which is a pattern of two samples (on, then off)
+        let (length, rate) = match self.pattern {
+            Some((_, rate)) => (AUDIO_PATTERN_LEN as f32 * 8.0, rate),
+            None => (2.0, FREQUENCY * 2.0),
+        };
+
+        for sample in out.iter_mut() {
+            let position = self.phase as usize;
+            let on = match self.pattern {
+                Some((ref pattern, _)) => pattern[position / 8] & (0x80 >> (position % 8)) != 0,
+                None => position == 0,
+            };
+
+            *sample = if on { VOLUME } else { -VOLUME };
+            self.phase = (self.phase + rate / SAMPLE_RATE as f32) % length;
+        }
+    }
+}
+
+/// Stores state used for doing I/O
+#[allow(missing_debug_implementations)]
+pub struct Io {
+    canvas: Canvas<Window>,
+    events: EventPump,
+    /// The audio device, or `None` if no audio device could be opened
+    audio: Option<AudioDevice<Beep>>,
+    keys: ::Keypad,
+    should_close: bool,
+    bindings: Bindings,
+    /// Actions triggered since the last call to `take_actions`
+    actions: Vec<Action>,
+    /// Actions whose keys are held down
+    held_actions: Vec<Action>,
+    /// The last file dropped onto the window since the last call to `dropped_file`
+    dropped_file: Option<PathBuf>,
+    /// The title of the program being run
+    title: Option<String>,
+    /// The status line shown in the title of the window
+    status: String,
+    /// The handle used to pause the emulator when the window loses focus, if that is enabled
+    focus_pause: Option<PauseHandle>,
+    /// Whether the emulator was paused because the window lost focus, so it should be resumed
+    /// when the window gains focus
+    paused_by_focus: bool,
+}
+
+impl Io {
+    /// Initializes the state, creating the window and opening the audio device
+    /// Panics if SDL or the window can't be initialized. The emulator runs without sound if no
+    /// audio device can be opened.
+    pub fn new() -> Io {
+        let sdl = sdl2::init().unwrap_or_else(|e| panic!("Failed to initialize SDL: {}", e));
+        let video = sdl.video().unwrap_or_else(|e| panic!("Failed to initialize video: {}", e));
+
+        let window = video.window("Chip-8 Emulator",
+                    (SCREEN_WIDTH * PIXEL_SIZE) as u32,
+                    (SCREEN_HEIGHT * PIXEL_SIZE) as u32)
+            .position_centered()
+            .build()
+            .unwrap_or_else(|e| panic!("Failed to create window: {}", e));
+
+        let canvas = window.into_canvas()
+            .build()
+            .unwrap_or_else(|e| panic!("Failed to create renderer: {}", e));
+
+        let events = sdl.event_pump()
+            .unwrap_or_else(|e| panic!("Failed to initialize input: {}", e));
+
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let audio = sdl.audio().ok().and_then(|audio| {
+            audio.open_playback(None, &spec, |_| {
+                    Beep {
+                        phase: 0.0,
+                        pattern: None,
+                    }
+                })
+                .ok()
+        });
+
+        Io {
+            canvas,
+            events,
+            audio,
+            keys: ::Keypad::new(),
+            should_close: false,
+            bindings: Bindings::default(),
+            actions: Vec::new(),
+            held_actions: Vec::new(),
+            dropped_file: None,
+            title: None,
+            status: String::new(),
+            focus_pause: None,
+            paused_by_focus: false,
+        }
+    }
+
+    /// Sets the title of the window, which is shown along with the name of the emulator
+    pub fn set_title(&mut self, title: &str) {
+        self.title = Some(title.to_string());
+        self.update_title();
+    }
+
+    /// Updates the title of the window to show the title of the program and the status line
+    fn update_title(&mut self) {
+        let mut title = match self.title {
+            Some(ref title) => format!("{} - Chip-8 Emulator", title),
+            None => "Chip-8 Emulator".to_string(),
+        };
+
+        if !self.status.is_empty() {
+            title = format!("{} [{}]", title, self.status);
+        }
+
+        let _ = self.canvas.window_mut().set_title(&title);
+    }
+
+    /// Sets the bindings of keys to emulator actions
+    /// Keys are named as in SDL, for example `F1` or `Backspace`
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    /// Returns the bindings of keys to emulator actions
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// Pauses the emulator using the handle (see `Chip8::pause_handle`) and stops any sound when
+    /// the window loses focus, and resumes it when the window gains focus again
+    /// The emulator is only resumed if it was paused by losing focus, not if it was paused
+    /// manually.
+    pub fn pause_on_focus_loss(&mut self, handle: PauseHandle) {
+        self.focus_pause = Some(handle);
+    }
+
+    /// Returns the actions triggered by key presses since the last call to this function
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        mem::take(&mut self.actions)
+    }
+
+    /// Handles all pending events, updating the keyboard state and detecting when to close the
+    /// window
+    fn handle_events(&mut self) {
+        while let Some(event) = self.events.poll_event() {
+            match event {
+                Event::Quit { .. } => self.should_close = true,
+                Event::DropFile { filename, .. } => self.drop_file(PathBuf::from(filename)),
+                Event::KeyDown { keycode: Some(key), repeat: false, .. } => self.set_key(key, true),
+                Event::KeyUp { keycode: Some(key), .. } => self.set_key(key, false),
+                Event::Window { win_event: WindowEvent::FocusGained, .. } => self.set_focus(true),
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => self.set_focus(false),
+                _ => {}
+            }
+        }
+    }
+
+    /// Handles a file being dropped onto the window, passing it on to the emulator to load and
+    /// showing its name in the title
+    fn drop_file(&mut self, path: PathBuf) {
+        if let Some(name) = path.file_stem() {
+            let name = name.to_string_lossy().into_owned();
+            self.set_title(&name);
+        }
+
+        self.dropped_file = Some(path);
+    }
+
+    /// Handles the window gaining or losing focus, pausing or resuming the emulator if enabled
+    fn set_focus(&mut self, focused: bool) {
+        let handle = match self.focus_pause {
+            Some(ref handle) => handle,
+            None => return,
+        };
+
+        if !focused && !handle.is_paused() {
+            handle.pause();
+            if let Some(ref audio) = self.audio {
+                audio.pause();
+            }
+            self.paused_by_focus = true;
+        } else if focused && self.paused_by_focus {
+            handle.resume();
+            self.paused_by_focus = false;
+        }
+    }
+
+    /// Handles a key press, setting the keyboard state
+    fn set_key(&mut self, key: Keycode, state: bool) {
+        // Keys bound to actions are not passed on to the program
+        if let Some(action) = self.bindings.action(&key.name()) {
+            self.held_actions.retain(|&a| a != action);
+            if state {
+                self.held_actions.push(action);
+                self.actions.push(action);
+            }
+            return;
+        }
+
+        let button = match key {
+            Keycode::Num1 => 0x1,
+            Keycode::Num2 => 0x2,
+            Keycode::Num3 => 0x3,
+            Keycode::Num4 => 0xC,
+            Keycode::Q => 0x4,
+            Keycode::W => 0x5,
+            Keycode::E => 0x6,
+            Keycode::R => 0xD,
+            Keycode::A => 0x7,
+            Keycode::S => 0x8,
+            Keycode::D => 0x9,
+            Keycode::F => 0xE,
+            Keycode::Z => 0xA,
+            Keycode::X => 0x0,
+            Keycode::C => 0xB,
+            Keycode::V => 0xF,
+            Keycode::Escape => {
+                self.should_close = true;
+                return;
+            }
+            _ => return,
+        };
+
+        self.keys.set(button, state);
+    }
+}
+
+impl Default for Io {
+    fn default() -> Io {
+        Io::new()
+    }
+}
+
+/// Converts a color to the format used by `sdl2`
+fn to_sdl_color(color: Color) -> SdlColor {
+    SdlColor::RGB(color[0], color[1], color[2])
+}
+
+impl ::Chip8IO for Io {
+    fn draw(&mut self, pixels: &[bool]) {
+        // Draw pixels that are on using the color of the first plane
+        let planes = pixels.iter().map(|&p| p as u8).collect::<Vec<_>>();
+        self.draw_planes(&planes, &Palette::default());
+    }
+
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        // Clear the screen with the background color
+        self.canvas.set_draw_color(to_sdl_color(palette.colors[0]));
+        self.canvas.clear();
+
+        // Draw a square for each pixel that is on, in the color of the planes it is on in
+        for (i, &pixel) in planes.iter().enumerate().filter(|&(_, &p)| p != 0) {
+            let x = (i % SCREEN_WIDTH * PIXEL_SIZE) as i32;
+            let y = (i / SCREEN_WIDTH * PIXEL_SIZE) as i32;
+
+            self.canvas.set_draw_color(to_sdl_color(palette.colors[pixel as usize]));
+            let _ = self.canvas.fill_rect(Rect::new(x, y, PIXEL_SIZE as u32, PIXEL_SIZE as u32));
+        }
+
+        self.canvas.present();
+    }
+
+    fn sound_start(&mut self) {
+        if let Some(ref audio) = self.audio {
+            audio.resume();
+        }
+    }
+
+    fn sound_stop(&mut self) {
+        if let Some(ref audio) = self.audio {
+            audio.pause();
+        }
+    }
+
+    fn set_audio_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_LEN], pitch: u8) {
+        if let Some(ref mut audio) = self.audio {
+            audio.lock().pattern = Some((*pattern, audio_sample_rate(pitch) as f32));
+        }
+    }
+
+    fn get_keys(&mut self) -> ::Keypad {
+        self.handle_events();
+        self.keys
+    }
+
+    fn actions(&mut self) -> Vec<Action> {
+        self.take_actions()
+    }
+
+    fn is_action_held(&self, action: Action) -> bool {
+        self.held_actions.contains(&action)
+    }
+
+    fn dropped_file(&mut self) -> Option<PathBuf> {
+        self.dropped_file.take()
+    }
+
+    fn set_status(&mut self, status: &str) {
+        if self.status != status {
+            self.status = status.to_string();
+            self.update_title();
+        }
+    }
+
+    fn should_close(&self) -> bool {
+        self.should_close
+    }
+}
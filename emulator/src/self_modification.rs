@@ -0,0 +1,108 @@
+//! Detection of self-modifying code, for finding programs that accidentally write over their own
+//! instructions
+//!
+//! When enabled (see `Config::detect_self_modification`), the emulator records which instruction
+//! last wrote each address of memory. Comparing the written addresses with the coverage map (see
+//! `coverage`) finds the instructions that were both written and run, in either order.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::Chip8;
+
+/// The addresses written by instructions, and the address of the instruction that last wrote each
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteMap {
+    /// The address of the last instruction that wrote each address
+    writers: BTreeMap<u16, u16>,
+}
+
+impl WriteMap {
+    /// Returns a map with no addresses written
+    pub fn new() -> WriteMap {
+        WriteMap::default()
+    }
+
+    /// Records a write to the address by the instruction at `writer`
+    pub(crate) fn record(&mut self, address: u16, writer: u16) {
+        self.writers.insert(address, writer);
+    }
+
+    /// Returns the address of the last instruction that wrote the address, or `None` if it was
+    /// never written
+    pub fn writer(&self, address: u16) -> Option<u16> {
+        self.writers.get(&address).cloned()
+    }
+
+    /// Returns the addresses that were written, in ascending order
+    pub fn addresses(&self) -> Vec<u16> {
+        self.writers.keys().cloned().collect()
+    }
+}
+
+/// An instruction that was both run and written by the program
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SelfModification {
+    /// The address of the instruction that was run
+    pub instruction: u16,
+    /// The address of the byte of the instruction that was written
+    pub written: u16,
+    /// The address of the last instruction that wrote the byte
+    pub writer: u16,
+}
+
+impl fmt::Display for SelfModification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "The instruction at 0x{:03X} was run, and 0x{:03X} was written by the instruction at \
+                0x{:03X}",
+               self.instruction,
+               self.written,
+               self.writer)
+    }
+}
+
+impl Chip8 {
+    /// Returns the addresses written by instructions since the emulator was created or the map was
+    /// last cleared, or `None` if self-modification detection is disabled (see
+    /// `Config::detect_self_modification`)
+    pub fn writes(&self) -> Option<&WriteMap> {
+        self.writes.as_ref()
+    }
+
+    /// Clears the addresses written by instructions
+    pub fn clear_writes(&mut self) {
+        if let Some(ref mut writes) = self.writes {
+            *writes = WriteMap::new();
+        }
+    }
+
+    /// Returns the instructions that were both run and written, found by comparing the written
+    /// addresses with the coverage map, in order of their addresses
+    /// Each written byte of an instruction is reported separately. Returns nothing if
+    /// self-modification detection is disabled.
+    pub fn self_modifications(&self) -> Vec<SelfModification> {
+        let writes = match self.writes {
+            Some(ref writes) => writes,
+            None => return Vec::new(),
+        };
+        let mut modifications = Vec::new();
+
+        for (&written, &writer) in &writes.writers {
+            // Both bytes of an instruction are part of it, but only the first is in the coverage
+            let instructions = [written.checked_sub(1), Some(written)];
+            for &instruction in instructions.iter().flatten() {
+                if self.coverage.is_covered(instruction) {
+                    modifications.push(SelfModification {
+                        instruction,
+                        written,
+                        writer,
+                    });
+                }
+            }
+        }
+
+        modifications.sort();
+        modifications
+    }
+}
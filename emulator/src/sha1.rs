@@ -0,0 +1,139 @@
+//! The SHA-1 hash function, used to identify programs
+//!
+//! Lists of Chip-8 programs identify them by the SHA-1 hash of the program file, so this is used
+//! to match programs against them. It is not meant for anything that needs to be secure.
+
+use std::fmt;
+
+/// The initial state of the hash
+const INITIAL_STATE: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+/// The size of the blocks the data is processed in, in bytes
+const BLOCK_SIZE: usize = 64;
+
+/// A SHA-1 hash, which is displayed as 40 lowercase hexadecimal digits
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Digest(pub [u8; 20]);
+
+impl Digest {
+    /// Parses a hash written as 40 hexadecimal digits, in either case
+    pub fn parse(text: &str) -> Option<Digest> {
+        if text.len() != 40 || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let mut digest = [0; 20];
+        for (i, byte) in digest.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+
+        Some(Digest(digest))
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the SHA-1 hash of the data
+pub fn sha1(data: &[u8]) -> Digest {
+    let mut state = INITIAL_STATE;
+
+    // Pad the data with a 1 bit, then zeros, then the length of the data in bits, so its length is
+    // a multiple of the block size
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % BLOCK_SIZE != BLOCK_SIZE - 8 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&be_bytes((data.len() as u64).wrapping_mul(8)));
+
+    for block in padded.chunks(BLOCK_SIZE) {
+        process_block(&mut state, block);
+    }
+
+    let mut digest = [0; 20];
+    for (i, word) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&be_bytes(*word as u64)[4..]);
+    }
+
+    Digest(digest)
+}
+
+/// Updates the state of the hash with a block of data
+fn process_block(state: &mut [u32; 5], block: &[u8]) {
+    let mut words = [0u32; 80];
+
+    for (i, bytes) in block.chunks(4).enumerate() {
+        words[i] = (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 |
+                   bytes[3] as u32;
+    }
+    for i in 16..80 {
+        words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+    }
+
+    let (mut a, mut b, mut c, mut d, mut e) = (state[0], state[1], state[2], state[3], state[4]);
+
+    for (i, &word) in words.iter().enumerate() {
+        let (f, k) = match i / 20 {
+            0 => ((b & c) | (!b & d), 0x5A827999),
+            1 => (b ^ c ^ d, 0x6ED9EBA1),
+            2 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _ => (b ^ c ^ d, 0xCA62C1D6),
+        };
+
+        let temp = a.rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    for (s, value) in state.iter_mut().zip(&[a, b, c, d, e]) {
+        *s = s.wrapping_add(*value);
+    }
+}
+
+/// Returns the bytes of the number, most significant first
+fn be_bytes(n: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (n >> (56 - i * 8)) as u8;
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!("da39a3ee5e6b4b0d3255bfef95601890afd80709", sha1(b"").to_string());
+        assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", sha1(b"abc").to_string());
+        assert_eq!("84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+                   sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq").to_string());
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let digest = sha1(b"abc");
+
+        assert_eq!(Some(digest), Digest::parse("a9993e364706816aba3e25717850c26c9cd0d89d"));
+        assert_eq!(Some(digest), Digest::parse("A9993E364706816ABA3E25717850C26C9CD0D89D"));
+        assert_eq!(None, Digest::parse("a9993e"));
+        assert_eq!(None, Digest::parse("g9993e364706816aba3e25717850c26c9cd0d89d"));
+    }
+}
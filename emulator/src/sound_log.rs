@@ -0,0 +1,195 @@
+//! Logging of changes to the sound timer, so the sound of a session can be reconstructed later
+//!
+//! A log can be written as text, with one event per line in the form `CYCLE FRAME VALUE`, where
+//! `CYCLE` is the number of cycles run before the event, `FRAME` is the number of 60 hz frames
+//! that had passed, and `VALUE` is the value the sound timer was set to. `render_wav` turns a log
+//! into audio without running the emulator.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use errors::*;
+
+/// The number of 60 hz frames per second
+const FRAMES_PER_SECOND: u32 = 60;
+
+/// A change to the sound timer made by the program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SoundEvent {
+    /// The number of cycles run before the event
+    pub cycle: u64,
+    /// The number of frames that had passed before the event
+    pub frame: u64,
+    /// The value the sound timer was set to
+    pub value: u8,
+}
+
+/// A log of changes to the sound timer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SoundLog {
+    /// The events, in the order they happened
+    events: Vec<SoundEvent>,
+}
+
+impl SoundLog {
+    /// Returns an empty log
+    pub fn new() -> SoundLog {
+        SoundLog { events: Vec::new() }
+    }
+
+    /// Adds an event to the log
+    pub fn push(&mut self, event: SoundEvent) {
+        self.events.push(event);
+    }
+
+    /// Returns the events in the log
+    pub fn events(&self) -> &[SoundEvent] {
+        &self.events
+    }
+
+    /// Parses a log from its text representation (see the module documentation)
+    pub fn parse(text: &str) -> Result<SoundLog> {
+        let mut log = SoundLog::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let invalid = || ErrorKind::InvalidSoundLog(i + 1, line.to_string());
+
+            if fields.len() != 3 {
+                bail!(invalid());
+            }
+
+            log.push(SoundEvent {
+                cycle: fields[0].parse().chain_err(invalid)?,
+                frame: fields[1].parse().chain_err(invalid)?,
+                value: fields[2].parse().chain_err(invalid)?,
+            });
+        }
+
+        Ok(log)
+    }
+}
+
+impl fmt::Display for SoundLog {
+    /// Writes the log in the format read by `SoundLog::parse`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{} {} {}", event.cycle, event.frame, event.value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders the log as a mono 8-bit WAV file, with a square wave of the given frequency playing
+/// while the sound timer is not zero
+/// The audio starts at frame zero and ends when the sound timer reaches zero after the last event
+pub fn render_wav<W: Write>(log: &SoundLog,
+                            mut writer: W,
+                            sample_rate: u32,
+                            frequency: u32)
+                            -> io::Result<()> {
+    let end = log.events
+        .iter()
+        .map(|e| e.frame + e.value as u64)
+        .max()
+        .unwrap_or(0);
+    let samples_per_frame = (sample_rate / FRAMES_PER_SECOND) as u64;
+    let sample_count = end * samples_per_frame;
+
+    // RIFF header
+    writer.write_all(b"RIFF")?;
+    write_u32(&mut writer, 36 + sample_count as u32)?;
+    writer.write_all(b"WAVE")?;
+    // Format chunk: PCM, 1 channel, 8 bits per sample
+    writer.write_all(b"fmt ")?;
+    write_u32(&mut writer, 16)?;
+    write_u16(&mut writer, 1)?;
+    write_u16(&mut writer, 1)?;
+    write_u32(&mut writer, sample_rate)?;
+    write_u32(&mut writer, sample_rate)?;
+    write_u16(&mut writer, 1)?;
+    write_u16(&mut writer, 8)?;
+    // Data chunk
+    writer.write_all(b"data")?;
+    write_u32(&mut writer, sample_count as u32)?;
+
+    let mut events = log.events.iter().peekable();
+    let mut timer = 0;
+    let mut sample = 0u64;
+    let mut frame_samples = Vec::with_capacity(samples_per_frame as usize);
+
+    for frame in 0..end {
+        while let Some(event) = events.peek().cloned() {
+            if event.frame > frame {
+                break;
+            }
+            timer = event.value;
+            events.next();
+        }
+
+        frame_samples.clear();
+        for _ in 0..samples_per_frame {
+            let value = if timer == 0 {
+                128
+            } else if (sample * 2 * frequency as u64 / sample_rate as u64).is_multiple_of(2) {
+                192
+            } else {
+                64
+            };
+
+            frame_samples.push(value);
+            sample += 1;
+        }
+        writer.write_all(&frame_samples)?;
+
+        timer = timer.saturating_sub(1);
+    }
+
+    Ok(())
+}
+
+/// Writes a little-endian `u32`
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8])
+}
+
+/// Writes a little-endian `u16`
+fn write_u16<W: Write>(writer: &mut W, value: u16) -> io::Result<()> {
+    writer.write_all(&[value as u8, (value >> 8) as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut log = SoundLog::new();
+        log.push(SoundEvent { cycle: 10, frame: 1, value: 4 });
+        log.push(SoundEvent { cycle: 200, frame: 9, value: 0 });
+
+        assert_eq!(log, SoundLog::parse(&log.to_string()).unwrap());
+    }
+
+    #[test]
+    fn test_render_wav() {
+        let mut log = SoundLog::new();
+        log.push(SoundEvent { cycle: 0, frame: 1, value: 2 });
+
+        let mut wav = Vec::new();
+        render_wav(&log, &mut wav, 600, 100).unwrap();
+
+        // 3 frames of 10 samples each, after a 44 byte header
+        assert_eq!(44 + 30, wav.len());
+        // The first frame is silent, and the next two play the tone
+        assert!(wav[44..54].iter().all(|&s| s == 128));
+        assert!(wav[54..74].iter().all(|&s| s != 128));
+    }
+}
@@ -0,0 +1,122 @@
+//! A timer for speedrunning, with splits triggered by conditions on the state of the emulator
+//!
+//! The timer counts frames (ticks of the 60 hz timers) rather than real time, so it is not
+//! affected by the speed of the host. It starts when the emulator is created, and each split is
+//! triggered the first frame its condition holds after the previous split was triggered. The
+//! timer stops when the last split is triggered.
+
+use std::fmt;
+use std::time::Duration;
+
+use condition::Condition;
+use super::Chip8;
+
+/// A split of a speedrun
+#[derive(Clone, Debug, PartialEq)]
+pub struct Split {
+    /// The name of the split
+    pub name: String,
+    /// The condition that triggers the split
+    pub condition: Condition,
+    /// The frame the split was triggered at, or `None` if it hasn't been triggered yet
+    pub frame: Option<u64>,
+}
+
+/// A speedrun timer (see the module documentation)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpeedrunTimer {
+    /// The splits, in the order they are triggered
+    splits: Vec<Split>,
+    /// The number of frames since the timer started
+    frames: u64,
+}
+
+impl SpeedrunTimer {
+    /// Returns a timer with no splits, which never stops
+    pub fn new() -> SpeedrunTimer {
+        SpeedrunTimer::default()
+    }
+
+    /// Adds a split, which is triggered after all previously added splits
+    pub fn split<S: Into<String>>(mut self, name: S, condition: Condition) -> SpeedrunTimer {
+        self.splits.push(Split {
+            name: name.into(),
+            condition,
+            frame: None,
+        });
+        self
+    }
+
+    /// Restarts the timer, clearing the times of all splits
+    pub fn reset(&mut self) {
+        self.frames = 0;
+
+        for split in &mut self.splits {
+            split.frame = None;
+        }
+    }
+
+    /// Counts a frame, triggering the next split if its condition holds
+    /// Does nothing if the timer has stopped
+    pub fn update(&mut self, chip8: &Chip8) {
+        if self.finished() {
+            return;
+        }
+
+        self.frames += 1;
+
+        let frames = self.frames;
+        if let Some(split) = self.splits.iter_mut().find(|s| s.frame.is_none()) {
+            if split.condition.eval(chip8) {
+                split.frame = Some(frames);
+            }
+        }
+    }
+
+    /// Returns whether the timer has stopped, which happens when the last split is triggered
+    pub fn finished(&self) -> bool {
+        self.splits.last().is_some_and(|s| s.frame.is_some())
+    }
+
+    /// Returns the splits
+    pub fn splits(&self) -> &[Split] {
+        &self.splits
+    }
+
+    /// Returns the number of frames counted
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the time counted
+    pub fn elapsed(&self) -> Duration {
+        frames_to_duration(self.frames)
+    }
+}
+
+impl fmt::Display for SpeedrunTimer {
+    /// Writes the time counted, followed by the name and time of the last triggered split
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_duration(self.elapsed()))?;
+
+        if let Some(split) = self.splits.iter().rev().find(|s| s.frame.is_some()) {
+            let time = frames_to_duration(split.frame.unwrap_or(0));
+            write!(f, " ({}: {})", split.name, format_duration(time))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts a number of 60 hz frames to a duration
+fn frames_to_duration(frames: u64) -> Duration {
+    Duration::new(frames / 60, (frames % 60 * 1_000_000_000 / 60) as u32)
+}
+
+/// Formats a duration as `M:SS.mmm`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let millis = duration.subsec_millis();
+
+    format!("{}:{:02}.{:03}", secs / 60, secs % 60, millis)
+}
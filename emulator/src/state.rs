@@ -0,0 +1,222 @@
+//! Snapshots of the state of the emulator
+
+use std::fmt;
+
+use errors::*;
+use register::Registers;
+use io::{Io, Keypad, PIXELS, PLANES, AUDIO_PATTERN_LEN};
+use super::Chip8;
+
+/// The bytes at the start of a serialized state
+const STATE_MAGIC: &[u8] = b"CH8STATE";
+/// The version of the format of serialized states
+const STATE_VERSION: u8 = 3;
+
+/// A snapshot of the state of an emulator, which can be restored later
+/// Configuration, such as quirks and the random number generator, is not included
+#[derive(Clone)]
+pub struct State {
+    /// RAM
+    memory: Vec<u8>,
+    /// The stack
+    stack: Vec<u16>,
+    /// Register state
+    registers: Registers,
+    /// I/O state
+    io: Io,
+    /// The delay timer
+    delay_timer: u8,
+    /// The sound timer
+    sound_timer: u8,
+    /// Whether the program has ended
+    program_ended: bool,
+    /// The audio pattern (XO-CHIP)
+    audio_pattern: [u8; AUDIO_PATTERN_LEN],
+    /// The pitch of the audio pattern (XO-CHIP)
+    pitch: u8,
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("State")
+            .field("stack", &self.stack)
+            .field("registers", &self.registers)
+            .field("delay_timer", &self.delay_timer)
+            .field("sound_timer", &self.sound_timer)
+            .field("program_ended", &self.program_ended)
+            .field("audio_pattern", &self.audio_pattern)
+            .field("pitch", &self.pitch)
+            .finish()
+    }
+}
+
+impl State {
+    /// Serializes the state into bytes, which can be stored and read with `from_bytes`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.memory.len() + PIXELS + 64 + self.stack.len() * 2);
+
+        bytes.extend_from_slice(STATE_MAGIC);
+        bytes.push(STATE_VERSION);
+        push_u32(&mut bytes, self.memory.len() as u32);
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(self.registers.get_registers());
+        push_u16(&mut bytes, self.registers.index);
+        push_u16(&mut bytes, self.registers.program_counter);
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.push(self.program_ended as u8);
+        bytes.push(self.io.selected_planes());
+        bytes.extend(self.io.keys().iter().map(|k| k as u8));
+        bytes.extend_from_slice(self.io.planes());
+        bytes.extend_from_slice(&self.audio_pattern);
+        bytes.push(self.pitch);
+        push_u16(&mut bytes, self.stack.len() as u16);
+
+        for &address in &self.stack {
+            push_u16(&mut bytes, address);
+        }
+
+        bytes
+    }
+
+    /// Deserializes a state from bytes created by `to_bytes`
+    /// Any bytes after the state are ignored, so the state can be stored in a larger buffer.
+    pub fn from_bytes(bytes: &[u8]) -> Result<State> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(STATE_MAGIC.len())? != STATE_MAGIC {
+            bail!(ErrorKind::InvalidState("not a saved state".to_string()));
+        }
+
+        let version = reader.take(1)?[0];
+        if version != STATE_VERSION {
+            bail!(ErrorKind::InvalidState(format!("unsupported version: {}", version)));
+        }
+
+        let memory_size = reader.u32()? as usize;
+        let memory = reader.take(memory_size)?.to_vec();
+
+        let mut registers = Registers::new(0);
+        registers.get_mut_registers().copy_from_slice(reader.take(16)?);
+        registers.index = reader.u16()?;
+        registers.program_counter = reader.u16()?;
+
+        let timers = reader.take(3)?;
+        let (delay_timer, sound_timer, program_ended) = (timers[0], timers[1], timers[2] != 0);
+
+        let mut io = Io::new();
+        io.select_planes(reader.take(1)?[0]);
+
+        let mut keys = [false; 16];
+        for (key, &byte) in keys.iter_mut().zip(reader.take(16)?) {
+            *key = byte != 0;
+        }
+        io.set_keys(Keypad::from(keys));
+
+        for (i, &pixel) in reader.take(PIXELS)?.iter().enumerate() {
+            for plane in (0..PLANES).map(|p| 1 << p) {
+                io.set_pixel(i, plane, pixel & plane != 0);
+            }
+        }
+
+        let mut audio_pattern = [0; AUDIO_PATTERN_LEN];
+        audio_pattern.copy_from_slice(reader.take(AUDIO_PATTERN_LEN)?);
+        let pitch = reader.take(1)?[0];
+
+        let stack_len = reader.u16()?;
+        let stack = (0..stack_len).map(|_| reader.u16()).collect::<Result<Vec<_>>>()?;
+
+        Ok(State {
+            memory,
+            stack,
+            registers,
+            io,
+            delay_timer,
+            sound_timer,
+            program_ended,
+            audio_pattern,
+            pitch,
+        })
+    }
+}
+
+/// Appends a big-endian `u16` to the bytes
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.push((value >> 8) as u8);
+    bytes.push(value as u8);
+}
+
+/// Appends a big-endian `u32` to the bytes
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    push_u16(bytes, (value >> 16) as u16);
+    push_u16(bytes, value as u16);
+}
+
+/// Reads a serialized state
+struct Reader<'a> {
+    /// The bytes not read yet
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Reads the next `len` bytes
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            bail!(ErrorKind::InvalidState("unexpected end of data".to_string()));
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    /// Reads a big-endian `u16`
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    /// Reads a big-endian `u32`
+    fn u32(&mut self) -> Result<u32> {
+        Ok((self.u16()? as u32) << 16 | self.u16()? as u32)
+    }
+}
+
+impl Chip8 {
+    /// Returns a snapshot of the state of the emulator
+    pub fn save_state(&self) -> State {
+        State {
+            memory: self.memory.clone(),
+            stack: self.stack.clone(),
+            registers: self.registers.clone(),
+            io: self.io.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            program_ended: self.program_ended,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+        }
+    }
+
+    /// Restores the emulator to the state in the snapshot
+    /// The memory of the state replaces the memory of the emulator, even if it is a different size.
+    pub fn load_state(&mut self, state: &State) {
+        self.memory.clone_from(&state.memory);
+        self.stack = state.stack.clone();
+        self.registers = state.registers.clone();
+        self.io = state.io.clone();
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.program_ended = state.program_ended;
+        // Only send the audio pattern if it changed, so backends keep their beep for programs
+        // that never set one
+        if state.audio_pattern != self.audio_pattern || state.pitch != self.pitch {
+            self.audio_pattern = state.audio_pattern;
+            self.pitch = state.pitch;
+            self.audio_changed = true;
+        }
+        // Redraw the restored screen
+        self.io.set_draw_flag();
+    }
+}
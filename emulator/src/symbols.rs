@@ -0,0 +1,142 @@
+//! Symbol tables, which name addresses in a program for debugging
+//!
+//! Symbols are stored as `name = address` lines, where the address is decimal or hexadecimal
+//! (starting with `0x`):
+//!
+//! ```text
+//! # Comments start with a '#'
+//! main = 0x200
+//! draw_paddle = 0x23A
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use errors::*;
+
+/// A mapping of addresses to names
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymbolTable {
+    /// The names of addresses
+    symbols: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    /// Returns an empty symbol table
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Names an address, replacing its previous name if it had one
+    pub fn insert(&mut self, address: u16, name: &str) {
+        self.symbols.insert(address, name.to_string());
+    }
+
+    /// Returns the name of the address, if it has one
+    pub fn get(&self, address: u16) -> Option<&str> {
+        self.symbols.get(&address).map(|s| &s[..])
+    }
+
+    /// Returns the closest named address at or before the address, along with its name
+    pub fn nearest(&self, address: u16) -> Option<(u16, &str)> {
+        self.symbols.range(..=address).next_back().map(|(&a, s)| (a, &s[..]))
+    }
+
+    /// Returns whether the table is empty
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Returns an iterator over the addresses and their names, in order of address
+    pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (u16, &'a str)> + 'a> {
+        Box::new(self.symbols.iter().map(|(&a, s)| (a, &s[..])))
+    }
+
+    /// Parses a symbol table from its text representation (see the module documentation for the
+    /// format)
+    pub fn parse(text: &str) -> Result<SymbolTable> {
+        let mut table = SymbolTable::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || ErrorKind::InvalidSymbol(i + 1, line.to_string());
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let address = parts.next().map(str::trim).ok_or_else(&invalid)?;
+
+            let address = if let Some(hex) = address.strip_prefix("0x") {
+                u16::from_str_radix(hex, 16)
+            } else {
+                address.parse()
+            };
+            let address = address.chain_err(invalid)?;
+
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                bail!(invalid());
+            }
+
+            table.insert(address, name);
+        }
+
+        Ok(table)
+    }
+
+    /// Returns a description of the address relative to the nearest symbol, such as `main+0x4`,
+    /// or `None` if there is no symbol at or before the address
+    pub fn describe(&self, address: u16) -> Option<String> {
+        self.nearest(address).map(|(a, name)| if a == address {
+            name.to_string()
+        } else {
+            format!("{}+0x{:X}", name, address - a)
+        })
+    }
+}
+
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (address, name) in self.iter() {
+            writeln!(f, "{} = 0x{:03X}", name, address)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let table = SymbolTable::parse("# Symbols\nmain = 0x200\n\nloop = 522").unwrap();
+
+        assert_eq!(Some("main"), table.get(0x200));
+        assert_eq!(Some("loop"), table.get(0x20A));
+        assert_eq!(table, SymbolTable::parse(&table.to_string()).unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(SymbolTable::parse("main 0x200").is_err());
+        assert!(SymbolTable::parse("main = 0x20G").is_err());
+        assert!(SymbolTable::parse("two words = 0x200").is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut table = SymbolTable::new();
+        table.insert(0x200, "main");
+        table.insert(0x210, "loop");
+
+        assert_eq!(None, table.describe(0x1FE));
+        assert_eq!(Some("main".to_string()), table.describe(0x200));
+        assert_eq!(Some("main+0xE".to_string()), table.describe(0x20E));
+        assert_eq!(Some("loop+0x2".to_string()), table.describe(0x212));
+    }
+}
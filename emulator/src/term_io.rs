@@ -0,0 +1,361 @@
+//! An implementation of `Chip8IO` that runs in a terminal using `crossterm`
+//! Press `Escape` or `Ctrl+C` to exit the emulator
+//!
+//! The screen is drawn with Unicode half block characters, so each character shows two pixels
+//! stacked vertically, and the terminal must be at least 128 columns wide and 32 rows tall. Colors
+//! are drawn with 24-bit color escape codes, and the sound is the terminal bell.
+//!
+//! Most terminals only report key presses, not releases, so a key is held for a short time after
+//! each press, and holding a key down relies on the key repeat of the terminal. Terminals that
+//! support the kitty keyboard protocol (and the Windows console) report releases, so keys are held
+//! until they are released.
+
+extern crate crossterm;
+
+use std::io::{self, Stdout, Write};
+use std::mem;
+use std::time::{Duration, Instant};
+
+use self::crossterm::QueueableCommand;
+use self::crossterm::cursor::{Hide, MoveTo, Show};
+use self::crossterm::event::{self, DisableFocusChange, EnableFocusChange, Event, KeyCode,
+                             KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+                             PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use self::crossterm::style::{Color as TermColor, Print, ResetColor, SetBackgroundColor,
+                             SetForegroundColor};
+use self::crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen,
+                                LeaveAlternateScreen, SetTitle};
+use super::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use bindings::{Action, Bindings};
+use io::{Color, Palette};
+use pause::PauseHandle;
+
+/// How long a key is held after it is pressed, if the terminal doesn't report key releases
+/// A key that is held down may be released briefly before key repeat starts.
+const KEY_HOLD: Duration = Duration::from_millis(250);
+/// The character used to draw two pixels, where the top one is the foreground color and the bottom
+/// one is the background color
+const HALF_BLOCK: char = '▀';
+
+/// Stores state used for doing I/O
+#[allow(missing_debug_implementations)]
+pub struct Io {
+    stdout: Stdout,
+    /// When each key was last pressed, or `None` if it is released
+    pressed: [Option<Instant>; 16],
+    /// Whether the terminal reports key releases
+    reports_releases: bool,
+    should_close: bool,
+    bindings: Bindings,
+    /// Actions triggered since the last call to `take_actions`
+    actions: Vec<Action>,
+    /// The title of the program being run
+    title: Option<String>,
+    /// The status line shown in the title of the terminal
+    status: String,
+    /// The handle used to pause the emulator when the terminal loses focus, if that is enabled
+    focus_pause: Option<PauseHandle>,
+    /// Whether the emulator was paused because the terminal lost focus, so it should be resumed
+    /// when the terminal gains focus
+    paused_by_focus: bool,
+    /// The screen that was last drawn, used to skip drawing it again if it is unchanged
+    last_frame: Vec<u8>,
+}
+
+impl Io {
+    /// Initializes the state, switching the terminal to raw mode and an alternate screen
+    /// The terminal is restored when the state is dropped.
+    pub fn new() -> io::Result<Io> {
+        terminal::enable_raw_mode()?;
+
+        // Windows always reports releases, and other terminals may support reporting them
+        let reports_releases = cfg!(windows) ||
+                               terminal::supports_keyboard_enhancement().unwrap_or(false);
+
+        let mut io = Io {
+            stdout: io::stdout(),
+            pressed: [None; 16],
+            reports_releases,
+            should_close: false,
+            bindings: Bindings::default(),
+            actions: Vec::new(),
+            title: None,
+            status: String::new(),
+            focus_pause: None,
+            paused_by_focus: false,
+            last_frame: Vec::new(),
+        };
+
+        io.stdout.queue(EnterAlternateScreen)?;
+        io.stdout.queue(Hide)?;
+        io.stdout.queue(EnableFocusChange)?;
+        if reports_releases && !cfg!(windows) {
+            let flags = KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+            io.stdout.queue(PushKeyboardEnhancementFlags(flags))?;
+        }
+        io.stdout.queue(Clear(ClearType::All))?;
+        io.stdout.flush()?;
+        io.update_title();
+
+        Ok(io)
+    }
+
+    /// Sets the title of the terminal, which is shown along with the name of the emulator
+    pub fn set_title(&mut self, title: &str) {
+        self.title = Some(title.to_string());
+        self.update_title();
+    }
+
+    /// Updates the title of the terminal to show the title of the program and the status line
+    fn update_title(&mut self) {
+        let mut title = match self.title {
+            Some(ref title) => format!("{} - Chip-8 Emulator", title),
+            None => "Chip-8 Emulator".to_string(),
+        };
+
+        if !self.status.is_empty() {
+            title = format!("{} [{}]", title, self.status);
+        }
+
+        let _ = self.stdout.queue(SetTitle(title)).and_then(|s| s.flush());
+    }
+
+    /// Sets the bindings of keys to emulator actions
+    /// Letter keys are named by their uppercase letter, and other keys as in `default_io`, for
+    /// example `F1` or `Backspace`.
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    /// Returns the bindings of keys to emulator actions
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// Pauses the emulator using the handle (see `Chip8::pause_handle`) when the terminal loses
+    /// focus, and resumes it when the terminal gains focus again
+    /// The emulator is only resumed if it was paused by losing focus, not if it was paused
+    /// manually. Not all terminals report focus changes.
+    pub fn pause_on_focus_loss(&mut self, handle: PauseHandle) {
+        self.focus_pause = Some(handle);
+    }
+
+    /// Returns the actions triggered by key presses since the last call to this function
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        mem::take(&mut self.actions)
+    }
+
+    /// Handles all pending events, updating the keyboard state and detecting when to close the
+    /// emulator
+    fn handle_events(&mut self) {
+        while let Ok(true) = event::poll(Duration::from_secs(0)) {
+            match event::read() {
+                Ok(Event::Key(key)) => self.handle_key(key),
+                Ok(Event::FocusGained) => self.set_focus(true),
+                Ok(Event::FocusLost) => self.set_focus(false),
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Handles the terminal gaining or losing focus, pausing or resuming the emulator if enabled
+    fn set_focus(&mut self, focused: bool) {
+        let handle = match self.focus_pause {
+            Some(ref handle) => handle,
+            None => return,
+        };
+
+        if !focused && !handle.is_paused() {
+            handle.pause();
+            self.paused_by_focus = true;
+        } else if focused && self.paused_by_focus {
+            handle.resume();
+            self.paused_by_focus = false;
+        }
+    }
+
+    /// Handles a key event, setting the keyboard state
+    fn handle_key(&mut self, key: KeyEvent) {
+        let state = match key.kind {
+            KeyEventKind::Press | KeyEventKind::Repeat => true,
+            KeyEventKind::Release => false,
+        };
+
+        // Raw mode stops `Ctrl+C` from sending an interrupt, so handle it here
+        if key.code == KeyCode::Esc ||
+           (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)) {
+            self.should_close = true;
+            return;
+        }
+
+        // Keys bound to actions are not passed on to the program
+        if let Some(action) = key_name(key.code).and_then(|name| self.bindings.action(&name)) {
+            if key.kind == KeyEventKind::Press {
+                self.actions.push(action);
+            }
+            return;
+        }
+
+        let button = match key.code {
+            KeyCode::Char(c) => {
+                match c.to_ascii_lowercase() {
+                    '1' => 0x1,
+                    '2' => 0x2,
+                    '3' => 0x3,
+                    '4' => 0xC,
+                    'q' => 0x4,
+                    'w' => 0x5,
+                    'e' => 0x6,
+                    'r' => 0xD,
+                    'a' => 0x7,
+                    's' => 0x8,
+                    'd' => 0x9,
+                    'f' => 0xE,
+                    'z' => 0xA,
+                    'x' => 0x0,
+                    'c' => 0xB,
+                    'v' => 0xF,
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+
+        self.pressed[button] = if state { Some(Instant::now()) } else { None };
+    }
+
+    /// Restores the terminal to how it was before the state was initialized
+    fn restore(&mut self) -> io::Result<()> {
+        self.stdout.queue(ResetColor)?;
+        if self.reports_releases && !cfg!(windows) {
+            self.stdout.queue(PopKeyboardEnhancementFlags)?;
+        }
+        self.stdout.queue(DisableFocusChange)?;
+        self.stdout.queue(Show)?;
+        self.stdout.queue(LeaveAlternateScreen)?;
+        self.stdout.flush()?;
+        terminal::disable_raw_mode()
+    }
+}
+
+impl Drop for Io {
+    fn drop(&mut self) {
+        let _ = self.restore();
+    }
+}
+
+/// Returns the name of a key used for bindings, or `None` if it can't be bound
+fn key_name(key: KeyCode) -> Option<String> {
+    let name = match key {
+        KeyCode::Char(c) => c.to_uppercase().collect(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::Backspace => "Backspace".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Enter => "Return".to_string(),
+        KeyCode::Delete => "Delete".to_string(),
+        KeyCode::Insert => "Insert".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Converts a color to the format used by `crossterm`
+fn to_term_color(color: Color) -> TermColor {
+    TermColor::Rgb {
+        r: color[0],
+        g: color[1],
+        b: color[2],
+    }
+}
+
+impl ::Chip8IO for Io {
+    fn draw(&mut self, pixels: &[bool]) {
+        // Draw pixels that are on using the color of the first plane
+        let planes = pixels.iter().map(|&p| p as u8).collect::<Vec<_>>();
+        self.draw_planes(&planes, &Palette::default());
+    }
+
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        if self.last_frame == planes {
+            return;
+        }
+
+        // Build the whole frame before writing it, so it is written all at once
+        let mut frame = Vec::new();
+        let mut colors = None;
+
+        for row in 0..SCREEN_HEIGHT / 2 {
+            let _ = frame.queue(MoveTo(0, row as u16));
+
+            for x in 0..SCREEN_WIDTH {
+                let top = planes[x + row * 2 * SCREEN_WIDTH] as usize;
+                let bottom = planes[x + (row * 2 + 1) * SCREEN_WIDTH] as usize;
+
+                // Only change the colors when they differ from the previous character
+                if colors != Some((top, bottom)) {
+                    let _ = frame.queue(SetForegroundColor(to_term_color(palette.colors[top])));
+                    let _ = frame.queue(SetBackgroundColor(to_term_color(palette.colors[bottom])));
+                    colors = Some((top, bottom));
+                }
+
+                let _ = frame.queue(Print(HALF_BLOCK));
+            }
+        }
+
+        let _ = frame.queue(ResetColor);
+
+        if self.stdout.write_all(&frame).and_then(|_| self.stdout.flush()).is_ok() {
+            self.last_frame = planes.to_vec();
+        }
+    }
+
+    fn sound_start(&mut self) {
+        // Ring the terminal bell
+        let _ = self.stdout.write_all(b"\x07").and_then(|_| self.stdout.flush());
+    }
+
+    fn sound_stop(&mut self) {}
+
+    fn get_keys(&mut self) -> ::Keypad {
+        self.handle_events();
+
+        let mut keys = ::Keypad::new();
+        for (key, pressed) in (0..16).zip(self.pressed.iter_mut()) {
+            // Release keys after a while if the terminal doesn't report releases
+            if let Some(time) = *pressed {
+                if !self.reports_releases && time.elapsed() >= KEY_HOLD {
+                    *pressed = None;
+                }
+            }
+
+            keys.set(key, pressed.is_some());
+        }
+
+        keys
+    }
+
+    fn actions(&mut self) -> Vec<Action> {
+        self.take_actions()
+    }
+
+    fn set_status(&mut self, status: &str) {
+        if self.status != status {
+            self.status = status.to_string();
+            self.update_title();
+        }
+    }
+
+    fn should_close(&self) -> bool {
+        self.should_close
+    }
+}
@@ -7,10 +7,19 @@
 
 #[macro_use]
 mod utils;
+mod properties;
+mod snapshot;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use self::utils::*;
+use self::snapshot::{assert_snapshot, assert_chip8_snapshot, rom};
 use Chip8;
-use config::Log;
+use bindings::{Action, Bindings};
+use clock::{Clock, ManualClock};
+use config::{Config, Log, Quirks, InputPolling};
 use errors::*;
 
 /// A version of `chip8::run` that runs a program, then returns the emulator and I/O state for
@@ -23,7 +32,7 @@ fn run_program<I>(program: &[u8],
                   -> (Chip8, I)
     where I: TestIO + ::Chip8IO
 {
-    let mut chip8 = Chip8::new(program, Log::Disabled).unwrap();
+    let mut chip8 = Chip8::new(program, Config::new()).unwrap();
     let mut io = I::new(keypresses.unwrap_or(Vec::new()));
 
     // Two bytes is one instruction, so only run half as many cycles as there are bytes
@@ -36,7 +45,7 @@ fn run_program<I>(program: &[u8],
         chip8.cycle(&mut io).unwrap();
         // Countdown timers
         // Not simulated at the correct speed, but still useful to test whether they work
-        chip8.update_timers(&mut io);
+        chip8.tick_60hz(&mut io);
     }
 
     (chip8, io)
@@ -46,11 +55,24 @@ fn run_program_default(program: &[u8]) -> Chip8 {
     run_program::<Io>(program, None, None).0
 }
 
+/// Runs each instruction of a program once with the given configuration, without simulating
+/// keypresses or timers
+fn run_program_config(program: &[u8], config: Config) -> Chip8 {
+    let mut chip8 = Chip8::new(program, config).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    for _ in 0..program.len() / 2 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    chip8
+}
+
 /// Tests that the emulator won't run programs that are too large
 #[test]
 fn program_too_large() {
     let program = [0; ::MEMORY];
-    let chip8 = Chip8::new(&program, Log::Disabled);
+    let chip8 = Chip8::new(&program, Config::new());
 
     match chip8 {
         Err(Error(ErrorKind::ProgramTooLarge(..), _)) => {}
@@ -59,6 +81,26 @@ fn program_too_large() {
     }
 }
 
+/// Tests instruction OffsetGoto
+#[test]
+fn offset_goto() {
+    let program = program!(0x6004, 0x6208, 0xB200);
+
+    let chip8 = run_program_config(&program, Config::new().quirks(Quirks::chip8()));
+
+    assert_eq!(0x204, chip8.registers.program_counter);
+}
+
+/// Tests that OffsetGoto uses VX with the jump quirk
+#[test]
+fn offset_goto_quirk() {
+    let program = program!(0x6004, 0x6208, 0xB200);
+
+    let chip8 = run_program_config(&program, Config::new().quirks(Quirks::schip()));
+
+    assert_eq!(0x208, chip8.registers.program_counter);
+}
+
 /// Tests instruction SetConst
 #[test]
 fn set_const() {
@@ -66,7 +108,7 @@ fn set_const() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x40, chip8.registers.get(0));
+    assert_eq!(0x40, chip8.registers.get(v(0)));
 }
 
 /// Tests instruction AddConst
@@ -76,7 +118,7 @@ fn add_const() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x40, chip8.registers.get(0));
+    assert_eq!(0x40, chip8.registers.get(v(0)));
 }
 
 /// Tests that AddConst wraps
@@ -86,7 +128,7 @@ fn add_const_wrap() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x40, chip8.registers.get(0));
+    assert_eq!(0x40, chip8.registers.get(v(0)));
 }
 
 /// Tests instruction Move
@@ -96,7 +138,7 @@ fn move_() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x40, chip8.registers.get(1));
+    assert_eq!(0x40, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction BitOr
@@ -106,7 +148,7 @@ fn bitor() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0xFF, chip8.registers.get(0));
+    assert_eq!(0xFF, chip8.registers.get(v(0)));
 }
 
 /// Tests instruction BitAnd
@@ -116,7 +158,7 @@ fn bitand() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0xAA, chip8.registers.get(0));
+    assert_eq!(0xAA, chip8.registers.get(v(0)));
 }
 
 /// Tests instruction BitXor
@@ -126,7 +168,7 @@ fn bitxor() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x55, chip8.registers.get(0));
+    assert_eq!(0x55, chip8.registers.get(v(0)));
 }
 
 /// Tests instruction Shr
@@ -136,7 +178,7 @@ fn shr() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x78, chip8.registers.get(0));
+    assert_eq!(0x78, chip8.registers.get(v(0)));
 }
 
 /// Tests that Shr sets VF to the least significant bit (LSB) of VX before the shift
@@ -147,7 +189,7 @@ fn shr_lsb_0() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x00, chip8.registers.get(0xF));
+    assert_eq!(0x00, chip8.registers.get(v(0xF)));
 }
 
 /// Tests that Shr sets VF to the least significant bit (LSB) of VX before the shift
@@ -158,7 +200,7 @@ fn shr_lsb_1() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x1, chip8.registers.get(0xF));
+    assert_eq!(0x1, chip8.registers.get(v(0xF)));
 }
 
 /// Tests instruction Shl
@@ -168,7 +210,7 @@ fn shl() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x1E, chip8.registers.get(0));
+    assert_eq!(0x1E, chip8.registers.get(v(0)));
 }
 
 /// Tests that Shl sets VF to the most significant bit (MSB) of VX before the shift
@@ -179,7 +221,7 @@ fn shl_msb_0() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x0, chip8.registers.get(0xF));
+    assert_eq!(0x0, chip8.registers.get(v(0xF)));
 }
 
 /// Tests that Shl sets VF to the most significant bit (MSB) of VX before the shift
@@ -190,7 +232,18 @@ fn shl_msb_1() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x1, chip8.registers.get(0xF));
+    assert_eq!(0x1, chip8.registers.get(v(0xF)));
+}
+
+/// Tests that Shr and Shl shift VY into VX without the shift quirk
+#[test]
+fn shift_no_quirk() {
+    let program = program!(0x61F0, 0x8016, 0x620F, 0x832E);
+
+    let chip8 = run_program_config(&program, Config::new().quirks(Quirks::chip8()));
+
+    assert_eq!(0x78, chip8.registers.get(v(0)));
+    assert_eq!(0x1E, chip8.registers.get(v(3)));
 }
 
 /// Tests instruction Add
@@ -200,7 +253,7 @@ fn add() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x30, chip8.registers.get(0));
+    assert_eq!(0x30, chip8.registers.get(v(0)));
 }
 
 /// Tests that Add wraps
@@ -210,7 +263,7 @@ fn add_wrap() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x40, chip8.registers.get(0));
+    assert_eq!(0x40, chip8.registers.get(v(0)));
 }
 
 /// Tests that Add sets VF to zero when a carry doesn't happen
@@ -220,7 +273,7 @@ fn add_carry_0() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x00, chip8.registers.get(0xF));
+    assert_eq!(0x00, chip8.registers.get(v(0xF)));
 }
 
 /// Tests that Add sets VF to one when a carry happens
@@ -230,7 +283,7 @@ fn add_carry_1() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x1, chip8.registers.get(0xF));
+    assert_eq!(0x1, chip8.registers.get(v(0xF)));
 }
 
 /// Tests instruction Sub
@@ -240,7 +293,7 @@ fn sub() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x20, chip8.registers.get(0));
+    assert_eq!(0x20, chip8.registers.get(v(0)));
 }
 
 /// Tests that Sub wraps
@@ -250,7 +303,7 @@ fn sub_wrap() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0xF0, chip8.registers.get(0));
+    assert_eq!(0xF0, chip8.registers.get(v(0)));
 }
 
 /// Tests that Sub sets VF to zero when a borrow doesn't happen
@@ -260,7 +313,7 @@ fn sub_borrow_0() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x00, chip8.registers.get(0xF));
+    assert_eq!(0x00, chip8.registers.get(v(0xF)));
 }
 
 /// Tests that Sub sets VF to one when a borrow happens
@@ -270,7 +323,7 @@ fn sub_borrow_1() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x1, chip8.registers.get(0xF));
+    assert_eq!(0x1, chip8.registers.get(v(0xF)));
 }
 
 /// Tests instruction InverseSub
@@ -280,7 +333,7 @@ fn inverse_sub() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x20, chip8.registers.get(0));
+    assert_eq!(0x20, chip8.registers.get(v(0)));
 }
 
 /// Tests that InverseSub wraps
@@ -290,7 +343,7 @@ fn inverse_sub_wrap() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0xF0, chip8.registers.get(0));
+    assert_eq!(0xF0, chip8.registers.get(v(0)));
 }
 
 /// Tests that InverseSub sets VF to zero when a borrow doesn't happen
@@ -300,7 +353,7 @@ fn inverse_sub_borrow_0() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x00, chip8.registers.get(0xF));
+    assert_eq!(0x00, chip8.registers.get(v(0xF)));
 }
 
 /// Tests that InverseSub sets VF to one when a borrow happens
@@ -310,7 +363,29 @@ fn inverse_sub_borrow_1() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x1, chip8.registers.get(0xF));
+    assert_eq!(0x1, chip8.registers.get(v(0xF)));
+}
+
+/// Tests that Rand masks the random number with N
+#[test]
+fn rand_mask() {
+    let program = program!(0xC00F, 0xC100);
+
+    let chip8 = run_program_default(&program);
+
+    assert!(chip8.registers.get(v(0)) <= 0x0F);
+    assert_eq!(0x00, chip8.registers.get(v(1)));
+}
+
+/// Tests that Rand produces the same numbers when the emulator is seeded with the same seed
+#[test]
+fn rand_seeded() {
+    let program = program!(0xC0FF, 0xC1FF, 0xC2FF, 0xC3FF, 0xC4FF, 0xC5FF, 0xC6FF, 0xC7FF);
+
+    let a = run_program_config(&program, Config::new().seed(1234));
+    let b = run_program_config(&program, Config::new().seed(1234));
+
+    assert_eq!(a.registers.get_registers(), b.registers.get_registers());
 }
 
 /// Tests instruction BCD
@@ -347,7 +422,7 @@ fn skip_eq_const_0() {
 
     let (chip8, _) = run_program::<Io>(&program, None, Some(3));
 
-    assert_eq!(0x00, chip8.registers.get(1));
+    assert_eq!(0x00, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipEqConst when the skip should not happen
@@ -357,7 +432,7 @@ fn skip_eq_const_1() {
 
     let (chip8, _) = run_program::<Io>(&program, None, Some(3));
 
-    assert_eq!(0xFF, chip8.registers.get(1));
+    assert_eq!(0xFF, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipNeqConst when the skip should not happen
@@ -367,7 +442,7 @@ fn skip_neq_const_0() {
 
     let (chip8, _) = run_program::<Io>(&program, None, Some(3));
 
-    assert_eq!(0xFF, chip8.registers.get(1));
+    assert_eq!(0xFF, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipNeqConst when the skip should happen
@@ -377,7 +452,7 @@ fn skip_neq_const_1() {
 
     let (chip8, _) = run_program::<Io>(&program, None, Some(3));
 
-    assert_eq!(0x00, chip8.registers.get(1));
+    assert_eq!(0x00, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipEq when the skip should happen
@@ -387,7 +462,7 @@ fn skip_eq_0() {
 
     let (chip8, _) = run_program::<Io>(&program, None, Some(3));
 
-    assert_eq!(0x00, chip8.registers.get(1));
+    assert_eq!(0x00, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipEq when the skip should not happen
@@ -397,7 +472,7 @@ fn skip_eq_1() {
 
     let (chip8, _) = run_program::<Io>(&program, None, Some(3));
 
-    assert_eq!(0xFF, chip8.registers.get(1));
+    assert_eq!(0xFF, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipNeq when the skip should not happen
@@ -407,7 +482,7 @@ fn skip_neq_0() {
 
     let (chip8, _) = run_program::<Io>(&program, None, Some(3));
 
-    assert_eq!(0xFF, chip8.registers.get(1));
+    assert_eq!(0xFF, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipNeq when the skip should happen
@@ -417,7 +492,7 @@ fn skip_neq_1() {
 
     let (chip8, _) = run_program::<Io>(&program, None, Some(3));
 
-    assert_eq!(0x00, chip8.registers.get(1));
+    assert_eq!(0x00, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction RegDump
@@ -481,6 +556,45 @@ fn reg_load_address() {
     assert_eq!(&[0; 16], chip8.registers.get_registers());
 }
 
+/// Tests that RegDump and RegLoad increment I without the memory quirk
+#[test]
+fn reg_dump_load_no_quirk() {
+    let program = program!(0xA300, 0xF255, 0xF065);
+
+    let chip8 = run_program_config(&program, Config::new().quirks(Quirks::chip8()));
+
+    assert_eq!(0x304, chip8.registers.index);
+}
+
+/// Tests that memory accesses past the end of memory wrap around with the wrap quirk, and are
+/// errors without it
+#[test]
+fn memory_wrap_quirk() {
+    // Stores V0 to V2 at 0xFFE, clears them and loads them back, then stores the BCD of 123 at
+    // 0xFFF
+    let program = program!(0x6001, 0x6102, 0x6203, 0xAFFE, 0xF255, 0x6000, 0x6100, 0x6200,
+                           0xAFFE, 0xF265, 0x607B, 0xAFFF, 0xF033);
+    let quirks = Quirks { wrap: true, ..Quirks::chip8() };
+
+    let chip8 = run_program_config(&program, Config::new().quirks(quirks));
+
+    assert_eq!(2, chip8.registers.get(v(1)));
+    assert_eq!(3, chip8.registers.get(v(2)));
+    assert_eq!(&[1, 1], &chip8.memory[0xFFE..]);
+    assert_eq!(&[2, 3], &chip8.memory[..2]);
+
+    let mut chip8 = Chip8::new(&program, Config::new().quirks(Quirks::chip8())).unwrap();
+    let mut io = Io::new(Vec::new());
+    for _ in 0..4 {
+        chip8.cycle(&mut io).unwrap();
+    }
+    match chip8.cycle(&mut io) {
+        Err(Error(ErrorKind::InvalidAddress(0xFFE, _), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+}
+
 /// Tests instruction SetIndex
 #[test]
 fn set_index() {
@@ -501,6 +615,42 @@ fn add_index() {
     assert_eq!(0xFF, chip8.registers.index);
 }
 
+/// Tests that AddIndex sets VF when I goes past 0xFFF with the index overflow quirk
+#[test]
+fn add_index_overflow_quirk() {
+    let program = program!(0x6F05, 0x6002, 0xAFFE, 0xF01E, 0x6F05, 0xAFFD, 0xF01E);
+    let quirks = Quirks { index_overflow: true, ..Quirks::chip8() };
+
+    let chip8 = run_program_config(&program, Config::new().quirks(quirks));
+    assert_eq!(0xFFF, chip8.registers.index);
+    assert_eq!(0, chip8.registers.get(v(0xF)));
+
+    let chip8 = run_program_config(&program[..8], Config::new().quirks(quirks));
+    assert_eq!(0x1000, chip8.registers.index);
+    assert_eq!(1, chip8.registers.get(v(0xF)));
+
+    // Without the quirk, VF is left unchanged
+    let chip8 = run_program_config(&program[..8], Config::new().quirks(Quirks::chip8()));
+    assert_eq!(5, chip8.registers.get(v(0xF)));
+}
+
+/// Tests that AddIndex returns an error instead of overflowing I
+#[test]
+fn add_index_overflow() {
+    let program = program!(0xF01E);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    chip8.registers.index = 0xFFFF;
+    chip8.registers.set(v(0), 1);
+
+    match chip8.cycle(&mut io) {
+        Err(Error(ErrorKind::InvalidAddress(0x10000, _), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+}
+
 /// Tests instruction SetIndexChar
 #[test]
 fn set_index_char() {
@@ -508,7 +658,93 @@ fn set_index_char() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(::FONTSET_START as u16 + 0x4B, chip8.registers.index);
+    assert_eq!(::fontset::FONTSET_START as u16 + 0x4B, chip8.registers.index);
+}
+
+/// Tests that SetIndexChar uses the lowest nibble of values over 15 without the font quirk
+#[test]
+fn set_index_char_mask() {
+    let program = program!(0x601A, 0xF029);
+
+    let chip8 = run_program_config(&program, Config::new().quirks(Quirks::chip8()));
+
+    assert_eq!(::fontset::FONTSET_START as u16 + 0x32, chip8.registers.index);
+}
+
+/// Tests that SetIndexChar returns an error for values over 15 with the font quirk
+#[test]
+fn set_index_char_quirk() {
+    let program = program!(0x601A, 0xF029);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    chip8.cycle(&mut io).unwrap();
+
+    match chip8.cycle(&mut io) {
+        Err(Error(ErrorKind::UnknownCharacter(0x1A, _), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+}
+
+/// Tests instruction SetIndexLargeChar, and that the digits of the large font are in memory
+#[test]
+fn set_index_large_char() {
+    let program = program!(0x6007, 0xF030);
+
+    let chip8 = run_program_config(&program, Config::new());
+
+    let index = chip8.registers.index as usize;
+    assert_eq!(::fontset::LARGE_FONTSET_START + 70, index);
+    assert_eq!(&::fontset::LARGE_FONTSET[70..80], &chip8.memory[index..index + 10]);
+
+    // The default large font only has the digits 0 through 9
+    let program = program!(0x600A, 0xF030);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    chip8.cycle(&mut io).unwrap();
+    match chip8.cycle(&mut io) {
+        Err(Error(ErrorKind::UnknownCharacter(0xA, _), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+}
+
+/// Tests that custom fonts are loaded at their addresses and used by SetIndexChar and
+/// SetIndexLargeChar, and that fonts that don't fit are rejected
+#[test]
+fn custom_fonts() {
+    let font = (0..80).collect::<Vec<u8>>();
+    let large_font = vec![0xAA; 160];
+    let config = || Config::new().font(&font, 0x100).large_font(&large_font, 0x10);
+    let program = program!(0x6003, 0xF029, 0x610F, 0xF130);
+
+    let mut chip8 = Chip8::new(&program, config()).unwrap();
+    let mut io = Io::new(Vec::new());
+    for _ in 0..2 {
+        chip8.cycle(&mut io).unwrap();
+    }
+    assert_eq!(0x10F, chip8.registers.index);
+    assert_eq!(&font[..], &chip8.memory[0x100..0x150]);
+    for _ in 0..2 {
+        chip8.cycle(&mut io).unwrap();
+    }
+    assert_eq!(0x10 + 150, chip8.registers.index);
+    assert_eq!(&large_font[..], &chip8.memory[0x10..0xB0]);
+
+    let invalid = vec![config().font(&font, 0x1F0),
+                       config().program_start(0x140),
+                       config().large_font(&large_font, 0xC0),
+                       config().font(&font[..75], 0x100),
+                       config().large_font(&large_font[..120], 0x10)];
+    for config in invalid {
+        match Chip8::new(&program, config) {
+            Err(Error(ErrorKind::InvalidFont(_), _)) => {}
+            Err(e) => panic!("Wrong error: {}", e),
+            Ok(_) => panic!("Expected error"),
+        }
+    }
 }
 
 /// Tests instruction GetDelay
@@ -520,7 +756,7 @@ fn get_delay() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x1, chip8.registers.get(1));
+    assert_eq!(0x1, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SetDelay
@@ -541,10 +777,12 @@ fn set_delay() {
 fn wait_key() {
     let program = program!(0xF00A);
 
-    // Uses KeyIO instead of Io
-    let (chip8, _) = run_program::<KeyIO>(&program, None, None);
+    // Uses KeyIO instead of Io, which presses a key during the 10th cycle
+    let (chip8, _) = run_program::<KeyIO>(&program, None, Some(10));
 
-    assert_eq!(15, chip8.registers.get(0));
+    assert_eq!(15, chip8.registers.get(v(0)));
+    assert_eq!(0x202, chip8.registers.program_counter);
+    assert!(!chip8.is_waiting_for_key());
 }
 
 /// Tests that WaitKey correctly waits for a key to be pressed
@@ -552,13 +790,15 @@ fn wait_key() {
 fn wait_key_delay() {
     // The implementation of Chip8IO for KeyIO will increment its counter until it reaches 10
     // When it reaches 10, it presses a key, ending the WaitKey instructions
-    // This test makes sure the counter reaches 10
+    // This test makes sure the instruction is run again each cycle until then
     let program = program!(0xF00A);
 
     // Uses KeyIO instead of Io
-    let (_, io) = run_program::<KeyIO>(&program, None, None);
+    let (chip8, io) = run_program::<KeyIO>(&program, None, Some(9));
 
-    assert_eq!(10, io.get_keys_counter);
+    assert_eq!(9, io.get_keys_counter);
+    assert_eq!(0x200, chip8.registers.program_counter);
+    assert!(chip8.is_waiting_for_key());
 }
 
 /// Tests instruction SkipKey when the skip should happen
@@ -570,7 +810,7 @@ fn skip_key_0() {
 
     let (chip8, _) = run_program::<Io>(&program, Some(keypresses), Some(3));
 
-    assert_eq!(0x1, chip8.registers.get(1));
+    assert_eq!(0x1, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipKey when the skip should not happen
@@ -582,7 +822,7 @@ fn skip_key_1() {
 
     let (chip8, _) = run_program::<Io>(&program, Some(keypresses), Some(3));
 
-    assert_eq!(0xFF, chip8.registers.get(1));
+    assert_eq!(0xFF, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipNotKey when the skip should happen
@@ -594,7 +834,7 @@ fn skip_not_key_0() {
 
     let (chip8, _) = run_program::<Io>(&program, Some(keypresses), Some(3));
 
-    assert_eq!(0x1, chip8.registers.get(1));
+    assert_eq!(0x1, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SkipNotKey when the skip should not happen
@@ -606,7 +846,7 @@ fn skip_not_key_1() {
 
     let (chip8, _) = run_program::<Io>(&program, Some(keypresses), Some(3));
 
-    assert_eq!(0xFF, chip8.registers.get(1));
+    assert_eq!(0xFF, chip8.registers.get(v(1)));
 }
 
 /// Tests instruction SetSound
@@ -622,23 +862,30 @@ fn set_sound() {
     assert_eq!(0xFE, chip8.sound_timer);
 }
 
-/// Tests that `Chip8IO::play_sound` is called when the sound timer reaches 0
-/// This test should panic because the implementation of `Chip8IO::play_sound` for `Io` panics when
-/// called
+/// Tests that the sound is started when the sound timer is set, and plays for as many timer
+/// updates as it was set to
 #[test]
-#[should_panic(expected = "playing sound")]
-fn play_sound_0() {
-    let program = program!(0x6001, 0xF018);
+fn play_sound() {
+    let program = program!(0x6002, 0xF018, 0x00E0, 0x00E0);
+
+    let (_, io) = run_program::<Io>(&program, None, Some(2));
+    assert!(io.sound);
 
-    run_program_default(&program);
+    let (_, io) = run_program::<Io>(&program, None, Some(3));
+    assert!(io.sound);
+
+    let (chip8, io) = run_program::<Io>(&program, None, Some(4));
+    assert!(!io.sound);
+    assert_eq!(0, chip8.sound_timer);
 }
 
-/// Tests that `Chip8IO::play_sound` is not called when the sound timer doesn't reach 0
+/// Tests that the sound isn't started when the sound timer is set to 0
 #[test]
-fn play_sound_1() {
-    let program = program!(0x6002, 0xF018);
+fn play_sound_zero() {
+    let program = program!(0x6000, 0xF018, 0x00E0);
 
-    run_program_default(&program);
+    let (_, io) = run_program::<Io>(&program, None, None);
+    assert!(!io.sound);
 }
 
 /// Tests instruction Draw
@@ -678,11 +925,13 @@ fn draw() {
 #[test]
 #[cfg_attr(rustfmt, rustfmt_skip)]
 fn draw_flip_0() {
-    let program = program!(0x6001, 0xF055, 0xD111, 0x6000, 0xF055, 0xD111);
+    // Drawing the same sprite twice erases it
+    let program = program!(0x6001, 0xF055, 0xD111, 0xD111);
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x1, chip8.registers.get(0xF));
+    assert_eq!(0x1, chip8.registers.get(v(0xF)));
+    assert!(chip8.io.pixels().iter().all(|&p| !p));
 }
 
 /// Tests that Draw sets VF to 0 when no pixel is flipped from set to unset
@@ -693,7 +942,63 @@ fn draw_flip_1() {
 
     let chip8 = run_program_default(&program);
 
-    assert_eq!(0x0, chip8.registers.get(0xF));
+    assert_eq!(0x0, chip8.registers.get(v(0xF)));
+}
+
+/// Tests that Draw flips the pixels of sprites at positions that aren't a multiple of 8
+#[test]
+fn draw_unaligned() {
+    // Draws 0xFF at (5, 0), then 0x0F over its right side at (7, 0)
+    let program = program!(0x60FF, 0xA300, 0xF055, 0x6105, 0x6200, 0xD121,
+                           0x600F, 0xA300, 0xF055, 0x6107, 0xA300, 0xD121);
+
+    let chip8 = run_program_default(&program);
+    let mut expected = [false; 16];
+    for x in (5..11).chain(13..15) {
+        expected[x] = true;
+    }
+
+    assert_eq!(&expected[..], &chip8.pixels()[..16]);
+    assert_eq!(0x1, chip8.registers.get(v(0xF)));
+}
+
+/// Tests that sprites must be entirely on the screen
+#[test]
+fn draw_edge() {
+    let program = program!(0x6178, 0x6200, 0xD121, 0x6179, 0xD121);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    chip8.run_for(&mut io, 3).unwrap();
+    match chip8.run_for(&mut io, 2) {
+        Err(Error(ErrorKind::PixelOutOfBounds(x, 0, _), _)) if x == ::SCREEN_WIDTH => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+}
+
+/// Tests that sprites drawn after loading a state or clearing the screen collide with the pixels
+/// that are on
+#[test]
+fn draw_after_load_state() {
+    // Draws the 0 character four times, clearing the screen before the last two
+    let program = program!(0xA050, 0xD005, 0xD005, 0xD005, 0x00E0, 0xD005, 0xD005);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+    chip8.run_for(&mut io, 2).unwrap();
+
+    let mut loaded = Chip8::new(&program, Config::new()).unwrap();
+    loaded.load_state(&chip8.save_state());
+    assert_eq!(chip8.pixels(), loaded.pixels());
+    loaded.run_for(&mut io, 1).unwrap();
+    assert_eq!(0x1, loaded.registers.get(v(0xF)));
+    assert!(loaded.pixels().iter().all(|&p| !p));
+
+    loaded.run_for(&mut io, 3).unwrap();
+    assert_eq!(0x0, loaded.registers.get(v(0xF)));
+    loaded.run_for(&mut io, 1).unwrap();
+    assert_eq!(0x1, loaded.registers.get(v(0xF)));
+    assert!(loaded.pixels().iter().all(|&p| !p));
 }
 
 /// Tests that Draw draws to the correct location
@@ -741,3 +1046,2124 @@ fn clear_screen() {
 
     assert_eq!(vec![false; ::SCREEN_WIDTH * ::SCREEN_HEIGHT], chip8.io.pixels().to_vec());
 }
+
+/// Runs a program for the given number of cycles, updating the timers after each cycle
+fn run_frames(chip8: &mut Chip8, cycles: usize) {
+    let mut io = Io::new(Vec::new());
+
+    for _ in 0..cycles {
+        chip8.cycle(&mut io).unwrap();
+        chip8.tick_60hz(&mut io);
+    }
+}
+
+/// Tests that the metrics count cycles, draws, and timer ticks, and can be reset
+#[test]
+fn metrics() {
+    use std::time::Duration;
+
+    // Draws a sprite, then waits for a key forever
+    let program = program!(0x6001, 0xD001, 0xF00A);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+
+    run_frames(&mut chip8, 63);
+
+    let metrics = chip8.metrics();
+    assert_eq!(63, metrics.cycles());
+    assert_eq!(1, metrics.instruction_classes()[0x6]);
+    assert_eq!(1, metrics.instruction_classes()[0xD]);
+    assert_eq!(61, metrics.instruction_classes()[0xF]);
+    assert_eq!(1, metrics.draws());
+    assert_eq!(63, metrics.timer_ticks());
+    assert_eq!(Duration::from_millis(1050), metrics.emulated_time());
+    assert_eq!(Duration::from_secs(1) + Duration::from_nanos(16_666_666),
+               metrics.key_wait_time());
+    assert_eq!(Some(60.0), metrics.instructions_per_second());
+
+    chip8.reset_metrics();
+    assert_eq!(0, chip8.metrics().cycles());
+    assert_eq!(None, chip8.metrics().draws_per_second());
+}
+
+/// Tests that rewinding restores the snapshots taken at earlier frames
+#[test]
+fn rewind() {
+    let program = program!(0x6001, 0x6002, 0x6003);
+    let mut chip8 = Chip8::new(&program, Config::new().rewind(1, 10)).unwrap();
+
+    run_frames(&mut chip8, 3);
+
+    assert!(chip8.rewind(1));
+    assert_eq!(0x02, chip8.registers.get(v(0)));
+    assert_eq!(0x204, chip8.registers.program_counter);
+
+    // The oldest snapshot is kept when rewinding past it
+    assert!(chip8.rewind(5));
+    assert_eq!(0x01, chip8.registers.get(v(0)));
+}
+
+/// Tests that the oldest snapshots are dropped when the rewind buffer is full
+#[test]
+fn rewind_capacity() {
+    let program = program!(0x6001, 0x6002, 0x6003);
+    let mut chip8 = Chip8::new(&program, Config::new().rewind(1, 2)).unwrap();
+
+    run_frames(&mut chip8, 3);
+
+    assert!(chip8.rewind(10));
+    assert_eq!(0x02, chip8.registers.get(v(0)));
+}
+
+/// Tests that rewinding does nothing if it is disabled
+#[test]
+fn rewind_disabled() {
+    let program = program!(0x6001, 0x6002);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+
+    run_frames(&mut chip8, 2);
+
+    assert!(!chip8.rewind(1));
+    assert_eq!(0x02, chip8.registers.get(v(0)));
+}
+
+/// I/O used for tests of `Chip8::run_until_break`, which requests the emulator to close once it
+/// has drawn the screen 100 times so the tests can't loop forever
+struct BreakIO {
+    draws: usize,
+}
+
+impl ::Chip8IO for BreakIO {
+    fn draw(&mut self, _: &[bool]) {
+        self.draws += 1;
+    }
+    fn get_keys(&mut self) -> ::Keypad {
+        ::Keypad::new()
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        self.draws >= 100
+    }
+}
+
+/// Tests that `run_until_break` stops at breakpoints, and can continue from them
+#[test]
+fn breakpoint() {
+    // Increments V0 in an infinite loop, drawing the screen each iteration
+    let program = program!(0x7001, 0x00E0, 0x1200);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = BreakIO { draws: 0 };
+
+    chip8.add_breakpoint(0x202);
+
+    assert_eq!(Some(0x202), chip8.run_until_break(&mut io).unwrap());
+    assert_eq!(0x01, chip8.registers.get(v(0)));
+    assert_eq!(Some(0x202), chip8.run_until_break(&mut io).unwrap());
+    assert_eq!(0x02, chip8.registers.get(v(0)));
+
+    assert!(chip8.remove_breakpoint(0x202));
+    assert!(chip8.breakpoints().is_empty());
+    assert_eq!(None, chip8.run_until_break(&mut io).unwrap());
+}
+
+/// Tests that SelectPlanes makes Draw draw only to the selected plane
+#[test]
+fn select_planes_draw() {
+    // Draws the top row of the 0 character to the second plane
+    let program = program!(0xF201, 0xA050, 0xD001);
+
+    let chip8 = run_program_default(&program);
+
+    assert_eq!(&[2, 2, 2, 2, 0], &chip8.io.planes()[..5]);
+    assert_eq!(&[true, true, true, true, false], &chip8.io.pixels()[..5]);
+}
+
+/// Tests that Draw reads the sprite for each selected plane one after another when both planes
+/// are selected
+#[test]
+fn select_planes_both() {
+    // The first row of the 1 character (0x20) follows the last row of the 0 character (0xF0)
+    let program = program!(0xF301, 0xA054, 0xD001);
+
+    let chip8 = run_program_default(&program);
+
+    assert_eq!(&[1, 1, 3, 1, 0], &chip8.io.planes()[..5]);
+}
+
+/// Tests that Draw draws nothing when no planes are selected
+#[test]
+fn select_planes_none() {
+    let program = program!(0xF001, 0xA050, 0xD001);
+
+    let chip8 = run_program_default(&program);
+
+    assert!(chip8.io.planes().iter().all(|&p| p == 0));
+}
+
+/// Tests that ClearScreen only clears the selected planes
+#[test]
+fn select_planes_clear() {
+    let program = program!(0xF301, 0xA054, 0xD001, 0xF101, 0x00E0);
+
+    let chip8 = run_program_default(&program);
+
+    assert_eq!(&[0, 0, 2, 0, 0], &chip8.io.planes()[..5]);
+}
+
+/// I/O that records the arguments of `Chip8IO::draw_planes`
+struct PlanesIO {
+    planes: Vec<u8>,
+    palette: Option<::Palette>,
+}
+
+impl ::Chip8IO for PlanesIO {
+    fn draw(&mut self, _: &[bool]) {
+        panic!("`draw` should not be called when `draw_planes` is implemented");
+    }
+    fn draw_planes(&mut self, planes: &[u8], palette: &::Palette) {
+        self.planes = planes.to_vec();
+        self.palette = Some(*palette);
+    }
+    fn get_keys(&mut self) -> ::Keypad {
+        ::Keypad::new()
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// Tests that the planes and the configured palette are passed to `Chip8IO::draw_planes`
+#[test]
+fn draw_planes_palette() {
+    let program = program!(0xF201, 0xA050, 0xD001);
+    let palette = ::Palette::new(&[[1; 3], [2; 3], [3; 3], [4; 3]]);
+    let mut chip8 = Chip8::new(&program, Config::new().palette(palette)).unwrap();
+    let mut io = PlanesIO {
+        planes: Vec::new(),
+        palette: None,
+    };
+
+    for _ in 0..3 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    assert_eq!(chip8.planes(), &io.planes[..]);
+    assert_eq!(Some(palette), io.palette);
+}
+
+/// Tests conditions on registers, timers, and memory
+#[test]
+fn condition_values() {
+    use condition::Condition;
+
+    let program = program!(0x6305, 0xA300, 0xF333, 0x6002, 0xF015);
+    let chip8 = run_program_config(&program, Config::new());
+    let holds = |text: &str| Condition::parse(text).unwrap().eval(&chip8);
+
+    assert!(holds("v3 == 5"));
+    assert!(holds("V3 >= 0x5"));
+    assert!(holds("i == 0x300 && mem[0x302] == 5"));
+    assert!(holds("pc > 0x208"));
+    assert!(holds("dt == 2 && st == 0"));
+    assert!(!holds("v3 < 5"));
+    assert!(holds("v3 != 5 || v0 == 2"));
+}
+
+/// Tests conditions on pixels
+#[test]
+fn condition_pixels() {
+    use condition::Condition;
+
+    // Draws the top row of the 0 character
+    let program = program!(0xA050, 0xD001);
+    let chip8 = run_program_config(&program, Config::new());
+    let holds = |text: &str| Condition::parse(text).unwrap().eval(&chip8);
+
+    assert!(holds("pixel(0, 0) == 1"));
+    assert!(holds("pixel(4, 0) == 0"));
+    assert!(holds("lit(0, 0, 8, 8) == 4"));
+    assert!(holds("lit(1, 0, 1000, 1000) == 3"));
+}
+
+/// Tests that invalid conditions are rejected
+#[test]
+fn condition_invalid() {
+    use condition::Condition;
+
+    for text in &["v3", "vg == 1", "mem[1, 2] == 0", "lit(0, 0) == 0", "pc == banana"] {
+        match Condition::parse(text) {
+            Err(Error(ErrorKind::InvalidCondition(..), _)) => {}
+            r => panic!("Expected error for `{}`, got {:?}", text, r),
+        }
+    }
+}
+
+/// Tests that the speedrun timer triggers splits in order, and stops after the last one
+#[test]
+fn speedrun_splits() {
+    use condition::Condition;
+    use speedrun::SpeedrunTimer;
+
+    let timer = SpeedrunTimer::new()
+        .split("two", Condition::parse("v0 >= 2").unwrap())
+        .split("one", Condition::parse("v0 >= 1").unwrap());
+    let program = program!(0x7001, 0x7001, 0x7001, 0x7001);
+    let mut chip8 = Chip8::new(&program, Config::new().speedrun(timer)).unwrap();
+
+    run_frames(&mut chip8, 4);
+
+    let timer = chip8.speedrun_timer().unwrap();
+    let frames = timer.splits().iter().map(|s| s.frame).collect::<Vec<_>>();
+
+    assert_eq!(vec![Some(2), Some(3)], frames);
+    assert!(timer.finished());
+    assert_eq!(3, timer.frames());
+}
+
+/// Tests that changes to the sound timer are logged with the cycle and frame they happened at
+#[test]
+fn sound_log() {
+    use sound_log::SoundEvent;
+
+    let program = program!(0x6005, 0xF018, 0x6000, 0xF018);
+    let mut chip8 = Chip8::new(&program, Config::new().sound_log(true)).unwrap();
+
+    run_frames(&mut chip8, 4);
+
+    let expected = [SoundEvent { cycle: 1, frame: 1, value: 5 },
+                    SoundEvent { cycle: 3, frame: 3, value: 0 }];
+    assert_eq!(&expected, chip8.sound_log().unwrap().events());
+}
+
+#[test]
+fn watch() {
+    use std::sync::{Arc, Mutex};
+    use watch::{Change, Watch};
+
+    let program = program!(0x6005, 0x6101, 0xA123, 0xF015, 0x6101);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let hook_changes = changes.clone();
+    chip8.set_watch_hook(move |change| hook_changes.lock().unwrap().push(change));
+
+    chip8.watch(Watch::Register(v(0)));
+    chip8.watch(Watch::Index);
+    chip8.watch(Watch::DelayTimer);
+
+    run_frames(&mut chip8, 4);
+
+    let expected = [Change { watch: Watch::Register(v(0)), old: 0, new: 5, pc: 0x200 },
+                    Change { watch: Watch::Index, old: 0, new: 0x123, pc: 0x204 },
+                    Change { watch: Watch::DelayTimer, old: 0, new: 5, pc: 0x206 },
+                    Change { watch: Watch::DelayTimer, old: 5, new: 4, pc: 0x208 }];
+    assert_eq!(&expected[..], &changes.lock().unwrap()[..]);
+
+    // Unwatched values don't trigger the hook
+    chip8.unwatch(Watch::DelayTimer);
+    run_frames(&mut chip8, 1);
+    assert_eq!(4, changes.lock().unwrap().len());
+}
+
+/// Tests that a paused emulator only runs the requested steps
+#[test]
+fn pause_step() {
+    let program = program!(0x7001, 0x00E0, 0x1200);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = BreakIO { draws: 0 };
+
+    let handle = chip8.pause_handle();
+    handle.step();
+    assert!(!chip8.is_paused());
+
+    chip8.pause();
+    assert!(handle.is_paused());
+
+    // Stepping twice reaches the breakpoint, without running any other cycles
+    handle.step();
+    handle.step();
+    chip8.add_breakpoint(0x204);
+    assert_eq!(Some(0x204), chip8.run_until_break(&mut io).unwrap());
+    assert_eq!(0x01, chip8.registers.get(v(0)));
+
+    chip8.step(&mut io).unwrap();
+    chip8.step(&mut io).unwrap();
+    assert_eq!(0x02, chip8.registers.get(v(0)));
+
+    chip8.resume();
+    assert!(!handle.is_paused());
+}
+
+/// Tests that a paused emulator runs whole frames when requested
+#[test]
+fn frame_advance() {
+    let program = program!(0x7001, 0x00E0, 0x1200);
+    let mut chip8 = Chip8::new(&program, Config::new().speed(600)).unwrap();
+    let mut io = BreakIO { draws: 0 };
+
+    // A frame is 10 cycles at this speed
+    chip8.pause();
+    chip8.advance_frame(&mut io).unwrap();
+    assert_eq!(10, chip8.cycles());
+    assert_eq!(1, chip8.frames());
+
+    // Requested frames are run by `run_until_break`, which stops at breakpoints after each one
+    chip8.pause_handle().advance_frame();
+    chip8.add_breakpoint(0x204);
+    assert_eq!(Some(0x204), chip8.run_until_break(&mut io).unwrap());
+    assert_eq!(20, chip8.cycles());
+    assert_eq!(2, chip8.frames());
+}
+
+/// Tests that the watchdog detects programs that stop changing the screen and registers
+#[test]
+fn watchdog() {
+    use watchdog::Watchdog;
+
+    // Draws a character, then loops forever
+    let program = program!(0xA050, 0xD005, 0x1204);
+    let mut chip8 = Chip8::new(&program, Config::new().watchdog(3)).unwrap();
+    let mut watchdog = Watchdog::new(3);
+
+    for _ in 0..2 {
+        run_frames(&mut chip8, 1);
+        assert!(!watchdog.update(&chip8));
+    }
+
+    // The loop changes nothing
+    for _ in 0..2 {
+        run_frames(&mut chip8, 1);
+        assert!(!watchdog.update(&chip8));
+        assert!(!chip8.stalled());
+    }
+
+    run_frames(&mut chip8, 1);
+    assert!(watchdog.update(&chip8));
+    assert!(chip8.stalled());
+
+    watchdog.reset();
+    assert!(!watchdog.stalled());
+    assert_eq!(0, watchdog.unchanged_frames());
+
+    // The emulator stops running once the program stalls
+    let mut io = BreakIO { draws: 0 };
+    assert_eq!(None, chip8.run_until_break(&mut io).unwrap());
+}
+
+/// Tests that frame sinks receive every frame
+#[test]
+fn frame_sink() {
+    use std::sync::{Arc, Mutex};
+
+    let program = program!(0xA050, 0xD001, 0x00E0, 0x00E0);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let sink_frames = frames.clone();
+    chip8.add_frame_sink(move |frame, planes: &[u8], _: &_| {
+        sink_frames.lock().unwrap().push((frame, planes[0]));
+    });
+
+    run_frames(&mut chip8, 3);
+    assert_eq!(vec![(1, 0), (2, 1), (3, 0)], *frames.lock().unwrap());
+
+    chip8.clear_frame_sinks();
+    run_frames(&mut chip8, 1);
+    assert_eq!(3, frames.lock().unwrap().len());
+}
+
+/// Tests that errors for invalid opcodes describe where the opcode was found
+#[test]
+fn invalid_opcode_context() {
+    use symbols::SymbolTable;
+
+    // Jumps to data
+    let program = program!(0x1204, 0x6001, 0xFFFF);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    chip8.set_symbols(SymbolTable::parse("main = 0x200\ndata = 0x202").unwrap());
+    let mut io = BreakIO { draws: 0 };
+
+    let error = chip8.run_until_break(&mut io).unwrap_err();
+    assert_eq!("Invalid opcode 0xFF 0xFF at address 0x204 (data+0x2), reached by a jump from 0x200",
+               error.to_string());
+    assert_eq!(Some(0xFFFF), error.location().map(|l| l.opcode));
+
+    // Runs into data
+    let program = program!(0x6001, 0x0000);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+
+    let error = chip8.run_until_break(&mut io).unwrap_err();
+    assert!(error.to_string().ends_with("running past the instruction at 0x200 (the program may \
+                                         have run into data)"));
+}
+
+/// Tests that permissive mode skips invalid opcodes, and reports them
+#[test]
+fn permissive() {
+    use std::sync::{Arc, Mutex};
+
+    // Data between instructions
+    let program = program!(0x6001, 0xFFFF, 0x6102, 0x0000);
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let hook_warnings = warnings.clone();
+    let config = Config::new().permissive(move |e: &Error| {
+        hook_warnings.lock().unwrap().push(e.location().unwrap().pc)
+    });
+    let mut chip8 = Chip8::new(&program, config).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    for _ in 0..4 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    assert_eq!(1, chip8.registers.get(v(0)));
+    assert_eq!(2, chip8.registers.get(v(1)));
+    assert_eq!(0x208, chip8.registers.program_counter);
+    assert_eq!(vec![0x202, 0x206], *warnings.lock().unwrap());
+}
+
+/// Tests that the trace records each step, and passes it to the hook
+#[test]
+fn trace() {
+    use std::sync::{Arc, Mutex};
+
+    let program = program!(0x6105, 0xA123, 0x00E0, 0x00E0);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    chip8.enable_trace(2);
+
+    let hooked = Arc::new(Mutex::new(Vec::new()));
+    let hook_entries = hooked.clone();
+    chip8.set_trace_hook(move |entry| hook_entries.lock().unwrap().push(entry.to_string()));
+
+    run_frames(&mut chip8, 3);
+
+    assert_eq!(vec!["0: 0x200: 0x6105 LD V1, 0x05 V1: 0x00 -> 0x05",
+                    "1: 0x202: 0xA123 LD I, 0x123 I: 0x00 -> 0x123",
+                    "2: 0x204: 0x00E0 CLS"],
+               *hooked.lock().unwrap());
+
+    // Only the most recent steps are recorded
+    let trace = chip8.trace();
+    assert_eq!(2, trace.len());
+    assert_eq!(0x202, trace[0].pc);
+    assert_eq!(0x00E0, trace[1].opcode);
+
+    chip8.disable_trace();
+    run_frames(&mut chip8, 1);
+    assert!(chip8.trace().is_empty());
+    assert_eq!(4, hooked.lock().unwrap().len());
+}
+
+/// Tests that the trace records changes to the timers, and that instructions are logged at the
+/// instruction level
+#[test]
+fn trace_timers() {
+    let program = program!(0x6005, 0xF015, 0xF018);
+    let mut chip8 = Chip8::new(&program, Config::new().log(Log::Instructions)).unwrap();
+    assert!(chip8.tracing(0x200));
+    chip8.enable_trace(2);
+
+    let mut io = Io::new(Vec::new());
+    for _ in 0..3 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    let trace = chip8.trace();
+    assert_eq!("1: 0x202: 0xF015 LD DT, V0 DT: 0x00 -> 0x05", trace[0].to_string());
+    assert_eq!("2: 0x204: 0xF018 LD ST, V0", trace[1].instruction());
+
+    let chip8 = Chip8::new(&program, Config::new().log(Log::Errors)).unwrap();
+    assert!(!chip8.tracing(0x200));
+}
+
+/// Tests that only the instructions in the log ranges are logged
+#[test]
+fn log_ranges() {
+    let config = Config::new().log(Log::State).log_range(0x300..0x310).log_range(0x200..0x202);
+    let chip8 = Chip8::new(&[], config).unwrap();
+
+    assert!(chip8.tracing(0x200));
+    assert!(!chip8.tracing(0x202));
+    assert!(chip8.tracing(0x30E));
+    assert!(!chip8.tracing(0x310));
+
+    let chip8 = Chip8::new(&[], Config::new().log(Log::State)).unwrap();
+    assert!(chip8.tracing(0x202));
+}
+
+/// Tests that running ahead draws the future screen without changing the emulator
+#[test]
+fn run_ahead() {
+    // Draws the top row of the 0 character, then loops forever
+    let program = program!(0xA050, 0xD001, 0x1204);
+    let chip8 = Chip8::new(&program, Config::new().run_ahead(1)).unwrap();
+    let mut io = PlanesIO {
+        planes: Vec::new(),
+        palette: None,
+    };
+
+    chip8.draw_ahead(&mut io, 1, 2).unwrap();
+
+    assert_eq!(&[1, 1, 1, 1, 0, 0, 0, 0], &io.planes[..8]);
+    assert_eq!(&[0; 8], &chip8.planes()[..8]);
+    assert_eq!(0x200, chip8.registers.program_counter);
+    assert_eq!(0, chip8.cycles());
+}
+
+/// Tests that the analyzer suggests the quirks a program depends on
+#[test]
+fn analyze_quirks() {
+    use analyzer::{self, Outcome};
+
+    // Jumps through a table using V0 (without the jump quirk), or V2 (with it), which holds an
+    // address that crashes the program
+    let program = program!(0x6000, 0x6202, 0xB208, 0x0000, 0x1208);
+
+    let results = analyzer::analyze(&program, 100);
+    assert_eq!(Outcome::Running, results[0].outcome);
+    assert!(!results[0].quirks.jump);
+    assert_eq!(Quirks::default(), results[0].quirks);
+    assert_eq!(None, analyzer::suggest(&program, 100));
+
+    match results[15].outcome {
+        Outcome::Crashed(_) => assert!(results[15].quirks.jump),
+        ref outcome => panic!("Unexpected outcome: {:?}", outcome),
+    }
+
+    // Shifts V1 into V0 (without the shift quirk), or shifts V0 in place (with it), then crashes
+    // if V0 isn't 1
+    // The memory quirk doesn't matter, so it is left at its default
+    let program = program!(0x6000, 0x6102, 0x8016, 0x3001, 0x0000, 0x120A);
+    let quirks = Quirks { shift: false, ..Quirks::default() };
+    assert_eq!(Some(quirks), analyzer::suggest(&program, 100));
+}
+
+/// Tests that scanning a program finds the extensions used by its reachable code, and problems with
+/// the file
+#[test]
+fn scan_program() {
+    use scan::{self, Extension, Problem};
+
+    // Selects a plane (XO-CHIP) and jumps over a scroll (SUPER-CHIP) that can't be reached
+    let program = program!(0xF101, 0x1206, 0x00FF, 0x1206);
+    let summary = scan::scan(&program);
+
+    assert_eq!(8, summary.size);
+    assert_eq!(Some(0xF101), summary.entry_opcode);
+    assert_eq!(vec![Extension::XoChip], summary.extensions);
+    assert!(summary.problems.is_empty());
+
+    let mut program = program!(0x00FF, 0x00E0);
+    program.push(0x12);
+    let summary = scan::scan(&program);
+
+    assert_eq!(vec![Extension::Schip], summary.extensions);
+    assert_eq!(vec![Problem::OddLength], summary.problems);
+    assert_eq!("250e7c6e00f6d78a12b191dcb4c68b455debf378", summary.sha1.to_string());
+}
+
+/// Tests that linting a program finds invalid opcodes, jumps outside the program, writes to the
+/// font, and recursive calls
+#[test]
+fn lint_program() {
+    use lint::{self, Issue, Warning};
+
+    let warning = |address, issue| {
+        Warning {
+            address,
+            issue,
+        }
+    };
+
+    // Calls a subroutine that calls itself if V0 isn't 0, then stores V0 to the font and jumps
+    // outside the program
+    let program = program!(0x220A, 0x6005, 0xF029, 0xF055, 0x1300, 0x3000, 0x220A, 0x00EE);
+    assert_eq!(vec![warning(0x206, Issue::FontWrite),
+                    warning(0x208, Issue::OutsideProgram(0x300)),
+                    warning(0x20C, Issue::Recursion(0x20A))],
+               lint::lint(&program));
+
+    // The invalid opcode is only reported once it can be reached, and I is set to memory outside
+    // the font before storing to it
+    let program = program!(0xA300, 0xF033, 0x3000, 0xFFFF, 0x1208);
+    assert_eq!(vec![warning(0x206, Issue::InvalidOpcode(0xFFFF))], lint::lint(&program));
+
+    let program = program!(0x1202, 0x1202, 0xFFFF);
+    assert!(lint::lint(&program).is_empty());
+}
+
+/// Tests that states can be serialized and deserialized
+#[test]
+fn state_bytes() {
+    use state::State;
+
+    // Calls a subroutine which sets the delay timer and draws to the second plane
+    let program = program!(0x2204, 0x0000, 0x6105, 0xF115, 0xF201, 0xA050, 0xD005, 0x120E);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    run_frames(&mut chip8, 6);
+
+    let bytes = chip8.save_state().to_bytes();
+    let mut padded = bytes.clone();
+    padded.extend_from_slice(&[0; 16]);
+    let state = State::from_bytes(&padded).unwrap();
+    assert_eq!(bytes, state.to_bytes());
+
+    let mut loaded = Chip8::new(&[], Config::new()).unwrap();
+    loaded.load_state(&state);
+    assert_eq!(chip8.planes(), loaded.planes());
+    assert_eq!(vec![0x200], loaded.stack);
+    assert_eq!(0x05, loaded.registers.get(v(1)));
+    assert_eq!(0x20E, loaded.registers.program_counter);
+
+    assert!(State::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(State::from_bytes(b"not a state").is_err());
+}
+
+/// Tests that resetting the emulator restarts the program
+#[test]
+fn reset() {
+    // Calls a subroutine which sets the delay timer, draws, and writes to memory
+    let program = program!(0x2204, 0x0000, 0x6105, 0xF115, 0xA050, 0xD005, 0xA300, 0xF155,
+                           0x1210);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    run_frames(&mut chip8, 7);
+
+    assert!(chip8.planes().iter().any(|&p| p != 0));
+    assert_eq!(5, chip8.memory[0x301]);
+
+    chip8.reset();
+
+    assert!(chip8.planes().iter().all(|&p| p == 0));
+    assert!(chip8.stack.is_empty());
+    assert_eq!(0, chip8.registers.get(v(1)));
+    assert_eq!(0x200, chip8.registers.program_counter);
+    assert_eq!(0, chip8.delay_timer);
+    assert_eq!(0, chip8.memory[0x301]);
+    assert_eq!(&program[..], &chip8.memory[0x200..0x200 + program.len()]);
+}
+
+/// Tests that loading a new program replaces the old one, and restarts the emulator
+#[test]
+fn load_program() {
+    use rom::Rom;
+
+    let program = program!(0x6105, 0xA300, 0xF155, 0x1206);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    run_frames(&mut chip8, 3);
+    assert_eq!(5, chip8.memory[0x301]);
+
+    let new_program = program!(0x6207, 0x1202);
+    chip8.load_program(&new_program).unwrap();
+
+    assert_eq!(0, chip8.registers.get(v(1)));
+    assert_eq!(0x200, chip8.registers.program_counter);
+    assert_eq!(0, chip8.memory[0x301]);
+    assert_eq!(&[0x62, 0x07, 0x12, 0x02, 0x00, 0x00], &chip8.memory[0x200..0x206]);
+
+    // Resetting restarts the new program
+    run_frames(&mut chip8, 1);
+    chip8.reset();
+    assert_eq!(0, chip8.registers.get(v(2)));
+    assert_eq!(0x62, chip8.memory[0x200]);
+
+    // Programs that are too large leave the emulator unchanged
+    assert!(chip8.load_program(&[0; 0x1000]).is_err());
+    assert_eq!(0x62, chip8.memory[0x200]);
+
+    // ROMs also set the speed the program asks for
+    let mut bytes = new_program.clone();
+    bytes.extend_from_slice(b"CH8META\nspeed = 700\n");
+    chip8.load_rom(&Rom::new(bytes).unwrap()).unwrap();
+    assert_eq!(Some(700), chip8.speed);
+
+    // A speed of zero can't be parsed, but can still be set, and leaves the emulator unchanged
+    let mut rom = Rom::new(vec![0x1F, 0xFF]).unwrap();
+    rom.metadata_mut().speed = Some(0);
+    assert!(chip8.load_rom(&rom).is_err());
+    assert_eq!(Some(700), chip8.speed);
+    assert_eq!(0x62, chip8.memory[0x200]);
+}
+
+/// Tests that each sprite drawn is reported to `Chip8IO::draw_sprites`
+#[test]
+fn draw_sprites() {
+    use Sprite;
+
+    struct SpritesIO {
+        sprites: Vec<Sprite>,
+    }
+
+    impl ::Chip8IO for SpritesIO {
+        fn draw(&mut self, _: &[bool]) {}
+        fn draw_sprites(&mut self, sprites: &[Sprite]) {
+            self.sprites.extend_from_slice(sprites);
+        }
+        fn get_keys(&mut self) -> ::Keypad {
+            ::Keypad::new()
+        }
+        fn sound_start(&mut self) {}
+        fn sound_stop(&mut self) {}
+        fn should_close(&self) -> bool {
+            false
+        }
+    }
+
+    // Draws the 0 character at (2, 3), then the 1 character over it, then clears the screen
+    let program = program!(0x6002, 0x6103, 0xA050, 0xD015, 0xA055, 0xD015, 0x00E0);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = SpritesIO { sprites: Vec::new() };
+
+    for _ in 0..7 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    let sprite = Sprite {
+        x: 2,
+        y: 3,
+        height: 5,
+        address: 0x50,
+        planes: 1,
+        collision: false,
+    };
+    let collision = Sprite {
+        address: 0x55,
+        collision: true,
+        ..sprite
+    };
+    assert_eq!(vec![sprite, collision], io.sprites);
+}
+
+/// Tests that register IDs can only be created for V0 through VF
+#[test]
+fn register_id() {
+    use RegisterId;
+
+    assert_eq!(Some(RegisterId::VF), RegisterId::new(0xF));
+    assert_eq!(None, RegisterId::new(0x10));
+    assert_eq!(RegisterId::V0, RegisterId::from_nibble(0x10));
+    assert_eq!(16, RegisterId::all().count());
+    assert_eq!("VA", v(0xA).to_string());
+}
+
+/// Tests that capturing a program picks the frame with the most pixels lit, and that it can be
+/// written as a PNG image
+#[test]
+fn screenshot() {
+    use screenshot;
+
+    // Draws the 0 character, waits for a frame, clears the screen, and loops forever
+    let program = program!(0xA050, 0xD015, 0x6001, 0xF015, 0xF007, 0x3000, 0x1208, 0x00E0,
+                           0x1210);
+    let capture = screenshot::capture(&program, Config::new(), 10, 10).unwrap();
+    let lit = capture.planes.iter().filter(|&&p| p != 0).count();
+
+    assert_eq!(1, capture.frame);
+    assert_eq!(14, lit);
+
+    let mut png = Vec::new();
+    screenshot::write_png(&capture.planes, &Default::default(), 2, &mut png).unwrap();
+
+    assert_eq!(b"\x89PNG\r\n\x1A\n", &png[..8]);
+    assert_eq!(b"IHDR\x00\x00\x01\x00\x00\x00\x00\x80", &png[12..24]);
+    assert_eq!(b"IEND\xAE\x42\x60\x82", &png[png.len() - 8..]);
+}
+
+/// Tests that pixels are drawn with any color of the palette, not only the colors of the planes
+#[test]
+fn palette_indices() {
+    use screenshot;
+
+    let mut colors = vec![[0; 3]; ::PALETTE_SIZE];
+    colors[200] = [1, 2, 3];
+    let palette = ::Palette::new(&colors);
+    assert_eq!([0; 3], ::Palette::new(&[]).colors[200]);
+
+    let mut planes = vec![0; ::SCREEN_WIDTH * ::SCREEN_HEIGHT];
+    planes[0] = 200;
+    let mut png = Vec::new();
+    screenshot::write_png(&planes, &palette, 1, &mut png).unwrap();
+
+    // The image data is stored uncompressed after the zlib and block headers, starting with the
+    // filter type of the first row
+    let data = png.windows(4).position(|w| w == b"IDAT").unwrap() + 4 + 7;
+    assert_eq!(&[0, 1, 2, 3, 0], &png[data..data + 5]);
+}
+
+/// Tests writing the pixels as a PBM image
+#[test]
+fn screenshot_pbm() {
+    use screenshot;
+
+    // Draws the top row of the 0 character
+    let program = program!(0xA050, 0xD011);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    run_frames(&mut chip8, 2);
+
+    let mut pbm = Vec::new();
+    screenshot::write_pbm(chip8.pixels(), &mut pbm).unwrap();
+    let pbm = String::from_utf8(pbm).unwrap();
+    let lines = pbm.lines().collect::<Vec<_>>();
+
+    assert_eq!(["P1", "128 64"], &lines[..2]);
+    assert_eq!(2 + ::SCREEN_HEIGHT, lines.len());
+    assert!(lines[2].starts_with("1 1 1 1 0 0 0 0 0"));
+    assert!(lines[3].chars().all(|c| c == '0' || c == ' '));
+}
+
+/// Tests that screenshot file names don't overwrite existing files
+#[test]
+fn screenshot_path() {
+    use std::env;
+    use std::fs::{self, File};
+    use screenshot;
+
+    let dir = env::temp_dir().join("chip8_screenshot_path_test");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+
+    assert_eq!(dir.join("shot-1.png"), screenshot::unused_path(&dir, "shot", "png"));
+    File::create(dir.join("shot-1.png")).unwrap();
+    assert_eq!(dir.join("shot-2.png"), screenshot::unused_path(&dir, "shot", "png"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Tests that recordings only store frames that changed, and are written as GIF images with the
+/// right delays
+#[test]
+fn recording() {
+    use recording::Recorder;
+    use io::Palette;
+    use super::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+    let mut recorder = Recorder::new();
+    let mut planes = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+    recorder.record(0, &planes, &Palette::default());
+    recorder.record(1, &planes, &Palette::default());
+    planes[5] = 1;
+    recorder.record(6, &planes, &Palette::default());
+    recorder.record(11, &planes, &Palette::default());
+
+    assert_eq!(2, recorder.images());
+
+    let mut gif = Vec::new();
+    recorder.write_gif(1, &mut gif).unwrap();
+
+    assert_eq!(b"GIF89a\x80\x00\x40\x00", &gif[..10]);
+    assert_eq!(Some(&0x3B), gif.last());
+
+    // The delays of the frames, in hundredths of a second
+    let delays = gif.windows(6)
+        .filter(|w| w[..3] == [0x21, 0xF9, 0x04])
+        .map(|w| w[4] as u16 | (w[5] as u16) << 8)
+        .collect::<Vec<_>>();
+    assert_eq!(vec![10, 10], delays);
+}
+
+/// Tests that the frame blender fades pixels out after they are turned off
+#[test]
+fn frame_blender() {
+    use blend::FrameBlender;
+    use io::Palette;
+
+    let palette = Palette::new(&[[0x00; 3], [0xFF; 3], [0x80; 3], [0x40; 3]]);
+    let mut blender = FrameBlender::new(3);
+
+    assert_eq!(&[[0x00; 3], [0xFF; 3], [0x80; 3]], blender.blend(&[0, 1, 2], &palette));
+    assert!(!blender.is_fading());
+
+    // Pixels fade out from the color of the planes they were on in
+    assert_eq!(&[[0x00; 3], [0xBF; 3], [0x60; 3]], blender.blend(&[0, 0, 0], &palette));
+    assert_eq!(&[[0x00; 3], [0x80; 3], [0x40; 3]], blender.blend(&[0, 0, 0], &palette));
+    assert!(blender.is_fading());
+
+    // Turning a pixel on again shows it at full brightness
+    assert_eq!(&[[0x00; 3], [0xFF; 3], [0x20; 3]], blender.blend(&[0, 1, 0], &palette));
+    assert_eq!(&[[0x00; 3], [0xBF; 3], [0x00; 3]], blender.blend(&[0, 0, 0], &palette));
+
+    blender.clear();
+    assert_eq!(&[[0x00; 3], [0x00; 3], [0x00; 3]], blender.blend(&[0, 0, 0], &palette));
+    assert!(!blender.is_fading());
+}
+
+/// Tests reading and writing memory from outside the emulator
+#[test]
+fn memory_access() {
+    let program = program!(0x6105);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+
+    assert_eq!(&[0x61, 0x05], chip8.read_memory(0x200..0x202).unwrap());
+
+    chip8.write_memory(0x300, &[1, 2, 3]).unwrap();
+    assert_eq!(&[1, 2, 3], &chip8.memory[0x300..0x303]);
+    chip8.write_memory(0xFFE, &[4, 5]).unwrap();
+    assert_eq!(&[4, 5], chip8.read_memory(0xFFE..0x1000).unwrap());
+
+    match chip8.read_memory(0xFFE..0x1001) {
+        Err(Error(ErrorKind::OutOfMemory(0x1000), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+
+    match chip8.write_memory(0xFFF, &[6, 7]) {
+        Err(Error(ErrorKind::OutOfMemory(0x1000), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+    assert_eq!(&[5], chip8.read_memory(0xFFF..0x1000).unwrap());
+}
+
+/// Tests inspecting and changing registers, the stack, and timers from outside the emulator
+#[test]
+fn register_access() {
+    // Calls a subroutine that sets V3 and I
+    let program = program!(0x2204, 0x0000, 0x6307, 0xA123);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    for _ in 0..3 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    assert_eq!(7, chip8.register(v(3)));
+    assert_eq!(0x123, chip8.index());
+    assert_eq!(0x208, chip8.program_counter());
+    assert_eq!(&[0x200], chip8.stack());
+
+    chip8.set_register(v(0xA), 9);
+    chip8.set_index(0x300);
+    chip8.set_program_counter(0x204);
+    chip8.set_stack(&[0x200, 0x202]).unwrap();
+    chip8.set_delay_timer(5);
+    chip8.set_sound_timer(6);
+
+    assert_eq!(9, chip8.registers.get(v(0xA)));
+    assert_eq!(0x300, chip8.registers.index);
+    assert_eq!(0x204, chip8.registers.program_counter);
+    assert_eq!(&[0x200, 0x202], chip8.stack());
+    assert_eq!((5, 6), (chip8.delay_timer(), chip8.sound_timer()));
+}
+
+/// Tests that errors keep the errors that caused them, and the location of runtime errors
+#[test]
+fn error_causes() {
+    // Returns with nothing on the stack
+    let program = program!(0x6001, 0x00EE);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    let error = chip8.run_for(&mut io, 10).chain_err(|| "Failed to run the program").unwrap_err();
+    let messages = error.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(&ErrorKind::Msg("Failed to run the program".to_string()), error.kind());
+    assert_eq!(2, messages.len());
+    assert!(messages[1].starts_with("Stack underflow"));
+    assert_eq!(Some(0x202), error.location().map(|l| l.pc));
+    assert_eq!(Some("Return"), error.location().map(|l| l.instruction));
+}
+
+/// Tests that calling a subroutine with the stack full is an error
+#[test]
+fn stack_overflow() {
+    // Calls itself forever
+    let program = program!(0x2200);
+    let mut chip8 = Chip8::new(&program, Config::new().stack_depth(4)).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    for _ in 0..4 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    match chip8.cycle(&mut io) {
+        Err(Error(ErrorKind::StackOverflow(4, location), _)) => {
+            assert_eq!(Location {
+                           pc: 0x200,
+                           opcode: 0x2200,
+                           instruction: "Call",
+                       },
+                       location)
+        }
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+    assert!(chip8.set_stack(&[0x200; 5]).is_err());
+}
+
+/// Tests that returning from a subroutine with nothing on the stack is an error
+#[test]
+fn stack_underflow() {
+    let program = program!(0x00EE);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    match chip8.cycle(&mut io) {
+        Err(Error(ErrorKind::StackUnderflow(_), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+}
+
+/// Tests recording a report of the steps of a program
+#[test]
+fn report() {
+    use report;
+
+    // Sets V1, points I at the sprite of 0, and draws it
+    let program = program!(0x6105, 0xA050, 0xD115);
+    let report = report::record(&program, Config::new(), 3).unwrap();
+
+    assert_eq!(3, report.steps.len());
+    assert_eq!(None, report.stopped);
+    assert_eq!("LD V1, 0x05", report.steps[0].instruction);
+    assert_eq!(5, report.steps[0].registers[1]);
+    assert_eq!(0x050, report.steps[1].index);
+    assert_eq!(None, report.steps[1].screen);
+    assert!(report.steps[2].screen.is_some());
+
+    let text = report.to_string();
+    assert!(text.contains("| 0 | 0x200 | 6105 | `LD V1, 0x05` | 00 | **05** |"));
+    assert!(text.contains("Screen after step 2:\n\n```text\n"));
+    assert!(text.contains("\n.....####"));
+
+    // Runs past the end of the program into zeroes
+    let report = report::record(&program, Config::new(), 10).unwrap();
+    assert_eq!(3, report.steps.len());
+    assert!(report.stopped.unwrap().starts_with("Error: "));
+}
+
+/// Tests describing the call stack after an error
+#[test]
+fn call_stack() {
+    use symbols::SymbolTable;
+
+    // Calls a subroutine, which calls another that runs into an invalid opcode
+    let program = program!(0x2204, 0x0000, 0x2208, 0x0000, 0xFFFF);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+    let mut symbols = SymbolTable::new();
+    symbols.insert(0x200, "main");
+    symbols.insert(0x208, "crash");
+    chip8.set_symbols(symbols);
+
+    chip8.cycle(&mut io).unwrap();
+    chip8.cycle(&mut io).unwrap();
+    assert!(chip8.cycle(&mut io).is_err());
+
+    assert_eq!("at 0x208 (crash)\ncalled from 0x204 (main+0x4)\ncalled from 0x200 (main)",
+               chip8.describe_call_stack());
+}
+
+/// Tests counting the instructions run by a program
+#[test]
+fn profile() {
+    // Counts V0 up to 3 in a loop
+    let program = program!(0x7001, 0x3003, 0x1200, 0x00E0);
+    let mut chip8 = Chip8::new(&program, Config::new().profile(true)).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    for _ in 0..9 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    let profile = chip8.profile().unwrap();
+    assert_eq!(9, profile.total());
+    assert_eq!(vec![("AddConst", 3), ("SkipEqConst", 3), ("Goto", 2), ("ClearScreen", 1)],
+               profile.instructions());
+    assert_eq!(vec![(0x200, 3), (0x202, 3), (0x204, 2), (0x206, 1)], profile.addresses());
+    assert!(profile.to_string().starts_with("Instructions run: 9\n"));
+
+    assert!(Chip8::new(&program, Config::new()).unwrap().profile().is_none());
+}
+
+/// Tests tracking the addresses instructions were run from
+#[test]
+fn coverage() {
+    // Skips over the instruction at 0x204
+    let program = program!(0x6000, 0x3000, 0x00E0, 0x00E0);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    for _ in 0..3 {
+        chip8.cycle(&mut io).unwrap();
+    }
+
+    assert_eq!(vec![0x200, 0x202, 0x206], chip8.coverage().addresses());
+    assert!(chip8.coverage().is_covered(0x202));
+    assert!(!chip8.coverage().is_covered(0x204));
+    assert!(!chip8.coverage().is_covered(0xFFFF));
+    assert_eq!(0b0100_0101, chip8.coverage().bitmap()[0x200 / 8]);
+
+    let lines = ::disasm::disassemble_covered(&program, chip8.coverage());
+    assert_eq!(vec![0x200, 0x202, 0x206], lines.iter().map(|l| l.address).collect::<Vec<_>>());
+
+    chip8.clear_coverage();
+    assert!(chip8.coverage().addresses().is_empty());
+}
+
+/// Tests running a bounded number of cycles without pacing
+#[test]
+fn run_for() {
+    // Loops forever
+    let program = program!(0x7001, 0x1200);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    assert_eq!(100, chip8.run_for(&mut io, 100).unwrap());
+    assert_eq!(100, chip8.cycles());
+    assert_eq!(50, chip8.register(v(0)));
+    assert_eq!(10, chip8.frames());
+
+    // Runs past the end of memory
+    let program = program!(0x1FFF);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+
+    assert_eq!(1, chip8.run_for(&mut io, 100).unwrap());
+    assert!(chip8.program_ended());
+}
+
+/// Tests running until a condition on the emulator is met
+#[test]
+fn run_until() {
+    // Counts up V0 forever
+    let program = program!(0x7001, 0x1200);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    assert!(chip8.run_until(&mut io, 100, |c| c.register(v(0)) == 10).unwrap());
+    assert_eq!(19, chip8.cycles());
+    assert!(!chip8.run_until(&mut io, 100, |c| c.register(v(0)) == 0).unwrap());
+    assert_eq!(119, chip8.cycles());
+}
+
+/// Tests that WaitKey waits for the key to be released without the key press quirk
+#[test]
+fn wait_key_release() {
+    let program = program!(0xF00A, 0x00E0);
+    let config = Config::new()
+        .quirks(Quirks::chip8())
+        .input_polling(InputPolling::EveryInstruction);
+    let mut chip8 = Chip8::new(&program, config).unwrap();
+    // Presses key 3 during the second and third cycles
+    let mut io = Io::new(keypresses!(3 @ 1..3));
+
+    for _ in 0..3 {
+        io.simulate_keypresses();
+        chip8.cycle(&mut io).unwrap();
+        assert!(chip8.is_waiting_for_key());
+    }
+
+    io.simulate_keypresses();
+    chip8.cycle(&mut io).unwrap();
+
+    assert!(!chip8.is_waiting_for_key());
+    assert_eq!(3, chip8.registers.get(v(0)));
+    assert_eq!(0x202, chip8.registers.program_counter);
+}
+
+/// Tests that the keys are read as often as the input polling strategy says
+#[test]
+fn input_polling() {
+    // Checks key 0 in a loop, so every other instruction reads the keys
+    let program = program!(0xE09E, 0x1200);
+    let expected = [(InputPolling::EveryInstruction, 20),
+                    (InputPolling::PerFrame, 2),
+                    (InputPolling::KeyInstructions, 10)];
+
+    for &(polling, reads) in &expected {
+        let mut chip8 = Chip8::new(&program, Config::new().input_polling(polling)).unwrap();
+        let mut io = KeyIO::new(Vec::new());
+
+        // Two frames of ten cycles
+        chip8.run_for(&mut io, 20).unwrap();
+        assert_eq!(reads, io.get_keys_counter, "{:?}", polling);
+    }
+}
+
+/// Tests setting keys of a `Keypad`, and converting it to and from its bits and an array
+#[test]
+fn keypad() {
+    let mut keys = ::Keypad::new();
+    assert!(!keys.any());
+
+    keys.set(0x0, true);
+    keys.set(0xF, true);
+    keys.set(0x3, true);
+    keys.set(0x3, false);
+    assert!(keys.get(0xF));
+    assert!(!keys.get(0x3));
+    assert_eq!(0x8001, keys.bits());
+    assert_eq!(keys, ::Keypad::from_bits(0x8001));
+
+    let array: [bool; 16] = keys.into();
+    assert_eq!(keys.iter().collect::<Vec<_>>(), array.to_vec());
+    assert_eq!(vec![0, 15], (0..16).filter(|&i| array[i]).collect::<Vec<_>>());
+    assert_eq!(keys, ::Keypad::from(array));
+}
+
+/// Tests that keys outside of the keypad are rejected
+#[test]
+#[should_panic]
+fn keypad_out_of_range() {
+    ::Keypad::new().set(0x10, true);
+}
+
+/// An implementation of `Chip8IO` that stores the audio pattern it receives
+struct AudioIO {
+    audio: Option<([u8; ::AUDIO_PATTERN_LEN], u8)>,
+}
+
+impl ::Chip8IO for AudioIO {
+    fn draw(&mut self, _: &[bool]) {}
+    fn get_keys(&mut self) -> ::Keypad {
+        ::Keypad::new()
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn set_audio_pattern(&mut self, pattern: &[u8; ::AUDIO_PATTERN_LEN], pitch: u8) {
+        self.audio = Some((*pattern, pitch));
+    }
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// Tests instructions LoadAudio and SetPitch, and that the pattern is sent to `Chip8IO`
+#[test]
+fn audio_pattern() {
+    // The pattern is stored after the instructions, at 0x208
+    let mut program = program!(0xA208, 0xF002, 0x6070, 0xF03A);
+    program.extend((0..16).map(|i| i * 3));
+    let pattern = (0..16).map(|i| i * 3).collect::<Vec<u8>>();
+
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = AudioIO { audio: None };
+
+    chip8.cycle(&mut io).unwrap();
+    assert_eq!(None, io.audio);
+
+    chip8.cycle(&mut io).unwrap();
+    let (received, pitch) = io.audio.take().unwrap();
+    assert_eq!(&pattern[..], &received[..]);
+    assert_eq!(64, pitch);
+
+    chip8.cycle(&mut io).unwrap();
+    chip8.cycle(&mut io).unwrap();
+    assert_eq!(0x70, io.audio.unwrap().1);
+    assert_eq!(0x70, chip8.pitch());
+    assert_eq!(&pattern[..], &chip8.audio_pattern()[..]);
+
+    assert_eq!(4000.0, ::audio_sample_rate(64));
+    assert_eq!(8000.0, ::audio_sample_rate(112));
+}
+
+/// An audio backend that stores the timers it receives each tick
+struct TimerAudio {
+    ticks: Vec<(u8, u8)>,
+}
+
+impl ::backend::Audio for TimerAudio {
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn on_timer_tick(&mut self, delay: u8, sound: u8) {
+        self.ticks.push((delay, sound));
+    }
+}
+
+/// Tests that the timers are sent to `Chip8IO` each tick, after they count down
+#[test]
+fn timer_tick() {
+    use backend::Split;
+
+    let program = program!(0x6003, 0x6102, 0xF015, 0xF118);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Split::new((), (), TimerAudio { ticks: Vec::new() });
+
+    for _ in 0..4 {
+        chip8.cycle(&mut io).unwrap();
+    }
+    for _ in 0..4 {
+        chip8.tick_60hz(&mut io);
+    }
+
+    assert_eq!(vec![(2, 1), (1, 0), (0, 0), (0, 0)], io.audio.ticks);
+}
+
+/// A clock that moves forward by a millisecond each time it is read
+struct StepClock {
+    clock: ManualClock,
+}
+
+impl Clock for StepClock {
+    fn now(&mut self) -> Duration {
+        self.clock.advance(Duration::from_millis(1));
+        self.clock.now()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.clock.sleep(duration);
+    }
+}
+
+/// Tests that `run_until_break` paces cycles and timer updates using the configured clock
+#[test]
+fn clock() {
+    // Sets the delay timer, then loops until it reaches zero
+    let program = program!(0x603C, 0xF015, 0xF107, 0x3100, 0x1204, 0x1FFF);
+    let config = Config::new().speed(1000).clock(StepClock { clock: ManualClock::new() });
+    let mut chip8 = Chip8::new(&program, config).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    // One cycle runs per millisecond, which is one read of the clock, and the timers are updated
+    // every 16 milliseconds, so the delay timer takes about 960 cycles to reach zero
+    assert_eq!(None, chip8.run_until_break(&mut io).unwrap());
+    assert_eq!(965, chip8.cycles());
+}
+
+/// Tests that the speed factor speeds up both cycles and timer updates
+#[test]
+fn speed_factor() {
+    // Sets the delay timer, then loops until it reaches zero
+    let program = program!(0x603C, 0xF015, 0xF107, 0x3100, 0x1204, 0x1FFF);
+    let clock = ManualClock::new();
+    let config = Config::new().speed(500).clock(StepClock { clock: clock.clone() });
+    let mut chip8 = Chip8::new(&program, config).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    // At twice the speed, one cycle runs per millisecond and the timers are updated every 8
+    // milliseconds, so the program takes about half as long as it would at normal speed
+    chip8.set_speed_factor(2.0);
+    assert_eq!(None, chip8.run_until_break(&mut io).unwrap());
+    assert_eq!(485, chip8.cycles());
+    assert_eq!(Duration::from_millis(487), clock.clone().now());
+}
+
+/// A clock that moves forward by a millisecond each time it is read, and counts the reads
+struct CountingClock {
+    clock: ManualClock,
+    reads: Arc<AtomicUsize>,
+}
+
+impl Clock for CountingClock {
+    fn now(&mut self) -> Duration {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.clock.advance(Duration::from_millis(1));
+        self.clock.now()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.clock.sleep(duration);
+    }
+}
+
+/// Tests that the emulator sleeps between cycles instead of reading the clock until they are due
+#[test]
+fn sleep_pacing() {
+    // Sets the delay timer, then loops until it reaches zero
+    let program = program!(0x603C, 0xF015, 0xF107, 0x3100, 0x1204, 0x1FFF);
+    let reads = Arc::new(AtomicUsize::new(0));
+    let clock = CountingClock {
+        clock: ManualClock::new(),
+        reads: reads.clone(),
+    };
+    let config = Config::new().speed(100).clock(clock);
+    let mut chip8 = Chip8::new(&program, config).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    // One cycle runs every 10 milliseconds, so the delay timer takes about 100 cycles to reach
+    // zero. Without sleeping, the clock would be read once per millisecond for the whole second.
+    assert_eq!(None, chip8.run_until_break(&mut io).unwrap());
+    assert_eq!(101, chip8.cycles());
+    assert!(reads.load(Ordering::SeqCst) < 300);
+}
+
+/// An implementation of `Chip8IO` that presses the keys bound to emulator actions
+struct ActionIO {
+    bindings: Bindings,
+    /// The names of the keys pressed since the actions were last read
+    pressed: Vec<&'static str>,
+}
+
+impl ::Chip8IO for ActionIO {
+    fn draw(&mut self, _: &[bool]) {}
+    fn get_keys(&mut self) -> ::Keypad {
+        ::Keypad::new()
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        false
+    }
+    fn actions(&mut self) -> Vec<Action> {
+        let bindings = &self.bindings;
+        self.pressed.drain(..).filter_map(|key| bindings.action(key)).collect()
+    }
+}
+
+/// Tests that the keys bound to the save state slots by default save and restore the state
+#[test]
+fn save_state_slots() {
+    // Increments V0 in a loop
+    let program = program!(0x7001, 0x1200);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = ActionIO {
+        bindings: Bindings::default(),
+        pressed: Vec::new(),
+    };
+
+    // Saves to slot 1
+    chip8.run_for(&mut io, 10).unwrap();
+    io.pressed.push("F1");
+    chip8.handle_actions(&mut io);
+
+    // Loads from slot 1
+    chip8.run_for(&mut io, 10).unwrap();
+    assert_eq!(10, chip8.registers.get(v(0)));
+    io.pressed.push("F5");
+    chip8.handle_actions(&mut io);
+    assert_eq!(5, chip8.registers.get(v(0)));
+    assert_eq!(0x200, chip8.registers.program_counter);
+
+    // Loading from an empty slot does nothing
+    io.pressed.push("F6");
+    chip8.handle_actions(&mut io);
+    assert_eq!(5, chip8.registers.get(v(0)));
+
+    // Loading a program clears the slots
+    chip8.load_program(&program).unwrap();
+    io.pressed.push("F5");
+    chip8.handle_actions(&mut io);
+    assert_eq!(0, chip8.registers.get(v(0)));
+}
+
+/// Tests that a manual clock only moves forward when told to, or by sleeping
+#[test]
+fn manual_clock() {
+    let mut clock = ManualClock::new();
+    let driver = clock.clone();
+
+    assert_eq!(Duration::new(0, 0), clock.now());
+    driver.advance(Duration::from_millis(5));
+    clock.sleep(Duration::from_millis(10));
+    assert_eq!(Duration::from_millis(15), clock.now());
+    assert_eq!(Duration::from_millis(15), driver.clone().now());
+}
+
+/// Counts the times the sound was started and stopped
+#[derive(Default)]
+struct CountingAudio {
+    starts: usize,
+    stops: usize,
+}
+
+impl ::backend::Audio for CountingAudio {
+    fn sound_start(&mut self) {
+        self.starts += 1;
+    }
+    fn sound_stop(&mut self) {
+        self.stops += 1;
+    }
+}
+
+/// Tests that a backend can be made of separate parts, doing nothing for the ones it doesn't need
+#[test]
+fn split_backend() {
+    use backend::Split;
+
+    // Plays the sound for 2 frames
+    let program = program!(0x6002, 0xF018);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Split::new((), (), CountingAudio::default());
+
+    chip8.step(&mut io).unwrap();
+    chip8.step(&mut io).unwrap();
+    for _ in 0..4 {
+        chip8.tick_60hz(&mut io);
+    }
+
+    assert_eq!(1, io.audio.starts);
+    assert_eq!(1, io.audio.stops);
+}
+
+/// Keeps the last frame of the Mega-Chip screen, and the color of its first pixel
+#[derive(Default)]
+struct MegaDisplay {
+    frame: Vec<u8>,
+    color: ::Color,
+}
+
+impl ::backend::Display for MegaDisplay {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_mega(&mut self, pixels: &[u8], palette: &::Palette) {
+        self.frame = pixels.to_vec();
+        self.color = palette.colors[pixels[0] as usize];
+    }
+}
+
+/// Tests drawing a colored sprite in Mega-Chip mode, with a palette loaded by the program
+#[test]
+fn mega_chip() {
+    use backend::Split;
+
+    // Enters Mega-Chip mode, loads one color, draws a 2x1 sprite whose second pixel is
+    // transparent, then shows the frame
+    let program = program!(0x0011, 0x0100, 0x0214, 0x0201, 0x0302, 0x0401, 0xA218, 0xD000,
+                           0x00E0, 0x0000, 0xFF10, 0x2030, 0x0100);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Split::new(MegaDisplay::default(), (), ());
+
+    assert_eq!(None, chip8.mega_pixels());
+    for _ in 0..7 {
+        chip8.step(&mut io).unwrap();
+    }
+
+    assert_eq!(&[1, 0], &chip8.mega_pixels().unwrap()[..2]);
+    assert!(io.display.frame.is_empty());
+
+    chip8.step(&mut io).unwrap();
+
+    assert_eq!(::MEGA_WIDTH * ::MEGA_HEIGHT, io.display.frame.len());
+    assert_eq!(&[1, 0], &io.display.frame[..2]);
+    assert_eq!([0x10, 0x20, 0x30], io.display.color);
+    assert!(chip8.mega_pixels().unwrap().iter().all(|&p| p == 0));
+}
+
+/// Tests that hi-res programs are detected, start after their header, and draw pixels two pixels
+/// of the display wide
+#[test]
+fn hires_mode() {
+    use DisplayMode;
+
+    // Draws the top row of the 0 character, clears the screen, then draws past the right edge
+    let mut program = program!(0x1260);
+    program.resize(0xC0, 0);
+    program.extend(program!(0xA050, 0xD011, 0x0230, 0x6040, 0xD011));
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    assert_eq!(DisplayMode::HiRes, chip8.display_mode());
+    assert_eq!(0x2C0, chip8.program_counter());
+
+    chip8.cycle(&mut io).unwrap();
+    chip8.cycle(&mut io).unwrap();
+    assert_eq!(vec![true; 8], &chip8.pixels()[..8]);
+    assert!(!chip8.pixels()[8]);
+
+    chip8.cycle(&mut io).unwrap();
+    assert!(chip8.pixels().iter().all(|&p| !p));
+
+    chip8.cycle(&mut io).unwrap();
+    match *chip8.cycle(&mut io).unwrap_err().kind() {
+        ErrorKind::PixelOutOfBounds(64, 0, _) => {}
+        ref e => panic!("Unexpected error: {}", e),
+    }
+
+    chip8.load_program(&program!(0x1260)[..1]).unwrap();
+    assert_eq!(DisplayMode::Normal, chip8.display_mode());
+}
+
+/// Keeps the last screen drawn, and the palette it was drawn with
+#[derive(Default)]
+struct PlanesDisplay {
+    planes: Vec<u8>,
+    palette: Option<::Palette>,
+}
+
+impl ::backend::Display for PlanesDisplay {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_planes(&mut self, planes: &[u8], palette: &::Palette) {
+        self.planes = planes.to_vec();
+        self.palette = Some(*palette);
+    }
+}
+
+/// Holds down the 4 key of the second keypad
+struct SecondKeypad;
+
+impl ::backend::Input for SecondKeypad {
+    fn get_keys(&mut self) -> ::Keypad {
+        ::Keypad::new()
+    }
+    fn get_second_keys(&mut self) -> ::Keypad {
+        let mut keys = ::Keypad::new();
+        keys.set(4, true);
+        keys
+    }
+}
+
+/// Tests coloring the screen, adding nibbles, and reading the second keypad of CHIP-8X
+#[test]
+fn chip8x() {
+    use backend::Split;
+
+    // Colors the top left zone yellow, draws a line of the 0 character, steps the background
+    // color, adds nibbles, and skips setting V5 if the 4 key of the second keypad is pressed
+    let program = program!(0x6000, 0x6100, 0x6205, 0xB020, 0xA050, 0xD011, 0x02A0, 0x6337,
+                           0x6475, 0x5341, 0x6604, 0xE6F2, 0x6501);
+    let mut chip8 = Chip8::new(&program, Config::new().chip8x(true)).unwrap();
+    let mut io = Split::new(PlanesDisplay::default(), SecondKeypad, ());
+
+    for _ in 0..12 {
+        chip8.step(&mut io).unwrap();
+    }
+
+    let palette = io.display.palette.unwrap();
+    // Yellow, and a black background
+    assert_eq!([0xFF, 0xFF, 0x00], palette.colors[io.display.planes[0] as usize]);
+    assert_eq!([0x00; 3], palette.colors[io.display.planes[4] as usize]);
+    assert_eq!(0x24, chip8.registers.get(v(3)));
+    assert_eq!(0, chip8.registers.get(v(5)));
+    assert_eq!(0x21A, chip8.registers.program_counter);
+
+    // Without CHIP-8X, setting the colors is a jump to 0x020 + V0
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    chip8.run_for(&mut Io::new(Vec::new()), 4).unwrap();
+    assert_eq!(0x020, chip8.registers.program_counter);
+}
+
+/// Tests loading programs at a different address, as on the ETI-660
+#[test]
+fn program_start() {
+    use config::{self, START_ETI_660};
+
+    let config = Config::new().program_start(START_ETI_660);
+    let chip8 = run_program_config(&program!(0x6105, 0x1600), config);
+
+    assert_eq!(5, chip8.registers.get(v(1)));
+    assert_eq!(0x600, chip8.registers.program_counter);
+    assert_eq!(0x600, chip8.program_start());
+    assert_eq!(0, chip8.memory[0x200]);
+    assert_eq!(Some(0x600), config::program_start_preset("eti660"));
+
+    let program = vec![0; ::MEMORY - 0x600];
+    assert!(Chip8::new(&program, Config::new().program_start(START_ETI_660)).is_err());
+    assert!(Chip8::new(&program, Config::new()).is_ok());
+
+    // Programs loaded after a hi-res program start at the program start
+    let mut chip8 = Chip8::new(&program!(0x1260), Config::new()).unwrap();
+    chip8.load_program(&program!(0x6105)).unwrap();
+    assert_eq!(0x200, chip8.program_counter());
+}
+
+/// Tests that the size of memory can be changed, and bounds the program and its addresses
+#[test]
+fn memory_size() {
+    use state::State;
+
+    // Stores V0 at 0x10FE, which is only in memory if it is larger than 4 KiB
+    let program = program!(0x60FF, 0xAFFF, 0xF01E, 0xF055);
+    let chip8 = run_program_config(&program, Config::new().memory_size(0x10000));
+    assert_eq!(0xFF, chip8.memory[0x10FE]);
+    assert_eq!(0x10000, chip8.memory.len());
+
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let mut io = Io::new(Vec::new());
+    for _ in 0..3 {
+        chip8.cycle(&mut io).unwrap();
+    }
+    assert!(chip8.cycle(&mut io).is_err());
+
+    // The state holds all of memory
+    let mut large = Chip8::new(&[], Config::new().memory_size(0x10000)).unwrap();
+    large.write_memory(0xFFFF, &[7]).unwrap();
+    let state = State::from_bytes(&large.save_state().to_bytes()).unwrap();
+    chip8.load_state(&state);
+    assert_eq!(7, chip8.memory[0xFFFF]);
+
+    // Jumps past the end of 1 KiB of memory
+    let mut chip8 = Chip8::new(&program!(0x1400), Config::new().memory_size(0x400)).unwrap();
+    match chip8.cycle(&mut io) {
+        Err(Error(ErrorKind::InvalidAddress(0x400, _), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+
+    match Chip8::new(&[0; 0x200], Config::new().memory_size(0x400)) {
+        Err(Error(ErrorKind::ProgramTooLarge(0x200, 0x200), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+}
+
+/// Tests that writes below the program start are errors or warnings with write protection, and
+/// that the debugger can still write there
+#[test]
+fn protect_interpreter() {
+    use std::sync::{Arc, Mutex};
+    use config::WriteProtection;
+
+    // Stores the BCD of V0 at the font, then V0 and V1 at 0x300
+    let program = program!(0x607B, 0xA050, 0xF033, 0xA300, 0xF155);
+
+    let config = Config::new().protect_interpreter(WriteProtection::Error);
+    let mut chip8 = Chip8::new(&program, config).unwrap();
+    let mut io = Io::new(Vec::new());
+    for _ in 0..2 {
+        chip8.cycle(&mut io).unwrap();
+    }
+    match chip8.cycle(&mut io) {
+        Err(Error(ErrorKind::ProtectedWrite(0x50, _), _)) => {}
+        Err(e) => panic!("Wrong error: {}", e),
+        Ok(_) => panic!("Expected error"),
+    }
+    assert_eq!(0xF0, chip8.memory[0x50]);
+    chip8.write_memory(0x50, &[1]).unwrap();
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let hook_warnings = warnings.clone();
+    let protection = WriteProtection::Warn(Box::new(move |e: &Error| {
+        hook_warnings.lock().unwrap().push(e.location().unwrap().pc)
+    }));
+    let chip8 = run_program_config(&program, Config::new().protect_interpreter(protection));
+    assert_eq!(&[1, 2, 3], &chip8.memory[0x50..0x53]);
+    assert_eq!(vec![0x204], *warnings.lock().unwrap());
+}
+
+/// Tests that instructions that are both written and run are found
+#[test]
+fn self_modification() {
+    use self_modification::SelfModification;
+
+    // Overwrites the second byte of the instruction at 0x20A with 0x05, then runs it, which sets V1
+    // to 5 instead of 0
+    let program = program!(0x6005, 0xA20B, 0xF055, 0xA300, 0xF055, 0x6100);
+    let config = Config::new().detect_self_modification(true);
+    let chip8 = run_program_config(&program, config);
+
+    assert_eq!(5, chip8.registers.get(v(1)));
+    assert_eq!(Some(0x204), chip8.writes().unwrap().writer(0x20B));
+    assert_eq!(vec![0x20B, 0x300], chip8.writes().unwrap().addresses());
+    assert_eq!(vec![SelfModification {
+                        instruction: 0x20A,
+                        written: 0x20B,
+                        writer: 0x204,
+                    }],
+               chip8.self_modifications());
+
+    let chip8 = run_program_config(&program, Config::new());
+    assert!(chip8.writes().is_none());
+    assert!(chip8.self_modifications().is_empty());
+}
+
+/// Tests that a runner runs the emulator on another thread, and handles commands
+#[test]
+fn runner() {
+    use runner::{Command, Event, Runner};
+
+    // Draws the 0 character, then loops forever
+    let program = program!(0xA050, 0xD005, 0x1204);
+    let runner = Runner::new(Chip8::new(&program, Config::new()).unwrap());
+
+    let planes = loop {
+        if let Some(Event::Frame(planes, _)) = runner.wait_event() {
+            break planes;
+        }
+    };
+    assert_eq!(&[1, 1, 1, 1, 0], &planes[..5]);
+
+    runner.send(Command::SetKeys(::Keypad::from_bits(0xFFFF)));
+    runner.send(Command::SaveState);
+    loop {
+        if let Some(Event::State(_)) = runner.wait_event() {
+            break;
+        }
+    }
+
+    // Jumps past the end of memory, which ends the program
+    runner.send(Command::LoadProgram(program!(0x1FFF)));
+    loop {
+        match runner.wait_event() {
+            Some(Event::Ended) => break,
+            Some(Event::Error(e)) => panic!("Unexpected error: {}", e),
+            _ => {}
+        }
+    }
+
+    let chip8 = runner.stop();
+    assert!(chip8.program_ended());
+    assert_eq!(0xFFFF, chip8.io.keys().bits());
+}
+
+/// Tests that a runner reports invalid speed factors instead of stopping
+#[test]
+fn runner_speed_factor() {
+    use runner::{Command, Event, Runner};
+
+    let runner = Runner::new(Chip8::new(&program!(0x1200), Config::new()).unwrap());
+
+    for &factor in &[0.0, -1.0, f64::NAN, 1e-300, 2.0] {
+        runner.send(Command::SetSpeedFactor(factor));
+    }
+    for _ in 0..4 {
+        loop {
+            match runner.wait_event() {
+                Some(Event::Error(_)) => break,
+                Some(_) => {}
+                None => panic!("The runner stopped"),
+            }
+        }
+    }
+
+    let chip8 = runner.stop();
+    assert_eq!(2.0, chip8.speed_factor());
+}
+
+/// Tests that a pool steps each emulator with its own keys, and returns all of their screens
+#[test]
+fn pool() {
+    use pool::Chip8Pool;
+
+    // Waits for key 0, then draws the 0 character and ends
+    let program = program!(0x6000, 0xE09E, 0x1202, 0xA050, 0xD005, 0x1FFF);
+    let mut pool = Chip8Pool::new(&program, 3, Config::new).unwrap();
+    let screen_size = ::SCREEN_WIDTH * ::SCREEN_HEIGHT;
+
+    assert_eq!(3, pool.len());
+    assert_eq!([3, ::SCREEN_HEIGHT, ::SCREEN_WIDTH], pool.framebuffer_shape());
+
+    pool.set_keys(1, ::Keypad::from_bits(0xFFFF));
+    for result in pool.step() {
+        result.unwrap();
+    }
+
+    assert_eq!(vec![false, true, false], pool.done());
+    let framebuffers = pool.framebuffers();
+    assert_eq!(3 * screen_size, framebuffers.len());
+    assert!(framebuffers[..screen_size].iter().all(|&p| p == 0));
+    assert_eq!(&[1, 1, 1, 1, 0], &framebuffers[screen_size..screen_size + 5]);
+    assert!(framebuffers[2 * screen_size..].iter().all(|&p| p == 0));
+
+    let mut keys = [::Keypad::new(); 3];
+    keys[2].set(0, true);
+    pool.set_all_keys(&keys);
+    pool.step();
+    assert_eq!(vec![false, true, true], pool.done());
+
+    pool.reset();
+    assert_eq!(vec![false; 3], pool.done());
+    assert!(pool.framebuffers().iter().all(|&p| p == 0));
+    assert_eq!(3, pool.into_instances().len());
+}
+
+/// Tests that an environment runs a fixed number of cycles each step with the chosen keys
+#[test]
+fn env() {
+    use env::Env;
+
+    // Waits for key 0, then draws the 0 character, starts the sound, and ends
+    let program = program!(0x6000, 0xE09E, 0x1202, 0xA050, 0xD005, 0x6108, 0xF118, 0x1FFF);
+    let mut env = Env::new(&program, Config::new(), 4).unwrap();
+
+    let observation = env.reset();
+    assert!(observation.screen.iter().all(|&p| p == 0));
+    assert!(!observation.sound);
+
+    let (observation, done) = env.step(::Keypad::new()).unwrap();
+    assert!(!done);
+    assert!(observation.screen.iter().all(|&p| p == 0));
+    assert_eq!(4, env.chip8().cycles());
+
+    let mut keys = ::Keypad::new();
+    keys.set(0, true);
+    let (observation, done) = env.step(keys).unwrap();
+    assert!(!done);
+    assert_eq!(&[1, 1, 1, 1, 0], &observation.screen[..5]);
+    assert_eq!(8, env.chip8().cycles());
+
+    let (observation, done) = env.step(keys).unwrap();
+    assert!(done);
+    assert!(observation.sound);
+
+    let observation = env.reset();
+    assert!(!env.is_done());
+    assert!(observation.screen.iter().all(|&p| p == 0));
+}
+
+/// Tests that running arbitrary bytes returns errors instead of panicking
+#[test]
+fn fuzz() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    // Skips over empty memory until the program counter wraps around the end of 64 KiB of memory
+    let program = program!(0x1FFE);
+    let config = Config::new().memory_size(0x10000).permissive(|_| {});
+    let mut chip8 = Chip8::new(&program, config).unwrap();
+    let mut io = Io::new(Vec::new());
+
+    chip8.run_for(&mut io, 1 + (0x10000 - 0xFFE) / 2).unwrap();
+    assert_eq!(0, chip8.program_counter());
+    assert!(!chip8.program_ended());
+
+    let mut rng = StdRng::from_seed(&[0][..]);
+    for _ in 0..200 {
+        let len = rng.gen_range(0, 64);
+        let input = rng.gen_iter().take(len).collect::<Vec<u8>>();
+        let _ = ::fuzz::run(&input, 500);
+    }
+}
+
+/// Tests drawing every character of the font, against a snapshot of the screen
+#[test]
+fn snapshot_font() {
+    // Draws the characters in rows of 8
+    let program = program!(0x6000,
+                           0x6100,
+                           0x6200,
+                           0xF029,
+                           0xD125,
+                           0x7001,
+                           0x7105,
+                           0x3128,
+                           0x1216,
+                           0x6100,
+                           0x7206,
+                           0x3010,
+                           0x1206,
+                           0x121A);
+
+    assert_snapshot("font", &program, Config::new(), 300);
+}
+
+/// Tests that moving a sprite by erasing and redrawing it leaves no pixels behind, against a
+/// snapshot of the screen
+#[test]
+fn snapshot_moving_sprite() {
+    // Draws and erases the 0 character 10 times, moving it right each time, then draws it again
+    let program = program!(0x6000,
+                           0xF029,
+                           0x610A,
+                           0x6208,
+                           0x6300,
+                           0xD125,
+                           0xD125,
+                           0x7103,
+                           0x7301,
+                           0x330A,
+                           0x120A,
+                           0xD125,
+                           0x1218);
+
+    assert_snapshot("moving_sprite", &program, Config::new(), 200);
+}
+
+/// Runs a ROM of the Timendus test suite with the quirks, and compares its screen with the
+/// snapshot of its pass screen (see `tests/roms`)
+/// If `platform` is given, it is written to address 0x1FF, which chooses the platform to test
+/// instead of showing a menu.
+fn test_suite_rom(rom_name: &str, snapshot: &str, quirks: Quirks, platform: Option<u8>) {
+    let rom = match rom(rom_name) {
+        Some(rom) => rom,
+        None => return,
+    };
+    let mut chip8 = Chip8::new(&rom, Config::new().quirks(quirks)).unwrap();
+    if let Some(platform) = platform {
+        chip8.memory[0x1FF] = platform;
+    }
+
+    assert_chip8_snapshot(snapshot, chip8, 100_000);
+}
+
+/// Tests that the logos of the test suite are drawn
+#[test]
+fn test_suite_logos() {
+    test_suite_rom("1-chip8-logo.ch8", "suite_chip8_logo", Quirks::chip8(), None);
+    test_suite_rom("2-ibm-logo.ch8", "suite_ibm_logo", Quirks::chip8(), None);
+}
+
+/// Tests the instructions with the opcode test of the test suite, for each quirk preset
+#[test]
+fn test_suite_opcodes() {
+    test_suite_rom("3-corax+.ch8", "suite_corax_chip8", Quirks::chip8(), None);
+    test_suite_rom("3-corax+.ch8", "suite_corax_schip", Quirks::schip(), None);
+}
+
+/// Tests the flags set by arithmetic with the flags test of the test suite, for each quirk preset
+#[test]
+fn test_suite_flags() {
+    test_suite_rom("4-flags.ch8", "suite_flags_chip8", Quirks::chip8(), None);
+    test_suite_rom("4-flags.ch8", "suite_flags_schip", Quirks::schip(), None);
+}
+
+/// Tests the quirks with the quirks test of the test suite, on the platform of each quirk preset
+#[test]
+fn test_suite_quirks() {
+    test_suite_rom("5-quirks.ch8", "suite_quirks_chip8", Quirks::chip8(), Some(1));
+    test_suite_rom("5-quirks.ch8", "suite_quirks_schip", Quirks::schip(), Some(2));
+}
+
+#[test]
+fn debug_dump() {
+    let program = program!(0x6105, 0x2206, 0x0000, 0xF129, 0xD005);
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    chip8.run_for(&mut Io::new(Vec::new()), 4).unwrap();
+
+    let dump = format!("{:?}", chip8);
+    let lines = dump.lines().collect::<Vec<_>>();
+
+    assert_eq!("V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF", lines[0]);
+    assert_eq!("00 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00", lines[1]);
+    assert_eq!("PC: 0x20A  I: 0x069  DT: 0x00  ST: 0x00  cycles: 4", lines[2]);
+    assert_eq!("Stack: 0x202", lines[3]);
+    assert_eq!("0x200:  61 05 22 06 00 00 F1 29 D0 05[00 00]00 00 00 00", lines[5]);
+    assert_eq!(4, lines.iter().filter(|line| line.starts_with("0x")).count());
+    assert!(lines[9].starts_with("|:'''  "));
+    assert!(lines[10].starts_with("|''':  "));
+    assert!(lines[11].starts_with("|''''  "));
+    assert_eq!(1 + ::SCREEN_HEIGHT / 2 + 1, lines[8..].len());
+}
+
+#[test]
+fn state_hash() {
+    let program = program!(0x6105, 0xA300, 0xF129, 0xD015, 0x1208);
+    let run = |cycles| {
+        let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+        chip8.run_for(&mut Io::new(Vec::new()), cycles).unwrap();
+        chip8
+    };
+
+    let mut chip8 = run(5);
+    let hash = chip8.state_hash();
+    assert_eq!(hash, run(5).state_hash());
+    assert!(hash != run(3).state_hash());
+
+    // Only the state is hashed, so looping without changing it keeps the hash
+    assert_eq!(hash, run(20).state_hash());
+
+    let state = chip8.save_state();
+    chip8.set_register(v(0xE), 1);
+    assert!(hash != chip8.state_hash());
+    chip8.load_state(&state);
+    assert_eq!(hash, chip8.state_hash());
+}
+
+#[test]
+fn differential_trace() {
+    use differential;
+
+    let program = program!(0x6105, 0xA300, 0x7101, 0x1204);
+    let reference = "0200 6105 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000\n\
+                     0202 A300 00 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000\n\
+                     0204 7101 00 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0300\n\
+                     0206 1204 00 06 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0300\n\
+                     0204 7101 00 06 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0300\n\
+                     0206 1204 00 07 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0300\n";
+    let expected = differential::parse(reference).unwrap();
+
+    let mut chip8 = Chip8::new(&program, Config::new()).unwrap();
+    let trace = differential::record(&mut chip8, &mut Io::new(Vec::new()), 6).unwrap();
+    assert_eq!(6, trace.len());
+    assert_eq!(None, differential::compare(&expected, &trace));
+
+    let mut written = Vec::new();
+    differential::write(&trace, &mut written).unwrap();
+    assert_eq!(reference, String::from_utf8(written).unwrap());
+
+    // A reference that expects ADD to set VF on a carry diverges at the step after it
+    let mut expected = expected;
+    expected[3].registers[0xF] = 1;
+    let divergence = differential::compare(&expected, &trace).unwrap();
+    assert_eq!(3, divergence.step);
+    assert!(divergence.to_string().ends_with("differs in: VF"));
+}
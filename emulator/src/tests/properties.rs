@@ -0,0 +1,301 @@
+//! Property-based tests for the instructions
+//! Each test runs a single instruction from random registers and memory, and checks the
+//! invariants that must hold for every input, rather than for a few chosen examples.
+
+use proptest::prelude::*;
+
+use super::utils::*;
+use Chip8;
+use config::{Config, Quirks};
+use errors::*;
+use register::RegisterId;
+
+/// The address of the instruction that is run
+const START: u16 = 0x200;
+
+/// Runs the instruction with the general purpose registers and the index register set, and
+/// returns the emulator afterwards or the error of the instruction
+fn try_run(opcode: u16, registers: [u8; 16], index: u16, quirks: Quirks) -> Result<Chip8> {
+    let program = program!(opcode);
+    let mut chip8 = Chip8::new(&program, Config::new().quirks(quirks)).unwrap();
+
+    for (id, &value) in RegisterId::all().zip(&registers) {
+        chip8.registers.set(id, value);
+    }
+    chip8.registers.index = index;
+
+    chip8.cycle(&mut Io::new(Vec::new()))?;
+    Ok(chip8)
+}
+
+/// Runs the instruction (see `try_run`), which must not return an error
+fn run(opcode: u16, registers: [u8; 16], index: u16, quirks: Quirks) -> Chip8 {
+    try_run(opcode, registers, index, quirks).unwrap()
+}
+
+/// Returns the opcode with the nibbles of X and Y set to the registers
+fn with_registers(opcode: u16, x: u8, y: u8) -> u16 {
+    opcode | (x as u16) << 8 | (y as u16) << 4
+}
+
+/// Returns the general purpose registers of the emulator
+fn registers(chip8: &Chip8) -> [u8; 16] {
+    *chip8.registers.get_registers()
+}
+
+/// Checks that every general purpose register except those in `changed` kept its value
+fn assert_unchanged(before: &[u8; 16], chip8: &Chip8, changed: &[u8]) {
+    for (x, (&old, &new)) in before.iter().zip(&registers(chip8)).enumerate() {
+        if !changed.contains(&(x as u8)) {
+            assert_eq!(old, new, "V{:X} changed", x);
+        }
+    }
+}
+
+/// Returns a strategy for a general purpose register other than VF, which the flag instructions
+/// overwrite
+fn not_vf() -> impl Strategy<Value = u8> {
+    0..15u8
+}
+
+/// Returns a strategy for any general purpose register
+fn any_register() -> impl Strategy<Value = u8> {
+    0..16u8
+}
+
+/// Returns a strategy for the quirks of the original interpreter, SUPER-CHIP, or any mix of them
+fn any_quirks() -> impl Strategy<Value = Quirks> {
+    prop::array::uniform7(any::<bool>()).prop_map(|q| {
+        Quirks {
+            shift: q[0],
+            memory: q[1],
+            jump: q[2],
+            font: q[3],
+            key_press: q[4],
+            wrap: q[5],
+            index_overflow: q[6],
+        }
+    })
+}
+
+proptest! {
+    #[test]
+    fn set_const(regs: [u8; 16], x in any_register(), n: u8) {
+        let chip8 = run(with_registers(0x6000, x, 0) | n as u16, regs, 0, Quirks::chip8());
+
+        prop_assert_eq!(n, chip8.registers.get(v(x)));
+        prop_assert_eq!(START + 2, chip8.registers.program_counter);
+        assert_unchanged(&regs, &chip8, &[x]);
+    }
+
+    #[test]
+    fn add_const_wraps(regs: [u8; 16], x in any_register(), n: u8) {
+        let chip8 = run(with_registers(0x7000, x, 0) | n as u16, regs, 0, Quirks::chip8());
+
+        // Unlike `Add`, VF is not changed by a carry
+        prop_assert_eq!(regs[x as usize].wrapping_add(n), chip8.registers.get(v(x)));
+        assert_unchanged(&regs, &chip8, &[x]);
+    }
+
+    #[test]
+    fn bitwise(regs: [u8; 16], x in any_register(), y in any_register(), op in 1..4u16) {
+        let chip8 = run(with_registers(0x8000 | op, x, y), regs, 0, Quirks::chip8());
+        let (a, b) = (regs[x as usize], regs[y as usize]);
+        let expected = match op {
+            1 => a | b,
+            2 => a & b,
+            _ => a ^ b,
+        };
+
+        prop_assert_eq!(expected, chip8.registers.get(v(x)));
+        assert_unchanged(&regs, &chip8, &[x]);
+    }
+
+    #[test]
+    fn add_sets_carry(regs: [u8; 16], x in not_vf(), y in any_register()) {
+        let chip8 = run(with_registers(0x8004, x, y), regs, 0, Quirks::chip8());
+        let (a, b) = (regs[x as usize], regs[y as usize]);
+
+        prop_assert_eq!(a.wrapping_add(b), chip8.registers.get(v(x)));
+        prop_assert_eq!((a as u16 + b as u16 > 0xFF) as u8, chip8.registers.get(v(0xF)));
+        assert_unchanged(&regs, &chip8, &[x, 0xF]);
+    }
+
+    #[test]
+    fn sub_sets_borrow(regs: [u8; 16], x in not_vf(), y in any_register()) {
+        let chip8 = run(with_registers(0x8005, x, y), regs, 0, Quirks::chip8());
+        let (a, b) = (regs[x as usize], regs[y as usize]);
+
+        prop_assert_eq!(a.wrapping_sub(b), chip8.registers.get(v(x)));
+        prop_assert_eq!((b > a) as u8, chip8.registers.get(v(0xF)));
+        assert_unchanged(&regs, &chip8, &[x, 0xF]);
+    }
+
+    #[test]
+    fn inverse_sub_sets_borrow(regs: [u8; 16], x in not_vf(), y in any_register()) {
+        let chip8 = run(with_registers(0x8007, x, y), regs, 0, Quirks::chip8());
+        let (a, b) = (regs[x as usize], regs[y as usize]);
+
+        prop_assert_eq!(b.wrapping_sub(a), chip8.registers.get(v(x)));
+        prop_assert_eq!((a > b) as u8, chip8.registers.get(v(0xF)));
+        assert_unchanged(&regs, &chip8, &[x, 0xF]);
+    }
+
+    #[test]
+    fn shifts_set_shifted_bit(regs: [u8; 16],
+                              x in not_vf(),
+                              y in any_register(),
+                              left: bool,
+                              shift_quirk: bool) {
+        let quirks = Quirks { shift: shift_quirk, ..Quirks::chip8() };
+        let opcode = if left { 0x800E } else { 0x8006 };
+        let chip8 = run(with_registers(opcode, x, y), regs, 0, quirks);
+        // The shift quirk shifts VX in place, otherwise VY is shifted into VX
+        let source = if shift_quirk { regs[x as usize] } else { regs[y as usize] };
+
+        if left {
+            prop_assert_eq!(source << 1, chip8.registers.get(v(x)));
+            prop_assert_eq!(source >> 7, chip8.registers.get(v(0xF)));
+        } else {
+            prop_assert_eq!(source >> 1, chip8.registers.get(v(x)));
+            prop_assert_eq!(source & 1, chip8.registers.get(v(0xF)));
+        }
+        assert_unchanged(&regs, &chip8, &[x, 0xF]);
+    }
+
+    #[test]
+    fn rand_is_masked(regs: [u8; 16], x in any_register(), n: u8) {
+        let chip8 = run(with_registers(0xC000, x, 0) | n as u16, regs, 0, Quirks::chip8());
+
+        prop_assert_eq!(0, chip8.registers.get(v(x)) & !n);
+        assert_unchanged(&regs, &chip8, &[x]);
+    }
+
+    #[test]
+    fn skips_move_past_one_instruction(regs: [u8; 16],
+                                       x in any_register(),
+                                       y in any_register(),
+                                       n: u8,
+                                       kind in 0..4) {
+        let (a, b) = (regs[x as usize], regs[y as usize]);
+        let (opcode, skip) = match kind {
+            0 => (with_registers(0x3000, x, 0) | n as u16, a == n),
+            1 => (with_registers(0x4000, x, 0) | n as u16, a != n),
+            2 => (with_registers(0x5000, x, y), a == b),
+            _ => (with_registers(0x9000, x, y), a != b),
+        };
+        let chip8 = run(opcode, regs, 0, Quirks::chip8());
+
+        let expected = if skip { START + 4 } else { START + 2 };
+        prop_assert_eq!(expected, chip8.registers.program_counter);
+        assert_unchanged(&regs, &chip8, &[]);
+    }
+
+    #[test]
+    fn goto_and_call_jump(regs: [u8; 16], addr in 0..0x1000u16, call: bool) {
+        // Programs starting with a jump to 0x260 are loaded as hi-res programs instead
+        prop_assume!(call || addr != 0x260);
+
+        let opcode = if call { 0x2000 } else { 0x1000 };
+        let chip8 = run(opcode | addr, regs, 0, Quirks::chip8());
+
+        prop_assert_eq!(addr, chip8.registers.program_counter);
+        if call {
+            prop_assert_eq!(&[START][..], chip8.stack());
+        } else {
+            prop_assert!(chip8.stack().is_empty());
+        }
+    }
+
+    #[test]
+    fn offset_goto_adds_the_register(regs: [u8; 16], addr in 0..0x1000u16, jump_quirk: bool) {
+        let quirks = Quirks { jump: jump_quirk, ..Quirks::chip8() };
+        // The jump quirk adds VX, where X is the highest nibble of the address
+        let offset = if jump_quirk { regs[(addr >> 8) as usize] } else { regs[0] };
+        let target = addr as usize + offset as usize;
+
+        match try_run(0xB000 | addr, regs, 0, quirks) {
+            Ok(chip8) => prop_assert_eq!(target, chip8.registers.program_counter as usize),
+            Err(Error(ErrorKind::InvalidAddress(address, _), _)) => {
+                prop_assert!(target >= ::MEMORY);
+                prop_assert_eq!(target, address);
+            }
+            Err(e) => panic!("Wrong error: {}", e),
+        }
+    }
+
+    #[test]
+    fn add_index_sets_overflow_flag(regs: [u8; 16],
+                                    x in not_vf(),
+                                    index in 0..0x1000u16,
+                                    overflow_quirk: bool) {
+        let quirks = Quirks { index_overflow: overflow_quirk, ..Quirks::chip8() };
+        let chip8 = run(with_registers(0xF01E, x, 0), regs, index, quirks);
+        let expected = index + regs[x as usize] as u16;
+
+        prop_assert_eq!(expected, chip8.registers.index);
+        if overflow_quirk {
+            prop_assert_eq!((expected > 0xFFF) as u8, chip8.registers.get(v(0xF)));
+            assert_unchanged(&regs, &chip8, &[0xF]);
+        } else {
+            assert_unchanged(&regs, &chip8, &[]);
+        }
+    }
+
+    #[test]
+    fn bcd_stores_digits(regs: [u8; 16], x in any_register(), index in 0x300..0xFFDu16) {
+        let chip8 = run(with_registers(0xF033, x, 0), regs, index, Quirks::chip8());
+        let value = regs[x as usize];
+        let i = index as usize;
+
+        prop_assert_eq!(&[value / 100, value / 10 % 10, value % 10][..], &chip8.memory[i..i + 3]);
+        prop_assert_eq!(index, chip8.registers.index);
+    }
+
+    #[test]
+    fn reg_dump_and_load(regs: [u8; 16],
+                         x in any_register(),
+                         index in 0x300..0xFF0u16,
+                         quirks in any_quirks()) {
+        let i = index as usize;
+        let count = x as usize + 1;
+        // The memory quirk leaves I unchanged
+        let expected_index = if quirks.memory { index } else { index + count as u16 };
+
+        let chip8 = run(with_registers(0xF055, x, 0), regs, index, quirks);
+        prop_assert_eq!(&regs[..count], &chip8.memory[i..i + count]);
+        prop_assert_eq!(expected_index, chip8.registers.index);
+        assert_unchanged(&regs, &chip8, &[]);
+
+        let mut loaded = Chip8::new(&program!(with_registers(0xF065, x, 0)),
+                                    Config::new().quirks(quirks))
+            .unwrap();
+        loaded.memory[i..i + count].copy_from_slice(&regs[..count]);
+        loaded.registers.index = index;
+        loaded.cycle(&mut Io::new(Vec::new())).unwrap();
+
+        prop_assert_eq!(&regs[..count], &registers(&loaded)[..count]);
+        prop_assert!(registers(&loaded)[count..].iter().all(|&r| r == 0));
+        prop_assert_eq!(expected_index, loaded.registers.index);
+    }
+
+    #[test]
+    fn drawing_twice_erases(sprite: [u8; 15], x in 0..121u8, y in 0..50u8, height in 1..16u16) {
+        let opcode = 0xD010 | height;
+        let mut chip8 = Chip8::new(&program!(opcode, opcode), Config::new()).unwrap();
+        chip8.registers.set(v(0), x);
+        chip8.registers.set(v(1), y);
+        chip8.registers.index = 0x300;
+        chip8.memory[0x300..0x30F].copy_from_slice(&sprite);
+        let mut io = Io::new(Vec::new());
+
+        chip8.cycle(&mut io).unwrap();
+        let lit = sprite[..height as usize].iter().map(|row| row.count_ones()).sum::<u32>();
+        prop_assert_eq!(lit as usize, chip8.pixels().iter().filter(|&&p| p).count());
+        prop_assert_eq!(0, chip8.registers.get(v(0xF)));
+
+        chip8.cycle(&mut io).unwrap();
+        prop_assert!(chip8.pixels().iter().all(|&p| !p));
+        prop_assert_eq!((lit > 0) as u8, chip8.registers.get(v(0xF)));
+    }
+}
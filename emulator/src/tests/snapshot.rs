@@ -0,0 +1,95 @@
+//! Golden framebuffer snapshots
+//!
+//! `assert_snapshot` runs a program headless and compares the screen with a PBM image stored in
+//! `src/tests/snapshots`, so changes to what programs draw are caught. After checking that a new
+//! screen is correct, run the tests with `BLESS=1` to write it as the snapshot.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use super::utils::*;
+use Chip8;
+use config::Config;
+use screenshot;
+
+/// The environment variable that makes snapshots be written instead of compared
+const BLESS: &str = "BLESS";
+
+/// Returns the ROM with the file name from `src/tests/roms`, or `None` if it wasn't downloaded (see
+/// the readme in that directory)
+pub fn rom(name: &str) -> Option<Vec<u8>> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/roms").join(name);
+
+    match fs::read(&path) {
+        Ok(rom) => Some(rom),
+        Err(_) => {
+            println!("Skipping `{}`, which wasn't downloaded", path.display());
+            None
+        }
+    }
+}
+
+/// Returns the path of the snapshot with the name
+fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/tests/snapshots")
+        .join(name)
+        .with_extension("pbm")
+}
+
+/// Draws a row of a PBM image with `#` for pixels that are on and `.` for pixels that are off
+fn draw_row(row: &str) -> String {
+    row.split(' ').map(|p| if p == "1" { '#' } else { '.' }).collect()
+}
+
+/// Runs the program for `cycles` cycles, and checks that the screen matches the snapshot with the
+/// name, or writes the snapshot if blessing
+///
+/// # Panics
+///
+/// Panics if the program crashes, the snapshot is missing, or the screen doesn't match it. The
+/// message shows the rows that differ.
+pub fn assert_snapshot(name: &str, program: &[u8], config: Config, cycles: u64) {
+    assert_chip8_snapshot(name, Chip8::new(program, config).unwrap(), cycles);
+}
+
+/// Like `assert_snapshot`, but runs an emulator that was already created, for programs that need
+/// their memory or registers set up first
+pub fn assert_chip8_snapshot(name: &str, mut chip8: Chip8, cycles: u64) {
+    chip8.run_for(&mut Io::new(Vec::new()), cycles).unwrap();
+
+    let mut actual = Vec::new();
+    screenshot::write_pbm(chip8.pixels(), &mut actual).unwrap();
+    let actual = String::from_utf8(actual).unwrap();
+    let path = path(name);
+
+    if env::var_os(BLESS).is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = match fs::read_to_string(&path) {
+        Ok(expected) => expected,
+        Err(e) => {
+            panic!("Failed to read snapshot `{}` ({}), run with {}=1 to write it",
+                   path.display(),
+                   e,
+                   BLESS)
+        }
+    };
+
+    if expected != actual {
+        let mut message = format!("The screen doesn't match snapshot `{}`\n", path.display());
+        for (y, (expected, actual)) in expected.lines().zip(actual.lines()).enumerate() {
+            // The first two lines are the header
+            if expected != actual && y >= 2 {
+                message += &format!("row {:2} expected: {}\n", y - 2, draw_row(expected));
+                message += &format!("         actual: {}\n", draw_row(actual));
+            }
+        }
+        panic!("{}Run with {}=1 to update the snapshot if the new screen is correct",
+               message,
+               BLESS);
+    }
+}
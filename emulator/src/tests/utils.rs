@@ -8,13 +8,13 @@ use super::run_program;
 /// A simulated key press, activated at cycle `start` and lasting for `duration` cycles
 #[derive(Debug, PartialEq)]
 pub struct Keypress {
-    key: usize,
+    key: u8,
     start: usize,
     duration: usize,
 }
 
 impl Keypress {
-    pub fn new(key: usize, range: ops::Range<usize>) -> Keypress {
+    pub fn new(key: u8, range: ops::Range<usize>) -> Keypress {
         assert!(key < 16, "Invalid keypress: {}", key);
 
         Keypress {
@@ -33,22 +33,26 @@ pub trait TestIO {
 
 /// A struct implements `Chip8IO`
 /// Stores internal state for simulating keypresses
-/// When a sound is played, this implementation panics, so sound is tested by making a `should_panic`
-/// test
+/// Also stores whether a sound is playing
 pub struct Io {
-    pub keys: Keys,
+    pub keys: Keypad,
     pub keypresses: Vec<Keypress>,
+    pub sound: bool,
 }
 
 // A simple implementation of `Chip8IO` for use in tests
-// Panics when a sound is played
 impl Chip8IO for Io {
     fn draw(&mut self, _: &[bool]) {}
-    fn get_keys(&mut self) -> Keys {
+    fn get_keys(&mut self) -> Keypad {
         self.keys
     }
-    fn play_sound(&mut self) {
-        panic!("playing sound");
+    fn sound_start(&mut self) {
+        assert!(!self.sound, "sound started twice");
+        self.sound = true;
+    }
+    fn sound_stop(&mut self) {
+        assert!(self.sound, "sound stopped while not playing");
+        self.sound = false;
     }
     fn should_close(&self) -> bool {
         false
@@ -58,8 +62,9 @@ impl Chip8IO for Io {
 impl TestIO for Io {
     fn new(keypresses: Vec<Keypress>) -> Io {
         Io {
-            keys: [false; 16],
+            keys: Keypad::new(),
             keypresses: keypresses,
+            sound: false,
         }
     }
 
@@ -69,7 +74,7 @@ impl TestIO for Io {
         // Unpress expired keypresses
         keypresses = keypresses.into_iter()
             .filter(|k| if k.duration == 0 {
-                self.keys[k.key] = false;
+                self.keys.set(k.key, false);
                 false
             } else {
                 true
@@ -81,7 +86,7 @@ impl TestIO for Io {
         // Simulate keypresses
         for key in &mut self.keypresses {
             if key.start == 0 && key.duration > 0 {
-                self.keys[key.key] = true;
+                self.keys.set(key.key, true);
                 key.duration -= 1;
             }
 
@@ -103,19 +108,20 @@ pub struct KeyIO {
 // counter reaches 10
 impl Chip8IO for KeyIO {
     fn draw(&mut self, _: &[bool]) {}
-    fn get_keys(&mut self) -> Keys {
+    fn get_keys(&mut self) -> Keypad {
         self.get_keys_counter += 1;
 
         if self.get_keys_counter >= 10 {
-            let mut keys = Keys::default();
-            keys[15] = true;
+            let mut keys = Keypad::new();
+            keys.set(15, true);
 
             keys
         } else {
             Default::default()
         }
     }
-    fn play_sound(&mut self) {}
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
     fn should_close(&self) -> bool {
         false
     }
@@ -159,6 +165,11 @@ macro_rules! program {
     }};
 }
 
+/// Returns the ID of register VX
+pub fn v(x: u8) -> RegisterId {
+    RegisterId::new(x).expect("Invalid register")
+}
+
 /// Returns a matrix, represented by a 2 dimensional vector, created from a slice.
 /// The matrix is generated assuming the slice is in row-major order, and has the given width and
 /// height
@@ -196,7 +207,7 @@ fn test_simulate_keypress_before() {
     let keypresses = keypresses!(0 @ 1..2);
     let (_, io) = run_program::<Io>(&program, Some(keypresses), None);
 
-    assert!(!io.keys[0]);
+    assert!(!io.keys.get(0));
 }
 
 /// Asserts that key 0 is pressed at cycle 0 (start of the range)
@@ -206,7 +217,7 @@ fn test_simulate_keypress_start() {
     let keypresses = keypresses!(0 @ 0..2);
     let (_, io) = run_program::<Io>(&program, Some(keypresses), None);
 
-    assert!(io.keys[0]);
+    assert!(io.keys.get(0));
 }
 
 /// Asserts that key 0 is pressed at cycle 2 (end of the range)
@@ -216,7 +227,7 @@ fn test_simulate_keypress_end() {
     let keypresses = keypresses!(0 @ 0..2);
     let (_, io) = run_program::<Io>(&program, Some(keypresses), None);
 
-    assert!(io.keys[0]);
+    assert!(io.keys.get(0));
 }
 
 /// Asserts that key 0 is not pressed at cycle 3 (after the range)
@@ -226,7 +237,7 @@ fn test_simulate_keypress_unpress() {
     let keypresses = keypresses!(0 @ 0..2);
     let (_, io) = run_program::<Io>(&program, Some(keypresses), None);
 
-    assert!(!io.keys[0]);
+    assert!(!io.keys.get(0));
 }
 
 // Helper function tests:
@@ -0,0 +1,230 @@
+//! Tracing of the instructions run by the emulator, for debugging programs
+//!
+//! Each step of the emulator can be recorded into a bounded buffer (see `Chip8::enable_trace`),
+//! passed to a hook as it happens (see `Chip8::set_trace_hook`), or both. Steps are also logged,
+//! depending on the log level (see `config::Log`).
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::Chip8;
+use config::{Log, LOG_TARGET};
+use register::{Registers, RegisterId};
+use watch::{Change, Watch};
+
+/// A record of a single instruction run by the emulator
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The number of cycles run before this one
+    pub cycle: u64,
+    /// The address of the instruction
+    pub pc: u16,
+    /// The raw opcode of the instruction
+    pub opcode: u16,
+    /// The instruction in assembly language, such as `LD V1, 0x05`
+    pub mnemonic: String,
+    /// The changes the instruction made to the general purpose registers, the index register, and
+    /// the timers
+    pub changes: Vec<Change>,
+}
+
+impl TraceEntry {
+    /// Describes the instruction, without the changes it made
+    pub fn instruction(&self) -> String {
+        format!("{}: 0x{:03X}: 0x{:04X} {}",
+                self.cycle,
+                self.pc,
+                self.opcode,
+                self.mnemonic)
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.instruction())?;
+
+        for change in &self.changes {
+            match change.watch {
+                Watch::Register(x) => write!(f, " V{:X}", x)?,
+                Watch::Index => write!(f, " I")?,
+                Watch::DelayTimer => write!(f, " DT")?,
+                Watch::SoundTimer => write!(f, " ST")?,
+            }
+
+            write!(f, ": 0x{:02X} -> 0x{:02X}", change.old, change.new)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The values that a step can change, from before the step
+#[derive(Clone, Debug)]
+pub(crate) struct Snapshot {
+    /// The registers
+    registers: Registers,
+    /// The delay timer
+    delay_timer: u8,
+    /// The sound timer
+    sound_timer: u8,
+}
+
+/// A function called with every trace entry
+pub type TraceHook = Box<dyn FnMut(&TraceEntry) + Send>;
+
+/// The recorded trace, and the hook to pass new entries to
+#[derive(Default)]
+pub struct Tracer {
+    /// The most recent entries, or `None` if recording is disabled
+    buffer: Option<VecDeque<TraceEntry>>,
+    /// The maximum number of entries to keep
+    capacity: usize,
+    /// The function to pass new entries to
+    hook: Option<TraceHook>,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("buffer", &self.buffer)
+            .field("capacity", &self.capacity)
+            .field("hook", &self.hook.as_ref().map(|_| "hook"))
+            .finish()
+    }
+}
+
+impl Tracer {
+    /// Returns a tracer with recording and the hook disabled
+    pub fn new() -> Tracer {
+        Tracer::default()
+    }
+
+    /// Returns whether entries are used by anything
+    pub fn is_enabled(&self) -> bool {
+        self.buffer.is_some() || self.hook.is_some()
+    }
+
+    /// Records the entry and passes it to the hook
+    fn push(&mut self, entry: TraceEntry) {
+        if let Some(ref mut hook) = self.hook {
+            hook(&entry);
+        }
+
+        if let Some(ref mut buffer) = self.buffer {
+            if buffer.len() == self.capacity {
+                buffer.pop_front();
+            }
+
+            buffer.push_back(entry);
+        }
+    }
+}
+
+impl Chip8 {
+    /// Enables recording the most recent `capacity` steps, which must not be zero (see `trace`)
+    pub fn enable_trace(&mut self, capacity: usize) {
+        assert!(capacity > 0, "Trace capacity must not be zero");
+
+        let mut buffer = self.tracer.buffer.take().unwrap_or_default();
+        while buffer.len() > capacity {
+            buffer.pop_front();
+        }
+
+        self.tracer.buffer = Some(buffer);
+        self.tracer.capacity = capacity;
+    }
+
+    /// Disables recording steps, discarding the recorded trace
+    pub fn disable_trace(&mut self) {
+        self.tracer.buffer = None;
+    }
+
+    /// Returns the recorded steps, from oldest to newest
+    /// This is empty if recording is disabled.
+    pub fn trace(&self) -> Vec<&TraceEntry> {
+        self.tracer.buffer.iter().flat_map(|b| b.iter()).collect()
+    }
+
+    /// Sets the function called with every step as it happens
+    pub fn set_trace_hook<F: FnMut(&TraceEntry) + Send + 'static>(&mut self, hook: F) {
+        self.tracer.hook = Some(Box::new(hook));
+    }
+
+    /// Removes the trace hook
+    pub fn remove_trace_hook(&mut self) {
+        self.tracer.hook = None;
+    }
+
+    /// Returns whether the step at the address should be traced
+    pub(crate) fn tracing(&self, pc: u16) -> bool {
+        self.logging_at(pc) || self.tracer.is_enabled()
+    }
+
+    /// Returns whether the instruction at the address should be logged
+    fn logging_at(&self, pc: u16) -> bool {
+        self.log >= Log::Instructions &&
+        (self.log_ranges.is_empty() || self.log_ranges.iter().any(|range| range.contains(&pc)))
+    }
+
+    /// Returns the values that a step is traced as changing, to be compared with the values after
+    /// the step
+    pub(crate) fn trace_snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.registers.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    /// Traces a step, given the values from before it
+    pub(crate) fn push_trace(&mut self, pc: u16, opcode: u16, mnemonic: String, before: &Snapshot) {
+        let mut changes = Vec::new();
+        let registers = &before.registers;
+
+        for x in RegisterId::all() {
+            let (old, new) = (registers.get_u16(x), self.registers.get_u16(x));
+
+            if old != new {
+                changes.push(Change {
+                    watch: Watch::Register(x),
+                    old,
+                    new,
+                    pc,
+                });
+            }
+        }
+
+        let others = [(Watch::Index, registers.index, self.registers.index),
+                      (Watch::DelayTimer, before.delay_timer as u16, self.delay_timer as u16),
+                      (Watch::SoundTimer, before.sound_timer as u16, self.sound_timer as u16)];
+
+        for &(watch, old, new) in &others {
+            if old != new {
+                changes.push(Change {
+                    watch,
+                    old,
+                    new,
+                    pc,
+                });
+            }
+        }
+
+        let entry = TraceEntry {
+            cycle: self.cycles,
+            pc,
+            opcode,
+            mnemonic,
+            changes,
+        };
+
+        if self.logging_at(pc) {
+            if self.log >= Log::State {
+                info!(target: LOG_TARGET, "{}", entry);
+            } else {
+                info!(target: LOG_TARGET, "{}", entry.instruction());
+            }
+        }
+
+        self.tracer.push(entry);
+    }
+}
@@ -0,0 +1,269 @@
+//! An implementation of `Chip8IO` for web browsers, using an HTML canvas for graphics and Web Audio
+//! for sound
+//!
+//! `WebEmulator` is exported to JavaScript with `wasm-bindgen`. A page creates it with a canvas and
+//! a program, forwards keyboard events to it, and runs a frame from each `requestAnimationFrame`
+//! callback:
+//!
+//! ```text
+//! const emulator = new WebEmulator(canvas, program, 700);
+//! document.addEventListener("keydown", e => emulator.key_down(e.code) && e.preventDefault());
+//! document.addEventListener("keyup", e => emulator.key_up(e.code) && e.preventDefault());
+//!
+//! function frame() {
+//!     if (emulator.frame()) {
+//!         requestAnimationFrame(frame);
+//!     }
+//! }
+//! requestAnimationFrame(frame);
+//! ```
+//!
+//! The canvas is resized to one pixel per Chip-8 pixel, so it should be scaled up with CSS (using
+//! `image-rendering: pixelated`). Browsers only allow sound after the user interacts with the page,
+//! so `resume_audio` should be called from an input event handler.
+//!
+//! Browsers don't provide `std::time::Instant`, so the emulator is paced by the calls to `frame`
+//! rather than by a clock.
+
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use wasm_bindgen::prelude::*;
+use js_sys::Math;
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode, CanvasRenderingContext2d,
+              HtmlCanvasElement, ImageData, OscillatorType};
+
+use super::{Chip8, Chip8IO, Keypad, Palette, SCREEN_WIDTH, SCREEN_HEIGHT, AUDIO_PATTERN_LEN,
+            audio_sample_rate};
+use clock::ManualClock;
+use config::Config;
+
+/// The frequency of the beep played for programs without an audio pattern
+const FREQUENCY: f32 = 440.0;
+/// The number of frames run per second
+const FPS: u32 = 60;
+
+/// Stores state used for doing I/O
+#[allow(missing_debug_implementations)]
+pub struct Io {
+    context: CanvasRenderingContext2d,
+    /// The audio context, or `None` if the browser doesn't support Web Audio
+    audio: Option<AudioContext>,
+    /// The sound being played, if any
+    source: Option<AudioScheduledSourceNode>,
+    /// The audio pattern set by the program, if any (see `Chip8IO::set_audio_pattern`)
+    pattern: Option<AudioBuffer>,
+    keys: Keypad,
+    /// The screen as RGBA pixels
+    image: Vec<u8>,
+}
+
+impl Io {
+    /// Initializes the state, resizing the canvas to the size of the screen
+    pub fn new(canvas: &HtmlCanvasElement) -> Result<Io, JsValue> {
+        canvas.set_width(SCREEN_WIDTH as u32);
+        canvas.set_height(SCREEN_HEIGHT as u32);
+
+        let context = canvas.get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("The canvas has no 2D context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+
+        Ok(Io {
+            context,
+            audio: AudioContext::new().ok(),
+            source: None,
+            pattern: None,
+            keys: Keypad::new(),
+            image: vec![0xFF; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+        })
+    }
+
+    /// Resumes the audio context, which browsers suspend until the user interacts with the page
+    pub fn resume_audio(&self) {
+        if let Some(ref audio) = self.audio {
+            let _ = audio.resume();
+        }
+    }
+
+    /// Handles a key being pressed or released, where `code` is the `code` of a `KeyboardEvent`
+    /// Returns whether the key is mapped to a key of the keypad.
+    pub fn set_key(&mut self, code: &str, state: bool) -> bool {
+        let key = match code {
+            "Digit1" => 0x1,
+            "Digit2" => 0x2,
+            "Digit3" => 0x3,
+            "Digit4" => 0xC,
+            "KeyQ" => 0x4,
+            "KeyW" => 0x5,
+            "KeyE" => 0x6,
+            "KeyR" => 0xD,
+            "KeyA" => 0x7,
+            "KeyS" => 0x8,
+            "KeyD" => 0x9,
+            "KeyF" => 0xE,
+            "KeyZ" => 0xA,
+            "KeyX" => 0x0,
+            "KeyC" => 0xB,
+            "KeyV" => 0xF,
+            _ => return false,
+        };
+
+        self.keys.set(key, state);
+        true
+    }
+
+    /// Starts playing the audio pattern if the program set one, or a square wave beep otherwise
+    fn start_source(&self, audio: &AudioContext) -> Result<AudioScheduledSourceNode, JsValue> {
+        let source: AudioScheduledSourceNode = match self.pattern {
+            Some(ref pattern) => {
+                let source = audio.create_buffer_source()?;
+                source.set_buffer(Some(pattern));
+                source.set_loop(true);
+                source.connect_with_audio_node(&audio.destination())?;
+                source.into()
+            }
+            None => {
+                let oscillator = audio.create_oscillator()?;
+                oscillator.set_type(OscillatorType::Square);
+                oscillator.frequency().set_value(FREQUENCY);
+                oscillator.connect_with_audio_node(&audio.destination())?;
+                oscillator.into()
+            }
+        };
+
+        source.start()?;
+        Ok(source)
+    }
+}
+
+impl Chip8IO for Io {
+    fn draw(&mut self, pixels: &[bool]) {
+        // Draw pixels that are on using the color of the first plane
+        let planes = pixels.iter().map(|&p| p as u8).collect::<Vec<_>>();
+        self.draw_planes(&planes, &Palette::default());
+    }
+
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        for (pixel, &plane) in self.image.chunks_mut(4).zip(planes) {
+            pixel[..3].copy_from_slice(&palette.colors[plane as usize]);
+        }
+
+        let image = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&self.image[..]),
+                                                                SCREEN_WIDTH as u32,
+                                                                SCREEN_HEIGHT as u32);
+        if let Ok(image) = image {
+            let _ = self.context.put_image_data(&image, 0.0, 0.0);
+        }
+    }
+
+    fn get_keys(&mut self) -> Keypad {
+        self.keys
+    }
+
+    fn sound_start(&mut self) {
+        if let Some(ref audio) = self.audio {
+            self.source = self.start_source(audio).ok();
+        }
+    }
+
+    fn sound_stop(&mut self) {
+        if let Some(source) = self.source.take() {
+            let _ = source.stop();
+        }
+    }
+
+    fn set_audio_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_LEN], pitch: u8) {
+        let audio = match self.audio {
+            Some(ref audio) => audio,
+            None => return,
+        };
+
+        // Each bit is a sample, most significant bit first
+        let samples = (0..AUDIO_PATTERN_LEN * 8)
+            .map(|i| if pattern[i / 8] & (0x80 >> (i % 8)) != 0 { 0.25 } else { -0.25 })
+            .collect::<Vec<f32>>();
+
+        let buffer = audio.create_buffer(1,
+                                         samples.len() as u32,
+                                         audio_sample_rate(pitch) as f32);
+        self.pattern = buffer.and_then(|b| b.copy_to_channel(&samples, 0).map(|_| b)).ok();
+    }
+
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// An emulator running in a web page (see the module documentation)
+#[wasm_bindgen]
+#[allow(missing_debug_implementations)]
+pub struct WebEmulator {
+    chip8: Chip8,
+    io: Io,
+    /// The number of cycles run per frame
+    frame_cycles: u32,
+}
+
+#[wasm_bindgen]
+impl WebEmulator {
+    /// Creates an emulator that draws to the canvas and runs `speed` instructions per second
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: &HtmlCanvasElement,
+               program: &[u8],
+               speed: u32)
+               -> Result<WebEmulator, JsValue> {
+        // The system clock and random number generator aren't available in browsers
+        let config = Config::new()
+            .speed(speed.max(1))
+            .clock(ManualClock::new())
+            .seed((Math::random() * 1e9) as usize);
+        let chip8 = Chip8::new(program, config).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        Ok(WebEmulator {
+            chip8,
+            io: Io::new(canvas)?,
+            frame_cycles: (speed / FPS).max(1),
+        })
+    }
+
+    /// Runs a frame, which should be done 60 times per second
+    /// Returns whether the program is still running, or an error if it crashed.
+    pub fn frame(&mut self) -> Result<bool, JsValue> {
+        if !self.chip8.is_paused() {
+            for _ in 0..self.frame_cycles {
+                if self.chip8.program_ended() {
+                    break;
+                }
+
+                self.chip8
+                    .cycle(&mut self.io)
+                    .map_err(|e| JsValue::from_str(&e.to_string()))?;
+            }
+
+            self.chip8.tick_60hz(&mut self.io);
+        }
+
+        Ok(!self.chip8.program_ended())
+    }
+
+    /// Handles a key being pressed, where `code` is the `code` of a `KeyboardEvent`
+    /// Returns whether the key is used by the emulator, so the page can prevent its default
+    /// action.
+    pub fn key_down(&mut self, code: &str) -> bool {
+        self.io.resume_audio();
+        self.io.set_key(code, true)
+    }
+
+    /// Handles a key being released (see `key_down`)
+    pub fn key_up(&mut self, code: &str) -> bool {
+        self.io.set_key(code, false)
+    }
+
+    /// Resumes audio, which browsers only allow from user input event handlers
+    pub fn resume_audio(&self) {
+        self.io.resume_audio();
+    }
+
+    /// Pauses the emulator, or resumes it if it is paused
+    pub fn toggle_pause(&mut self) {
+        self.chip8.pause_handle().toggle();
+    }
+}
@@ -0,0 +1,124 @@
+//! Notifications of changes to registers and timers, for tracking down where a program changes a
+//! value
+
+use std::fmt;
+
+use super::Chip8;
+use register::RegisterId;
+
+/// A value that can be watched for changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    /// The general purpose register VX
+    Register(RegisterId),
+    /// The index register
+    Index,
+    /// The delay timer
+    DelayTimer,
+    /// The sound timer
+    SoundTimer,
+}
+
+/// A change to a watched value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// The value that changed
+    pub watch: Watch,
+    /// The value before the change
+    pub old: u16,
+    /// The value after the change
+    pub new: u16,
+    /// The address of the instruction that made the change
+    /// For changes made by the timers counting down, this is the address of the next instruction
+    pub pc: u16,
+}
+
+/// A function called with every change to a watched value
+pub type WatchHook = Box<dyn FnMut(Change) + Send>;
+
+/// The watched values, their last known values, and the hook to notify of changes
+#[derive(Default)]
+pub struct Watches {
+    /// Watched values, and their values when they were last checked
+    values: Vec<(Watch, u16)>,
+    /// The function to notify of changes
+    hook: Option<WatchHook>,
+}
+
+impl fmt::Debug for Watches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watches")
+            .field("values", &self.values)
+            .field("hook", &self.hook.as_ref().map(|_| "hook"))
+            .finish()
+    }
+}
+
+impl Watches {
+    /// Returns an empty set of watches without a hook
+    pub fn new() -> Watches {
+        Watches::default()
+    }
+}
+
+impl Chip8 {
+    /// Starts watching the value, so the watch hook is called when it changes (see
+    /// `set_watch_hook`)
+    pub fn watch(&mut self, watch: Watch) {
+        if !self.watches.values.iter().any(|&(w, _)| w == watch) {
+            let value = self.watched_value(watch);
+            self.watches.values.push((watch, value));
+        }
+    }
+
+    /// Stops watching the value
+    pub fn unwatch(&mut self, watch: Watch) {
+        self.watches.values.retain(|&(w, _)| w != watch);
+    }
+
+    /// Sets the function called with every change to a watched value
+    pub fn set_watch_hook<F: FnMut(Change) + Send + 'static>(&mut self, hook: F) {
+        self.watches.hook = Some(Box::new(hook));
+    }
+
+    /// Removes the watch hook
+    pub fn remove_watch_hook(&mut self) {
+        self.watches.hook = None;
+    }
+
+    /// Calls the watch hook for each watched value that changed since the last check
+    /// `pc` is the address of the instruction that may have changed the values
+    pub(crate) fn check_watches(&mut self, pc: u16) {
+        if self.watches.values.is_empty() {
+            return;
+        }
+
+        for i in 0..self.watches.values.len() {
+            let (watch, old) = self.watches.values[i];
+            let new = self.watched_value(watch);
+
+            if old != new {
+                self.watches.values[i].1 = new;
+
+                if let Some(ref mut hook) = self.watches.hook {
+                    hook(Change {
+                        watch,
+                        old,
+                        new,
+                        pc,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the current value of the watched value
+    fn watched_value(&self, watch: Watch) -> u16 {
+        match watch {
+            Watch::Register(x) => self.registers.get_u16(x),
+            Watch::Index => self.registers.index,
+            Watch::DelayTimer => self.delay_timer as u16,
+            Watch::SoundTimer => self.sound_timer as u16,
+        }
+    }
+}
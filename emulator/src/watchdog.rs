@@ -0,0 +1,69 @@
+//! Detection of programs that have stopped doing anything, such as ones waiting for input
+//!
+//! This is useful for headless runs, where a program waiting for input would otherwise run until
+//! its cycle budget is used up.
+
+use super::Chip8;
+
+/// The state checked by the watchdog: the planes of the screen, the general purpose registers, and
+/// the index register
+type Snapshot = (Vec<u8>, [u8; 16], u16);
+
+/// Detects when neither the screen nor the registers have changed for a number of frames
+#[derive(Clone, Debug)]
+pub struct Watchdog {
+    /// The number of frames without changes after which the program is stalled
+    limit: u64,
+    /// The number of frames without changes so far
+    unchanged: u64,
+    /// The state at the last update
+    last: Option<Snapshot>,
+}
+
+impl Watchdog {
+    /// Returns a watchdog that considers a program stalled after `frames` frames without changes
+    /// to the screen or the registers
+    pub fn new(frames: u64) -> Watchdog {
+        assert!(frames > 0, "Watchdog frames must not be zero");
+
+        Watchdog {
+            limit: frames,
+            unchanged: 0,
+            last: None,
+        }
+    }
+
+    /// Compares the state of the emulator to the state at the last update, and returns whether
+    /// the program is stalled
+    /// This should be called once per frame.
+    pub fn update(&mut self, chip8: &Chip8) -> bool {
+        let mut general = [0; 16];
+        general.copy_from_slice(chip8.registers.get_registers());
+        let snapshot = (chip8.planes().to_vec(), general, chip8.registers.index);
+
+        if self.last.as_ref() == Some(&snapshot) {
+            self.unchanged += 1;
+        } else {
+            self.unchanged = 0;
+            self.last = Some(snapshot);
+        }
+
+        self.stalled()
+    }
+
+    /// Returns whether the program is stalled
+    pub fn stalled(&self) -> bool {
+        self.unchanged >= self.limit
+    }
+
+    /// Returns the number of frames without changes so far
+    pub fn unchanged_frames(&self) -> u64 {
+        self.unchanged
+    }
+
+    /// Resets the watchdog, as if it had never been updated
+    pub fn reset(&mut self) {
+        self.unchanged = 0;
+        self.last = None;
+    }
+}
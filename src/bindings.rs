@@ -0,0 +1,36 @@
+use app_dirs::{self, AppDataType};
+use chip8::bindings::Bindings;
+use chip8::{Result, ResultExt};
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+
+use APP_INFO;
+
+/// Returns the user's bindings of keys to emulator actions
+/// Creates the bindings file with the default bindings if it doesn't exist, so users can edit it
+pub fn load_bindings() -> Result<Bindings> {
+    let path = app_dirs::app_root(AppDataType::UserConfig, &APP_INFO)
+        .chain_err(|| "Failed to get app config directory")?
+        .join("bindings.cfg");
+
+    // Test if the file exists (fs::metadata returns an error if it doesn't)
+    // If the file does exist, but fs::metadata returns an error anyways, the error will be caught
+    // when attempting to create the file
+    if fs::metadata(&path).is_err() {
+        let bindings = Bindings::default();
+
+        File::create(&path)
+            .and_then(|mut f| f.write_all(bindings.to_string().as_bytes()))
+            .chain_err(|| format!("Failed to create bindings file: `{}`", path.display()))?;
+
+        return Ok(bindings);
+    }
+
+    let mut text = String::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .chain_err(|| format!("Failed to read bindings file: `{}`", path.display()))?;
+
+    Bindings::parse(&text).chain_err(|| format!("Invalid bindings file: `{}`", path.display()))
+}
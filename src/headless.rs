@@ -0,0 +1,128 @@
+//! Running programs without a window, for scripted runs such as testing a program in CI, and for
+//! measuring the speed of the emulator
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use chip8::{Chip8, Chip8IO, Color, Keypad, Palette, Result, ResultExt};
+use chip8::screenshot;
+
+/// The number of cycles run between checks of the clock when benchmarking
+const BENCH_CYCLES: u64 = 10_000;
+
+/// An implementation of `Chip8IO` that shows nothing, plays no sound, and never presses any keys
+pub struct NullIo;
+
+impl Chip8IO for NullIo {
+    fn draw(&mut self, _: &[bool]) {}
+    fn get_keys(&mut self) -> Keypad {
+        Keypad::new()
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// An implementation of `Chip8IO` for benchmarking, which converts the screen to colors when it is
+/// drawn like a backend would, and measures the time spent doing so
+struct BenchIo {
+    /// The colors of the pixels of the last frame drawn
+    colors: Vec<Color>,
+    /// The number of frames drawn
+    frames: u64,
+    /// The time spent drawing frames
+    draw_time: Duration,
+}
+
+impl Chip8IO for BenchIo {
+    fn draw(&mut self, _: &[bool]) {}
+    fn draw_planes(&mut self, planes: &[u8], palette: &Palette) {
+        let start = Instant::now();
+
+        self.colors.clear();
+        self.colors.extend(planes.iter().map(|&p| palette.colors[p as usize]));
+
+        self.frames += 1;
+        self.draw_time += start.elapsed();
+    }
+    fn get_keys(&mut self) -> Keypad {
+        Keypad::new()
+    }
+    fn sound_start(&mut self) {}
+    fn sound_stop(&mut self) {}
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// Runs the program as fast as possible for `duration`, and prints the number of instructions run
+/// per second, the number of frames drawn, and how the time was split between running
+/// instructions and drawing
+/// Stops early if the program ends or stalls.
+pub fn bench(chip8: &mut Chip8, duration: Duration) -> Result<()> {
+    let mut io = BenchIo {
+        colors: Vec::new(),
+        frames: 0,
+        draw_time: Duration::from_secs(0),
+    };
+    let start = Instant::now();
+    let mut cycles = 0;
+
+    loop {
+        cycles += chip8.run_for(&mut io, BENCH_CYCLES)?;
+
+        if chip8.program_ended() || chip8.stalled() {
+            println!("The program stopped after {} cycles", cycles);
+            break;
+        }
+        if start.elapsed() >= duration {
+            break;
+        }
+    }
+
+    let elapsed = seconds(start.elapsed());
+    let draw_time = seconds(io.draw_time);
+
+    println!("Ran {} instructions in {:.2} s ({:.0} instructions/s)",
+             cycles,
+             elapsed,
+             cycles as f64 / elapsed);
+    println!("Drew {} frames ({:.0} frames/s)", io.frames, io.frames as f64 / elapsed);
+    println!("Time spent running instructions: {:.3} s ({:.1}%)",
+             elapsed - draw_time,
+             (elapsed - draw_time) / elapsed * 100.0);
+    println!("Time spent drawing: {:.3} s ({:.1}%)",
+             draw_time,
+             draw_time / elapsed * 100.0);
+
+    Ok(())
+}
+
+/// Returns the duration in seconds
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+/// Runs at most `max_cycles` cycles of the program as fast as possible, and prints how many were
+/// run
+/// If `dump_path` is given, the screen is written to it as a PBM image afterwards.
+pub fn run(chip8: &mut Chip8, max_cycles: u64, dump_path: Option<&str>) -> Result<()> {
+    let cycles = chip8.run_for(&mut NullIo, max_cycles)?;
+
+    println!("Ran {} cycles", cycles);
+
+    if let Some(path) = dump_path {
+        File::create(path)
+            .and_then(|f| {
+                let mut writer = BufWriter::new(f);
+                screenshot::write_pbm(chip8.pixels(), &mut writer)?;
+                writer.flush()
+            })
+            .chain_err(|| format!("Failed to write screen: `{}`", path))?;
+    }
+
+    Ok(())
+}
@@ -1,12 +1,244 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::Read;
 
-/// Returns a program, loaded from the file at the given path
-pub fn load_program<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
-    let mut file = File::open(path)?;
+use app_dirs::{self, AppDataType};
+use chip8::config::Quirks;
+#[cfg(feature = "game_db")]
+use chip8::gamedb;
+use chip8::gamedb::GameDb;
+use chip8::keymap::KeyMap;
+use chip8::rom::{Metadata, Rom};
+use chip8::symbols::SymbolTable;
+use chip8::{Result, ResultExt};
+use serde_json::{self, Value};
+
+use zip;
+use APP_INFO;
+
+/// The extensions of ROM files, used to find the ROM in a zip archive
+const ROM_EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+
+/// Returns a ROM, loaded from the file at the given path
+///
+/// If a file with the same name and the extension `toml` or `json` exists next to the ROM, it is
+/// read as metadata for the ROM, overriding any metadata embedded in the ROM itself. `toml` files
+/// use the same format as embedded metadata (see `chip8::rom`), and `json` files contain an
+/// object with the same keys. Metadata that isn't set by either is filled in from the database of
+/// known programs (see `load_game_db`).
+///
+/// ROMs can also be loaded from zip archives. If the archive holds a single ROM it is loaded,
+/// otherwise the file to load can be chosen with a path such as `archive.zip:GAME.ch8`. Metadata
+/// files are then looked for next to the archive.
+pub fn load_rom<P: AsRef<Path>>(path: P) -> Result<Rom> {
+    let (path, entry) = split_archive_path(path.as_ref());
+    let path = &path;
+    let bytes = read_file(path)
+        .and_then(|bytes| if entry.is_some() || is_zip(path) {
+            read_zip(&bytes, entry.as_ref().map(|e| &e[..]))
+        } else {
+            Ok(bytes)
+        })
+        .chain_err(|| format!("Could not load program from file: `{}`", path.display()))?;
+    let mut rom = Rom::new(bytes).chain_err(|| format!("Invalid metadata in `{}`", path.display()))?;
+
+    let toml_path = path.with_extension("toml");
+    if toml_path.is_file() {
+        let metadata = read_text(&toml_path)
+            .and_then(|text| Metadata::parse(&text))
+            .chain_err(|| format!("Invalid metadata file: `{}`", toml_path.display()))?;
+
+        rom.metadata_mut().merge(metadata);
+    }
+
+    let json_path = path.with_extension("json");
+    if json_path.is_file() {
+        let metadata = read_file(&json_path)
+            .and_then(|bytes| parse_json_metadata(&bytes))
+            .chain_err(|| format!("Invalid metadata file: `{}`", json_path.display()))?;
+
+        rom.metadata_mut().merge(metadata);
+    }
+
+    if let Some(known) = load_game_db()?.get(rom.program()) {
+        let mut metadata = known.clone();
+        metadata.merge(rom.metadata().clone());
+        *rom.metadata_mut() = metadata;
+    }
+
+    Ok(rom)
+}
+
+/// Returns the database of known programs
+///
+/// This is the database built into the library if the `game_db` feature is enabled, with the
+/// user's database merged into it. The user's database is stored in `games.toml`, in the same
+/// directory as the bindings file, and uses the format of `chip8::gamedb`. The built-in database
+/// has no programs yet, so only the programs in the user's database are known for now.
+pub fn load_game_db() -> Result<GameDb> {
+    #[cfg(feature = "game_db")]
+    let mut db = gamedb::builtin();
+    #[cfg(not(feature = "game_db"))]
+    let mut db = GameDb::new();
+
+    let path = app_dirs::app_root(AppDataType::UserConfig, &APP_INFO)
+        .chain_err(|| "Failed to get app config directory")?
+        .join("games.toml");
+
+    if path.is_file() {
+        let user_db = read_text(&path)
+            .and_then(|text| GameDb::parse(&text))
+            .chain_err(|| format!("Invalid game database: `{}`", path.display()))?;
+        db.merge(user_db);
+    }
+
+    Ok(db)
+}
+
+/// Returns a symbol table, loaded from the file at the given path (see `chip8::symbols` for the
+/// format)
+pub fn load_symbols<P: AsRef<Path>>(path: P) -> Result<SymbolTable> {
+    let path = path.as_ref();
+
+    read_text(path)
+        .and_then(|text| SymbolTable::parse(&text))
+        .chain_err(|| format!("Invalid symbol file: `{}`", path.display()))
+}
+
+/// Returns a key map, loaded from the file at the given path (see `chip8::keymap` for the format)
+pub fn load_keymap<P: AsRef<Path>>(path: P) -> Result<KeyMap> {
+    let path = path.as_ref();
+
+    read_text(path)
+        .and_then(|text| KeyMap::parse(&text))
+        .chain_err(|| format!("Invalid key map file: `{}`", path.display()))
+}
+
+/// Returns the contents of the text file at the given path
+pub fn read_text<P: AsRef<Path>>(path: P) -> Result<String> {
+    read_file(path.as_ref())
+        .and_then(|bytes| String::from_utf8(bytes).chain_err(|| "File is not valid UTF-8"))
+}
+
+/// Splits a path such as `archive.zip:GAME.ch8` into the path of the archive and the name of the
+/// file in it
+/// Paths of files that exist, or that don't name a file in a zip archive, are returned as they are.
+fn split_archive_path(path: &Path) -> (PathBuf, Option<String>) {
+    if !path.exists() {
+        if let Some(text) = path.to_str() {
+            if let Some(i) = text.to_lowercase().find(".zip:") {
+                let (archive, entry) = text.split_at(i + ".zip".len());
+                return (PathBuf::from(archive), Some(entry[1..].to_string()));
+            }
+        }
+    }
+
+    (path.to_path_buf(), None)
+}
+
+/// Returns whether the path is of a zip archive, according to its extension
+fn is_zip(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+}
+
+/// Returns the contents of a file in a zip archive, or of the only ROM in it if no name is given
+fn read_zip(archive: &[u8], name: Option<&str>) -> Result<Vec<u8>> {
+    let entries = zip::entries(archive)?;
+    let files = entries.iter().filter(|e| !e.is_dir()).collect::<Vec<_>>();
+
+    let entry = match name {
+        Some(name) => {
+            files.iter()
+                .cloned()
+                .find(|e| e.name == name)
+                .ok_or_else(|| format!("The archive has no file named `{}`", name))?
+        }
+        None => {
+            let roms = files.iter().cloned().filter(|e| is_rom(&e.name)).collect::<Vec<_>>();
+            // Archives without any files named like ROMs may still hold a single program
+            let candidates = if roms.is_empty() { files } else { roms };
+
+            match candidates.len() {
+                0 => bail!("The archive is empty"),
+                1 => candidates[0],
+                _ => {
+                    let names = candidates.iter()
+                        .map(|e| format!("`{}`", e.name))
+                        .collect::<Vec<_>>();
+                    bail!("The archive holds more than one program, so one must be chosen with \
+                           `archive.zip:NAME` (found {})",
+                          names.join(", "));
+                }
+            }
+        }
+    };
+
+    zip::extract(archive, entry)
+}
+
+/// Returns whether the name of a file has the extension of a ROM
+fn is_rom(name: &str) -> bool {
+    let extension = Path::new(name).extension().and_then(|e| e.to_str());
+    extension.is_some_and(|e| ROM_EXTENSIONS.iter().any(|r| r.eq_ignore_ascii_case(e)))
+}
+
+/// Returns the contents of the file at the given path
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path).chain_err(|| "Failed to open file")?;
     let mut buf = Vec::new();
-    file.read_to_end(&mut buf)?;
+    file.read_to_end(&mut buf).chain_err(|| "Failed to read file")?;
 
     Ok(buf)
 }
+
+/// Parses metadata stored as a JSON object
+fn parse_json_metadata(bytes: &[u8]) -> Result<Metadata> {
+    let value: Value = serde_json::from_slice(bytes).chain_err(|| "Invalid JSON")?;
+    let object = value.as_object().ok_or("Metadata must be an object")?;
+    let mut metadata = Metadata::default();
+
+    for (key, value) in object {
+        match key.as_str() {
+            "title" => {
+                let title = value.as_str().ok_or("`title` must be a string")?;
+                metadata.title = Some(title.to_string());
+            }
+            "speed" => {
+                let speed = value.as_u64()
+                    .and_then(|s| if s > 0 && s <= u32::MAX as u64 {
+                        Some(s as u32)
+                    } else {
+                        None
+                    })
+                    .ok_or("`speed` must be a positive integer")?;
+                metadata.speed = Some(speed);
+            }
+            "quirks" => {
+                let invalid = "`quirks` must be a preset name or a list of quirk names";
+
+                let quirks = if let Some(preset) = value.as_str() {
+                    Quirks::preset(preset).ok_or(invalid)?
+                } else {
+                    let names = value.as_array().ok_or(invalid)?;
+                    let mut quirks = Quirks::chip8();
+
+                    for name in names {
+                        if !name.as_str().is_some_and(|n| quirks.enable(n)) {
+                            bail!(invalid);
+                        }
+                    }
+
+                    quirks
+                };
+                metadata.quirks = Some(quirks);
+            }
+            "keys" => {
+                let keys = value.as_str().ok_or("`keys` must be a string")?;
+                metadata.keys = Some(keys.to_string());
+            }
+            _ => bail!("Unknown key: `{}`", key),
+        }
+    }
+
+    Ok(metadata)
+}
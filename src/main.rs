@@ -1,26 +1,101 @@
 //! A CLI for the `chip8` library
 //!
-//! Handles loading programs, argument parsing and storing sound data
+//! Handles loading programs and argument parsing
 
-#[macro_use]
-extern crate error_chain;
 extern crate env_logger;
 extern crate app_dirs;
 extern crate chip8;
 extern crate clap;
+extern crate serde_json;
+extern crate miniz_oxide;
+
+/// Returns early with an error, which is either a message formatted like with `format!` or
+/// anything that converts to an error
+macro_rules! bail {
+    ($e:expr) => {
+        return Err($e.into())
+    };
+    ($fmt:expr, $($arg:tt)+) => {
+        return Err(format!($fmt, $($arg)+).into())
+    };
+}
 
-mod sound;
 mod load;
+mod bindings;
+mod settings;
+mod headless;
+mod zip;
 
-use clap::{App, Arg};
-use chip8::default_io::Io;
+use std::fs::File;
+use std::process;
+use std::ops::Range;
+use std::io::Write;
+use std::time::Duration;
 
-quick_main!(run);
+use app_dirs::AppInfo;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use chip8::Chip8;
+#[cfg(feature = "piston")]
+use chip8::default_io::{self, Io, Options};
+#[cfg(all(feature = "sdl2", not(feature = "piston")))]
+use chip8::sdl2_io::Io;
+#[cfg(all(feature = "term", not(any(feature = "piston", feature = "sdl2"))))]
+use chip8::term_io::Io;
+use chip8::config::{self, Config, Log, Quirks, WriteProtection, InputPolling};
+use chip8::ResultExt;
+use chip8::speedrun::SpeedrunTimer;
+use chip8::analyzer;
+use chip8::disasm;
+use chip8::lint;
+use chip8::asm;
+use chip8::report;
+use chip8::scan;
+use settings::Settings;
 
 const NAME: &'static str = env!("CARGO_PKG_NAME");
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 const AUTHORS: &'static str = env!("CARGO_PKG_AUTHORS");
 
+/// Used for finding the directories where user data and configuration is stored
+const APP_INFO: AppInfo = AppInfo {
+    name: "chip8_bin",
+    author: "pengowen",
+};
+
+/// The number of cycles to run a program for with each combination of quirks when detecting the
+/// quirks it expects
+const ANALYZE_CYCLES: u64 = 10_000;
+/// The default number of instructions recorded in an execution report
+const REPORT_STEPS: &str = "100";
+/// The default number of seconds to run a program for when benchmarking
+const BENCH_SECONDS: &str = "5";
+/// The default number of cycles to run a program for in headless mode
+const HEADLESS_CYCLES: &str = "1000000";
+/// The flags that enable each quirk, along with the name of the quirk and a description
+const QUIRK_FLAGS: [(&str, &str, &str); 7] =
+    [("quirk-shift", "shift", "Shift VX in place instead of shifting VY into VX"),
+     ("quirk-memory", "memory", "Leave I unchanged when saving and loading registers"),
+     ("quirk-jump", "jump", "Add VX to the address of BXNN jumps instead of V0"),
+     ("quirk-font", "font", "Return an error if FX29 is given a value over 15"),
+     ("quirk-key-press", "key_press", "Finish FX0A when a key is pressed, not released"),
+     ("quirk-wrap", "wrap", "Wrap memory accesses past the end of memory around to the start"),
+     ("quirk-index-overflow", "index_overflow", "Set VF when FX1E makes I go past 0xFFF")];
+
+fn main() {
+    if let Err(e) = run() {
+        let mut causes = e.iter();
+
+        if let Some(error) = causes.next() {
+            eprintln!("Error: {}", error);
+        }
+        for cause in causes {
+            eprintln!("Caused by: {}", cause);
+        }
+
+        process::exit(1);
+    }
+}
+
 /// Loads a program from a file and runs in it a Chip-8 emulator
 fn run() -> chip8::Result<()> {
     env_logger::init().unwrap();
@@ -29,23 +104,635 @@ fn run() -> chip8::Result<()> {
         .version(VERSION)
         .author(AUTHORS)
         .about("A Chip-8 emulator")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("file").required(true))
         .arg(Arg::with_name("log")
             .short("l")
             .long("enable-logging")
-            .help("Enable logging of opcodes"))
+            .help("Enable logging of instructions"))
+        .arg(Arg::with_name("log-level")
+            .long("log-level")
+            .takes_value(true)
+            .possible_values(&["off", "errors", "instructions", "state"])
+            .conflicts_with("log")
+            .help("What to log, where `state` also logs the changes each instruction makes \
+                   (messages are logged to the `chip8::emulation` target)"))
+        .arg(Arg::with_name("log-range")
+            .long("log-range")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("START..END")
+            .help("Only log the instructions with addresses in a range, such as `0x300..0x340` \
+                   (the end is excluded)"))
+        .arg(Arg::with_name("speed")
+            .long("speed")
+            .takes_value(true)
+            .help("The number of instructions to run per second, instead of the speed the \
+                   program asks for"))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .help("Seed the random number generator, making runs reproducible"))
+        .arg(Arg::with_name("auto-quirks")
+            .long("auto-quirks")
+            .help("Detect the quirks the program expects, if it doesn't specify them in its \
+                   metadata"))
+        .arg(Arg::with_name("quirks")
+            .long("quirks")
+            .takes_value(true)
+            .possible_values(&["chip8", "schip", "xochip"])
+            .help("Use the quirks of an interpreter, instead of the ones the program asks for"))
+        .args(&QUIRK_FLAGS.iter()
+            .map(|&(flag, _, help)| Arg::with_name(flag).long(flag).help(help))
+            .collect::<Vec<_>>())
+        .arg(Arg::with_name("rewind")
+            .long("rewind")
+            .help("Enable rewinding with the rewind key (Backspace by default)"))
+        .arg(Arg::with_name("permissive")
+            .long("permissive")
+            .help("Skip invalid opcodes with a warning instead of stopping the program"))
+        .arg(Arg::with_name("strict")
+            .long("strict")
+            .help("Stop the program when it writes to the memory below it, which holds the font"))
+        .arg(Arg::with_name("chip8x")
+            .long("chip8x")
+            .help("Run the program as a CHIP-8X program, with colors and a second keypad"))
+        .arg(Arg::with_name("program-start")
+            .long("program-start")
+            .takes_value(true)
+            .value_name("ADDRESS")
+            .help("The address to load the program at (0x200 by default), or the platform it was \
+                   written for (vip, chip8x, or eti660)"))
+        .arg(Arg::with_name("memory")
+            .long("memory")
+            .takes_value(true)
+            .value_name("KIB")
+            .help("The size of memory in KiB, from 1 to 64 (4 by default, and up to 64 for \
+                   XO-CHIP programs)"))
+        .arg(Arg::with_name("input-polling")
+            .long("input-polling")
+            .takes_value(true)
+            .possible_values(&["instruction", "frame", "key"])
+            .help("When to read the keys: before every instruction, once per frame (the \
+                   default), or before the instructions that use them"))
+        .arg(Arg::with_name("run-ahead")
+            .long("run-ahead")
+            .help("Reduce input latency by showing the screen one frame ahead"))
+        .arg(Arg::with_name("scale")
+            .long("scale")
+            .takes_value(true)
+            .help("The size of each pixel of the screen when the window is opened (10 by \
+                   default)"))
+        .arg(Arg::with_name("fullscreen")
+            .long("fullscreen")
+            .help("Start in fullscreen mode (toggled with F11 by default)"))
+        .arg(Arg::with_name("phosphor")
+            .long("phosphor")
+            .takes_value(true)
+            .value_name("FRAMES")
+            .help("Fade pixels out over this many frames after they are turned off, which \
+                   reduces flicker"))
+        .arg(Arg::with_name("palette")
+            .long("palette")
+            .takes_value(true)
+            .value_name("COLORS")
+            .help("The colors of the screen, separated by commas and starting with the \
+                   background, written as `#RRGGBB` or by name (such as `black,amber`)"))
+        .arg(Arg::with_name("mute")
+            .long("mute")
+            .help("Disable sound"))
+        .arg(Arg::with_name("keymap")
+            .long("keymap")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Load the mapping of keys to the keys of the keypad from a file, where each \
+                   line maps a keypad key to a key, such as `C = D4`"))
+        .arg(Arg::with_name("crt")
+            .long("crt")
+            .help("Draw the screen like an old CRT monitor (toggled with F9 by default)"))
+        .arg(Arg::with_name("no-focus-pause")
+            .long("no-focus-pause")
+            .help("Keep running when the window loses focus, instead of pausing"))
+        .arg(Arg::with_name("split")
+            .long("split")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Add a split to the speedrun timer, triggered by a condition such as \
+                   `pc == 0x2A4` (the timer stops at the last split)"))
+        .arg(Arg::with_name("sound-log")
+            .long("sound-log")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Log changes to the sound timer to a file, which can be rendered to audio \
+                   with the `render_sound` example of the library"))
+        .arg(Arg::with_name("profile")
+            .long("profile")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Count how many times each instruction and address is run, and write a report \
+                   of the counts to a file when the emulator exits"))
+        .arg(Arg::with_name("detect-self-modification")
+            .long("detect-self-modification")
+            .help("Report the instructions that the program both wrote and ran when the emulator \
+                   exits"))
+        .arg(Arg::with_name("symbols")
+            .long("symbols")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Load names of addresses in the program from a file, which are shown in error \
+                   messages (one `name = address` per line)"))
+        .arg(Arg::with_name("headless")
+            .long("headless")
+            .help("Run the program as fast as possible without opening a window, for scripted \
+                   runs (no keys are pressed)"))
+        .arg(Arg::with_name("max-cycles")
+            .long("max-cycles")
+            .takes_value(true)
+            .value_name("N")
+            .requires("headless")
+            .help("The number of cycles to run the program for in headless mode, unless it ends \
+                   or stalls first (1000000 by default)"))
+        .arg(Arg::with_name("dump-screen")
+            .long("dump-screen")
+            .takes_value(true)
+            .value_name("FILE")
+            .requires("headless")
+            .help("Write the screen to a file as a PBM image after running in headless mode"))
+        .subcommand(SubCommand::with_name("info")
+            .about("Show the metadata of a program, a summary of its code, and detect the quirks \
+                    it expects")
+            .arg(Arg::with_name("file").required(true)))
+        .subcommand(SubCommand::with_name("disasm")
+            .about("Print the disassembly of a program")
+            .arg(Arg::with_name("file").required(true))
+            .arg(Arg::with_name("reachable")
+                .long("reachable")
+                .help("Only disassemble instructions that can be reached from the start of the \
+                       program")))
+        .subcommand(SubCommand::with_name("lint")
+            .about("Check the code of a program for invalid opcodes, jumps outside the program, \
+                    writes to the font and recursive calls, without running it")
+            .arg(Arg::with_name("file").required(true)))
+        .subcommand(SubCommand::with_name("asm")
+            .about("Assemble a program (see the documentation of `chip8::asm` for the language)")
+            .arg(Arg::with_name("source").required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("FILE")
+                .required(true)
+                .help("The file to write the program to"))
+            .arg(Arg::with_name("symbols")
+                .long("symbols")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Write the addresses of labels to a file, which can be loaded with \
+                       `--symbols` when running the program")))
+        .subcommand(SubCommand::with_name("bench")
+            .about("Run a program as fast as possible without a window, and report how fast the \
+                    emulator ran it")
+            .arg(Arg::with_name("file").required(true))
+            .arg(Arg::with_name("duration")
+                .long("duration")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .default_value(BENCH_SECONDS)
+                .help("The number of seconds to run the program for")))
+        .subcommand(SubCommand::with_name("report")
+            .about("Write a step-by-step Markdown report of running a program, showing the \
+                    registers after each instruction and the screen after each draw")
+            .arg(Arg::with_name("file").required(true))
+            .arg(Arg::with_name("steps")
+                .long("steps")
+                .takes_value(true)
+                .default_value(REPORT_STEPS)
+                .help("The number of instructions to record"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("The file to write the report to, instead of printing it")))
         .get_matches();
 
-    let log = matches.is_present("log").into();
+    match matches.subcommand() {
+        ("info", Some(matches)) => return info(matches),
+        ("disasm", Some(matches)) => return disassemble(matches),
+        ("lint", Some(matches)) => return lint(matches),
+        ("asm", Some(matches)) => return assemble(matches),
+        ("report", Some(matches)) => return write_report(matches),
+        ("bench", Some(matches)) => return bench(matches),
+        _ => {}
+    }
+
+    let mut settings = settings::load_settings()?;
+
+    if let Some(path) = matches.value_of("keymap") {
+        settings.keymap = Some(load::load_keymap(path)?);
+    }
+
+    let log = matches.value_of("log-level")
+        .and_then(Log::from_name)
+        .unwrap_or_else(|| matches.is_present("log").into());
+    let mut config = Config::new().log(log);
+
+    if let Some(ranges) = matches.values_of("log-range") {
+        for range in ranges {
+            config = config.log_range(parse_range(range)?);
+        }
+    }
+
+    if let Some(speed) = settings.speed {
+        config = config.speed(speed);
+    }
+    if let Some(palette) = settings.palette {
+        config = config.palette(palette);
+    }
+    if let Some(quirks) = settings.quirks {
+        config = config.quirks(quirks);
+    }
+
+    if let Some(colors) = matches.value_of("palette") {
+        let palette = settings::parse_palette_arg(colors)
+            .ok_or_else(|| format!("Invalid palette: `{}`", colors))?;
+        config = config.palette(palette);
+    }
+
+    if let Some(seed) = matches.value_of("seed") {
+        let seed = seed.parse().chain_err(|| format!("Invalid seed: `{}`", seed))?;
+        config = config.seed(seed);
+    }
+
+    if matches.is_present("rewind") {
+        // Take 10 snapshots per second, and keep 30 seconds of them
+        config = config.rewind(6, 300);
+    }
+
+    if matches.is_present("run-ahead") {
+        config = config.run_ahead(1);
+    }
+
+    if matches.is_present("permissive") {
+        config = config.permissive(|warning| eprintln!("Warning: {}", warning));
+    }
+
+    if matches.is_present("strict") {
+        config = config.protect_interpreter(WriteProtection::Error);
+    }
+
+    if matches.is_present("chip8x") {
+        config = config.chip8x(true);
+    }
+
+    if let Some(start) = matches.value_of("program-start") {
+        // The memory below 0x200 belongs to the interpreter
+        let address = config::program_start_preset(start)
+            .or_else(|| parse_address(start))
+            .filter(|&address| address >= config::START_COSMAC_VIP)
+            .ok_or_else(|| format!("Invalid program start: `{}`", start))?;
+        config = config.program_start(address);
+    }
+
+    if let Some(size) = matches.value_of("memory") {
+        let kib = size.parse::<usize>()
+            .ok()
+            .filter(|kib| (1..=64).contains(kib))
+            .ok_or_else(|| format!("Invalid memory size: `{}`", size))?;
+        config = config.memory_size(kib * 1024);
+    }
+
+    if let Some(polling) = matches.value_of("input-polling").and_then(InputPolling::from_name) {
+        config = config.input_polling(polling);
+    }
+
+    if let Some(splits) = matches.values_of("split") {
+        let mut timer = SpeedrunTimer::new();
+
+        for (i, split) in splits.enumerate() {
+            timer = timer.split(format!("Split {}", i + 1), split.parse()?);
+        }
+
+        config = config.speedrun(timer);
+    }
+
+    let sound_log_path = matches.value_of("sound-log");
+    config = config.sound_log(sound_log_path.is_some());
+
+    let profile_path = matches.value_of("profile");
+    config = config.profile(profile_path.is_some());
+    config = config.detect_self_modification(matches.is_present("detect-self-modification"));
+
+    let file = matches.value_of("file").unwrap();
+    let rom = load::load_rom(file)?;
+
+    if matches.is_present("auto-quirks") && rom.metadata().quirks.is_none() &&
+       !matches.is_present("quirks") {
+        if let Some(quirks) = analyzer::suggest(rom.program(), ANALYZE_CYCLES) {
+            config = config.quirks(quirks);
+        }
+    }
+
+    // Use the speed and quirks the program asks for, unless they were chosen with flags
+    let mut config = rom.metadata().apply(config);
+
+    if let Some(speed) = matches.value_of("speed") {
+        let speed = speed.parse::<u32>()
+            .ok()
+            .filter(|&speed| speed > 0)
+            .ok_or_else(|| format!("Invalid speed: `{}`", speed))?;
+        config = config.speed(speed);
+    }
+
+    if let Some(quirks) = quirks_from_args(&matches, rom.metadata().quirks.or(settings.quirks)) {
+        config = config.quirks(quirks);
+    }
+
+    let mut chip8 = Chip8::new(rom.program(), config)
+        .chain_err(|| "Failed to initialize emulator")?;
+
+    if let Some(path) = matches.value_of("symbols") {
+        chip8.set_symbols(load::load_symbols(path)?);
+    }
+
+    let result = if matches.is_present("headless") {
+        let max_cycles = matches.value_of("max-cycles").unwrap_or(HEADLESS_CYCLES);
+        let max_cycles = max_cycles.parse()
+            .chain_err(|| format!("Invalid number of cycles: `{}`", max_cycles))?;
+
+        headless::run(&mut chip8, max_cycles, matches.value_of("dump-screen"))
+    } else {
+        // Initialize I/O state
+        let mut io = new_io(&matches, &settings)?;
+        io.set_bindings(bindings::load_bindings()?);
+
+        if let Some(ref title) = rom.metadata().title {
+            io.set_title(title);
+        }
+        if let Some(ref keys) = rom.metadata().keys {
+            println!("Controls: {}", keys);
+        }
+
+        if !matches.is_present("no-focus-pause") {
+            io.pause_on_focus_loss(chip8.pause_handle());
+        }
+
+        chip8.run_until_break(&mut io).map(|_| ())
+    };
+
+    // Reported even if the program crashed, as self-modification may be why
+    for modification in chip8.self_modifications() {
+        println!("Self-modifying code: {}", modification);
+    }
+
+    if let Err(e) = result {
+        let call_stack = chip8.describe_call_stack();
+        return Err(e)
+            .chain_err(|| format!("The program crashed, with the call stack:\n{}", call_stack));
+    }
+
+    if let (Some(path), Some(log)) = (sound_log_path, chip8.sound_log()) {
+        File::create(path)
+            .and_then(|mut f| f.write_all(log.to_string().as_bytes()))
+            .chain_err(|| format!("Failed to write sound log: `{}`", path))?;
+    }
+
+    if let (Some(path), Some(profile)) = (profile_path, chip8.profile()) {
+        File::create(path)
+            .and_then(|mut f| f.write_all(profile.to_string().as_bytes()))
+            .chain_err(|| format!("Failed to write profile: `{}`", path))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the quirks chosen with `--quirks` and the flags that enable each quirk, which are
+/// enabled on top of the preset, or the quirks that would be used otherwise (or the default ones)
+/// Returns `None` if none of the flags were used.
+fn quirks_from_args(matches: &ArgMatches, base: Option<Quirks>) -> Option<Quirks> {
+    let mut quirks = matches.value_of("quirks").and_then(Quirks::preset);
+
+    for &(flag, name, _) in &QUIRK_FLAGS {
+        if matches.is_present(flag) {
+            quirks.get_or_insert(base.unwrap_or_default()).enable(name);
+        }
+    }
+
+    quirks
+}
+
+/// Parses a range of addresses such as `0x300..0x340`, with decimal or hexadecimal addresses
+fn parse_range(text: &str) -> chip8::Result<Range<u16>> {
+    let mut parts = text.splitn(2, "..").map(str::trim);
+    match (parts.next().and_then(parse_address), parts.next().and_then(parse_address)) {
+        (Some(start), Some(end)) if start < end => Ok(start..end),
+        _ => bail!("Invalid address range: `{}`", text),
+    }
+}
+
+/// Parses an address, written in decimal or in hexadecimal starting with `0x`
+fn parse_address(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Initializes the I/O state of the backend selected with Cargo features
+#[cfg(feature = "piston")]
+fn new_io(matches: &ArgMatches, settings: &Settings) -> chip8::Result<Io> {
+    let mut options = Options::new()
+        .fullscreen(matches.is_present("fullscreen"))
+        .crt(matches.is_present("crt"))
+        .mute(matches.is_present("mute"));
+
+    if let Some(scale) = settings.scale {
+        options = options.scale(scale);
+    }
+
+    if let Some(scale) = matches.value_of("scale") {
+        let scale = scale.parse().chain_err(|| format!("Invalid scale: `{}`", scale))?;
+        options = options.scale(scale);
+    }
+
+    if let Some(frames) = matches.value_of("phosphor") {
+        let frames = frames.parse()
+            .chain_err(|| format!("Invalid number of frames: `{}`", frames))?;
+        options = options.phosphor(frames);
+    }
+
+    let mut io = Io::with_options(options);
+
+    if let Some(ref keymap) = settings.keymap {
+        keymap.check_names(&default_io::key_names())
+            .chain_err(|| "Invalid key map (keys are named after the variants of \
+                           `piston_window::Key`, such as `D1`, `Space`, or `Up`)")?;
+        io.set_keymap(keymap.clone());
+    }
+
+    Ok(io)
+}
+
+/// Initializes the I/O state of the backend selected with Cargo features
+/// The window options, muting, and key map are only supported by the piston backend.
+#[cfg(all(feature = "sdl2", not(feature = "piston")))]
+fn new_io(_: &ArgMatches, _: &Settings) -> chip8::Result<Io> {
+    Ok(Io::new())
+}
+
+/// Initializes the I/O state of the backend selected with Cargo features
+/// The window options, muting, and key map are only supported by the piston backend.
+#[cfg(all(feature = "term", not(any(feature = "piston", feature = "sdl2"))))]
+fn new_io(_: &ArgMatches, _: &Settings) -> chip8::Result<Io> {
+    Io::new().chain_err(|| "Failed to initialize the terminal")
+}
+
+/// Prints the metadata of a program, a summary of its code and problems with it, and the results
+/// of running it with each combination of quirks
+fn info(matches: &ArgMatches) -> chip8::Result<()> {
+    let file = matches.value_of("file").unwrap();
+    let rom = load::load_rom(file)?;
+    let metadata = rom.metadata();
+
+    println!("Title: {}", metadata.title.as_ref().map_or("unknown", |t| &t[..]));
+    println!("Speed: {}",
+             metadata.speed.map_or("unknown".to_string(), |s| format!("{} instructions/s", s)));
+    println!("Quirks: {}",
+             metadata.quirks.map_or("unknown".to_string(), |q| q.to_string()));
+    println!("Keys: {}", metadata.keys.as_ref().map_or("unknown", |k| &k[..]));
+
+    let summary = scan::scan(rom.program());
+
+    println!();
+    println!("Size: {} bytes", summary.size);
+    println!("SHA-1: {}", summary.sha1);
+    println!("Entry opcode: {}",
+             summary.entry_opcode.map_or("none".to_string(), |o| format!("{:04X}", o)));
+    println!("Extensions: {}",
+             if summary.extensions.is_empty() {
+                 "none".to_string()
+             } else {
+                 summary.extensions.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+             });
+
+    if !summary.problems.is_empty() {
+        println!();
+        println!("Problems:");
+        for problem in &summary.problems {
+            println!("  {}", problem);
+        }
+    }
+
+    println!();
+    println!("Results of running the program for {} cycles:", ANALYZE_CYCLES);
+
+    let results = analyzer::analyze(rom.program(), ANALYZE_CYCLES);
+    for result in &results {
+        println!("  [{}]: {} after {} cycles",
+                 result.quirks,
+                 result.outcome,
+                 result.cycles);
+    }
+
+    println!();
+    println!("Suggested quirks: {}", results[0].quirks);
+
+    Ok(())
+}
+
+/// Prints the disassembly of a program
+fn disassemble(matches: &ArgMatches) -> chip8::Result<()> {
+    let file = matches.value_of("file").unwrap();
+    let rom = load::load_rom(file)?;
+
+    let lines = if matches.is_present("reachable") {
+        disasm::disassemble_reachable(rom.program())
+    } else {
+        disasm::disassemble(rom.program())
+    };
+
+    for line in lines {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+/// Prints the problems found in a program by static checks
+/// Returns an error if any were found, so scripts can check the result.
+fn lint(matches: &ArgMatches) -> chip8::Result<()> {
+    let file = matches.value_of("file").unwrap();
+    let rom = load::load_rom(file)?;
+    let warnings = lint::lint(rom.program());
+
+    for warning in &warnings {
+        println!("{}", warning);
+    }
+
+    if !warnings.is_empty() {
+        bail!("Found {} problems in `{}`", warnings.len(), file);
+    }
+
+    Ok(())
+}
+
+/// Assembles a program, and writes it to a file
+fn assemble(matches: &ArgMatches) -> chip8::Result<()> {
+    let source_path = matches.value_of("source").unwrap();
+    let output_path = matches.value_of("output").unwrap();
+
+    let source = load::read_text(source_path)
+        .chain_err(|| format!("Could not read source file: `{}`", source_path))?;
+    let assembly = asm::assemble(&source)
+        .chain_err(|| format!("Failed to assemble `{}`", source_path))?;
+
+    File::create(output_path)
+        .and_then(|mut f| f.write_all(&assembly.program))
+        .chain_err(|| format!("Failed to write program: `{}`", output_path))?;
+
+    if let Some(path) = matches.value_of("symbols") {
+        File::create(path)
+            .and_then(|mut f| f.write_all(assembly.symbols.to_string().as_bytes()))
+            .chain_err(|| format!("Failed to write symbols: `{}`", path))?;
+    }
+
+    Ok(())
+}
+
+/// Records a report of running a program, and prints it or writes it to a file
+fn write_report(matches: &ArgMatches) -> chip8::Result<()> {
+    let file = matches.value_of("file").unwrap();
+    let steps = matches.value_of("steps").unwrap();
+    let steps = steps.parse().chain_err(|| format!("Invalid number of steps: `{}`", steps))?;
+    let rom = load::load_rom(file)?;
+
+    let config = rom.metadata().apply(Config::new().seed(0));
+    let report = report::record(rom.program(), config, steps)?;
+
+    match matches.value_of("output") {
+        Some(path) => {
+            File::create(path)
+                .and_then(|mut f| f.write_all(report.to_string().as_bytes()))
+                .chain_err(|| format!("Failed to write report: `{}`", path))?
+        }
+        None => print!("{}", report),
+    }
+
+    Ok(())
+}
+
+/// Runs a program headless for a while, and prints how fast it ran
+fn bench(matches: &ArgMatches) -> chip8::Result<()> {
     let file = matches.value_of("file").unwrap();
-    let program = load::load_program(file).unwrap_or_else(|e| {
-        panic!("Could not load program from file: `{}` ({})", file, e);
-    });
+    let duration = matches.value_of("duration").unwrap();
+    let duration = duration.parse().chain_err(|| format!("Invalid duration: `{}`", duration))?;
+    let rom = load::load_rom(file)?;
 
-    // Get the path to the sound file
-    let sound_path = sound::sound_path();
-    // Initialize I/O state
-    let mut io = Io::new(&sound_path);
+    let config = rom.metadata().apply(Config::new().seed(0));
+    let mut chip8 = Chip8::new(rom.program(), config)
+        .chain_err(|| "Failed to initialize emulator")?;
 
-    chip8::run(&program, &mut io, log)
+    headless::bench(&mut chip8, Duration::from_secs(duration))
 }
@@ -0,0 +1,303 @@
+//! Loading the configuration file, which holds defaults for the options of the emulator
+//!
+//! The file is named `config.toml`, and is stored in the same directory as the bindings file. It
+//! uses a subset of TOML, where each line sets an option, and the `[keymap]` table maps keypad
+//! keys to keys (see `chip8::keymap`):
+//!
+//! ```text
+//! # Comments start with a '#'
+//! speed = 700
+//! scale = 8
+//! palette = ["#000000", "#FFCC00"]
+//! quirks = "schip"
+//!
+//! [keymap]
+//! 1 = "D1"
+//! C = "D4"
+//! ```
+//!
+//! The supported options are:
+//!
+//! - `speed`: the number of instructions to run per second, which must be above zero
+//! - `scale`: the size of each pixel of the screen when the window is opened
+//! - `palette`: up to four colors, written as `#RRGGBB` or by name (see `COLOR_NAMES`), which
+//!   replace the colors of the default palette in order (see `chip8::Palette`)
+//! - `quirks`: either the name of a preset (`"chip8"`, `"schip"`, or `"xochip"`), or a list of
+//!   quirks to enable
+//!
+//! Options set in the metadata of a program override the file, and command line flags override
+//! both.
+
+use app_dirs::{self, AppDataType};
+use chip8::config::Quirks;
+use chip8::keymap::KeyMap;
+use chip8::{Color, Palette, Result, ResultExt};
+
+use load;
+use APP_INFO;
+
+/// The colors that can be written by name
+const COLOR_NAMES: [(&str, Color); 12] = [("black", [0x00, 0x00, 0x00]),
+                                          ("white", [0xFF, 0xFF, 0xFF]),
+                                          ("gray", [0xAA, 0xAA, 0xAA]),
+                                          ("dark-gray", [0x55, 0x55, 0x55]),
+                                          ("red", [0xFF, 0x00, 0x00]),
+                                          ("green", [0x00, 0xFF, 0x00]),
+                                          ("blue", [0x00, 0x00, 0xFF]),
+                                          ("yellow", [0xFF, 0xFF, 0x00]),
+                                          ("cyan", [0x00, 0xFF, 0xFF]),
+                                          ("magenta", [0xFF, 0x00, 0xFF]),
+                                          ("amber", [0xFF, 0xB0, 0x00]),
+                                          ("phosphor", [0x33, 0xFF, 0x33])];
+
+/// Defaults for the options of the emulator, each of which is `None` if it wasn't set
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    /// The number of instructions to run per second
+    pub speed: Option<u32>,
+    /// The size of each pixel of the screen when the window is opened
+    pub scale: Option<u32>,
+    /// The colors of the screen
+    pub palette: Option<Palette>,
+    /// The quirks used for programs that don't ask for any
+    pub quirks: Option<Quirks>,
+    /// The mapping of keys to the keys of the keypad
+    pub keymap: Option<KeyMap>,
+}
+
+/// The tables of the configuration file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Table {
+    /// The options before the first table header
+    Root,
+    Keymap,
+}
+
+impl Settings {
+    /// Parses settings from the text of a configuration file (see the module documentation for
+    /// the format)
+    pub fn parse(text: &str) -> Result<Settings> {
+        let mut settings = Settings::default();
+        let mut table = Table::Root;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || format!("Invalid setting on line {}: `{}`", i + 1, line);
+
+            if line.starts_with('[') && line.ends_with(']') {
+                table = match line[1..line.len() - 1].trim() {
+                    "keymap" => Table::Keymap,
+                    name => bail!("Unknown table on line {}: `{}`", i + 1, name),
+                };
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts.next().map(str::trim).ok_or_else(&invalid)?;
+
+            match (table, key) {
+                (Table::Root, "speed") => {
+                    let speed = value.parse::<u32>().chain_err(invalid)?;
+                    if speed == 0 {
+                        bail!(invalid());
+                    }
+                    settings.speed = Some(speed);
+                }
+                (Table::Root, "scale") => settings.scale = Some(value.parse().chain_err(invalid)?),
+                (Table::Root, "palette") => {
+                    settings.palette = Some(parse_palette(value).ok_or_else(&invalid)?)
+                }
+                (Table::Root, "quirks") => {
+                    settings.quirks = Some(parse_quirks(value).ok_or_else(&invalid)?)
+                }
+                (Table::Root, _) => bail!("Unknown setting on line {}: `{}`", i + 1, key),
+                (Table::Keymap, _) => {
+                    let keymap = settings.keymap.get_or_insert_with(KeyMap::new);
+                    let name = parse_string(value).ok_or_else(&invalid)?;
+
+                    if key.len() != 1 {
+                        bail!(invalid());
+                    }
+                    let button = u8::from_str_radix(key, 16).chain_err(invalid)?;
+
+                    keymap.map(&name, button).chain_err(invalid)?;
+                }
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Returns the user's settings, or the default settings if there is no configuration file
+pub fn load_settings() -> Result<Settings> {
+    let path = app_dirs::app_root(AppDataType::UserConfig, &APP_INFO)
+        .chain_err(|| "Failed to get app config directory")?
+        .join("config.toml");
+
+    if !path.is_file() {
+        return Ok(Settings::default());
+    }
+
+    load::read_text(&path)
+        .and_then(|text| Settings::parse(&text))
+        .chain_err(|| format!("Invalid configuration file: `{}`", path.display()))
+}
+
+/// Parses a double-quoted string
+fn parse_string(value: &str) -> Option<String> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Some(value[1..value.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+/// Parses a list of double-quoted strings
+fn parse_list(value: &str) -> Option<Vec<String>> {
+    if !value.starts_with('[') || !value.ends_with(']') {
+        return None;
+    }
+
+    let list = value[1..value.len() - 1].trim();
+
+    if list.is_empty() {
+        return Some(Vec::new());
+    }
+
+    list.split(',').map(|s| parse_string(s.trim())).collect()
+}
+
+/// Parses a color written as `#RRGGBB` or by name
+fn parse_color(value: &str) -> Option<Color> {
+    if let Some(&(_, color)) = COLOR_NAMES.iter().find(|&&(name, _)| name == value) {
+        return Some(color);
+    }
+
+    if value.len() != 7 || !value.starts_with('#') {
+        return None;
+    }
+
+    let mut color = [0; 3];
+    for (i, component) in color.iter_mut().enumerate() {
+        *component = u8::from_str_radix(value.get(1 + i * 2..3 + i * 2)?, 16).ok()?;
+    }
+
+    Some(color)
+}
+
+/// Parses a list of up to four colors, which replace the colors of the default palette in order
+fn parse_palette(value: &str) -> Option<Palette> {
+    palette_from_colors(&parse_list(value)?)
+}
+
+/// Parses colors separated by commas, such as `black,#FFCC00`, which replace the colors of the
+/// default palette in order
+pub fn parse_palette_arg(value: &str) -> Option<Palette> {
+    palette_from_colors(&value.split(',').map(str::trim).collect::<Vec<_>>())
+}
+
+/// Returns the default palette, with its first colors replaced by up to four colors
+fn palette_from_colors<S: AsRef<str>>(colors: &[S]) -> Option<Palette> {
+    if colors.is_empty() || colors.len() > 4 {
+        return None;
+    }
+
+    let mut palette = Palette::default();
+    for (i, color) in colors.iter().enumerate() {
+        palette.colors[i] = parse_color(color.as_ref())?;
+    }
+
+    Some(palette)
+}
+
+/// Parses either a preset name or a list of quirk names
+fn parse_quirks(value: &str) -> Option<Quirks> {
+    if let Some(name) = parse_string(value) {
+        return Quirks::preset(&name);
+    }
+
+    let mut quirks = Quirks::chip8();
+
+    for name in parse_list(value)? {
+        if !quirks.enable(&name) {
+            return None;
+        }
+    }
+
+    Some(quirks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the message of the error returned for the text
+    fn error(text: &str) -> String {
+        match Settings::parse(text) {
+            Err(e) => e.to_string(),
+            Ok(settings) => panic!("Expected error, got {:?}", settings),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "# comment\n\nspeed = 700\nscale = 8\npalette = [\"black\", \"#FFCC00\"]\n\
+                    quirks = [\"jump\"]\n";
+        let settings = Settings::parse(text).unwrap();
+        let mut quirks = Quirks::chip8();
+        quirks.jump = true;
+
+        assert_eq!(Some(700), settings.speed);
+        assert_eq!(Some(8), settings.scale);
+        assert_eq!([0x00, 0x00, 0x00], settings.palette.unwrap().colors[0]);
+        assert_eq!([0xFF, 0xCC, 0x00], settings.palette.unwrap().colors[1]);
+        assert_eq!(Some(quirks), settings.quirks);
+        assert_eq!(None, settings.keymap);
+
+        let settings = Settings::parse("quirks = \"schip\"").unwrap();
+
+        assert_eq!(Some(Quirks::schip()), settings.quirks);
+        assert_eq!(Settings::default(), Settings::parse("").unwrap());
+    }
+
+    #[test]
+    fn test_parse_keymap() {
+        let text = "speed = 500\n\n[keymap]\n1 = \"D1\"\nc = \"D4\"\n";
+        let settings = Settings::parse(text).unwrap();
+        let keymap = settings.keymap.unwrap();
+
+        assert_eq!(Some(500), settings.speed);
+        assert_eq!(Some(0x1), keymap.button("D1"));
+        assert_eq!(Some(0xC), keymap.button("D4"));
+
+        // Options can't be set after the table header
+        assert_eq!("Invalid setting on line 2: `speed = 500`", error("[keymap]\nspeed = 500"));
+        assert_eq!("Unknown table on line 1: `keys`", error("[keys]"));
+        assert_eq!("Invalid setting on line 2: `10 = \"D1\"`", error("[keymap]\n10 = \"D1\""));
+        assert_eq!("Invalid setting on line 2: `1 = D1`", error("[keymap]\n1 = D1"));
+    }
+
+    #[test]
+    fn test_parse_unknown() {
+        assert_eq!("Unknown setting on line 3: `fullscreen`",
+                   error("speed = 700\n\nfullscreen = true"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!("Invalid setting on line 2: `speed = fast`", error("scale = 8\nspeed = fast"));
+        assert_eq!("Invalid setting on line 1: `speed = 0`", error("speed = 0"));
+        assert_eq!("Invalid setting on line 1: `scale`", error("scale"));
+        assert_eq!("Invalid setting on line 1: `palette = [\"#FFCC\"]`",
+                   error("palette = [\"#FFCC\"]"));
+        assert_eq!("Invalid setting on line 1: `quirks = [\"fast\"]`",
+                   error("quirks = [\"fast\"]"));
+    }
+}
@@ -0,0 +1,284 @@
+//! Reading files from zip archives, which ROM packs are often distributed as
+//!
+//! Only what is needed to extract files is supported: files must be stored or compressed with
+//! deflate, and can't be encrypted. Files larger than the largest memory of the emulator aren't
+//! extracted, so a small archive can't decompress to a huge file.
+
+use chip8::Result;
+use miniz_oxide::inflate::{self, TINFLStatus};
+
+/// The signature of the end of central directory record
+const END_SIGNATURE: u32 = 0x06054B50;
+/// The signature of a central directory file header
+const CENTRAL_SIGNATURE: u32 = 0x02014B50;
+/// The signature of a local file header
+const LOCAL_SIGNATURE: u32 = 0x04034B50;
+/// The size of the end of central directory record, without the comment
+const END_SIZE: usize = 22;
+/// The size of a central directory file header, without the name, extra field and comment
+const CENTRAL_SIZE: usize = 46;
+/// The size of a local file header, without the name and extra field
+const LOCAL_SIZE: usize = 30;
+/// The compression method of files that are stored without compression
+const METHOD_STORED: u16 = 0;
+/// The compression method of files that are compressed with deflate
+const METHOD_DEFLATE: u16 = 8;
+/// The size of the largest file that is extracted, which is the largest memory size (64 KiB)
+const MAX_FILE_SIZE: usize = 0x10000;
+
+/// A file in a zip archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The path of the file in the archive
+    pub name: String,
+    /// The compression method of the file
+    method: u16,
+    /// Whether the file is encrypted
+    encrypted: bool,
+    /// The size of the file in the archive
+    compressed_size: usize,
+    /// The offset of the local header of the file
+    offset: usize,
+}
+
+impl Entry {
+    /// Returns whether the entry is a directory rather than a file
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+/// Returns the entries of the zip archive
+pub fn entries(archive: &[u8]) -> Result<Vec<Entry>> {
+    // The end of central directory record is followed by a comment of up to 65535 bytes
+    let end = (0..archive.len().saturating_sub(END_SIZE) + 1)
+        .rev()
+        .take(0x10000)
+        .find(|&i| read_u32(archive, i) == Some(END_SIGNATURE))
+        .ok_or("Not a zip archive")?;
+
+    let count = read_u16(archive, end + 10).ok_or("Invalid zip archive")?;
+    let mut offset = read_u32(archive, end + 16).ok_or("Invalid zip archive")? as usize;
+    let mut entries = Vec::new();
+
+    for _ in 0..count {
+        if read_u32(archive, offset) != Some(CENTRAL_SIGNATURE) {
+            bail!("Invalid zip archive");
+        }
+
+        let field = |at| read_u16(archive, offset + at).ok_or("Invalid zip archive");
+        let flags = field(8)?;
+        let method = field(10)?;
+        let name_len = field(28)? as usize;
+        let extra_len = field(30)? as usize;
+        let comment_len = field(32)? as usize;
+        let compressed_size = read_u32(archive, offset + 20).ok_or("Invalid zip archive")?;
+        let local_offset = read_u32(archive, offset + 42).ok_or("Invalid zip archive")?;
+
+        let name = archive.get(offset + CENTRAL_SIZE..offset + CENTRAL_SIZE + name_len)
+            .ok_or("Invalid zip archive")?;
+
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method,
+            encrypted: flags & 1 != 0,
+            compressed_size: compressed_size as usize,
+            offset: local_offset as usize,
+        });
+
+        offset += CENTRAL_SIZE + name_len + extra_len + comment_len;
+    }
+
+    Ok(entries)
+}
+
+/// Returns the contents of a file in the zip archive
+pub fn extract(archive: &[u8], entry: &Entry) -> Result<Vec<u8>> {
+    if entry.encrypted {
+        bail!("`{}` is encrypted", entry.name);
+    }
+
+    let offset = entry.offset;
+    if read_u32(archive, offset) != Some(LOCAL_SIGNATURE) {
+        bail!("Invalid zip archive");
+    }
+
+    let name_len = read_u16(archive, offset + 26).ok_or("Invalid zip archive")? as usize;
+    let extra_len = read_u16(archive, offset + 28).ok_or("Invalid zip archive")? as usize;
+    let start = offset + LOCAL_SIZE + name_len + extra_len;
+    let data = archive.get(start..start + entry.compressed_size).ok_or("Invalid zip archive")?;
+
+    match entry.method {
+        METHOD_STORED if data.len() > MAX_FILE_SIZE => bail!("`{}` is too large", entry.name),
+        METHOD_STORED => Ok(data.to_vec()),
+        METHOD_DEFLATE => {
+            inflate::decompress_to_vec_with_limit(data, MAX_FILE_SIZE).map_err(|e| {
+                match e.status {
+                    TINFLStatus::HasMoreOutput => format!("`{}` is too large", entry.name).into(),
+                    status => format!("Failed to decompress `{}`: {:?}", entry.name, status).into(),
+                }
+            })
+        }
+        method => bail!("`{}` uses an unsupported compression method ({})", entry.name, method),
+    }
+}
+
+/// Returns the little-endian number at the offset
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    bytes.get(offset..offset + 2).map(|b| b[0] as u16 | (b[1] as u16) << 8)
+}
+
+/// Returns the little-endian number at the offset
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes.get(offset..offset + 4).map(|b| {
+        b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use miniz_oxide::deflate;
+
+    use super::*;
+
+    /// A file to put in a test archive
+    struct File {
+        name: &'static str,
+        method: u16,
+        flags: u16,
+        data: Vec<u8>,
+    }
+
+    impl File {
+        fn new(name: &'static str, method: u16, data: &[u8]) -> File {
+            let data = match method {
+                METHOD_DEFLATE => deflate::compress_to_vec(data, 6),
+                _ => data.to_vec(),
+            };
+
+            File {
+                name,
+                method,
+                flags: 0,
+                data,
+            }
+        }
+    }
+
+    fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+        bytes.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+    }
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        push_u16(bytes, value as u16);
+        push_u16(bytes, (value >> 16) as u16);
+    }
+
+    /// Returns a zip archive holding the files, with the fields that aren't read left as zero
+    fn archive(files: &[File]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+
+        for file in files {
+            let offset = archive.len() as u32;
+
+            push_u32(&mut archive, LOCAL_SIGNATURE);
+            archive.extend_from_slice(&[0; 22]);
+            push_u16(&mut archive, file.name.len() as u16);
+            push_u16(&mut archive, 0);
+            archive.extend_from_slice(file.name.as_bytes());
+            archive.extend_from_slice(&file.data);
+
+            push_u32(&mut directory, CENTRAL_SIGNATURE);
+            directory.extend_from_slice(&[0; 4]);
+            push_u16(&mut directory, file.flags);
+            push_u16(&mut directory, file.method);
+            directory.extend_from_slice(&[0; 8]);
+            push_u32(&mut directory, file.data.len() as u32);
+            directory.extend_from_slice(&[0; 4]);
+            push_u16(&mut directory, file.name.len() as u16);
+            directory.extend_from_slice(&[0; 12]);
+            push_u32(&mut directory, offset);
+            directory.extend_from_slice(file.name.as_bytes());
+        }
+
+        let directory_offset = archive.len() as u32;
+        archive.extend_from_slice(&directory);
+
+        push_u32(&mut archive, END_SIGNATURE);
+        archive.extend_from_slice(&[0; 6]);
+        push_u16(&mut archive, files.len() as u16);
+        push_u32(&mut archive, directory.len() as u32);
+        push_u32(&mut archive, directory_offset);
+        push_u16(&mut archive, 0);
+
+        archive
+    }
+
+    #[test]
+    fn test_extract() {
+        let program = [0x60, 0x01, 0x12, 0x00];
+        let archive = archive(&[File::new("games/", METHOD_STORED, &[]),
+                                File::new("games/STORED.ch8", METHOD_STORED, &program),
+                                File::new("games/DEFLATED.ch8", METHOD_DEFLATE, &program)]);
+        let entries = entries(&archive).unwrap();
+
+        assert_eq!(vec!["games/", "games/STORED.ch8", "games/DEFLATED.ch8"],
+                   entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>());
+        assert!(entries[0].is_dir());
+        assert!(!entries[1].is_dir());
+        assert_eq!(&program, &extract(&archive, &entries[1]).unwrap()[..]);
+        assert_eq!(&program, &extract(&archive, &entries[2]).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_not_zip() {
+        assert_eq!("Not a zip archive", entries(b"").unwrap_err().to_string());
+        assert_eq!("Not a zip archive",
+                   entries(&[0x60, 0x01, 0x12, 0x00]).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let archive = archive(&[File::new("GAME.ch8", METHOD_STORED, &[0x12, 0x00])]);
+        let entry = entries(&archive).unwrap().remove(0);
+
+        // Cut off in the end record, which can't be found then
+        assert_eq!("Not a zip archive",
+                   entries(&archive[..archive.len() - 4]).unwrap_err().to_string());
+
+        // Only the end record is left, so the central directory it points to is missing
+        assert_eq!("Invalid zip archive",
+                   entries(&archive[archive.len() - END_SIZE..]).unwrap_err().to_string());
+
+        // Cut off in the data of the file
+        assert_eq!("Invalid zip archive",
+                   extract(&archive[..LOCAL_SIZE + 9], &entry).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let mut file = File::new("GAME.ch8", METHOD_STORED, &[0x12, 0x00]);
+        file.flags = 1;
+        let archive = archive(&[file]);
+        let entry = entries(&archive).unwrap().remove(0);
+
+        assert_eq!("`GAME.ch8` is encrypted",
+                   extract(&archive, &entry).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_too_large() {
+        // A megabyte of zeros compresses to about a kilobyte
+        let bomb = archive(&[File::new("BOMB.ch8", METHOD_DEFLATE, &[0; 0x100000])]);
+        let entry = entries(&bomb).unwrap().remove(0);
+
+        assert!(bomb.len() < 0x1000);
+        assert_eq!("`BOMB.ch8` is too large", extract(&bomb, &entry).unwrap_err().to_string());
+
+        let largest = archive(&[File::new("LARGEST.ch8", METHOD_DEFLATE, &[0; MAX_FILE_SIZE])]);
+        let entry = entries(&largest).unwrap().remove(0);
+
+        assert_eq!(MAX_FILE_SIZE, extract(&largest, &entry).unwrap().len());
+    }
+}